@@ -0,0 +1,20 @@
+fn main() {
+    #[cfg(feature = "ffi-roundtrip")]
+    ffi_roundtrip();
+}
+
+/// Compiles the C side of tests/ffi.rs, linked into the integration tests only
+/// so that it never ends up in the library.
+#[cfg(feature = "ffi-roundtrip")]
+fn ffi_roundtrip() {
+    println!("cargo:rerun-if-changed=tests/ffi/roundtrip.c");
+    println!("cargo:rerun-if-changed=include/gcrs.h");
+    cc::Build::new()
+        .file("tests/ffi/roundtrip.c")
+        .include("include")
+        .warnings_into_errors(true)
+        .cargo_metadata(false)
+        .compile("gcrs_roundtrip");
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    println!("cargo:rustc-link-arg-tests={out_dir}/libgcrs_roundtrip.a");
+}
@@ -0,0 +1,25 @@
+/* C functions of gcrs, built with --features ffi, see src/ffi.rs. */
+
+#ifndef GCRS_H
+#define GCRS_H
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/* The roots of the local store as the JSON of gcrs print --json, to be freed
+ * with gcrs_free, or NULL on failure, see gcrs_last_error. */
+char *gcrs_discover_json(void);
+
+/* Message of the last call on this thread that returned NULL, or NULL if it
+ * succeeded. Owned by gcrs and valid until the next call, not to be freed. */
+const char *gcrs_last_error(void);
+
+/* Frees a string returned by gcrs, NULL is ignored. */
+void gcrs_free(char *string);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
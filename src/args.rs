@@ -1,17 +1,459 @@
+use std::{num::NonZeroUsize, str::FromStr, time::Duration};
+
+use camino::Utf8PathBuf;
 use clap::{Parser, Subcommand};
 
+use crate::{
+    date::DateFormat,
+    duration,
+    render::{Column, Format, HyperlinkMode},
+    report::{self, Section},
+    scope::UserScope,
+    size::{self, Estimate},
+    sort::{SortKey, SortLocale},
+    source::SourceKind,
+    strategy::Strategy,
+};
+
 #[derive(Parser)]
 #[command(author, version, about)]
 pub struct Args {
     #[command(subcommand)]
     pub command: Option<Command>,
+    /// Order standalone roots and profiles by this key, ties are ordered by path
+    #[arg(long, global = true, value_enum)]
+    pub sort: Option<SortKey>,
+    /// Reverse the sort order, entries missing the sort key still come last
+    #[arg(long, global = true)]
+    pub reverse: bool,
+    /// How paths and other text are compared when sorting, only affects
+    /// human readable output
+    #[arg(long, global = true, value_enum, default_value_t = SortLocale::Byte)]
+    pub sort_locale: SortLocale,
+    /// Don't ask for confirmation before deleting, required when stdin isn't a terminal.
+    /// Protections like keeping the active generation still apply
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+    /// Number of roots removed in parallel
+    #[arg(short, long, global = true, default_value = "4")]
+    pub jobs: NonZeroUsize,
+    /// Show a desktop notification with notify-send when clean or apply-plan finishes
+    #[arg(long, global = true)]
+    pub notify: bool,
+    /// Remove planned roots even if they aren't symlinks, e.g. directories or regular
+    /// files, which could hold data other than links
+    #[arg(long, global = true)]
+    pub force_non_symlink: bool,
+    /// How dates are shown: relative, iso, unix or a strftime pattern like %Y-%m-%d.
+    /// JSON output always uses RFC 3339
+    #[arg(long, global = true, value_name = "FORMAT", value_parser = DateFormat::from_str)]
+    pub date_format: Option<DateFormat>,
+    /// Show dates in UTC instead of the local timezone
+    #[arg(long, global = true)]
+    pub utc: bool,
+    /// Prefix roots with icons in the grouped and table formats: active generation,
+    /// broken target, not deletable, or older than the [check] max-age
+    #[arg(long, global = true)]
+    pub icons: bool,
+    /// Use plain ASCII icons, the default if the locale isn't UTF-8
+    #[arg(long, global = true)]
+    pub ascii: bool,
+    /// Print root and profile paths as clickable hyperlinks in the grouped and table formats
+    #[arg(long, global = true, value_enum, default_value_t = HyperlinkMode::Auto)]
+    pub hyperlinks: HyperlinkMode,
+    /// Don't record destructive actions in the journal
+    #[arg(long, global = true)]
+    pub no_journal: bool,
+    /// Also treat targets in this directory as store paths, e.g. /mnt/cross/nix/store
+    /// for a store mounted for a chroot. Can be repeated. Other directories
+    /// ending in nix/store are detected from the targets
+    #[arg(long = "store-dir", global = true, value_name = "DIR")]
+    pub store_dirs: Vec<Utf8PathBuf>,
+    /// Print how long each phase of the run took to stderr at the end,
+    /// and include them in JSON output under "timings". The history of
+    /// trend --json is then under "snapshots"
+    #[arg(long, global = true)]
+    pub timings: bool,
+    /// Wait for another gcrs run that is removing roots to finish
+    /// instead of failing
+    #[arg(long, global = true)]
+    pub wait: bool,
+    /// Write newline-delimited JSON events about discovery, the plan and every
+    /// removal to this open file descriptor, for tools wrapping gcrs
+    #[arg(long, global = true, value_name = "FD", conflicts_with = "status_json")]
+    pub status_fd: Option<i32>,
+    /// Write the events of --status-fd to stderr
+    #[arg(long, global = true)]
+    pub status_json: bool,
+    /// Read roots from this file instead of asking nix-store, either captured
+    /// output of nix-store --gc --print-roots or root paths, one per line, as
+    /// printed by nix-store --query --roots. Can be repeated
+    #[arg(long = "input", global = true, value_name = "FILE")]
+    pub inputs: Vec<Utf8PathBuf>,
+    /// Don't group generations into profiles, list every root on its own.
+    /// Active and pinned generations are still kept
+    #[arg(long, global = true)]
+    pub no_group: bool,
+    /// Where roots are discovered from without --input
+    #[arg(long, global = true, value_enum, default_value_t = SourceKind::Command)]
+    pub source: SourceKind,
+    /// When running as root, also remove roots owned by these users, e.g. alice,bob,
+    /// or by all of them. Without it roots of other users are left out of removals
+    #[arg(long, global = true, value_name = "USERS", value_parser = UserScope::parse)]
+    pub users: Option<UserScope>,
 }
 
+const COUNT_HELP: &str = "\
+Prompt snippets, showing the number of stale roots from a cache refreshed in the background:
+
+  fish:
+    function fish_right_prompt
+        set -l stale (gcrs count --stale --older-than 90d --cached --refresh-async)
+        test \"$stale\" -gt 0; and echo \"⚠ $stale stale gcroots\"
+    end
+
+  zsh:
+    gcrs_prompt() {
+        local stale=$(gcrs count --stale --older-than 90d --cached --refresh-async)
+        (( stale > 0 )) && echo \"⚠ $stale stale gcroots\"
+    }
+    setopt prompt_subst
+    RPROMPT='$(gcrs_prompt)'";
+
 #[derive(Subcommand)]
 pub enum Command {
     /// Print garbage collection roots
     Print {
-        #[arg(short, long)]
+        /// Same as --format plain: path<TAB>target per root, sorted by path, for scripts
+        #[arg(short, long, conflicts_with = "format")]
         plain: bool,
+        /// Same as --format json
+        #[arg(long, conflicts_with_all = ["format", "plain", "columns"])]
+        json: bool,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = Format::Grouped)]
+        format: Format,
+        /// Comma-separated list of columns for the table and csv formats
+        #[arg(long, value_enum, value_delimiter = ',')]
+        columns: Option<Vec<Column>>,
+        /// Show the closure size of every root and the combined size of every profile,
+        /// queried from nix in one go. Implied by the size column
+        #[arg(long)]
+        sizes: bool,
+        /// Only show generations of profiles whose path matches this glob,
+        /// where * doesn't match / and ** does
+        #[arg(long, value_name = "GLOB")]
+        profile: Option<String>,
+        /// Only show roots of this user: in a per-user directory or their home,
+        /// or elsewhere owned by them
+        #[arg(long, value_name = "NAME")]
+        user: Option<String>,
+        /// Only show standalone roots
+        #[arg(long, conflicts_with_all = ["profiles_only", "profile"])]
+        standalone_only: bool,
+        /// Only show profile generations
+        #[arg(long)]
+        profiles_only: bool,
+        /// Only show roots whose symlink was last modified longer ago than this, e.g. 30d
+        #[arg(long, value_parser = duration::parse)]
+        older_than: Option<Duration>,
+        /// Only show dead roots, whose symlink or target is gone. For their paths
+        /// alone use --format csv --columns path
+        #[arg(long)]
+        dead: bool,
+    },
+    /// Check garbage collection roots against thresholds, exiting with 4 on violations
+    Check {
+        /// Maximum number of standalone roots
+        #[arg(long)]
+        max_standalone: Option<usize>,
+        /// Maximum age of any standalone root, e.g. 180d
+        #[arg(long, value_parser = duration::parse)]
+        max_age: Option<Duration>,
+        /// Maximum number of generations of any profile
+        #[arg(long)]
+        max_generations: Option<usize>,
+        /// Print violations as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Print one report of several analyses of the roots, discovered once
+    Report {
+        /// Comma-separated sections in the order they're printed,
+        /// every one by default or the sections of the [report] config
+        #[arg(long, value_enum, value_delimiter = ',')]
+        sections: Option<Vec<Section>>,
+        /// Print the report as JSON
+        #[arg(long)]
+        json: bool,
+        /// Print the report as Markdown
+        #[arg(long, conflicts_with = "json")]
+        markdown: bool,
+        /// Number of roots listed by the largest section
+        #[arg(long, value_name = "N", default_value_t = report::DEFAULT_LARGEST)]
+        largest: usize,
+        /// Generations a profile can have before the generations section lists it,
+        /// the [check] max-generations or 20 by default
+        #[arg(long)]
+        max_generations: Option<usize>,
+        /// Age after which the stale section lists a standalone root,
+        /// the [check] max-age or 90d by default
+        #[arg(long, value_parser = duration::parse)]
+        max_age: Option<Duration>,
+        /// Only read the metadata of this many roots, picked evenly from the
+        /// sorted paths, for the summary and stale sections, which then show
+        /// estimates like 1234 ± 56 for a 95% confidence
+        #[arg(long, value_name = "N")]
+        sample: Option<usize>,
+    },
+    /// Diagnose common problems with the Nix setup
+    Doctor,
+    /// Print the number of garbage collection roots, by default of all of them
+    #[command(after_help = COUNT_HELP)]
+    Count {
+        /// Count standalone roots
+        #[arg(long, group = "what")]
+        standalone: bool,
+        /// Count profile generations
+        #[arg(long, group = "what")]
+        generations: bool,
+        /// Count profiles
+        #[arg(long, group = "what")]
+        profiles: bool,
+        /// Count roots older than --older-than, except active generations
+        #[arg(long, group = "what", requires = "older_than")]
+        stale: bool,
+        /// Age after which a root is stale, e.g. 90d
+        #[arg(long, requires = "stale", value_parser = duration::parse)]
+        older_than: Option<Duration>,
+        /// Exit with 1 if the count is 0
+        #[arg(long)]
+        nonzero_exit: bool,
+        /// Print the last count if it is recent enough instead of discovering roots,
+        /// for use in shell prompts
+        #[arg(long)]
+        cached: bool,
+        /// How old a cached count may be, e.g. 60m
+        #[arg(
+            long,
+            requires = "cached",
+            default_value = "60m",
+            value_parser = duration::parse
+        )]
+        max_age: Duration,
+        /// Print an outdated cached count right away and update the cache in the background
+        #[arg(long, requires = "cached")]
+        refresh_async: bool,
+        /// Only update the cache without printing, used by --refresh-async
+        #[arg(long, hide = true, conflicts_with = "cached")]
+        refresh_cache: bool,
+        /// Only read the mtimes of this many roots, picked evenly from the sorted
+        /// paths, and print an estimate like 1234 ± 56 for a 95% confidence
+        #[arg(
+            long,
+            value_name = "N",
+            requires = "stale",
+            conflicts_with_all = ["cached", "refresh_cache"]
+        )]
+        sample: Option<usize>,
+    },
+    /// Remove standalone roots and inactive generations
+    #[command(group = clap::ArgGroup::new("selection").required(true).multiple(true))]
+    Clean {
+        /// Remove standalone roots and inactive generations older than this, e.g. 90d
+        #[arg(long, group = "selection", value_parser = duration::parse)]
+        older_than: Option<Duration>,
+        /// Remove roots pointing outside the store, they protect nothing from garbage collection
+        #[arg(long, group = "selection")]
+        non_store: bool,
+        /// Remove inactive generations except the newest one of each of the last
+        /// N calendar months, the current one included
+        #[arg(
+            long,
+            value_name = "N",
+            group = "selection",
+            conflicts_with_all = ["older_than", "non_store"]
+        )]
+        keep_monthly: Option<usize>,
+        /// Remove inactive generations except the newest N of each profile.
+        /// With --keep-monthly or --keep-since also keep these, keep-last of
+        /// the retention config by default
+        #[arg(
+            long,
+            value_name = "N",
+            group = "selection",
+            conflicts_with_all = ["older_than", "non_store"]
+        )]
+        keep_last: Option<usize>,
+        /// Remove inactive generations except ones modified less than this
+        /// long ago, e.g. 14d
+        #[arg(
+            long,
+            group = "selection",
+            value_parser = duration::parse,
+            conflicts_with_all = ["older_than", "non_store", "keep_monthly"]
+        )]
+        keep_since: Option<Duration>,
+        /// Remove roots in --strategy order until about this much space would be freed,
+        /// e.g. 20G. Roots whose size can't be estimated are left out
+        #[arg(long, group = "selection", value_parser = size::parse)]
+        free: Option<u64>,
+        /// How --free estimates the space removing a root frees
+        #[arg(long, value_enum, default_value_t = Estimate::Closure, requires = "free")]
+        estimate: Estimate,
+        /// Order in which roots are considered, oldest by default when using --free
+        #[arg(long)]
+        strategy: Option<Strategy>,
+        /// Only remove roots a run at least --grace earlier selected too,
+        /// unchanged since. Newly selected roots are recorded for a later run,
+        /// except with --dry-run
+        #[arg(long)]
+        confirm_across_runs: bool,
+        /// How long a root has to stay selected with --confirm-across-runs
+        #[arg(
+            long,
+            value_parser = duration::parse,
+            default_value = "1d",
+            requires = "confirm_across_runs"
+        )]
+        grace: Duration,
+        /// Only print what would be removed
+        #[arg(long)]
+        dry_run: bool,
+        /// Write the plan to this file instead of removing anything
+        #[arg(long, value_name = "FILE")]
+        emit_plan: Option<Utf8PathBuf>,
+        /// Ask about every selected root before removing it
+        #[arg(short, long, conflicts_with_all = ["dry_run", "emit_plan"])]
+        interactive: bool,
+        /// Edit the plan in $VISUAL or $EDITOR before removing anything,
+        /// deleting the line of a root keeps it
+        #[arg(long, conflicts_with_all = ["dry_run", "emit_plan", "interactive"])]
+        edit: bool,
+        /// Also select directory roots, removing them along with the links inside them
+        /// also needs --force-non-symlink
+        #[arg(long)]
+        recursive: bool,
+        /// List the store paths that would become garbage, largest first
+        #[arg(long)]
+        show_dead: bool,
+        /// Number of paths listed by --show-dead
+        #[arg(long, value_name = "N", default_value_t = 20, requires = "show_dead")]
+        show_dead_limit: usize,
+    },
+    /// Remove generations of a profile or a single root, keeping the active generation
+    Delete {
+        /// Profile, or a standalone root or generation link without a selection
+        path: Utf8PathBuf,
+        /// Generation numbers to remove, e.g. 5,6,7
+        #[arg(long, value_delimiter = ',', num_args = 1.., conflicts_with = "older_than")]
+        generations: Vec<u64>,
+        /// Remove the generations older than this, e.g. 30d
+        #[arg(long, value_parser = duration::parse)]
+        older_than: Option<Duration>,
+        /// Only print what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Remove the roots of a plan written by clean --emit-plan,
+    /// refusing entries that changed since
+    ApplyPlan {
+        /// Plan file
+        plan: Utf8PathBuf,
+    },
+    /// Inspect the journal of destructive actions performed by gcrs
+    Journal {
+        #[command(subcommand)]
+        command: JournalCommand,
+    },
+    /// List nix-channel profiles with their generations and ages
+    Channels {
+        #[command(subcommand)]
+        command: Option<ChannelsCommand>,
+    },
+    /// List symlinks into the store in well-known directories, telling the ones
+    /// registered as roots from the ones whose targets can be garbage collected
+    AuditLinks {
+        /// Directories to search instead of /etc, ~/.config/systemd/user,
+        /// ~/.local/bin and ~/.local/share/applications
+        dirs: Vec<Utf8PathBuf>,
+        /// Register the unregistered links as indirect roots in the per-user
+        /// gcroots directory, so that their targets survive garbage collection
+        #[arg(long)]
+        register: bool,
+    },
+    /// Record the current numbers of roots and compare them to earlier runs,
+    /// listing the roots that changed since the last run
+    Trend {
+        /// How far back to compare, e.g. 30d
+        #[arg(long, default_value = "7d", value_parser = duration::parse)]
+        since: Duration,
+        /// Print the recorded history as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Share the thresholds and retention of the config as a policy file
+    Policy {
+        #[command(subcommand)]
+        command: PolicyCommand,
+    },
+    /// Protect a profile generation from clean and channels prune,
+    /// or list and remove pins
+    PinGeneration {
+        /// Profile path, or its name like system if no other profile has it
+        #[arg(long, required_unless_present = "list")]
+        profile: Option<String>,
+        /// Generation number
+        #[arg(required_unless_present = "list")]
+        generation: Option<u64>,
+        /// Why the generation is pinned, shown when it's skipped
+        #[arg(long, conflicts_with = "remove")]
+        reason: Option<String>,
+        /// List pinned generations
+        #[arg(long, conflicts_with_all = ["profile", "generation", "reason", "remove"])]
+        list: bool,
+        /// Unpin the generation
+        #[arg(long)]
+        remove: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum ChannelsCommand {
+    /// Remove old generations of channel profiles, the channels stay registered
+    Prune {
+        /// Number of newest generations to keep in every channel profile,
+        /// the active one is always kept
+        #[arg(long, value_name = "N")]
+        keep_last: usize,
+        /// Only print what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum PolicyCommand {
+    /// Print the [check] thresholds and [retention] of the effective config
+    /// as a policy document
+    Export,
+    /// Merge a policy document into the config file, stricter thresholds apply
+    /// right away, looser ones only with --overwrite
+    Import {
+        /// Policy file written by policy export
+        file: Utf8PathBuf,
+        /// Also apply thresholds looser than the local ones
+        #[arg(long)]
+        overwrite: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum JournalCommand {
+    /// Print journal entries
+    Show {
+        /// Only print entries newer than this, e.g. 7d
+        #[arg(long, value_parser = duration::parse)]
+        since: Option<Duration>,
     },
 }
@@ -0,0 +1,195 @@
+use std::fmt::Display;
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::{
+    gcroot::GCRoots,
+    plan::count,
+    walk::{self, WalkOptions},
+};
+
+#[derive(Debug, Clone)]
+/// A symlink into the store found by [audit].
+pub struct StoreLink {
+    pub path: Utf8PathBuf,
+    pub target: Utf8PathBuf,
+    /// Number of roots keeping the target alive, for links that aren't roots.
+    pub kept_by: usize,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Symlinks into the store, partitioned by whether Nix knows them as roots.
+pub struct Audit {
+    pub registered: Vec<StoreLink>,
+    pub unregistered: Vec<StoreLink>,
+}
+
+/// Finds the symlinks into the store in dirs, walked within options.
+/// Directories that don't exist are skipped, ones that can't be read warn.
+pub fn audit(gcroots: &GCRoots, dirs: &[Utf8PathBuf], options: &WalkOptions) -> Audit {
+    let mut audit = Audit::default();
+    for dir in dirs {
+        let walk = match walk::walk(dir, options) {
+            Ok(walk) => walk,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => continue,
+            Err(e) => {
+                eprintln!("warning: couldn't read {dir}: {e}");
+                continue;
+            }
+        };
+        for (path, e) in walk.errors.iter() {
+            eprintln!("warning: couldn't read {path}: {e}");
+        }
+        for entry in walk.entries.iter().filter(|e| e.file_type.is_symlink()) {
+            let Some(target) = store_target(&entry.path, gcroots) else {
+                continue;
+            };
+            let registered = gcroots.find(&entry.path).is_some();
+            let link = StoreLink {
+                kept_by: match registered {
+                    true => 0,
+                    false => gcroots.find_by_target(&target).len(),
+                },
+                path: entry.path.clone(),
+                target,
+            };
+            match registered {
+                true => audit.registered.push(link),
+                false => audit.unregistered.push(link),
+            }
+        }
+    }
+    audit
+}
+
+/// The target of the link if it's in a store directory,
+/// relative targets are resolved from the directory of the link.
+fn store_target(link: &Utf8Path, gcroots: &GCRoots) -> Option<Utf8PathBuf> {
+    let target = link.read_link_utf8().ok()?;
+    let target = match (target.is_relative(), link.parent()) {
+        (true, Some(parent)) => parent.join(target),
+        _ => target,
+    };
+    gcroots.store_dirs().contains(&target).then_some(target)
+}
+
+impl Display for StoreLink {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} -> {}", self.path, self.target)
+    }
+}
+
+impl Display for Audit {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} registered as roots:",
+            count(self.registered.len(), "link", "links")
+        )?;
+        for link in self.registered.iter() {
+            write!(f, "\n  {}", link)?;
+        }
+        write!(
+            f,
+            "\n{} not registered, their targets can be garbage collected:",
+            count(self.unregistered.len(), "link", "links")
+        )?;
+        for link in self.unregistered.iter() {
+            write!(f, "\n  {}", link)?;
+            if link.kept_by > 0 {
+                write!(
+                    f,
+                    " (target kept by {} for now)",
+                    count(link.kept_by, "other root", "other roots")
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_dir() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        (dir, path)
+    }
+
+    /// Links in dir: registered and kept pointing at targets of roots, lone
+    /// pointing at a target no root has, and one link out of the store.
+    fn audited() -> (tempfile::TempDir, Utf8PathBuf, Audit) {
+        let (tempdir, dir) = temporary_dir();
+        std::fs::create_dir(dir.join("bin")).unwrap();
+        let link = |name: &str, target: &str| {
+            std::os::unix::fs::symlink(target, dir.join(name)).unwrap();
+        };
+        link("registered", "/nix/store/aaaa-a");
+        link("bin/kept", "/nix/store/aaaa-a");
+        link("lone", "/nix/store/bbbb-b");
+        link("elsewhere", "/home/a/notes");
+        std::fs::write(dir.join("file"), "").unwrap();
+        let output =
+            format!("{dir}/registered -> /nix/store/aaaa-a\n/home/a/result -> /nix/store/aaaa-a\n");
+        let gcroots = GCRoots::from_print_roots_output(&output).unwrap();
+        let missing = dir.join("missing");
+        let audit = audit(&gcroots, &[missing, dir.clone()], &WalkOptions::WELL_KNOWN);
+        (tempdir, dir, audit)
+    }
+
+    #[test]
+    fn links_are_partitioned_by_being_roots() {
+        let (_tempdir, dir, audit) = audited();
+        let registered = audit
+            .registered
+            .iter()
+            .map(|link| (link.path.clone(), link.kept_by))
+            .collect::<Vec<_>>();
+        assert_eq!(registered, [(dir.join("registered"), 0)]);
+        let unregistered = audit
+            .unregistered
+            .iter()
+            .map(|link| (link.path.clone(), link.target.as_str(), link.kept_by))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            unregistered,
+            [
+                // Each directory's links before those of its subdirectories.
+                (dir.join("lone"), "/nix/store/bbbb-b", 0),
+                (dir.join("bin/kept"), "/nix/store/aaaa-a", 2),
+            ]
+        );
+    }
+
+    #[test]
+    fn relative_links_are_resolved_from_their_directory() {
+        let (_tempdir, dir) = temporary_dir();
+        let hash = "c".repeat(32);
+        std::os::unix::fs::symlink(format!("nix/store/{hash}-c"), dir.join("relative")).unwrap();
+        std::os::unix::fs::symlink(format!("{hash}-c"), dir.join("outside")).unwrap();
+        // The store in dir is detected from the target of the root.
+        let output = format!("/home/a/result -> {dir}/nix/store/{hash}-a\n");
+        let gcroots = GCRoots::from_print_roots_output(&output).unwrap();
+        assert_eq!(
+            store_target(&dir.join("relative"), &gcroots),
+            Some(dir.join(format!("nix/store/{hash}-c")))
+        );
+        assert_eq!(store_target(&dir.join("outside"), &gcroots), None);
+    }
+
+    #[test]
+    fn audit_wording() {
+        let (_tempdir, dir, audit) = audited();
+        assert_eq!(
+            audit.to_string(),
+            format!(
+                "1 link registered as roots:\n  {dir}/registered -> /nix/store/aaaa-a\n\
+                2 links not registered, their targets can be garbage collected:\n  \
+                {dir}/lone -> /nix/store/bbbb-b\n  \
+                {dir}/bin/kept -> /nix/store/aaaa-a (target kept by 2 other roots for now)"
+            )
+        );
+    }
+}
@@ -0,0 +1,469 @@
+use std::{
+    collections::BTreeSet,
+    io::{Read, Write},
+    os::fd::AsRawFd,
+    time::SystemTime,
+};
+
+use camino::Utf8PathBuf;
+use eyre::{Result, WrapErr};
+use nix::sys::termios::{self, SetArg, Termios};
+
+use crate::{
+    clipboard,
+    gcroot::{GCRoot, GCRoots},
+    plan::count,
+    render::Icons,
+};
+
+const HELP: &str = "j/k move, l/h expand/collapse, space mark, y copy, d delete marked, q quit";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A line of the browser.
+enum Row {
+    /// Index into [GCRoots::profiles].
+    Profile(usize),
+    /// Profile index and generation number.
+    Generation(usize, u64),
+    /// Index into [GCRoots::standalone].
+    Standalone(usize),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A key press the browser reacts to.
+enum Key {
+    Up,
+    Down,
+    Left,
+    Right,
+    Enter,
+    Space,
+    Copy,
+    Delete,
+    Quit,
+}
+
+impl Key {
+    /// The key of the bytes of a single read from the terminal, if it's one
+    /// the browser knows.
+    fn parse(bytes: &[u8]) -> Option<Self> {
+        match bytes {
+            b"\x1b[A" | b"\x1bOA" | b"k" => Some(Key::Up),
+            b"\x1b[B" | b"\x1bOB" | b"j" => Some(Key::Down),
+            b"\x1b[D" | b"\x1bOD" | b"h" => Some(Key::Left),
+            b"\x1b[C" | b"\x1bOC" | b"l" => Some(Key::Right),
+            b"\r" | b"\n" => Some(Key::Enter),
+            b" " => Some(Key::Space),
+            b"y" => Some(Key::Copy),
+            b"d" => Some(Key::Delete),
+            // Escape, q and Ctrl-C, which doesn't raise SIGINT in raw mode.
+            b"\x1b" | b"q" | b"\x03" => Some(Key::Quit),
+            _ => None,
+        }
+    }
+}
+
+/// What the user left the browser with.
+enum Outcome {
+    Quit,
+    Delete,
+}
+
+/// State of the interactive browser: which profiles are expanded, where the
+/// cursor is and which roots are marked for deletion.
+struct Browser<'a> {
+    gcroots: &'a GCRoots,
+    /// Prefix roots with the icons of the other formats.
+    icons: Option<&'a Icons>,
+    expanded: BTreeSet<usize>,
+    /// Index into [Browser::rows].
+    cursor: usize,
+    /// First row shown, so that the cursor stays in view.
+    top: usize,
+    /// Paths of the marked roots.
+    marked: BTreeSet<Utf8PathBuf>,
+    /// Shown instead of the help until the next key press.
+    message: Option<String>,
+    /// Puts text on the clipboard, [clipboard::copy] outside of tests.
+    clipboard: fn(&str) -> Result<()>,
+}
+
+impl<'a> Browser<'a> {
+    fn new(gcroots: &'a GCRoots, icons: Option<&'a Icons>) -> Self {
+        Browser {
+            gcroots,
+            icons,
+            expanded: BTreeSet::new(),
+            cursor: 0,
+            top: 0,
+            marked: BTreeSet::new(),
+            message: None,
+            clipboard: clipboard::copy,
+        }
+    }
+
+    /// Profiles, with the generations of expanded ones newest first, then standalone roots.
+    fn rows(&self) -> Vec<Row> {
+        let mut rows = Vec::new();
+        for (index, profile) in self.gcroots.profiles().iter().enumerate() {
+            rows.push(Row::Profile(index));
+            if self.expanded.contains(&index) {
+                let generations = profile.generations_desc().map(|(id, _)| id);
+                rows.extend(generations.map(|id| Row::Generation(index, id)));
+            }
+        }
+        rows.extend((0..self.gcroots.standalone().len()).map(Row::Standalone));
+        rows
+    }
+
+    fn root(&self, row: Row) -> Option<&'a GCRoot> {
+        match row {
+            Row::Profile(_) => None,
+            Row::Generation(profile, id) => self.gcroots.profiles()[profile].generations.get(&id),
+            Row::Standalone(index) => self.gcroots.standalone().get(index),
+        }
+    }
+
+    fn handle(&mut self, key: Key) -> Option<Outcome> {
+        self.message = None;
+        let rows = self.rows();
+        let Some(row) = rows.get(self.cursor).copied() else {
+            return matches!(key, Key::Quit).then_some(Outcome::Quit);
+        };
+        match (key, row) {
+            (Key::Up, _) => self.cursor = self.cursor.saturating_sub(1),
+            (Key::Down, _) => self.cursor = (self.cursor + 1).min(rows.len() - 1),
+            (Key::Right | Key::Enter, Row::Profile(index)) => {
+                self.expanded.insert(index);
+            }
+            (Key::Left, Row::Profile(index)) => {
+                self.expanded.remove(&index);
+            }
+            (Key::Space, Row::Profile(index)) => {
+                match self.expanded.contains(&index) {
+                    true => self.expanded.remove(&index),
+                    false => self.expanded.insert(index),
+                };
+            }
+            (Key::Left, Row::Generation(index, _)) => {
+                self.expanded.remove(&index);
+                self.cursor = self
+                    .rows()
+                    .iter()
+                    .position(|row| *row == Row::Profile(index))
+                    .unwrap_or(0);
+            }
+            (Key::Space, row) => self.toggle(row),
+            (Key::Copy, row) => self.copy(row),
+            (Key::Delete, _) => match self.marked.is_empty() {
+                true => self.message = Some("nothing marked, mark roots with space".to_string()),
+                false => return Some(Outcome::Delete),
+            },
+            (Key::Quit, _) => return Some(Outcome::Quit),
+            _ => {}
+        }
+        None
+    }
+
+    /// Marks or unmarks the root, refusing ones that can't be deleted.
+    fn toggle(&mut self, row: Row) {
+        let Some(gcroot) = self.root(row) else {
+            return;
+        };
+        if let Row::Generation(index, id) = row {
+            if self.gcroots.profiles()[index].active_generation == Some(id) {
+                self.message = Some("the active generation can't be deleted".to_string());
+                return;
+            }
+        }
+        if !gcroot.deletable() {
+            self.message = Some(format!("{} can't be deleted", gcroot.path));
+            return;
+        }
+        let path = gcroot.path.to_path_buf();
+        if !self.marked.remove(&path) {
+            self.marked.insert(path);
+        }
+    }
+
+    /// Copies the paths of the marked roots, one per line,
+    /// or the path of the root under the cursor if none are marked.
+    fn copy(&mut self, row: Row) {
+        let paths: Vec<&str> = match self.marked.is_empty() {
+            true => self
+                .root(row)
+                .map(|gcroot| gcroot.path.as_str())
+                .into_iter()
+                .collect(),
+            false => self.marked.iter().map(|path| path.as_str()).collect(),
+        };
+        if paths.is_empty() {
+            self.message = Some("nothing to copy, mark roots with space".to_string());
+            return;
+        }
+        self.message = Some(match (self.clipboard)(&paths.join("\n")) {
+            Ok(()) => format!("copied {}", count(paths.len(), "path", "paths")),
+            Err(e) => format!("couldn't copy: {e}"),
+        });
+    }
+
+    fn line(&self, row: Row) -> String {
+        let mark = |gcroot: &GCRoot| match (self.marked.contains(&*gcroot.path), gcroot.deletable())
+        {
+            (true, _) => "[x]",
+            (false, true) => "[ ]",
+            (false, false) => "   ",
+        };
+        let now = SystemTime::now();
+        let icon = |gcroot: Option<&GCRoot>, active: Option<bool>| match self.icons {
+            Some(icons) => icons.prefix(gcroot.and_then(|g| icons.state(g, active, now))),
+            None => String::new(),
+        };
+        match row {
+            Row::Profile(index) => {
+                let profile = &self.gcroots.profiles()[index];
+                let fold = match self.expanded.contains(&index) {
+                    true => '-',
+                    false => '+',
+                };
+                let marked = profile
+                    .generations
+                    .values()
+                    .filter(|gcroot| self.marked.contains(&*gcroot.path))
+                    .count();
+                let marked = match marked {
+                    0 => String::new(),
+                    n => format!(", {n} marked"),
+                };
+                format!(
+                    "{}{} {} ({}{})",
+                    icon(None, None),
+                    fold,
+                    profile.path,
+                    count(profile.len(), "generation", "generations"),
+                    marked
+                )
+            }
+            Row::Generation(index, id) => {
+                let profile = &self.gcroots.profiles()[index];
+                let gcroot = &profile.generations[&id];
+                let digits = profile.latest().map_or(1, |(id, _)| id.to_string().len());
+                let marker = match profile.active_generation == Some(id) {
+                    true => '>',
+                    false => ' ',
+                };
+                format!(
+                    "{}  {} {} {: >digits$} -> {}",
+                    icon(Some(gcroot), Some(profile.active_generation == Some(id))),
+                    mark(gcroot),
+                    marker,
+                    id,
+                    gcroot.target
+                )
+            }
+            Row::Standalone(index) => {
+                let gcroot = &self.gcroots.standalone()[index];
+                format!("{}{} {}", icon(Some(gcroot), None), mark(gcroot), gcroot)
+            }
+        }
+    }
+
+    /// The screen for a terminal of the given size: as many rows as fit,
+    /// cut to the width, and the help or a message on the last line.
+    fn frame(&mut self, height: usize, width: usize) -> String {
+        let visible = height.saturating_sub(1).max(1);
+        let rows = self.rows();
+        self.cursor = self.cursor.min(rows.len().saturating_sub(1));
+        if self.cursor < self.top {
+            self.top = self.cursor;
+        } else if self.cursor >= self.top + visible {
+            self.top = self.cursor + 1 - visible;
+        }
+        let mut frame = String::from("\x1b[H\x1b[2J");
+        if rows.is_empty() {
+            frame.push_str("no roots found\r\n");
+        }
+        for (index, row) in rows.iter().enumerate().skip(self.top).take(visible) {
+            let line = self.line(*row).chars().take(width).collect::<String>();
+            // The active generation is bold, like the > marker of print stands out.
+            let active = match *row {
+                Row::Generation(profile, id) => {
+                    self.gcroots.profiles()[profile].active_generation == Some(id)
+                }
+                _ => false,
+            };
+            if active {
+                frame.push_str("\x1b[1m");
+            }
+            if index == self.cursor {
+                frame.push_str("\x1b[7m");
+            }
+            frame.push_str(&format!("{line}\x1b[0m\r\n"));
+        }
+        frame.push_str(&format!("\x1b[{height};1H"));
+        let footer = match &self.message {
+            Some(message) => message.clone(),
+            None => format!("{} marked | {HELP}", self.marked.len()),
+        };
+        frame.extend(footer.chars().take(width));
+        frame
+    }
+}
+
+/// Puts the terminal in raw mode on the alternate screen, restoring it when dropped.
+struct Terminal {
+    original: Termios,
+}
+
+impl Terminal {
+    fn enter() -> Result<Self> {
+        let stdin = std::io::stdin();
+        let original =
+            termios::tcgetattr(stdin.as_raw_fd()).wrap_err("couldn't read the terminal mode")?;
+        let mut raw = original.clone();
+        termios::cfmakeraw(&mut raw);
+        termios::tcsetattr(stdin.as_raw_fd(), SetArg::TCSANOW, &raw)
+            .wrap_err("couldn't switch the terminal to raw mode")?;
+        let terminal = Terminal { original };
+        terminal.write("\x1b[?1049h\x1b[?25l")?;
+        Ok(terminal)
+    }
+
+    fn write(&self, text: &str) -> Result<()> {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(text.as_bytes())?;
+        Ok(stdout.flush()?)
+    }
+
+    /// Rows and columns of the terminal, 24 by 80 if it can't be asked.
+    fn size(&self) -> (usize, usize) {
+        nix::ioctl_read_bad!(window_size, nix::libc::TIOCGWINSZ, nix::libc::winsize);
+        let mut size = nix::libc::winsize {
+            ws_row: 0,
+            ws_col: 0,
+            ws_xpixel: 0,
+            ws_ypixel: 0,
+        };
+        match unsafe { window_size(std::io::stdout().as_raw_fd(), &mut size) } {
+            Ok(_) if size.ws_row > 0 && size.ws_col > 0 => {
+                (size.ws_row as usize, size.ws_col as usize)
+            }
+            _ => (24, 80),
+        }
+    }
+
+    /// Waits for a key, None when stdin is closed.
+    fn key(&self) -> Result<Option<Option<Key>>> {
+        let mut buffer = [0; 16];
+        let read = std::io::stdin().lock().read(&mut buffer)?;
+        Ok((read != 0).then(|| Key::parse(&buffer[..read])))
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = self.write("\x1b[?25h\x1b[?1049l");
+        let _ = termios::tcsetattr(
+            std::io::stdin().as_raw_fd(),
+            SetArg::TCSANOW,
+            &self.original,
+        );
+    }
+}
+
+/// Browses the roots on the terminal, profiles expanding to their generations.
+/// Returns the paths of the roots marked for deletion when the user asked to
+/// delete them, or None if they quit. Stdin and stdout have to be a terminal.
+pub fn run(gcroots: &GCRoots, icons: Option<&Icons>) -> Result<Option<BTreeSet<Utf8PathBuf>>> {
+    let terminal = Terminal::enter()?;
+    let mut browser = Browser::new(gcroots, icons);
+    loop {
+        let (height, width) = terminal.size();
+        terminal.write(&browser.frame(height, width))?;
+        let Some(key) = terminal.key()? else {
+            return Ok(None);
+        };
+        match key.and_then(|key| browser.handle(key)) {
+            Some(Outcome::Quit) => return Ok(None),
+            Some(Outcome::Delete) => return Ok(Some(browser.marked)),
+            None => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+
+    thread_local! {
+        static COPIED: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    }
+
+    fn record(text: &str) -> Result<()> {
+        COPIED.with(|copied| copied.borrow_mut().push(text.to_string()));
+        Ok(())
+    }
+
+    fn copied() -> Vec<String> {
+        COPIED.with(|copied| std::mem::take(&mut *copied.borrow_mut()))
+    }
+
+    fn gcroots() -> GCRoots {
+        GCRoots::from_print_roots_output(
+            "/tmp/gcrs-browse/a -> /nix/store/aaaa-a\n\
+             /tmp/gcrs-browse/b -> /nix/store/bbbb-b\n\
+             /tmp/gcrs-browse/c -> /nix/store/cccc-c\n",
+        )
+        .unwrap()
+    }
+
+    fn browser(gcroots: &GCRoots) -> Browser<'_> {
+        let mut browser = Browser::new(gcroots, None);
+        browser.clipboard = record;
+        browser
+    }
+
+    #[test]
+    fn copy_takes_the_root_under_the_cursor_without_marks() {
+        let gcroots = gcroots();
+        let mut browser = browser(&gcroots);
+        browser.handle(Key::Down);
+        browser.handle(Key::Copy);
+        assert_eq!(copied(), ["/tmp/gcrs-browse/b"]);
+        assert_eq!(browser.message.as_deref(), Some("copied 1 path"));
+    }
+
+    #[test]
+    fn copy_takes_the_marked_roots() {
+        let gcroots = gcroots();
+        let mut browser = browser(&gcroots);
+        browser.marked.insert("/tmp/gcrs-browse/a".into());
+        browser.marked.insert("/tmp/gcrs-browse/c".into());
+        assert!(browser.handle(Key::Copy).is_none());
+        assert_eq!(copied(), ["/tmp/gcrs-browse/a\n/tmp/gcrs-browse/c"]);
+        assert_eq!(browser.message.as_deref(), Some("copied 2 paths"));
+        assert_eq!(browser.marked.len(), 2);
+    }
+
+    #[test]
+    fn copy_failure_is_shown() {
+        let gcroots = gcroots();
+        let mut browser = browser(&gcroots);
+        browser.clipboard = |_| Err(eyre::eyre!("no clipboard"));
+        browser.handle(Key::Copy);
+        assert_eq!(
+            browser.message.as_deref(),
+            Some("couldn't copy: no clipboard")
+        );
+        browser.handle(Key::Down);
+        assert_eq!(browser.message, None);
+    }
+
+    #[test]
+    fn y_is_the_copy_key() {
+        assert_eq!(Key::parse(b"y"), Some(Key::Copy));
+        assert_eq!(Key::parse(b"d"), Some(Key::Delete));
+    }
+}
@@ -0,0 +1,75 @@
+use std::{
+    collections::BTreeMap,
+    io::Write,
+    time::{Duration, SystemTime},
+};
+
+use camino::Utf8PathBuf;
+use chrono::{DateTime, Utc};
+use eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::state;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// A count as of the time it was taken.
+pub struct CachedCount {
+    pub timestamp: DateTime<Utc>,
+    pub count: usize,
+}
+
+/// Last results of count, keyed by what was counted, so that shell prompts
+/// don't pay for discovery every time they are drawn.
+pub struct CountCache {
+    path: Utf8PathBuf,
+}
+
+impl CountCache {
+    /// The cache at `$XDG_CACHE_HOME/gcrs/counts.json`, see [state::dir].
+    pub fn open() -> Result<Self> {
+        Ok(CountCache {
+            path: state::dir(state::Kind::Cache)?.join("counts.json"),
+        })
+    }
+
+    /// The cached count for the key, if there is one. A missing or corrupt
+    /// cache file counts as empty.
+    pub fn get(&self, key: &str) -> Option<CachedCount> {
+        self.read().remove(key)
+    }
+
+    /// If the count was taken at most max_age ago.
+    pub fn is_fresh(count: &CachedCount, max_age: Duration) -> bool {
+        SystemTime::now()
+            .duration_since(count.timestamp.into())
+            .is_ok_and(|age| age <= max_age)
+    }
+
+    /// Stores a count taken now. The file is replaced atomically, so readers
+    /// never see a partial write.
+    pub fn set(&self, key: &str, count: usize) -> Result<()> {
+        let mut counts = self.read();
+        counts.insert(
+            key.to_string(),
+            CachedCount {
+                timestamp: Utc::now(),
+                count,
+            },
+        );
+        let dir = state::create_dir(state::Kind::Cache)?;
+        let temporary = dir.join(format!(".counts.json.{}", std::process::id()));
+        let mut file = std::fs::File::create(&temporary)
+            .wrap_err_with(|| format!("couldn't create {temporary}"))?;
+        file.write_all(serde_json::to_string(&counts)?.as_bytes())?;
+        file.sync_data()?;
+        std::fs::rename(&temporary, &self.path)
+            .wrap_err_with(|| format!("couldn't replace {}", self.path))
+    }
+
+    fn read(&self) -> BTreeMap<String, CachedCount> {
+        std::fs::read(&self.path)
+            .ok()
+            .and_then(|json| serde_json::from_slice(&json).ok())
+            .unwrap_or_default()
+    }
+}
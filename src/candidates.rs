@@ -0,0 +1,338 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::Write,
+    time::{Duration, SystemTime},
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use eyre::{eyre, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    duration,
+    plan::{count, PlanEntry},
+    state,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// A root a run of clean --confirm-across-runs selected but didn't remove.
+pub struct Candidate {
+    pub target: Utf8PathBuf,
+    /// Modification time of the symlink in seconds since the epoch, if known.
+    pub mtime: Option<u64>,
+    pub recorded: DateTime<Utc>,
+}
+
+/// Roots waiting to be selected again before they're removed,
+/// kept in `$XDG_STATE_HOME/gcrs/candidates.json`.
+pub struct Candidates {
+    path: Utf8PathBuf,
+    candidates: BTreeMap<Utf8PathBuf, Candidate>,
+}
+
+/// What [Candidates::settle] made of the selected entries.
+pub struct Settled {
+    /// Entries recorded at least the grace period ago and unchanged since.
+    pub ready: Vec<PlanEntry>,
+    /// Entries selected for the first time, or again after they changed.
+    pub recorded: usize,
+    /// Entries recorded less than the grace period ago.
+    pub waiting: usize,
+    /// Candidates that changed or aren't selected anymore.
+    pub dropped: usize,
+    pub grace: Duration,
+}
+
+impl Candidates {
+    /// Reads the candidates, a missing file has none.
+    pub fn open() -> Result<Self> {
+        Self::open_at(state::dir(state::Kind::State)?.join("candidates.json"))
+    }
+
+    fn open_at(path: Utf8PathBuf) -> Result<Self> {
+        let candidates = match std::fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json)
+                .wrap_err_with(|| format!("couldn't parse candidates in {path}"))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(eyre!(e).wrap_err(format!("couldn't read {path}"))),
+        };
+        Ok(Candidates { path, candidates })
+    }
+
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    /// Replaces the candidates with the selected entries, keeping when
+    /// unchanged ones were recorded, and returns the entries that can be
+    /// removed now. An entry is unchanged if its root still points to the
+    /// same target and wasn't modified since it was recorded.
+    pub fn settle(&mut self, entries: Vec<PlanEntry>, grace: Duration, now: SystemTime) -> Settled {
+        let now: DateTime<Utc> = now.into();
+        let grace_chrono = chrono::Duration::from_std(grace).unwrap_or(chrono::Duration::MAX);
+        let mut previous = std::mem::take(&mut self.candidates);
+        let mut settled = Settled {
+            ready: Vec::new(),
+            recorded: 0,
+            waiting: 0,
+            dropped: 0,
+            grace,
+        };
+        for entry in entries {
+            let recorded = match previous.remove(&entry.path) {
+                Some(candidate)
+                    if candidate.target == entry.target && candidate.mtime == entry.mtime =>
+                {
+                    match now - candidate.recorded >= grace_chrono {
+                        true => settled.ready.push(entry.clone()),
+                        false => settled.waiting += 1,
+                    }
+                    candidate.recorded
+                }
+                changed => {
+                    settled.dropped += changed.is_some() as usize;
+                    settled.recorded += 1;
+                    now
+                }
+            };
+            self.candidates.insert(
+                entry.path.clone(),
+                Candidate {
+                    target: entry.target,
+                    mtime: entry.mtime,
+                    recorded,
+                },
+            );
+        }
+        settled.dropped += previous.len();
+        settled
+    }
+
+    /// Writes the candidates, replacing the file atomically.
+    pub fn save(&self) -> Result<()> {
+        state::create_dir(state::Kind::State)?;
+        self.write()
+    }
+
+    fn write(&self) -> Result<()> {
+        let dir = self.path.parent().unwrap_or(Utf8Path::new("."));
+        let temporary = dir.join(format!(".candidates.json.{}", std::process::id()));
+        let mut file = std::fs::File::create(&temporary)
+            .wrap_err_with(|| format!("couldn't create {temporary}"))?;
+        file.write_all(serde_json::to_string_pretty(&self.candidates)?.as_bytes())?;
+        file.sync_data()?;
+        std::fs::rename(&temporary, &self.path)
+            .wrap_err_with(|| format!("couldn't replace {}", self.path))
+    }
+}
+
+impl Display for Settled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut parts = Vec::new();
+        if !self.ready.is_empty() {
+            parts.push(format!(
+                "{} selected at least {} ago and unchanged since",
+                count(self.ready.len(), "candidate", "candidates"),
+                duration::format_exact(self.grace)
+            ));
+        }
+        if self.recorded > 0 {
+            parts.push(format!(
+                "recorded {}",
+                count(self.recorded, "new candidate", "new candidates")
+            ));
+        }
+        if self.waiting > 0 {
+            parts.push(format!(
+                "{} still within the {} grace period",
+                count(self.waiting, "candidate", "candidates"),
+                duration::format_exact(self.grace)
+            ));
+        }
+        if self.dropped > 0 {
+            parts.push(format!(
+                "dropped {}",
+                count(
+                    self.dropped,
+                    "candidate that changed or isn't selected anymore",
+                    "candidates that changed or aren't selected anymore"
+                )
+            ));
+        }
+        if parts.is_empty() {
+            return write!(f, "no candidates for removal");
+        }
+        write!(f, "{}", parts.join(", "))?;
+        if self.recorded + self.waiting > 0 {
+            write!(
+                f,
+                ", they're removed by a run at least {} after they were recorded",
+                duration::format_exact(self.grace)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        gcroot::NodeType,
+        plan::{EntryKind, SelectionReason},
+    };
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+    const GRACE: Duration = Duration::from_secs(3 * 24 * 60 * 60);
+
+    fn temporary_dir() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        (dir, path)
+    }
+
+    fn entry(path: &str, target: &str, mtime: u64) -> PlanEntry {
+        PlanEntry {
+            path: Utf8PathBuf::from(path),
+            target: Utf8PathBuf::from(target),
+            kind: EntryKind::Standalone,
+            reasons: vec![SelectionReason::Requested],
+            node: NodeType::Symlink,
+            contents: Vec::new(),
+            mtime: Some(mtime),
+            size: None,
+            substitutable: None,
+        }
+    }
+
+    fn selected() -> Vec<PlanEntry> {
+        vec![
+            entry("/home/a/result", "/nix/store/aaaa-a", 100),
+            entry("/home/b/result", "/nix/store/bbbb-b", 200),
+        ]
+    }
+
+    /// The candidates after a first run at start that selected entries.
+    fn recorded(dir: &Utf8Path, start: SystemTime) -> Candidates {
+        let mut candidates = Candidates::open_at(dir.join("candidates.json")).unwrap();
+        let settled = candidates.settle(selected(), GRACE, start);
+        assert_eq!(settled.recorded, 2);
+        assert!(settled.ready.is_empty());
+        candidates
+    }
+
+    fn paths(entries: &[PlanEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.path.as_str()).collect()
+    }
+
+    #[test]
+    fn candidates_survive_a_round_trip() {
+        let (_dir, dir) = temporary_dir();
+        let start = SystemTime::UNIX_EPOCH + 1000 * DAY;
+        let candidates = recorded(&dir, start);
+        candidates.write().unwrap();
+        let read = Candidates::open_at(dir.join("candidates.json")).unwrap();
+        assert_eq!(read.candidates, candidates.candidates);
+        let candidate = &read.candidates[Utf8Path::new("/home/a/result")];
+        assert_eq!(candidate.target, "/nix/store/aaaa-a");
+        assert_eq!(candidate.mtime, Some(100));
+        assert_eq!(candidate.recorded, DateTime::<Utc>::from(start));
+    }
+
+    #[test]
+    fn missing_file_has_no_candidates_and_corrupt_ones_are_errors() {
+        let (_dir, dir) = temporary_dir();
+        let path = dir.join("candidates.json");
+        assert!(Candidates::open_at(path.clone())
+            .unwrap()
+            .candidates
+            .is_empty());
+        std::fs::write(&path, "{").unwrap();
+        let error = Candidates::open_at(path.clone()).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!("couldn't parse candidates in {path}")
+        );
+    }
+
+    #[test]
+    fn unchanged_candidates_wait_for_the_grace_period() {
+        let (_dir, dir) = temporary_dir();
+        let start = SystemTime::UNIX_EPOCH + 1000 * DAY;
+        let mut candidates = recorded(&dir, start);
+        let settled = candidates.settle(selected(), GRACE, start + DAY);
+        assert!(settled.ready.is_empty());
+        assert_eq!(
+            (settled.recorded, settled.waiting, settled.dropped),
+            (0, 2, 0)
+        );
+        let settled = candidates.settle(selected(), GRACE, start + 4 * DAY);
+        assert_eq!(paths(&settled.ready), ["/home/a/result", "/home/b/result"]);
+    }
+
+    #[test]
+    fn grace_period_ends_at_exactly_its_length() {
+        let (_dir, dir) = temporary_dir();
+        let start = SystemTime::UNIX_EPOCH + 1000 * DAY;
+        let before =
+            recorded(&dir, start).settle(selected(), GRACE, start + GRACE - Duration::from_secs(1));
+        assert_eq!((before.ready.len(), before.waiting), (0, 2));
+        let at = recorded(&dir, start).settle(selected(), GRACE, start + GRACE);
+        assert_eq!((at.ready.len(), at.waiting), (2, 0));
+    }
+
+    #[test]
+    fn roots_that_disappear_are_dropped() {
+        let (_dir, dir) = temporary_dir();
+        let start = SystemTime::UNIX_EPOCH + 1000 * DAY;
+        let mut candidates = recorded(&dir, start);
+        let remaining = selected().into_iter().skip(1).collect();
+        let settled = candidates.settle(remaining, GRACE, start + 4 * DAY);
+        assert_eq!(paths(&settled.ready), ["/home/b/result"]);
+        assert_eq!(settled.dropped, 1);
+        assert!(!candidates
+            .candidates
+            .contains_key(Utf8Path::new("/home/a/result")));
+    }
+
+    #[test]
+    fn retargeted_or_touched_roots_start_over() {
+        let (_dir, dir) = temporary_dir();
+        let start = SystemTime::UNIX_EPOCH + 1000 * DAY;
+        let mut candidates = recorded(&dir, start);
+        let changed = vec![
+            entry("/home/a/result", "/nix/store/cccc-a", 100),
+            entry("/home/b/result", "/nix/store/bbbb-b", 250),
+        ];
+        let later = start + 4 * DAY;
+        let settled = candidates.settle(changed, GRACE, later);
+        assert!(settled.ready.is_empty());
+        assert_eq!((settled.recorded, settled.dropped), (2, 2));
+        assert!(candidates
+            .candidates
+            .values()
+            .all(|candidate| candidate.recorded == DateTime::<Utc>::from(later)));
+    }
+
+    #[test]
+    fn settled_wording() {
+        let (_dir, dir) = temporary_dir();
+        let start = SystemTime::UNIX_EPOCH + 1000 * DAY;
+        let mut candidates = recorded(&dir, start);
+        let settled = candidates.settle(selected(), GRACE, start + DAY);
+        assert_eq!(
+            settled.to_string(),
+            "2 candidates still within the 3d grace period, \
+            they're removed by a run at least 3d after they were recorded"
+        );
+        let settled = candidates.settle(Vec::new(), GRACE, start + DAY);
+        assert_eq!(
+            settled.to_string(),
+            "dropped 2 candidates that changed or aren't selected anymore"
+        );
+        let settled = candidates.settle(Vec::new(), GRACE, start + DAY);
+        assert_eq!(settled.to_string(), "no candidates for removal");
+    }
+}
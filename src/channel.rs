@@ -0,0 +1,171 @@
+use std::{fmt::Display, time::SystemTime};
+
+use camino::Utf8Path;
+
+use crate::{
+    duration,
+    gcroot::{GCRoots, Profile},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Whose channels a channel profile holds.
+pub enum ChannelOwner {
+    User(String),
+    /// E.g. `~/.local/state/nix/profiles/channels`, where the path doesn't say.
+    Unknown,
+}
+
+/// Some(owner) if the profile at path holds nix-channel generations.
+/// Channel profiles are named `channels`, with the user taken from a
+/// `per-user/<user>` parent, or `channels-<user>`.
+pub fn owner(profile: &Utf8Path) -> Option<ChannelOwner> {
+    let name = profile.file_name()?;
+    if let Some(user) = name.strip_prefix("channels-") {
+        return (!user.is_empty()).then(|| ChannelOwner::User(user.to_string()));
+    }
+    if name != "channels" {
+        return None;
+    }
+    let parent = profile.parent();
+    let per_user = parent
+        .and_then(|parent| parent.parent())
+        .and_then(|grandparent| grandparent.file_name());
+    match (per_user, parent.and_then(|parent| parent.file_name())) {
+        (Some("per-user"), Some(user)) => Some(ChannelOwner::User(user.to_string())),
+        _ => Some(ChannelOwner::Unknown),
+    }
+}
+
+/// If the profile holds nix-channel generations.
+pub fn is_channel(profile: &Profile) -> bool {
+    owner(&profile.path).is_some()
+}
+
+/// Channel profiles with their generations and ages.
+pub struct Channels<'a> {
+    gcroots: &'a GCRoots,
+    now: SystemTime,
+}
+
+impl<'a> Channels<'a> {
+    pub fn new(gcroots: &'a GCRoots, now: SystemTime) -> Self {
+        Channels { gcroots, now }
+    }
+
+    fn profiles(&self) -> impl Iterator<Item = &'a Profile> {
+        self.gcroots.profiles().iter().filter(|p| is_channel(p))
+    }
+}
+
+impl Display for ChannelOwner {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChannelOwner::User(user) => write!(f, "{user}"),
+            ChannelOwner::Unknown => write!(f, "unknown user"),
+        }
+    }
+}
+
+impl Display for Channels<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut written = false;
+        for profile in self.profiles() {
+            if written {
+                write!(f, "\n\n")?;
+            }
+            written = true;
+            let owner = owner(&profile.path).unwrap_or(ChannelOwner::Unknown);
+            write!(f, "{} ({})", profile.path, owner)?;
+            let digits = profile.latest().map_or(1, |(id, _)| id.to_string().len());
+            for (id, generation) in profile.generations_desc() {
+                let marker = match profile.active_generation == Some(id) {
+                    true => '>',
+                    false => ' ',
+                };
+                write!(f, "\n{marker} {id: >digits$} -> {}", generation.target)?;
+                let age = generation
+                    .modified()
+                    .ok()
+                    .and_then(|mtime| self.now.duration_since(mtime).ok());
+                if let Some(age) = age {
+                    write!(f, ", {} old", duration::format(age))?;
+                }
+            }
+        }
+        if !written {
+            write!(f, "no channel profiles")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+
+    use super::*;
+
+    fn owner_of(path: &str) -> Option<ChannelOwner> {
+        owner(Utf8Path::new(path))
+    }
+
+    #[test]
+    fn owner_from_the_profile_path() {
+        let alice = Some(ChannelOwner::User("alice".to_string()));
+        assert_eq!(
+            owner_of("/nix/var/nix/profiles/per-user/alice/channels"),
+            alice
+        );
+        assert_eq!(owner_of("/nix/var/nix/profiles/channels-alice"), alice);
+        assert_eq!(
+            owner_of("/home/alice/.local/state/nix/profiles/channels"),
+            Some(ChannelOwner::Unknown)
+        );
+        assert_eq!(
+            owner_of("/nix/var/nix/profiles/channels"),
+            Some(ChannelOwner::Unknown)
+        );
+    }
+
+    #[test]
+    fn other_profiles_arent_channels() {
+        assert_eq!(owner_of("/nix/var/nix/profiles/system"), None);
+        assert_eq!(owner_of("/nix/var/nix/profiles/channels-"), None);
+        assert_eq!(owner_of("/nix/var/nix/profiles/my-channels"), None);
+        assert_eq!(
+            owner_of("/nix/var/nix/profiles/per-user/alice/profile"),
+            None
+        );
+    }
+
+    #[test]
+    fn channel_profiles_are_listed_with_their_generations() {
+        let dir = tempfile::tempdir().unwrap();
+        let dir = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let mut output = String::new();
+        for (name, generations) in [("channels-alice", 2), ("system", 1)] {
+            for generation in 1..=generations {
+                let link = dir.join(format!("{name}-{generation}-link"));
+                std::os::unix::fs::symlink(&dir, &link).unwrap();
+                output.push_str(&format!("{link} -> /nix/store/aaaa-{name}-{generation}\n"));
+            }
+            std::os::unix::fs::symlink(format!("{name}-1-link"), dir.join(name)).unwrap();
+        }
+        let gcroots = GCRoots::from_print_roots_output(&output).unwrap();
+        // Links from after now have no age, which keeps the output stable.
+        let channels = Channels::new(&gcroots, SystemTime::UNIX_EPOCH).to_string();
+        assert_eq!(
+            channels,
+            format!(
+                "{dir}/channels-alice (alice)\n  \
+                 2 -> /nix/store/aaaa-channels-alice-2\n\
+                 > 1 -> /nix/store/aaaa-channels-alice-1"
+            )
+        );
+        let none = GCRoots::from_print_roots_output("").unwrap();
+        assert_eq!(
+            Channels::new(&none, SystemTime::now()).to_string(),
+            "no channel profiles"
+        );
+    }
+}
@@ -0,0 +1,267 @@
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
+
+use serde::Serialize;
+
+use crate::{
+    duration,
+    gcroot::{GCRoot, GCRoots},
+    summary::SummaryOptions,
+};
+
+#[derive(Debug, Default, Clone)]
+/// Limits the discovered GCRoots are checked against.
+/// A None threshold is not checked.
+pub struct Thresholds {
+    /// Maximum number of standalone roots.
+    pub max_standalone: Option<usize>,
+    /// Maximum age of a standalone root.
+    pub max_age: Option<Duration>,
+    /// Maximum number of generations of a single profile.
+    pub max_generations: Option<usize>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "rule", rename_all = "kebab-case")]
+/// A threshold that was exceeded.
+pub enum Violation {
+    MaxStandalone {
+        count: usize,
+        max: usize,
+    },
+    MaxAge {
+        path: String,
+        age_secs: u64,
+        max_secs: u64,
+    },
+    MaxGenerations {
+        profile: String,
+        count: usize,
+        max: usize,
+    },
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::MaxStandalone { count, max } => {
+                write!(f, "{count} standalone roots (max {max})")
+            }
+            Violation::MaxAge {
+                path,
+                age_secs,
+                max_secs,
+            } => write!(
+                f,
+                "{path} is {} old (max {})",
+                duration::format(Duration::from_secs(*age_secs)),
+                duration::format_exact(Duration::from_secs(*max_secs)),
+            ),
+            Violation::MaxGenerations {
+                profile,
+                count,
+                max,
+            } => write!(f, "{profile} has {count} generations (max {max})"),
+        }
+    }
+}
+
+impl Thresholds {
+    /// If no threshold is set.
+    pub fn is_empty(&self) -> bool {
+        self.max_standalone.is_none() && self.max_age.is_none() && self.max_generations.is_none()
+    }
+
+    /// Evaluates every rule, returning all violations found.
+    pub fn check(&self, gcroots: &GCRoots, now: SystemTime) -> Vec<Violation> {
+        let mut violations = Vec::new();
+        if let Some(max) = self.max_standalone {
+            violations.extend(Self::check_max_standalone(gcroots, max));
+        }
+        if let Some(max) = self.max_age {
+            violations.extend(Self::check_max_age(gcroots, max, now));
+        }
+        if let Some(max) = self.max_generations {
+            violations.extend(Self::check_max_generations(gcroots, max));
+        }
+        violations
+    }
+
+    fn check_max_standalone(gcroots: &GCRoots, max: usize) -> Option<Violation> {
+        let count = gcroots.summary(SummaryOptions::default()).standalone;
+        (count > max).then_some(Violation::MaxStandalone { count, max })
+    }
+
+    fn check_max_age(gcroots: &GCRoots, max: Duration, now: SystemTime) -> Vec<Violation> {
+        gcroots
+            .standalone()
+            .iter()
+            .filter_map(|gcroot| Self::max_age_violation(gcroot, max, now))
+            .collect()
+    }
+
+    /// The violation of a root older than max, roots whose modification time
+    /// can't be read are not checked.
+    pub fn max_age_violation(gcroot: &GCRoot, max: Duration, now: SystemTime) -> Option<Violation> {
+        let age = now.duration_since(gcroot.modified().ok()?).ok()?;
+        (age > max).then(|| Violation::MaxAge {
+            path: gcroot.path.to_string(),
+            age_secs: age.as_secs(),
+            max_secs: max.as_secs(),
+        })
+    }
+
+    fn check_max_generations(gcroots: &GCRoots, max: usize) -> Vec<Violation> {
+        gcroots
+            .profiles()
+            .iter()
+            .filter(|profile| profile.len() > max)
+            .map(|profile| Violation::MaxGenerations {
+                profile: profile.path.to_string(),
+                count: profile.len(),
+                max,
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+    use nix::sys::{
+        stat::{utimensat, UtimensatFlags},
+        time::TimeSpec,
+    };
+
+    use super::*;
+
+    const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+    /// Standalone roots in a temporary directory modified the given number
+    /// of days before now, and a profile listing without its profile link,
+    /// so its three generations are standalone too.
+    fn gcroots(ages: &[u32], now: SystemTime) -> (tempfile::TempDir, GCRoots) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let mut output = String::new();
+        for (index, days) in ages.iter().enumerate() {
+            let root = path.join(format!("result-{index}"));
+            std::os::unix::fs::symlink("/nix/store/aaaa-a", &root).unwrap();
+            let mtime = now - DAY * *days;
+            let time =
+                TimeSpec::from_duration(mtime.duration_since(SystemTime::UNIX_EPOCH).unwrap());
+            utimensat(
+                None,
+                root.as_std_path(),
+                &time,
+                &time,
+                UtimensatFlags::NoFollowSymlink,
+            )
+            .unwrap();
+            output.push_str(&format!("{root} -> /nix/store/aaaa-a\n"));
+        }
+        (dir, GCRoots::from_print_roots_output(&output).unwrap())
+    }
+
+    #[test]
+    fn no_thresholds_no_violations() {
+        let now = SystemTime::now();
+        let (_dir, gcroots) = gcroots(&[1, 400], now);
+        let thresholds = Thresholds::default();
+        assert!(thresholds.is_empty());
+        assert!(thresholds.check(&gcroots, now).is_empty());
+    }
+
+    #[test]
+    fn max_standalone() {
+        let now = SystemTime::now();
+        let (_dir, gcroots) = gcroots(&[1, 2, 3], now);
+        let check = |max| {
+            Thresholds {
+                max_standalone: Some(max),
+                ..Thresholds::default()
+            }
+            .check(&gcroots, now)
+        };
+        assert!(check(3).is_empty());
+        assert_eq!(check(2), [Violation::MaxStandalone { count: 3, max: 2 }]);
+        assert_eq!(check(2)[0].to_string(), "3 standalone roots (max 2)");
+    }
+
+    #[test]
+    fn max_age_lists_every_root_older_than_it() {
+        let now = SystemTime::now();
+        let (_dir, gcroots) = gcroots(&[1, 100, 400], now);
+        let thresholds = Thresholds {
+            max_age: Some(DAY * 90),
+            ..Thresholds::default()
+        };
+        let violations = thresholds.check(&gcroots, now);
+        let ages = violations
+            .iter()
+            .map(|violation| match violation {
+                Violation::MaxAge {
+                    age_secs, max_secs, ..
+                } => (*age_secs / DAY.as_secs(), *max_secs),
+                other => panic!("unexpected violation {other:?}"),
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(ages, [(100, 90 * DAY.as_secs()), (400, 90 * DAY.as_secs())]);
+        assert!(violations[0]
+            .to_string()
+            .ends_with("result-1 is 100d old (max 90d)"));
+    }
+
+    #[test]
+    fn roots_without_metadata_are_not_too_old() {
+        let now = SystemTime::now();
+        let gcroots =
+            GCRoots::from_print_roots_output("/nonexistent/result -> /nix/store/aaaa-a\n").unwrap();
+        let gcroot = &gcroots.standalone()[0];
+        assert_eq!(
+            Thresholds::max_age_violation(gcroot, Duration::ZERO, now),
+            None
+        );
+    }
+
+    #[test]
+    fn max_generations() {
+        let violation = Violation::MaxGenerations {
+            profile: "/nix/var/nix/profiles/system".to_string(),
+            count: 30,
+            max: 20,
+        };
+        assert_eq!(
+            violation.to_string(),
+            "/nix/var/nix/profiles/system has 30 generations (max 20)"
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let mut output = String::new();
+        for generation in 1..=3 {
+            let link = path.join(format!("system-{generation}-link"));
+            std::os::unix::fs::symlink(&path, &link).unwrap();
+            output.push_str(&format!("{link} -> /nix/store/aaaa-system-{generation}\n"));
+        }
+        std::os::unix::fs::symlink("system-3-link", path.join("system")).unwrap();
+        let gcroots = GCRoots::from_print_roots_output(&output).unwrap();
+        let check = |max| {
+            Thresholds {
+                max_generations: Some(max),
+                ..Thresholds::default()
+            }
+            .check(&gcroots, SystemTime::now())
+        };
+        assert!(check(3).is_empty());
+        assert_eq!(
+            check(2),
+            [Violation::MaxGenerations {
+                profile: path.join("system").to_string(),
+                count: 3,
+                max: 2,
+            }]
+        );
+    }
+}
@@ -0,0 +1,119 @@
+use std::{
+    io::{IsTerminal, Write},
+    process::{Command, Stdio},
+};
+
+use eyre::{eyre, Result, WrapErr};
+
+/// Longest text sent through OSC 52, many terminals drop longer sequences.
+const OSC52_MAX_LEN: usize = 74_994;
+
+/// Commands tried when OSC 52 can't be used, in order.
+const COMMANDS: &[(&str, &[&str])] = &[
+    ("wl-copy", &[]),
+    ("xclip", &["-selection", "clipboard"]),
+    ("pbcopy", &[]),
+];
+
+/// Puts text on the system clipboard, through the terminal with OSC 52 when
+/// stdout is one, which also works over SSH, or else with the first of
+/// wl-copy, xclip and pbcopy that is installed.
+pub fn copy(text: &str) -> Result<()> {
+    let stdout = std::io::stdout();
+    if stdout.is_terminal() {
+        if let Some(sequence) = osc52(text) {
+            let mut stdout = stdout.lock();
+            stdout.write_all(sequence.as_bytes())?;
+            return Ok(stdout.flush()?);
+        }
+    }
+    for (program, args) in COMMANDS {
+        let child = Command::new(program)
+            .args(*args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn();
+        let Ok(mut child) = child else {
+            continue;
+        };
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .wrap_err_with(|| format!("couldn't write to {program}"))?;
+        }
+        let status = child.wait()?;
+        return match status.success() {
+            true => Ok(()),
+            false => Err(eyre!("{program} exited with code {status}")),
+        };
+    }
+    Err(eyre!(
+        "no way to reach the clipboard, stdout isn't a terminal and none of \
+        wl-copy, xclip or pbcopy is installed"
+    ))
+}
+
+/// The OSC 52 sequence setting the clipboard to text,
+/// None if the text is too long for terminals to accept.
+pub fn osc52(text: &str) -> Option<String> {
+    let encoded = base64(text.as_bytes());
+    (encoded.len() <= OSC52_MAX_LEN).then(|| format!("\x1b]52;c;{encoded}\x07"))
+}
+
+/// Standard base64 with padding.
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let n = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |n, (i, byte)| n | (*byte as u32) << (16 - 8 * i));
+        for i in 0..4 {
+            match i <= chunk.len() {
+                true => encoded.push(ALPHABET[(n >> (18 - 6 * i) & 0x3f) as usize] as char),
+                false => encoded.push('='),
+            }
+        }
+    }
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base64_pads_to_whole_quads() {
+        // The vectors of RFC 4648.
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foob"), "Zm9vYg==");
+        assert_eq!(base64(b"fooba"), "Zm9vYmE=");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+    }
+
+    #[test]
+    fn base64_uses_the_whole_alphabet() {
+        assert_eq!(base64(&[0xfb, 0xff, 0xbf]), "+/+/");
+        assert_eq!(base64(&[0x00, 0x00, 0x00]), "AAAA");
+    }
+
+    #[test]
+    fn osc52_frames_the_encoded_text() {
+        assert_eq!(osc52("foo").unwrap(), "\x1b]52;c;Zm9v\x07");
+        assert_eq!(osc52("").unwrap(), "\x1b]52;c;\x07");
+    }
+
+    #[test]
+    fn osc52_refuses_text_longer_than_terminals_accept() {
+        // 56244 bytes encode to 74992 characters, 56245 to 74996.
+        let longest = "a".repeat(OSC52_MAX_LEN / 4 * 3);
+        assert_eq!(base64(longest.as_bytes()).len(), 74_992);
+        assert!(osc52(&longest).is_some());
+        assert!(osc52(&format!("{longest}a")).is_none());
+    }
+}
@@ -0,0 +1,156 @@
+use std::time::Duration;
+
+use camino::Utf8PathBuf;
+use eyre::{Result, WrapErr};
+use serde::Deserialize;
+
+use crate::{date::DateFormat, duration, report::Section, retention::RetentionPolicy, xdg};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+/// Contents of the gcrs configuration file.
+pub struct Config {
+    pub check: CheckConfig,
+    pub journal: JournalConfig,
+    pub display: DisplayConfig,
+    pub retention: RetentionConfig,
+    pub discovery: DiscoveryConfig,
+    pub report: ReportConfig,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+/// Default thresholds for the check subcommand.
+pub struct CheckConfig {
+    pub max_standalone: Option<usize>,
+    #[serde(deserialize_with = "duration::deserialize_option")]
+    pub max_age: Option<Duration>,
+    pub max_generations: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+/// Defaults for how human readable output looks.
+pub struct DisplayConfig {
+    /// relative, iso, unix or a strftime pattern.
+    pub date_format: Option<DateFormat>,
+    /// Show dates in UTC instead of the local timezone.
+    pub utc: bool,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+/// Generations clean keeps even when they're selected.
+pub struct RetentionConfig {
+    /// Keep the newest generation of each of this many calendar months.
+    pub keep_monthly: Option<usize>,
+    /// Keep this many of the newest generations.
+    pub keep_last: Option<usize>,
+}
+
+impl RetentionConfig {
+    pub fn policy(&self) -> Option<RetentionPolicy> {
+        match (self.keep_monthly, self.keep_last) {
+            (Some(months), keep_last) => Some(RetentionPolicy::KeepMonthly {
+                months,
+                keep_last: keep_last.unwrap_or(0),
+            }),
+            (None, Some(n)) => Some(RetentionPolicy::KeepLast(n)),
+            (None, None) => None,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+/// Defaults for the report subcommand.
+pub struct ReportConfig {
+    /// Sections of the report in order, like `["summary", "dead"]`.
+    pub sections: Option<Vec<Section>>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+/// Settings of the operation journal.
+pub struct JournalConfig {
+    /// Record destructive actions in the journal.
+    pub enabled: bool,
+}
+
+impl Default for JournalConfig {
+    fn default() -> Self {
+        JournalConfig { enabled: true }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+/// How roots are discovered.
+pub struct DiscoveryConfig {
+    /// Group generations into profiles, false for the flat view of --no-group.
+    pub group: bool,
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        DiscoveryConfig { group: true }
+    }
+}
+
+impl Config {
+    /// Loads the config file, or the default config if there is none.
+    pub fn load() -> Result<Self> {
+        let Some(path) = Self::path() else {
+            return Ok(Self::default());
+        };
+        match std::fs::read_to_string(&path) {
+            Ok(contents) => {
+                toml::from_str(&contents).wrap_err_with(|| format!("invalid config file {path}"))
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Self::default()),
+            Err(e) => Err(e).wrap_err_with(|| format!("couldn't read config file {path}")),
+        }
+    }
+
+    /// Location of the config file: `$XDG_CONFIG_HOME/gcrs/config.toml`,
+    /// falling back to `~/.config/gcrs/config.toml`.
+    pub fn path() -> Option<Utf8PathBuf> {
+        Some(xdg::config_home()?.join("gcrs").join("config.toml"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn empty_config_has_the_defaults() {
+        let config: Config = toml::from_str("").unwrap();
+        assert_eq!(config.check.max_standalone, None);
+        assert_eq!(config.check.max_age, None);
+        assert!(config.journal.enabled);
+        assert!(config.discovery.group);
+        assert!(config.retention.policy().is_none());
+    }
+
+    #[test]
+    fn check_thresholds() {
+        let config: Config = toml::from_str(
+            "[check]\nmax-standalone = 50\nmax-age = \"90d\"\nmax-generations = 20\n",
+        )
+        .unwrap();
+        assert_eq!(config.check.max_standalone, Some(50));
+        assert_eq!(
+            config.check.max_age,
+            Some(Duration::from_secs(90 * 24 * 60 * 60))
+        );
+        assert_eq!(config.check.max_generations, Some(20));
+    }
+
+    #[test]
+    fn invalid_values_and_unknown_keys_are_errors() {
+        assert!(toml::from_str::<Config>("[check]\nmax-age = \"90 days\"\n").is_err());
+        assert!(toml::from_str::<Config>("[check]\nmax_age = \"90d\"\n").is_err());
+        assert!(toml::from_str::<Config>("[checks]\n").is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use std::{str::FromStr, time::SystemTime};
+
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, Local, Utc,
+};
+use serde::{Deserialize, Deserializer};
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+const MONTH: u64 = 30 * DAY;
+const YEAR: u64 = 365 * DAY;
+
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+/// How dates are rendered in human readable output.
+/// JSON output always uses RFC 3339 in UTC.
+pub enum DateFormat {
+    /// Time relative to now, e.g. `3 weeks ago`.
+    Relative,
+    /// ISO 8601 with seconds, e.g. `2025-01-01T12:00:00+01:00`.
+    #[default]
+    Iso,
+    /// Seconds since the epoch.
+    Unix,
+    /// A strftime pattern, e.g. `%Y-%m-%d`.
+    Pattern(String),
+}
+
+#[derive(Debug, Clone, Default)]
+/// A date format together with the timezone dates are shown in.
+pub struct DateStyle {
+    pub format: DateFormat,
+    /// Show dates in UTC instead of the local timezone.
+    pub utc: bool,
+}
+
+impl FromStr for DateFormat {
+    type Err = String;
+
+    /// Parses `relative`, `iso`, `unix` or a strftime pattern containing `%`.
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input {
+            "relative" => Ok(DateFormat::Relative),
+            "iso" => Ok(DateFormat::Iso),
+            "unix" => Ok(DateFormat::Unix),
+            pattern if pattern.contains('%') => {
+                match StrftimeItems::new(pattern).any(|item| item == Item::Error) {
+                    true => Err(format!("invalid strftime pattern \"{pattern}\"")),
+                    false => Ok(DateFormat::Pattern(pattern.to_string())),
+                }
+            }
+            _ => Err(format!(
+                "unknown date format \"{input}\", expected relative, iso, unix \
+                or a strftime pattern like %Y-%m-%d"
+            )),
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for DateFormat {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(serde::de::Error::custom)
+    }
+}
+
+impl DateStyle {
+    /// Renders a point in time, relative formatting is relative to now.
+    pub fn render(&self, time: SystemTime, now: SystemTime) -> String {
+        let pattern = match &self.format {
+            DateFormat::Relative => return relative(time, now),
+            DateFormat::Unix => {
+                return match time.duration_since(SystemTime::UNIX_EPOCH) {
+                    Ok(since_epoch) => since_epoch.as_secs().to_string(),
+                    Err(e) => format!("-{}", e.duration().as_secs()),
+                }
+            }
+            DateFormat::Iso if self.utc => "%Y-%m-%dT%H:%M:%SZ",
+            DateFormat::Iso => "%Y-%m-%dT%H:%M:%S%:z",
+            DateFormat::Pattern(pattern) => pattern,
+        };
+        let time = DateTime::<Utc>::from(time);
+        match self.utc {
+            true => time.format(pattern).to_string(),
+            false => time.with_timezone(&Local).format(pattern).to_string(),
+        }
+    }
+}
+
+/// Formats the time between two points in the largest whole unit,
+/// e.g. `3 weeks ago` or `in 2 days`.
+pub fn relative(time: SystemTime, now: SystemTime) -> String {
+    let (secs, past) = match now.duration_since(time) {
+        Ok(ago) => (ago.as_secs(), true),
+        Err(e) => (e.duration().as_secs(), false),
+    };
+    let (n, unit) = match secs {
+        s if s >= YEAR => (s / YEAR, "year"),
+        s if s >= MONTH => (s / MONTH, "month"),
+        s if s >= WEEK => (s / WEEK, "week"),
+        s if s >= DAY => (s / DAY, "day"),
+        s if s >= HOUR => (s / HOUR, "hour"),
+        s if s >= MINUTE => (s / MINUTE, "minute"),
+        _ => return "just now".to_string(),
+    };
+    let plural = match n {
+        1 => "",
+        _ => "s",
+    };
+    match past {
+        true => format!("{n} {unit}{plural} ago"),
+        false => format!("in {n} {unit}{plural}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn at(secs: i64) -> SystemTime {
+        match secs >= 0 {
+            true => SystemTime::UNIX_EPOCH + Duration::from_secs(secs as u64),
+            false => SystemTime::UNIX_EPOCH - Duration::from_secs(secs.unsigned_abs()),
+        }
+    }
+
+    fn style(format: &str, utc: bool) -> DateStyle {
+        DateStyle {
+            format: format.parse().unwrap(),
+            utc,
+        }
+    }
+
+    #[test]
+    fn relative_uses_the_largest_whole_unit() {
+        let now = at(100 * YEAR as i64);
+        let ago = |secs: u64| relative(now - Duration::from_secs(secs), now);
+        assert_eq!(ago(0), "just now");
+        assert_eq!(ago(59), "just now");
+        assert_eq!(ago(MINUTE), "1 minute ago");
+        assert_eq!(ago(2 * HOUR + 59 * MINUTE), "2 hours ago");
+        assert_eq!(ago(DAY), "1 day ago");
+        assert_eq!(ago(13 * DAY), "1 week ago");
+        assert_eq!(ago(2 * MONTH), "2 months ago");
+        assert_eq!(ago(YEAR + 11 * MONTH), "1 year ago");
+    }
+
+    #[test]
+    fn relative_future_times_are_in_some_time() {
+        let now = at(0);
+        assert_eq!(
+            relative(now + Duration::from_secs(2 * DAY), now),
+            "in 2 days"
+        );
+        assert_eq!(relative(now + Duration::from_secs(HOUR), now), "in 1 hour");
+        assert_eq!(relative(now + Duration::from_secs(30), now), "just now");
+    }
+
+    #[test]
+    fn formats_are_parsed() {
+        assert_eq!("relative".parse(), Ok(DateFormat::Relative));
+        assert_eq!("iso".parse(), Ok(DateFormat::Iso));
+        assert_eq!("unix".parse(), Ok(DateFormat::Unix));
+        assert_eq!(
+            "%Y-%m-%d".parse(),
+            Ok(DateFormat::Pattern("%Y-%m-%d".to_string()))
+        );
+        assert!("yesterday"
+            .parse::<DateFormat>()
+            .unwrap_err()
+            .starts_with("unknown date format \"yesterday\""));
+    }
+
+    #[test]
+    fn invalid_strftime_specs_are_rejected_when_parsed() {
+        for pattern in ["%Q", "%Y-%", "%-"] {
+            assert_eq!(
+                pattern.parse::<DateFormat>(),
+                Err(format!("invalid strftime pattern \"{pattern}\"")),
+            );
+        }
+    }
+
+    #[test]
+    fn utc_rendering() {
+        let time = at(1_700_000_000);
+        assert_eq!(
+            style("iso", true).render(time, time),
+            "2023-11-14T22:13:20Z"
+        );
+        assert_eq!(
+            style("%Y-%m-%d %H:%M", true).render(time, time),
+            "2023-11-14 22:13"
+        );
+        assert_eq!(style("unix", true).render(time, time), "1700000000");
+        assert_eq!(style("relative", true).render(time, time), "just now");
+    }
+
+    #[test]
+    fn local_rendering_is_in_the_local_timezone() {
+        let time = at(1_700_000_000);
+        let local = DateTime::<Utc>::from(time).with_timezone(&Local);
+        assert_eq!(
+            style("iso", false).render(time, time),
+            local.format("%Y-%m-%dT%H:%M:%S%:z").to_string()
+        );
+        assert_eq!(
+            style("%H:%M", false).render(time, time),
+            local.format("%H:%M").to_string()
+        );
+        // Seconds since the epoch don't depend on the timezone.
+        assert_eq!(style("unix", false).render(time, time), "1700000000");
+    }
+
+    #[test]
+    fn pre_epoch_times() {
+        let time = at(-(DAY as i64));
+        assert_eq!(style("unix", true).render(time, at(0)), "-86400");
+        assert_eq!(
+            style("iso", true).render(time, at(0)),
+            "1969-12-31T00:00:00Z"
+        );
+        assert_eq!(style("relative", true).render(time, at(0)), "1 day ago");
+    }
+
+    #[test]
+    fn future_times() {
+        let now = at(1_700_000_000);
+        let time = now + Duration::from_secs(3 * WEEK);
+        assert_eq!(style("relative", true).render(time, now), "in 3 weeks");
+        assert_eq!(style("iso", true).render(time, now), "2023-12-05T22:13:20Z");
+    }
+}
@@ -0,0 +1,183 @@
+use std::{collections::BTreeSet, fmt::Display};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use eyre::Result;
+
+use crate::{
+    gcroot::{GCRoots, StoreDirs},
+    nix_command,
+    nix_config::GcSettings,
+    plan::count,
+    plan::Plan,
+    render, size,
+    timing::{self, Phase},
+};
+
+#[derive(Debug, Clone)]
+/// A store path that is only kept alive by roots in the plan.
+pub struct DeadPath {
+    pub path: Utf8PathBuf,
+    pub nar_size: Option<u64>,
+}
+
+#[derive(Debug, Clone)]
+/// Store paths that become garbage once a plan is executed.
+pub struct DeadPaths {
+    /// Largest first.
+    pub paths: Vec<DeadPath>,
+    /// How many paths are shown when displayed.
+    pub limit: usize,
+}
+
+impl DeadPaths {
+    /// Computes the closures of the planned roots minus the closures of every
+    /// other root nix knows about, including the ones gcrs doesn't list.
+    /// The closures of remaining roots are extended the way settings make
+    /// the garbage collector keep more.
+    pub fn of(plan: &Plan, live: &GCRoots, limit: usize, settings: &GcSettings) -> Result<Self> {
+        let planned = plan
+            .entries
+            .iter()
+            .map(|entry| entry.path.as_path())
+            .collect::<BTreeSet<_>>();
+        let selected = plan
+            .entries
+            .iter()
+            .map(|entry| entry.target.as_path())
+            .collect::<Vec<_>>();
+        let remaining = Self::remaining_targets(live, &planned);
+        let store_dirs = live.store_dirs();
+        let mut alive = requisites(&remaining, settings.keep_outputs, store_dirs)?;
+        if settings.keep_derivations {
+            let derivers = derivers(&alive)?;
+            let derivers = derivers
+                .iter()
+                .map(|path| path.as_path())
+                .collect::<Vec<_>>();
+            alive.extend(requisites(&derivers, settings.keep_outputs, store_dirs)?);
+        }
+        let dead = requisites(&selected, false, store_dirs)?
+            .into_iter()
+            .filter(|path| !alive.contains(path))
+            .collect::<Vec<_>>();
+        let dead_paths = dead.iter().map(|p| p.as_path()).collect::<Vec<_>>();
+        let sizes = size::nar_sizes(&dead_paths, store_dirs)?;
+        let mut paths = dead
+            .into_iter()
+            .map(|path| DeadPath {
+                nar_size: sizes.get(&path).copied(),
+                path,
+            })
+            .collect::<Vec<_>>();
+        paths.sort_by(|p1, p2| {
+            (
+                p1.nar_size.is_none(),
+                std::cmp::Reverse(p1.nar_size),
+                &p1.path,
+            )
+                .cmp(&(
+                    p2.nar_size.is_none(),
+                    std::cmp::Reverse(p2.nar_size),
+                    &p2.path,
+                ))
+        });
+        Ok(DeadPaths { paths, limit })
+    }
+
+    /// Targets of the roots of live that aren't planned, and of the hidden ones.
+    pub(crate) fn remaining_targets<'a>(
+        live: &'a GCRoots,
+        planned: &BTreeSet<&Utf8Path>,
+    ) -> Vec<&'a Utf8Path> {
+        live.profiles()
+            .iter()
+            .flat_map(|profile| profile.generations.values())
+            .chain(live.standalone().iter())
+            .filter(|gcroot| !planned.contains(&*gcroot.path))
+            .map(|gcroot| &*gcroot.target)
+            .chain(live.hidden_targets())
+            .collect()
+    }
+
+    pub fn total(&self) -> u64 {
+        self.paths.iter().filter_map(|path| path.nar_size).sum()
+    }
+}
+
+/// Closure of the paths, queried with a single nix-store -qR, with the outputs
+/// of derivations in it if include_outputs is set.
+/// Paths that don't exist or aren't store paths are left out.
+fn requisites(
+    paths: &[&Utf8Path],
+    include_outputs: bool,
+    store_dirs: &StoreDirs,
+) -> Result<BTreeSet<Utf8PathBuf>> {
+    let mut existing = paths
+        .iter()
+        .filter(|path| store_dirs.contains(path) && path.exists())
+        .map(|path| path.as_str())
+        .collect::<Vec<_>>();
+    existing.sort_unstable();
+    existing.dedup();
+    if existing.is_empty() {
+        return Ok(BTreeSet::new());
+    }
+    let output = timing::time(Phase::Enrichment("closures"), || {
+        nix_command::output(
+            nix_command::command("nix-store")
+                .args(["--query", "--requisites"])
+                .args(include_outputs.then_some("--include-outputs"))
+                .args(existing),
+        )
+    })?;
+    let output = nix_command::success(output, "nix-store --query --requisites")?;
+    let stdout = String::from_utf8(output.stdout)?;
+    Ok(stdout.lines().map(Utf8PathBuf::from).collect())
+}
+
+/// Derivations the paths were built from that are still in the store,
+/// queried with nix-store -qd in chunks to stay below the argument limit.
+fn derivers(paths: &BTreeSet<Utf8PathBuf>) -> Result<BTreeSet<Utf8PathBuf>> {
+    let paths = paths.iter().map(|path| path.as_str()).collect::<Vec<_>>();
+    let mut derivers = BTreeSet::new();
+    for chunk in paths.chunks(DERIVERS_CHUNK) {
+        let output = timing::time(Phase::Enrichment("derivers"), || {
+            nix_command::output(
+                nix_command::command("nix-store")
+                    .args(["--query", "--deriver"])
+                    .args(chunk),
+            )
+        })?;
+        let output = nix_command::success(output, "nix-store --query --deriver")?;
+        derivers.extend(
+            String::from_utf8(output.stdout)?
+                .lines()
+                .filter(|line| *line != "unknown-deriver")
+                .map(Utf8PathBuf::from)
+                .filter(|path| path.exists()),
+        );
+    }
+    Ok(derivers)
+}
+
+/// Number of paths passed to a single nix-store -qd.
+const DERIVERS_CHUNK: usize = 1000;
+
+impl Display for DeadPaths {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} would become garbage, {} in total:",
+            count(self.paths.len(), "store path", "store paths"),
+            size::format(self.total())
+        )?;
+        for path in self.paths.iter().take(self.limit) {
+            let size = path.nar_size.map(size::format).unwrap_or_default();
+            write!(f, "\n  {:>10}  {}", size, render::short_target(&path.path))?;
+        }
+        if self.paths.len() > self.limit {
+            write!(f, "\n  and {} more", self.paths.len() - self.limit)?;
+        }
+        Ok(())
+    }
+}
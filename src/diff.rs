@@ -0,0 +1,331 @@
+use std::{collections::BTreeMap, fmt::Display};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use serde::{Deserialize, Serialize};
+
+use crate::gcroot::{GCRoot, GCRoots};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "change", rename_all = "kebab-case")]
+/// A change to a profile as a whole, as opposed to its generations.
+pub enum ProfileChange {
+    Added {
+        profile: Utf8PathBuf,
+        active_generation: Option<u64>,
+    },
+    Removed {
+        profile: Utf8PathBuf,
+    },
+    /// The profile now points at another generation, or it became unknown
+    /// which one it points at.
+    Switched {
+        profile: Utf8PathBuf,
+        from: Option<u64>,
+        to: Option<u64>,
+    },
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// The roots of a discovery and the active generation of every profile,
+/// what a [Diff] compares. Small enough to be kept between runs.
+pub struct Listing {
+    /// Roots of profiles and standalone ones, sorted by path.
+    pub roots: Vec<GCRoot>,
+    pub profiles: BTreeMap<Utf8PathBuf, Option<u64>>,
+}
+
+impl Listing {
+    pub fn of(gcroots: &GCRoots) -> Self {
+        let mut roots: Vec<GCRoot> = gcroots
+            .profiles()
+            .iter()
+            .flat_map(|profile| profile.generations.values())
+            .chain(gcroots.standalone())
+            .cloned()
+            .collect();
+        roots.sort_by(|r1, r2| r1.path.cmp(&r2.path));
+        Listing {
+            roots,
+            profiles: gcroots
+                .profiles()
+                .iter()
+                .map(|profile| (profile.path.clone(), profile.active_generation))
+                .collect(),
+        }
+    }
+
+    fn by_path(&self) -> BTreeMap<&Utf8Path, &GCRoot> {
+        self.roots
+            .iter()
+            .map(|gcroot| (&*gcroot.path, gcroot))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+/// What changed between two discoveries of GCRoots, with roots matched by path.
+/// Targets are compared as paths, a listing read back from a file doesn't know
+/// which store directories its targets were classified against.
+/// A root that moved between standalone and a profile, because its profile
+/// appeared or disappeared, is neither added nor removed.
+pub struct Diff {
+    pub added: Vec<GCRoot>,
+    pub removed: Vec<GCRoot>,
+    /// Roots pointing somewhere else now, as (old, new) pairs.
+    pub retargeted: Vec<(GCRoot, GCRoot)>,
+    pub profile_changes: Vec<ProfileChange>,
+}
+
+impl Diff {
+    /// Compares old against new, every list is sorted by path.
+    pub fn between(old: &GCRoots, new: &GCRoots) -> Self {
+        Self::of(&Listing::of(old), &Listing::of(new))
+    }
+
+    /// Compares old against new listings, like [Diff::between].
+    pub fn of(old: &Listing, new: &Listing) -> Self {
+        let old_roots = old.by_path();
+        let new_roots = new.by_path();
+        let mut diff = Diff::default();
+        for (path, old_root) in old_roots.iter() {
+            match new_roots.get(path) {
+                None => diff.removed.push((*old_root).clone()),
+                Some(new_root) if *new_root.target != *old_root.target => diff
+                    .retargeted
+                    .push(((*old_root).clone(), (*new_root).clone())),
+                Some(_) => {}
+            }
+        }
+        diff.added = new_roots
+            .iter()
+            .filter(|(path, _)| !old_roots.contains_key(*path))
+            .map(|(_, root)| (*root).clone())
+            .collect();
+        diff.profile_changes = Self::profile_changes(&old.profiles, &new.profiles);
+        diff
+    }
+
+    /// If nothing changed.
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty()
+            && self.removed.is_empty()
+            && self.retargeted.is_empty()
+            && self.profile_changes.is_empty()
+    }
+
+    fn profile_changes(
+        old: &BTreeMap<Utf8PathBuf, Option<u64>>,
+        new: &BTreeMap<Utf8PathBuf, Option<u64>>,
+    ) -> Vec<ProfileChange> {
+        let mut changes = Vec::new();
+        for (profile, from) in old.iter() {
+            match new.get(profile) {
+                None => changes.push(ProfileChange::Removed {
+                    profile: profile.clone(),
+                }),
+                Some(to) if to != from => changes.push(ProfileChange::Switched {
+                    profile: profile.clone(),
+                    from: *from,
+                    to: *to,
+                }),
+                Some(_) => {}
+            }
+        }
+        for (profile, active_generation) in new.iter() {
+            if !old.contains_key(profile) {
+                changes.push(ProfileChange::Added {
+                    profile: profile.clone(),
+                    active_generation: *active_generation,
+                });
+            }
+        }
+        changes.sort_by(|c1, c2| c1.profile().cmp(c2.profile()));
+        changes
+    }
+}
+
+impl ProfileChange {
+    pub fn profile(&self) -> &Utf8Path {
+        match self {
+            ProfileChange::Added { profile, .. } => profile,
+            ProfileChange::Removed { profile } => profile,
+            ProfileChange::Switched { profile, .. } => profile,
+        }
+    }
+}
+
+/// A generation number, or `unknown` for None.
+fn generation(generation: &Option<u64>) -> String {
+    generation.map_or_else(|| "unknown".to_string(), |id| id.to_string())
+}
+
+impl Display for ProfileChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfileChange::Added {
+                profile,
+                active_generation,
+            } => write!(
+                f,
+                "+ profile {profile}, active generation {}",
+                generation(active_generation)
+            ),
+            ProfileChange::Removed { profile } => write!(f, "- profile {profile}"),
+            ProfileChange::Switched { profile, from, to } => write!(
+                f,
+                "~ profile {profile}, active generation {} -> {}",
+                generation(from),
+                generation(to)
+            ),
+        }
+    }
+}
+
+impl Display for Diff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.is_empty() {
+            return write!(f, "no changes");
+        }
+        let mut lines = Vec::new();
+        lines.extend(self.profile_changes.iter().map(|change| change.to_string()));
+        let root = |gcroot: &GCRoot| format!("{} -> {}", gcroot.path, gcroot.target.as_str());
+        lines.extend(
+            self.removed
+                .iter()
+                .map(|gcroot| format!("- {}", root(gcroot))),
+        );
+        lines.extend(
+            self.added
+                .iter()
+                .map(|gcroot| format!("+ {}", root(gcroot))),
+        );
+        lines.extend(
+            self.retargeted
+                .iter()
+                .map(|(old, new)| format!("~ {} (was {})", root(new), old.target.as_str())),
+        );
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gcroots(output: &str) -> GCRoots {
+        GCRoots::from_print_roots_output(output).unwrap()
+    }
+
+    #[test]
+    fn added_removed_and_retargeted_roots() {
+        let old = gcroots(
+            "/tmp/a -> /nix/store/aaaa-a\n\
+             /tmp/b -> /nix/store/bbbb-b\n\
+             /tmp/c -> /nix/store/cccc-c\n",
+        );
+        let new = gcroots(
+            "/tmp/a -> /nix/store/aaaa-a\n\
+             /tmp/c -> /nix/store/cccc-c2\n\
+             /tmp/d -> /nix/store/dddd-d\n",
+        );
+        let diff = Diff::between(&old, &new);
+        let paths = |roots: &[GCRoot]| roots.iter().map(|r| r.path.to_string()).collect::<Vec<_>>();
+        assert_eq!(paths(&diff.removed), ["/tmp/b"]);
+        assert_eq!(paths(&diff.added), ["/tmp/d"]);
+        assert_eq!(diff.retargeted.len(), 1);
+        assert_eq!(diff.retargeted[0].1.target.as_str(), "/nix/store/cccc-c2");
+        assert!(diff.profile_changes.is_empty());
+        assert_eq!(
+            diff.to_string(),
+            "- /tmp/b -> /nix/store/bbbb-b\n\
+             + /tmp/d -> /nix/store/dddd-d\n\
+             ~ /tmp/c -> /nix/store/cccc-c2 (was /nix/store/cccc-c)"
+        );
+    }
+
+    /// Output listing two generations of a profile in dir, which exists and
+    /// points at the generation active if it's Some. The generation links
+    /// point at dir so that the profile is readable.
+    fn profile(dir: &Utf8Path, active: Option<u64>) -> String {
+        for generation in [1, 2] {
+            let _ = std::os::unix::fs::symlink(dir, dir.join(format!("system-{generation}-link")));
+        }
+        let profile = dir.join("system");
+        let _ = std::fs::remove_file(&profile);
+        if let Some(active) = active {
+            std::os::unix::fs::symlink(format!("system-{active}-link"), &profile).unwrap();
+        }
+        format!(
+            "{dir}/system-1-link -> /nix/store/aaaa-system\n\
+             {dir}/system-2-link -> /nix/store/bbbb-system\n"
+        )
+    }
+
+    fn temporary_dir() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn root_moving_into_a_profile_is_not_added() {
+        let (_dir, dir) = temporary_dir();
+        let old = gcroots(&profile(&dir, None));
+        assert_eq!(old.standalone().len(), 2);
+        let new = gcroots(&profile(&dir, Some(2)));
+        assert_eq!(new.profiles().len(), 1);
+        let diff = Diff::between(&old, &new);
+        assert!(diff.added.is_empty());
+        assert!(diff.removed.is_empty());
+        assert!(diff.retargeted.is_empty());
+        assert_eq!(
+            diff.profile_changes,
+            [ProfileChange::Added {
+                profile: dir.join("system"),
+                active_generation: Some(2),
+            }]
+        );
+    }
+
+    #[test]
+    fn active_generation_switch_without_new_roots() {
+        let (_dir, dir) = temporary_dir();
+        let old = gcroots(&profile(&dir, Some(2)));
+        let new = gcroots(&profile(&dir, Some(1)));
+        let diff = Diff::between(&old, &new);
+        assert!(diff.added.is_empty() && diff.removed.is_empty());
+        assert_eq!(
+            diff.profile_changes,
+            [ProfileChange::Switched {
+                profile: dir.join("system"),
+                from: Some(2),
+                to: Some(1),
+            }]
+        );
+        assert_eq!(
+            diff.to_string(),
+            format!("~ profile {dir}/system, active generation 2 -> 1")
+        );
+    }
+
+    #[test]
+    fn listing_roundtrips_through_json() {
+        let (_dir, dir) = temporary_dir();
+        let listing = Listing::of(&gcroots(&format!(
+            "{}/tmp/a -> /nix/store/aaaa-a\n",
+            profile(&dir, Some(1))
+        )));
+        assert_eq!(listing.profiles.len(), 1);
+        let json = serde_json::to_string(&listing).unwrap();
+        let parsed: Listing = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, listing);
+        assert!(Diff::of(&parsed, &listing).is_empty());
+        let other_store = Listing::of(&gcroots(
+            "/tmp/a -> /srv/nix/store/0123456789abcdfghijklmnpqrsvwxyz-a\n",
+        ));
+        let parsed: Listing =
+            serde_json::from_str(&serde_json::to_string(&other_store).unwrap()).unwrap();
+        assert!(Diff::of(&parsed, &other_store).is_empty());
+        assert_eq!(Diff::default().to_string(), "no changes");
+    }
+}
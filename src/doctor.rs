@@ -0,0 +1,386 @@
+use std::{env, ffi::OsStr, fmt::Display, os::unix::fs::PermissionsExt};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use nix::unistd::{AccessFlags, Uid, User};
+
+use crate::{nix_command, nix_config::GcSettings, pin::Pins, xdg};
+
+/// Default location of the Nix state directory.
+const NIX_STATE_DIR: &str = "/nix/var/nix";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// Outcome of a single diagnostic check.
+pub enum Status {
+    Pass,
+    Warn,
+    Fail,
+}
+
+#[derive(Debug, Clone)]
+/// Result of a single diagnostic check.
+pub struct Diagnosis {
+    /// Short name of what was checked.
+    pub name: &'static str,
+    pub status: Status,
+    /// What was found.
+    pub message: String,
+    /// How to fix it, if it isn't passing.
+    pub hint: Option<String>,
+}
+
+impl Diagnosis {
+    fn pass(name: &'static str, message: impl Into<String>) -> Self {
+        Diagnosis {
+            name,
+            status: Status::Pass,
+            message: message.into(),
+            hint: None,
+        }
+    }
+
+    fn warn(name: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Diagnosis {
+            name,
+            status: Status::Warn,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+
+    fn fail(name: &'static str, message: impl Into<String>, hint: impl Into<String>) -> Self {
+        Diagnosis {
+            name,
+            status: Status::Fail,
+            message: message.into(),
+            hint: Some(hint.into()),
+        }
+    }
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Pass => write!(f, "pass"),
+            Status::Warn => write!(f, "warn"),
+            Status::Fail => write!(f, "fail"),
+        }
+    }
+}
+
+impl Display for Diagnosis {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}] {}: {}", self.status, self.name, self.message)?;
+        if let Some(hint) = &self.hint {
+            write!(f, "\n       hint: {}", hint)?;
+        }
+        Ok(())
+    }
+}
+
+/// Runs every check against the live system.
+/// The Nix state directory, `$NIX_STATE_DIR` or the default.
+pub fn state_dir() -> Utf8PathBuf {
+    env::var("NIX_STATE_DIR")
+        .map(Utf8PathBuf::from)
+        .unwrap_or_else(|_| Utf8PathBuf::from(NIX_STATE_DIR))
+}
+
+pub fn diagnose() -> Vec<Diagnosis> {
+    let state_dir = state_dir();
+    let user = User::from_uid(Uid::current())
+        .ok()
+        .flatten()
+        .map(|u| u.name);
+    let search_path = nix_command::bin_dir().or_else(|| env::var_os("PATH"));
+    let mut diagnoses = vec![check_binary(search_path.as_deref())];
+    let output = nix_command::command("nix-store")
+        .args(["--gc", "--print-roots"])
+        .output();
+    let (print_roots, stdout) = match output {
+        Ok(output) => (
+            check_print_roots(
+                output.status.success(),
+                &String::from_utf8_lossy(&output.stderr),
+            ),
+            output
+                .status
+                .success()
+                .then(|| String::from_utf8_lossy(&output.stdout).into_owned()),
+        ),
+        Err(e) => (
+            Diagnosis::fail(
+                "print roots",
+                format!("couldn't run nix-store: {e}"),
+                "make sure nix-store is installed and on PATH",
+            ),
+            None,
+        ),
+    };
+    diagnoses.push(print_roots);
+    diagnoses.push(check_state_dir(&state_dir));
+    diagnoses.push(check_profile_dir(&state_dir, user.as_deref()));
+    diagnoses.push(match stdout {
+        Some(stdout) => check_censored_ratio(&stdout),
+        None => Diagnosis::warn(
+            "censored roots",
+            "skipped, couldn't list roots",
+            "fix the print roots check first",
+        ),
+    });
+    diagnoses.push(check_gc_settings(GcSettings::read()));
+    diagnoses.push(check_pins_dir(&state_dir, user.as_deref()));
+    diagnoses.push(match Pins::open() {
+        Ok(pins) => check_pinned_generations(&pins),
+        Err(e) => Diagnosis::warn(
+            "pinned generations",
+            format!("{e:#}"),
+            "fix or remove the pins file",
+        ),
+    });
+    diagnoses
+}
+
+/// Checks that `nix-store` resolves to an executable file in the given PATH,
+/// which is `$GCRS_NIX_BIN_DIR` when that is set.
+pub fn check_binary(path_var: Option<&OsStr>) -> Diagnosis {
+    let found = path_var
+        .map(env::split_paths)
+        .into_iter()
+        .flatten()
+        .map(|dir| dir.join("nix-store"))
+        .find(|candidate| {
+            candidate
+                .metadata()
+                .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+                .unwrap_or(false)
+        });
+    match found {
+        Some(path) => Diagnosis::pass("nix-store binary", path.display().to_string()),
+        None => Diagnosis::fail(
+            "nix-store binary",
+            "nix-store not found in PATH",
+            "is Nix installed? add its bin directory to PATH",
+        ),
+    }
+}
+
+/// Checks the outcome of `nix-store --gc --print-roots`.
+pub fn check_print_roots(success: bool, stderr: &str) -> Diagnosis {
+    if success {
+        return Diagnosis::pass("print roots", "nix-store --gc --print-roots succeeded");
+    }
+    let error = stderr
+        .lines()
+        .find(|line| !line.trim().is_empty())
+        .unwrap_or("no error output");
+    let hint = nix_command::explain(stderr)
+        .unwrap_or("run nix-store --gc --print-roots manually to see the full error");
+    Diagnosis::fail("print roots", error.trim(), hint)
+}
+
+/// Checks that the Nix state directory exists.
+pub fn check_state_dir(state_dir: &Utf8Path) -> Diagnosis {
+    match state_dir.is_dir() {
+        true => Diagnosis::pass("state directory", state_dir.as_str()),
+        false => Diagnosis::fail(
+            "state directory",
+            format!("{state_dir} does not exist"),
+            "set NIX_STATE_DIR if Nix uses a non-default location",
+        ),
+    }
+}
+
+/// Checks that the per-user profile directory exists, either the XDG one used by
+/// newer Nix versions or the legacy one in the state directory.
+pub fn check_profile_dir(state_dir: &Utf8Path, user: Option<&str>) -> Diagnosis {
+    let xdg = xdg::state_home().map(|dir| dir.join("nix/profiles"));
+    let legacy = user.map(|user| state_dir.join("profiles/per-user").join(user));
+    match xdg.iter().chain(legacy.iter()).find(|dir| dir.is_dir()) {
+        Some(dir) => Diagnosis::pass("profile directory", dir.as_str()),
+        None => Diagnosis::warn(
+            "profile directory",
+            "no per-user profile directory found",
+            "it is created the first time you install something with nix-env or nix profile",
+        ),
+    }
+}
+
+/// Checks how many of the printed roots are censored, which happens when
+/// not running as root.
+pub fn check_censored_ratio(print_roots_output: &str) -> Diagnosis {
+    let total = print_roots_output.lines().count();
+    let censored = print_roots_output
+        .lines()
+        .filter(|line| line.starts_with("{censored}"))
+        .count();
+    let message = format!("{censored} of {total} roots are censored");
+    match censored {
+        0 => Diagnosis::pass("censored roots", message),
+        c if c == total => Diagnosis::warn(
+            "censored roots",
+            message,
+            "all roots are hidden, run gcrs as root to see them",
+        ),
+        _ => Diagnosis::warn(
+            "censored roots",
+            message,
+            "roots of other users are hidden, run gcrs as root to see them",
+        ),
+    }
+}
+
+/// Reports the settings that keep paths alive beyond the closures of roots,
+/// which gcrs accounts for in --show-dead but not in per-root sizes.
+pub fn check_gc_settings(settings: eyre::Result<GcSettings>) -> Diagnosis {
+    match settings {
+        Ok(settings) if settings.keep_outputs => Diagnosis::pass(
+            "gc settings",
+            format!("{settings}, outputs of live derivations survive, so size estimates may be too high"),
+        ),
+        Ok(settings) => Diagnosis::pass("gc settings", settings.to_string()),
+        Err(e) => Diagnosis::warn(
+            "gc settings",
+            format!("{e:#}"),
+            "check that nix config show works",
+        ),
+    }
+}
+
+/// Checks that every pinned generation still exists.
+pub fn check_pinned_generations(pins: &Pins) -> Diagnosis {
+    let dangling = pins.dangling().collect::<Vec<_>>();
+    let Some(first) = dangling.first() else {
+        return Diagnosis::pass(
+            "pinned generations",
+            format!("{} pinned, all exist", pins.pins().len()),
+        );
+    };
+    Diagnosis::warn(
+        "pinned generations",
+        format!(
+            "{} of {} pinned no longer exist: {}",
+            dangling.len(),
+            pins.pins().len(),
+            dangling
+                .iter()
+                .map(|pin| pin.to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ),
+        format!(
+            "unpin them, e.g. gcrs pin-generation --remove --profile {} {}",
+            first.profile, first.generation
+        ),
+    )
+}
+
+/// Checks write access to the per-user gcroots directory used for pinning.
+pub fn check_pins_dir(state_dir: &Utf8Path, user: Option<&str>) -> Diagnosis {
+    let Some(user) = user else {
+        return Diagnosis::warn(
+            "pins directory",
+            "couldn't determine the current user",
+            "make sure the current user has a passwd entry",
+        );
+    };
+    let dir = state_dir.join("gcroots/per-user").join(user);
+    match nix::unistd::access(dir.as_str(), AccessFlags::W_OK) {
+        Ok(()) => Diagnosis::pass("pins directory", dir.as_str()),
+        Err(e) => Diagnosis::warn(
+            "pins directory",
+            format!("{dir} is not writable: {e}"),
+            format!("create it with: sudo mkdir -p {dir} && sudo chown {user} {dir}"),
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{ffi::OsString, os::unix::fs::PermissionsExt};
+
+    use super::*;
+
+    fn temporary_dir() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn binary_has_to_be_executable() {
+        let (_dir, dir) = temporary_dir();
+        let path_var = OsString::from(format!("/nonexistent:{dir}"));
+        assert_eq!(check_binary(Some(&path_var)).status, Status::Fail);
+        let binary = dir.join("nix-store");
+        std::fs::write(&binary, "#!/bin/sh\n").unwrap();
+        assert_eq!(check_binary(Some(&path_var)).status, Status::Fail);
+        std::fs::set_permissions(&binary, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let diagnosis = check_binary(Some(&path_var));
+        assert_eq!(diagnosis.status, Status::Pass);
+        assert_eq!(diagnosis.message, binary.as_str());
+        assert_eq!(check_binary(None).status, Status::Fail);
+    }
+
+    #[test]
+    fn print_roots_failure_explains_known_errors() {
+        assert_eq!(check_print_roots(true, "").status, Status::Pass);
+        let diagnosis = check_print_roots(
+            false,
+            "\nerror: cannot connect to socket at '/nix/var/nix/daemon-socket/socket'\n",
+        );
+        assert_eq!(diagnosis.status, Status::Fail);
+        assert_eq!(
+            diagnosis.message,
+            "error: cannot connect to socket at '/nix/var/nix/daemon-socket/socket'"
+        );
+        assert!(diagnosis
+            .hint
+            .unwrap()
+            .contains("nix-daemon does not appear to be running"));
+        let diagnosis = check_print_roots(false, "");
+        assert_eq!(diagnosis.message, "no error output");
+    }
+
+    #[test]
+    fn state_and_profile_directories() {
+        let (_dir, dir) = temporary_dir();
+        assert_eq!(check_state_dir(&dir).status, Status::Pass);
+        assert_eq!(check_state_dir(&dir.join("missing")).status, Status::Fail);
+        std::fs::create_dir_all(dir.join("profiles/per-user/alice")).unwrap();
+        let diagnosis = check_profile_dir(&dir, Some("alice"));
+        // Passes with the legacy directory, or the XDG one of whoever runs the tests.
+        assert_eq!(diagnosis.status, Status::Pass);
+    }
+
+    #[test]
+    fn censored_ratio() {
+        let diagnosis = |output| check_censored_ratio(output);
+        assert_eq!(diagnosis("/a -> /nix/store/a\n").status, Status::Pass);
+        let some = diagnosis("/a -> /nix/store/a\n{censored} -> /nix/store/b\n");
+        assert_eq!(some.status, Status::Warn);
+        assert_eq!(some.message, "1 of 2 roots are censored");
+        let all = diagnosis("{censored} -> /nix/store/b\n");
+        assert!(all.hint.unwrap().starts_with("all roots are hidden"));
+    }
+
+    #[test]
+    fn gc_settings() {
+        let settings = GcSettings::parse("keep-outputs = true\n");
+        let diagnosis = check_gc_settings(Ok(settings));
+        assert_eq!(diagnosis.status, Status::Pass);
+        assert!(diagnosis.message.contains("size estimates may be too high"));
+        let diagnosis = check_gc_settings(Err(eyre::eyre!("nix not found")));
+        assert_eq!(diagnosis.status, Status::Warn);
+        assert_eq!(diagnosis.message, "nix not found");
+    }
+
+    #[test]
+    fn rendering() {
+        let diagnosis = Diagnosis::fail("state directory", "/x does not exist", "set it");
+        assert_eq!(
+            diagnosis.to_string(),
+            "[fail] state directory: /x does not exist\n       hint: set it"
+        );
+        assert_eq!(Diagnosis::pass("a", "b").to_string(), "[pass] a: b");
+    }
+}
@@ -0,0 +1,192 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Deserializer};
+
+const MINUTE: u64 = 60;
+const HOUR: u64 = 60 * MINUTE;
+const DAY: u64 = 24 * HOUR;
+const WEEK: u64 = 7 * DAY;
+
+/// Parses a duration like `90m`, `12h`, `180d`, `2w` or a combination like `1d12h`.
+/// A number without a unit is interpreted as seconds.
+pub fn parse(input: &str) -> Result<Duration, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        return Err("empty duration, expected e.g. 30d".to_string());
+    }
+    if input.starts_with('-') {
+        return Err(format!("negative duration \"{input}\" isn't allowed"));
+    }
+    if input.bytes().all(|b| b.is_ascii_digit()) {
+        return input
+            .parse::<u64>()
+            .map(Duration::from_secs)
+            .map_err(|_| format!("duration \"{input}\" is too large"));
+    }
+    let mut total = 0u64;
+    let mut seen = Vec::new();
+    let mut rest = input;
+    while !rest.is_empty() {
+        let split = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let (number, tail) = rest.split_at(split);
+        let unit_len = tail
+            .find(|c: char| !c.is_ascii_alphabetic())
+            .unwrap_or(tail.len());
+        let (unit, tail) = tail.split_at(unit_len);
+        if number.is_empty() {
+            return Err(format!(
+                "invalid duration \"{input}\", expected e.g. 30d or 1d12h"
+            ));
+        }
+        let multiplier = match unit {
+            "s" => 1,
+            "m" => MINUTE,
+            "h" => HOUR,
+            "d" => DAY,
+            "w" => WEEK,
+            "" => return Err(format!("missing unit after {number} in \"{input}\"")),
+            _ => return Err(format!("unknown duration unit \"{unit}\" in \"{input}\"")),
+        };
+        if seen.contains(&unit) {
+            return Err(format!("duration unit \"{unit}\" repeated in \"{input}\""));
+        }
+        seen.push(unit);
+        total = number
+            .parse::<u64>()
+            .ok()
+            .and_then(|number| number.checked_mul(multiplier))
+            .and_then(|secs| total.checked_add(secs))
+            .ok_or_else(|| format!("duration \"{input}\" is too large"))?;
+        rest = tail;
+    }
+    Ok(Duration::from_secs(total))
+}
+
+/// Formats a duration using its largest whole unit, e.g. `203d` or `5h`.
+pub fn format(duration: Duration) -> String {
+    let secs = duration.as_secs();
+    match secs {
+        s if s >= DAY => format!("{}d", s / DAY),
+        s if s >= HOUR => format!("{}h", s / HOUR),
+        s if s >= MINUTE => format!("{}m", s / MINUTE),
+        s => format!("{s}s"),
+    }
+}
+
+/// Formats a duration with every unit up to days, e.g. `1d12h`, for echoing
+/// durations given by the user.
+pub fn format_exact(duration: Duration) -> String {
+    let mut secs = duration.as_secs();
+    if secs == 0 {
+        return "0s".to_string();
+    }
+    let mut output = String::new();
+    for (unit, name) in [(DAY, "d"), (HOUR, "h"), (MINUTE, "m"), (1, "s")] {
+        if secs >= unit {
+            output.push_str(&format!("{}{}", secs / unit, name));
+            secs %= unit;
+        }
+    }
+    output
+}
+
+/// Deserializes an optional duration written as a string, for use in the config file.
+pub fn deserialize_option<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Option::<String>::deserialize(deserializer)?
+        .map(|s| parse(&s).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn units_and_combinations() {
+        assert_eq!(parse("90m"), Ok(Duration::from_secs(90 * MINUTE)));
+        assert_eq!(parse("12h"), Ok(Duration::from_secs(12 * HOUR)));
+        assert_eq!(parse("30d"), Ok(Duration::from_secs(30 * DAY)));
+        assert_eq!(parse("2w"), Ok(Duration::from_secs(2 * WEEK)));
+        assert_eq!(parse("1d12h"), Ok(Duration::from_secs(DAY + 12 * HOUR)));
+        assert_eq!(parse("12h1d"), Ok(Duration::from_secs(DAY + 12 * HOUR)));
+        assert_eq!(parse(" 45 "), Ok(Duration::from_secs(45)));
+    }
+
+    #[test]
+    fn zero() {
+        assert_eq!(parse("0"), Ok(Duration::ZERO));
+        assert_eq!(parse("0d"), Ok(Duration::ZERO));
+        assert_eq!(format(Duration::ZERO), "0s");
+        assert_eq!(format_exact(Duration::ZERO), "0s");
+    }
+
+    #[test]
+    fn junk_is_explained() {
+        assert_eq!(
+            parse(""),
+            Err("empty duration, expected e.g. 30d".to_string())
+        );
+        assert_eq!(
+            parse("-5d"),
+            Err("negative duration \"-5d\" isn't allowed".to_string())
+        );
+        assert_eq!(
+            parse("d"),
+            Err("invalid duration \"d\", expected e.g. 30d or 1d12h".to_string())
+        );
+        assert_eq!(
+            parse("1d 2h"),
+            Err("invalid duration \"1d 2h\", expected e.g. 30d or 1d12h".to_string())
+        );
+        assert_eq!(
+            parse("1d12"),
+            Err("missing unit after 12 in \"1d12\"".to_string())
+        );
+        assert_eq!(
+            parse("3y"),
+            Err("unknown duration unit \"y\" in \"3y\"".to_string())
+        );
+        assert_eq!(
+            parse("1d1d"),
+            Err("duration unit \"d\" repeated in \"1d1d\"".to_string())
+        );
+    }
+
+    #[test]
+    fn huge_values() {
+        assert_eq!(
+            parse(&u64::MAX.to_string()),
+            Ok(Duration::from_secs(u64::MAX))
+        );
+        let too_large = "18446744073709551616";
+        assert_eq!(
+            parse(too_large),
+            Err(format!("duration \"{too_large}\" is too large"))
+        );
+        let overflowing = format!("{}w", u64::MAX / WEEK + 1);
+        assert_eq!(
+            parse(&overflowing),
+            Err(format!("duration \"{overflowing}\" is too large"))
+        );
+        let sum = format!("{}s1m", u64::MAX);
+        assert_eq!(parse(&sum), Err(format!("duration \"{sum}\" is too large")));
+    }
+
+    #[test]
+    fn formatting() {
+        assert_eq!(format(Duration::from_secs(203 * DAY + 5 * HOUR)), "203d");
+        assert_eq!(format(Duration::from_secs(5 * HOUR + 59)), "5h");
+        assert_eq!(format(Duration::from_secs(59)), "59s");
+        assert_eq!(format_exact(Duration::from_secs(DAY + 12 * HOUR)), "1d12h");
+        assert_eq!(format_exact(Duration::from_secs(2 * WEEK + 61)), "14d1m1s");
+        for input in ["1d12h", "90m", "3h30m15s"] {
+            let duration = parse(input).unwrap();
+            assert_eq!(parse(&format_exact(duration)), Ok(duration));
+        }
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::HashSet,
+    fmt::Write,
+    fs::OpenOptions,
+    io::Write as _,
+    process::Command,
+    time::{Duration, SystemTime},
+};
+
+use camino::Utf8PathBuf;
+use eyre::{eyre, Result, WrapErr};
+
+use crate::{
+    duration,
+    plan::{EntryKind, PlanEntry},
+};
+
+/// Lets the user drop entries by editing the plan in $VISUAL or $EDITOR, like
+/// git rebase -i. Returns the entries left in the file, in plan order.
+pub fn edit(entries: Vec<PlanEntry>, now: SystemTime) -> Result<Vec<PlanEntry>> {
+    let path = Utf8PathBuf::try_from(std::env::temp_dir())?
+        .join(format!("gcrs-plan-{}.txt", std::process::id()));
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)
+        .wrap_err_with(|| format!("couldn't create {path}"))?;
+    file.write_all(to_text(&entries, now).as_bytes())?;
+    drop(file);
+    let result = run_editor(&path).and_then(|()| {
+        let text =
+            std::fs::read_to_string(&path).wrap_err_with(|| format!("couldn't read {path}"))?;
+        parse(&text, entries)
+    });
+    let _ = std::fs::remove_file(&path);
+    result
+}
+
+fn run_editor(path: &camino::Utf8Path) -> Result<()> {
+    let editor = ["VISUAL", "EDITOR"]
+        .iter()
+        .filter_map(|var| std::env::var(var).ok())
+        .find(|editor| !editor.trim().is_empty())
+        .unwrap_or_else(|| "vi".to_string());
+    // Through the shell, so that editors with arguments like "code --wait" work.
+    let status = Command::new("sh")
+        .args(["-c", &format!("{editor} \"$1\""), "sh", path.as_str()])
+        .status()
+        .wrap_err_with(|| format!("couldn't run editor {editor}"))?;
+    match status.success() {
+        true => Ok(()),
+        false => Err(eyre!("editor {editor} failed with {status}, aborting")),
+    }
+}
+
+/// The plan as a file for editing, one root path per line preceded by a
+/// comment with its metadata.
+pub fn to_text(entries: &[PlanEntry], now: SystemTime) -> String {
+    let mut text = String::from(
+        "# Roots to remove, one path per line.\n\
+        # Delete the line of a root to keep it, lines starting with # are ignored.\n\
+        # Only roots of the original plan are accepted, an empty plan aborts.\n",
+    );
+    for entry in entries {
+        let _ = write!(text, "\n# {}, target {}", entry.reason(), entry.target);
+        if let EntryKind::Generation {
+            profile,
+            generation,
+        } = &entry.kind
+        {
+            let _ = write!(text, ", generation {generation} of {profile}");
+        }
+        let age = entry
+            .mtime
+            .map(|mtime| SystemTime::UNIX_EPOCH + Duration::from_secs(mtime))
+            .and_then(|mtime| now.duration_since(mtime).ok());
+        if let Some(age) = age {
+            let _ = write!(text, ", {} old", duration::format(age));
+        }
+        let _ = write!(text, "\n{}\n", entry.path);
+    }
+    text
+}
+
+/// Keeps the entries whose paths are listed in the edited text.
+/// Lines that aren't paths of the original entries are an error, so that
+/// editing can only remove entries, never add new ones.
+pub fn parse(text: &str, entries: Vec<PlanEntry>) -> Result<Vec<PlanEntry>> {
+    let mut listed = HashSet::new();
+    for (index, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        if !entries.iter().any(|entry| entry.path == line) {
+            return Err(eyre!(
+                "line {} \"{}\" isn't a root of the plan",
+                index + 1,
+                line
+            ));
+        }
+        if !listed.insert(line) {
+            return Err(eyre!("line {} lists {} again", index + 1, line));
+        }
+    }
+    if listed.is_empty() {
+        return Err(eyre!("empty plan, aborting"));
+    }
+    Ok(entries
+        .into_iter()
+        .filter(|entry| listed.contains(entry.path.as_str()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+
+    use super::*;
+    use crate::{gcroot::NodeType, plan::SelectionReason};
+
+    const NOW: u64 = 100 * 24 * 60 * 60;
+
+    fn entries() -> Vec<PlanEntry> {
+        let standalone = PlanEntry {
+            path: Utf8PathBuf::from("/home/a/result"),
+            target: Utf8PathBuf::from("/nix/store/aaaa-hello"),
+            kind: EntryKind::Standalone,
+            reasons: vec![SelectionReason::older_than(Duration::from_secs(
+                90 * 24 * 60 * 60,
+            ))],
+            node: NodeType::Symlink,
+            contents: Vec::new(),
+            mtime: Some(0),
+            size: None,
+            substitutable: None,
+        };
+        let generation = PlanEntry {
+            path: Utf8PathBuf::from("/nix/var/nix/profiles/system-1-link"),
+            target: Utf8PathBuf::from("/nix/store/bbbb-system"),
+            kind: EntryKind::Generation {
+                profile: Utf8PathBuf::from("/nix/var/nix/profiles/system"),
+                generation: 1,
+            },
+            reasons: vec![SelectionReason::Requested],
+            mtime: None,
+            ..standalone.clone()
+        };
+        vec![standalone, generation]
+    }
+
+    fn text() -> String {
+        to_text(
+            &entries(),
+            SystemTime::UNIX_EPOCH + Duration::from_secs(NOW),
+        )
+    }
+
+    fn paths(entries: &[PlanEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.path.as_str()).collect()
+    }
+
+    #[test]
+    fn every_entry_has_a_line_and_a_comment() {
+        let text = text();
+        let entries = text.split("\n\n").skip(1).collect::<Vec<_>>();
+        assert_eq!(
+            entries,
+            [
+                "# older than 90d, target /nix/store/aaaa-hello, 100d old\n/home/a/result",
+                "# requested, target /nix/store/bbbb-system, \
+                 generation 1 of /nix/var/nix/profiles/system\n\
+                 /nix/var/nix/profiles/system-1-link\n",
+            ]
+        );
+    }
+
+    #[test]
+    fn unedited_plans_are_kept_whole() {
+        assert_eq!(parse(&text(), entries()).unwrap(), entries());
+    }
+
+    #[test]
+    fn deleted_lines_are_dropped_and_order_is_kept() {
+        let edited = "/nix/var/nix/profiles/system-1-link\n  \n# /home/a/result\n";
+        let kept = parse(edited, entries()).unwrap();
+        assert_eq!(paths(&kept), ["/nix/var/nix/profiles/system-1-link"]);
+        let reordered = "/nix/var/nix/profiles/system-1-link\n/home/a/result\n";
+        let kept = parse(reordered, entries()).unwrap();
+        assert_eq!(
+            paths(&kept),
+            ["/home/a/result", "/nix/var/nix/profiles/system-1-link"]
+        );
+    }
+
+    #[test]
+    fn new_or_repeated_lines_are_errors() {
+        let added = text() + "/home/b/result\n";
+        let lines = added.lines().count();
+        assert_eq!(
+            parse(&added, entries()).unwrap_err().to_string(),
+            format!("line {lines} \"/home/b/result\" isn't a root of the plan")
+        );
+        let repeated = "/home/a/result\n/home/a/result\n";
+        assert_eq!(
+            parse(repeated, entries()).unwrap_err().to_string(),
+            "line 2 lists /home/a/result again"
+        );
+    }
+
+    #[test]
+    fn emptied_plans_abort() {
+        let comments = text()
+            .lines()
+            .filter(|line| line.starts_with('#'))
+            .collect::<Vec<_>>()
+            .join("\n");
+        for text in ["", comments.as_str()] {
+            assert_eq!(
+                parse(text, entries()).unwrap_err().to_string(),
+                "empty plan, aborting"
+            );
+        }
+    }
+}
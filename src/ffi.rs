@@ -0,0 +1,92 @@
+use std::{
+    cell::RefCell,
+    ffi::{c_char, CString},
+    panic::{self, AssertUnwindSafe},
+    ptr,
+};
+
+use eyre::{eyre, Result};
+
+use crate::{
+    gcroot::{DiscoverOptions, GCRoots},
+    render,
+    source::{NixStore, Source},
+};
+
+thread_local! {
+    /// Error of the last call on the thread that failed, see [gcrs_last_error].
+    static LAST_ERROR: RefCell<Option<CString>> = const { RefCell::new(None) };
+}
+
+/// Runs f, turning an error or a panic into null and [LAST_ERROR],
+/// as unwinding into C is undefined behavior.
+fn boundary(f: impl FnOnce() -> Result<String>) -> *mut c_char {
+    let result = match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(result) => result,
+        Err(payload) => {
+            let message = payload
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| payload.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string());
+            Err(eyre!("gcrs panicked: {message}"))
+        }
+    };
+    let result = result.and_then(|output| Ok(CString::new(output)?));
+    match result {
+        Ok(output) => {
+            LAST_ERROR.with(|error| *error.borrow_mut() = None);
+            output.into_raw()
+        }
+        Err(e) => {
+            let message = format!("{e:#}").replace('\0', "");
+            let message = CString::new(message).unwrap_or_default();
+            LAST_ERROR.with(|error| *error.borrow_mut() = Some(message));
+            ptr::null_mut()
+        }
+    }
+}
+
+/// Discovers the roots of the local store like `gcrs print --json`, returning
+/// the JSON document as a NUL-terminated UTF-8 string to be freed with
+/// [gcrs_free]. The schema is versioned by its `"version"` field.
+/// Returns null on failure, see [gcrs_last_error].
+#[no_mangle]
+pub extern "C" fn gcrs_discover_json() -> *mut c_char {
+    boundary(|| {
+        let sources = [&NixStore as &dyn Source];
+        let options = DiscoverOptions {
+            root_status: true,
+            ..DiscoverOptions::default()
+        };
+        let (gcroots, _) = GCRoots::discover(&sources, &options)?;
+        let json = serde_json::to_string_pretty(&render::json(&gcroots))?;
+        Ok(json)
+    })
+}
+
+/// Message of the last call on this thread that returned null, or null if
+/// it succeeded. The string stays owned by gcrs and is valid until the next
+/// call on the thread, it must not be freed.
+#[no_mangle]
+pub extern "C" fn gcrs_last_error() -> *const c_char {
+    LAST_ERROR.with(|error| {
+        error
+            .borrow()
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
+/// Frees a string returned by gcrs, null is ignored.
+///
+/// # Safety
+///
+/// The pointer has to come from a function of gcrs returning `char *` and
+/// must not be used or freed again afterwards.
+#[no_mangle]
+pub unsafe extern "C" fn gcrs_free(string: *mut c_char) {
+    if !string.is_null() {
+        drop(CString::from_raw(string));
+    }
+}
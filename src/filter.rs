@@ -0,0 +1,169 @@
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
+
+use camino::Utf8Path;
+
+use crate::{
+    gcroot::{GCRoot, Profile},
+    user::Attribution,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A shell-style pattern for paths: `*` matches anything but `/`,
+/// `**` anything including `/` and `?` a single character but `/`.
+pub struct Glob(String);
+
+impl Glob {
+    pub fn new(pattern: &str) -> Self {
+        Glob(pattern.to_string())
+    }
+
+    /// If the whole path matches the pattern.
+    pub fn matches(&self, path: &Utf8Path) -> bool {
+        let pattern = self.0.chars().collect::<Vec<_>>();
+        let path = path.as_str().chars().collect::<Vec<_>>();
+        Self::match_from(&pattern, &path)
+    }
+
+    fn match_from(pattern: &[char], path: &[char]) -> bool {
+        match pattern {
+            [] => path.is_empty(),
+            ['*', '*', rest @ ..] => (0..=path.len()).any(|i| Self::match_from(rest, &path[i..])),
+            ['*', rest @ ..] => {
+                let segment = path.iter().position(|c| *c == '/').unwrap_or(path.len());
+                (0..=segment).any(|i| Self::match_from(rest, &path[i..]))
+            }
+            ['?', rest @ ..] => {
+                matches!(path.first(), Some(c) if *c != '/') && Self::match_from(rest, &path[1..])
+            }
+            [c, rest @ ..] => path.first() == Some(c) && Self::match_from(rest, &path[1..]),
+        }
+    }
+}
+
+impl Display for Glob {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+/// Which kinds of roots [Filter] keeps.
+pub enum RootKinds {
+    #[default]
+    All,
+    Standalone,
+    Generations,
+}
+
+#[derive(Debug, Clone, Default)]
+/// Which roots [crate::gcroot::GCRoots::retain] keeps. Every criterion that's
+/// set has to match, the default keeps everything.
+pub struct Filter {
+    /// Only generations of profiles whose path matches.
+    pub profile: Option<Glob>,
+    /// Only roots of the user: under a `per-user/<user>` directory, or
+    /// attributed to them by [crate::user::Attribution::user_of].
+    pub user: Option<String>,
+    pub kinds: RootKinds,
+    /// Only roots whose symlink was last modified longer than this ago.
+    /// Roots whose modification time can't be read are left out.
+    pub older_than: Option<Duration>,
+    /// Only roots that are gone or whose target is, see [crate::gcroot::RootStatus::is_dead].
+    pub dead: bool,
+}
+
+impl Filter {
+    /// If the root passes, profile is Some if it's a generation of one.
+    pub fn keeps(&self, gcroot: &GCRoot, profile: Option<&Profile>, now: SystemTime) -> bool {
+        let kind = match (self.kinds, profile) {
+            (RootKinds::All, _) => true,
+            (RootKinds::Standalone, profile) => profile.is_none(),
+            (RootKinds::Generations, profile) => profile.is_some(),
+        };
+        let profile = match (&self.profile, profile) {
+            (None, _) => true,
+            (Some(_), None) => false,
+            (Some(glob), Some(profile)) => glob.matches(&profile.path),
+        };
+        let user = self
+            .user
+            .as_ref()
+            .is_none_or(|user| Self::belongs_to(gcroot, user));
+        let age = self.older_than.is_none_or(|older_than| {
+            gcroot
+                .modified()
+                .ok()
+                .and_then(|mtime| now.duration_since(mtime).ok())
+                .is_some_and(|age| age > older_than)
+        });
+        let dead = !self.dead || gcroot.status.is_dead();
+        kind && profile && user && age && dead
+    }
+
+    fn belongs_to(gcroot: &GCRoot, user: &str) -> bool {
+        let components = gcroot
+            .path
+            .components()
+            .map(|c| c.as_str())
+            .collect::<Vec<_>>();
+        let per_user = components
+            .windows(2)
+            .any(|pair| pair[0] == "per-user" && pair[1] == user);
+        per_user || {
+            let owner = gcroot.metadata().ok().map(|metadata| metadata.uid);
+            Attribution::system()
+                .user_of(&gcroot.path, owner)
+                .as_deref()
+                == Some(user)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+
+    use camino::Utf8PathBuf;
+
+    use super::*;
+    use crate::gcroot::{StoreDirs, Target};
+
+    fn root(path: &Utf8Path) -> GCRoot {
+        let target = Target::new(
+            Rc::from(Utf8Path::new("/nix/store/aaaa-a")),
+            &StoreDirs::default(),
+        );
+        GCRoot::new(Rc::from(path), target)
+    }
+
+    fn user(name: &str) -> Filter {
+        Filter {
+            user: Some(name.to_string()),
+            ..Filter::default()
+        }
+    }
+
+    #[test]
+    fn per_user_directories_belong_to_their_user() {
+        let gcroot = root(Utf8Path::new(
+            "/nix/var/nix/profiles/per-user/alice/profile-3-link",
+        ));
+        let now = SystemTime::now();
+        assert!(user("alice").keeps(&gcroot, None, now));
+        assert!(!user("bob").keeps(&gcroot, None, now));
+    }
+
+    #[test]
+    fn roots_outside_homes_belong_to_their_owner() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().join("result")).unwrap();
+        std::os::unix::fs::symlink("/nix/store/aaaa-a", &path).unwrap();
+        let owner = crate::render::owner_name(nix::unistd::getuid().as_raw());
+        let now = SystemTime::now();
+        assert!(user(&owner).keeps(&root(&path), None, now));
+        assert!(!user("gcrs-nobody").keeps(&root(&path), None, now));
+    }
+}
@@ -0,0 +1,29 @@
+use std::os::unix::fs::MetadataExt;
+
+use camino::Utf8Path;
+
+use crate::gcroot::{MetadataError, NodeType, RootMetadata};
+
+/// Somewhere the metadata of roots is read from, see [crate::gcroot::GCRoot::metadata_with].
+pub trait Fs {
+    /// Reads the metadata of path itself, without following a symlink.
+    fn lstat(&self, path: &Utf8Path) -> Result<RootMetadata, MetadataError>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// The filesystem gcrs runs on.
+pub struct LocalFs;
+
+impl Fs for LocalFs {
+    /// A single lstat.
+    fn lstat(&self, path: &Utf8Path) -> Result<RootMetadata, MetadataError> {
+        let metadata = path.symlink_metadata().map_err(MetadataError::from)?;
+        Ok(RootMetadata {
+            mtime: metadata.modified().map_err(MetadataError::from)?,
+            uid: metadata.uid(),
+            gid: metadata.gid(),
+            node: NodeType::of(&metadata),
+            size: metadata.len(),
+        })
+    }
+}
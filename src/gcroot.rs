@@ -1,21 +1,272 @@
 use std::{
-    collections::BTreeMap,
+    borrow::Borrow,
+    cell::OnceCell,
+    collections::{BTreeMap, HashMap},
     fmt::Display,
-    process::{Command, Output},
+    fs::Metadata,
+    hash::{Hash, Hasher},
+    io::ErrorKind,
+    ops::Deref,
+    os::unix::ffi::OsStrExt,
     rc::Rc,
+    time::{Duration, Instant, SystemTime},
 };
 
 use camino::{Utf8Path, Utf8PathBuf};
-use eyre::{eyre, Result};
+use chrono::{DateTime, Utc};
+use eyre::Result;
 use nix::unistd::AccessFlags;
+use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
-/// A Nix Garbage Collection Root.
+use crate::{
+    doctor,
+    filter::Filter,
+    fs::{Fs, LocalFs},
+    retention::RetentionPolicy,
+    size,
+    sort::{SortKey, SortLocale},
+    source::{DiscoveryReport, Filesystem, NixStore, Source, SourceOutcome, SourceReport},
+    summary::{Summary, SummaryOptions},
+    timing::{self, Phase},
+    walk::{self, WalkOptions},
+};
+
+/// Default directory holding the store paths roots point at.
+const NIX_STORE_DIR: &str = "/nix/store";
+
+/// The store directory, $NIX_STORE_DIR if set.
+pub fn store_dir() -> Utf8PathBuf {
+    std::env::var("NIX_STORE_DIR")
+        .map(Utf8PathBuf::from)
+        .unwrap_or_else(|_| Utf8PathBuf::from(NIX_STORE_DIR))
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(from = "SerializedRoot", into = "SerializedRoot")]
+/// A Nix Garbage Collection Root. Roots are compared by their fields,
+/// leaving out the cached metadata.
 pub struct GCRoot {
     /// Location of the symlink.
     pub path: Rc<Utf8Path>,
     /// Where the symlink points to.
-    pub target: Rc<Utf8Path>,
+    pub target: Target,
+    /// Closure size of the target, see [GCRoots::gather_sizes].
+    /// None until gathered and for targets that aren't in the store.
+    pub closure_size: Option<u64>,
+    /// If the root and its target still exist, checked when it's discovered
+    /// with [DiscoverOptions::root_status].
+    pub status: RootStatus,
+    /// Result of the first [GCRoot::metadata_with].
+    metadata: OnceCell<std::result::Result<RootMetadata, MetadataError>>,
+}
+
+impl GCRoot {
+    /// A root of unknown closure size and [RootStatus::Unchecked].
+    pub fn new(path: Rc<Utf8Path>, target: Target) -> Self {
+        GCRoot {
+            path,
+            target,
+            closure_size: None,
+            status: RootStatus::Unchecked,
+            metadata: OnceCell::new(),
+        }
+    }
+
+    fn key(&self) -> (&Utf8Path, &Utf8Path, Option<u64>, RootStatus) {
+        (&self.path, &self.target, self.closure_size, self.status)
+    }
+}
+
+impl PartialEq for GCRoot {
+    fn eq(&self, other: &Self) -> bool {
+        self.key() == other.key()
+    }
+}
+
+impl Eq for GCRoot {}
+
+impl PartialOrd for GCRoot {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for GCRoot {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.key().cmp(&other.key())
+    }
+}
+
+/// If the error means a root is gone. Roots vanish after discovery all the time,
+/// e.g. when a build finishes or another cleanup runs, which isn't an error.
+pub fn is_gone(error: &std::io::Error) -> bool {
+    error.kind() == ErrorKind::NotFound
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Store directories whose paths count as store paths, e.g. the usual
+/// one and another store mounted for a chroot.
+pub struct StoreDirs(Vec<Utf8PathBuf>);
+
+impl Default for StoreDirs {
+    /// Only [store_dir].
+    fn default() -> Self {
+        StoreDirs(vec![store_dir()])
+    }
+}
+
+impl StoreDirs {
+    /// [store_dir] followed by extra.
+    pub fn new(extra: impl IntoIterator<Item = Utf8PathBuf>) -> Self {
+        let mut dirs = StoreDirs::default();
+        for dir in extra {
+            dirs.register(dir);
+        }
+        dirs
+    }
+
+    pub fn dirs(&self) -> &[Utf8PathBuf] {
+        &self.0
+    }
+
+    /// The registered store directory path is in, if any.
+    pub fn store_dir_of(&self, path: &Utf8Path) -> Option<&Utf8Path> {
+        self.0
+            .iter()
+            .find(|dir| {
+                path.strip_prefix(dir)
+                    .is_ok_and(|rest| rest.components().next().is_some())
+            })
+            .map(|dir| dir.as_path())
+    }
+
+    /// If path is inside one of the store directories.
+    pub fn contains(&self, path: &Utf8Path) -> bool {
+        self.store_dir_of(path).is_some()
+    }
+
+    fn register(&mut self, dir: Utf8PathBuf) {
+        if !self.0.contains(&dir) {
+            self.0.push(dir);
+        }
+    }
+
+    /// If path is inside one of the store directories or looks like a path
+    /// in another one, the way [StoreDirs::detect] would register it.
+    pub fn recognizes(&self, path: &Utf8Path) -> bool {
+        self.contains(path) || Self::detected(path).is_some()
+    }
+
+    /// Registers the directory of path if it looks like a store path somewhere
+    /// else, `<prefix>/nix/store/<32 character hash>-<name>`.
+    fn detect(&mut self, path: &Utf8Path) {
+        if self.contains(path) {
+            return;
+        }
+        if let Some(dir) = Self::detected(path) {
+            self.register(dir.to_path_buf());
+        }
+    }
+
+    fn detected(path: &Utf8Path) -> Option<&Utf8Path> {
+        if !path.is_absolute() {
+            return None;
+        }
+        let dir = path.ancestors().find(|dir| dir.ends_with("nix/store"))?;
+        path.strip_prefix(dir)
+            .ok()
+            .and_then(|rest| rest.components().next())
+            .and_then(|name| name.as_str().split_once('-'))
+            .is_some_and(|(hash, _)| hash.len() == 32)
+            .then_some(dir)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+/// Where a gcroot points to. Derefs to the path either way.
+pub enum Target {
+    /// A path in the store.
+    Store(Rc<Utf8Path>),
+    /// Anything else, e.g. a relative path or a path outside the store.
+    /// Such roots protect nothing from garbage collection.
+    Other(Rc<Utf8Path>),
+}
+
+impl Target {
+    /// Classifies path against the store directories.
+    pub fn new(path: Rc<Utf8Path>, store_dirs: &StoreDirs) -> Self {
+        match store_dirs.contains(&path) {
+            true => Target::Store(path),
+            false => Target::Other(path),
+        }
+    }
+
+    pub fn is_store(&self) -> bool {
+        matches!(self, Target::Store(_))
+    }
+}
+
+impl From<Utf8PathBuf> for Target {
+    /// Classifies path against the default [StoreDirs].
+    fn from(path: Utf8PathBuf) -> Self {
+        Target::new(path.into_boxed_path().into(), &StoreDirs::default())
+    }
+}
+
+impl Deref for Target {
+    type Target = Utf8Path;
+
+    fn deref(&self) -> &Utf8Path {
+        match self {
+            Target::Store(path) => path,
+            Target::Other(path) => path,
+        }
+    }
+}
+
+impl Borrow<Utf8Path> for Target {
+    fn borrow(&self) -> &Utf8Path {
+        self
+    }
+}
+
+impl Hash for Target {
+    /// Hashes only the path, like [Utf8Path] does, so that maps keyed by
+    /// Target can be queried with a path.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        (**self).hash(state)
+    }
+}
+
+impl Display for Target {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Target::Store(path) => write!(f, "{path}"),
+            Target::Other(path) => write!(f, "{path} (not a store path)"),
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+/// GCRoot as it's serialized, serde doesn't handle Rc without a feature.
+struct SerializedRoot {
+    path: Utf8PathBuf,
+    target: Utf8PathBuf,
+}
+
+impl From<SerializedRoot> for GCRoot {
+    fn from(root: SerializedRoot) -> Self {
+        GCRoot::new(root.path.into_boxed_path().into(), root.target.into())
+    }
+}
+
+impl From<GCRoot> for SerializedRoot {
+    fn from(root: GCRoot) -> Self {
+        SerializedRoot {
+            path: root.path.to_path_buf(),
+            target: root.target.to_path_buf(),
+        }
+    }
 }
 
 impl GCRoot {
@@ -29,7 +280,7 @@ impl GCRoot {
     /// Returns Some(generation number) of this profile generation if this gcroot file
     /// name fits the naming scheme of a profile generation, None otherwise.
     pub fn get_profile_gen(&self) -> Option<u64> {
-        let Some(file_name) = self.path.file_name() else { return None };
+        let file_name = self.path.file_name()?;
         if file_name.chars().filter(|x| *x == '-').count() >= 2 {
             let mut iter = file_name.rsplitn(3, '-');
             if iter.next().unwrap() == "link" {
@@ -43,6 +294,52 @@ impl GCRoot {
         }
     }
 
+    /// Reads the metadata of the gcroot itself with a single lstat,
+    /// without following the symlink, see [GCRoot::metadata_with].
+    pub fn metadata(&self) -> std::result::Result<RootMetadata, MetadataError> {
+        self.metadata_with(&LocalFs)
+    }
+
+    /// Reads the metadata of the gcroot itself from fs on the first call.
+    /// Later calls return the same result without reading it again, even
+    /// if the gcroot changed since.
+    pub fn metadata_with(&self, fs: &dyn Fs) -> std::result::Result<RootMetadata, MetadataError> {
+        *self.metadata.get_or_init(|| fs.lstat(&self.path))
+    }
+
+    /// What kind of file the gcroot itself is.
+    pub fn node_type(&self) -> std::result::Result<NodeType, MetadataError> {
+        Ok(self.metadata()?.node)
+    }
+
+    /// Symlinks into one of the stores directly inside a directory gcroot,
+    /// as (link, target) pairs sorted by link.
+    pub fn contained_links(
+        &self,
+        store_dirs: &StoreDirs,
+    ) -> std::io::Result<Vec<(Utf8PathBuf, Utf8PathBuf)>> {
+        let options = WalkOptions {
+            max_depth: 1,
+            ..WalkOptions::WELL_KNOWN
+        };
+        let mut links = Vec::new();
+        for entry in walk::walk(&self.path, &options)?.entries {
+            if !entry.file_type.is_symlink() {
+                continue;
+            }
+            let target = entry.path.read_link_utf8()?;
+            if store_dirs.contains(&target) {
+                links.push((entry.path, target));
+            }
+        }
+        Ok(links)
+    }
+
+    /// Modification time of the gcroot symlink itself.
+    pub fn modified(&self) -> std::result::Result<SystemTime, MetadataError> {
+        Ok(self.metadata()?.mtime)
+    }
+
     /// If the gcroot can be deleted.
     /// Doesn't check if the gcroot is an active profile.
     pub fn deletable(&self) -> bool {
@@ -60,7 +357,163 @@ impl GCRoot {
 
 impl Display for GCRoot {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} -> {}", self.path, self.target)
+        write!(
+            f,
+            "{} -> {}{}",
+            self.path,
+            self.target,
+            self.status.annotation()
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// If a root still protects its target, see [RootStatus::check].
+pub enum RootStatus {
+    /// Not checked, see [DiscoverOptions::root_status].
+    #[default]
+    Unchecked,
+    Live,
+    /// The symlink itself is gone.
+    BrokenLink,
+    /// The symlink exists but its target doesn't.
+    MissingTarget,
+    /// The root or its target couldn't be inspected, e.g. for lack of permission.
+    Unknown,
+}
+
+impl RootStatus {
+    /// Checks the root and its target with an lstat each, relative targets
+    /// resolved from the directory of the root. Neither is followed further,
+    /// so a target that's itself a symlink counts as existing.
+    pub fn check(path: &Utf8Path, target: &Utf8Path) -> Self {
+        let missing = |e: &std::io::Error| is_gone(e) || e.kind() == ErrorKind::NotADirectory;
+        match path.symlink_metadata() {
+            Ok(_) => {}
+            Err(e) if missing(&e) => return RootStatus::BrokenLink,
+            Err(_) => return RootStatus::Unknown,
+        }
+        let target = match path.parent() {
+            Some(parent) if target.is_relative() => parent.join(target),
+            _ => target.to_path_buf(),
+        };
+        match target.symlink_metadata() {
+            Ok(_) => RootStatus::Live,
+            Err(e) if missing(&e) => RootStatus::MissingTarget,
+            Err(_) => RootStatus::Unknown,
+        }
+    }
+
+    /// ` (status)` for roots that aren't known to be live, nothing otherwise.
+    pub fn annotation(self) -> String {
+        match self {
+            RootStatus::Live | RootStatus::Unchecked => String::new(),
+            status => format!(" ({status})"),
+        }
+    }
+
+    /// If the root protects nothing anymore.
+    pub fn is_dead(self) -> bool {
+        matches!(self, RootStatus::BrokenLink | RootStatus::MissingTarget)
+    }
+}
+
+impl Display for RootStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RootStatus::Live => write!(f, "live"),
+            RootStatus::BrokenLink => write!(f, "broken"),
+            RootStatus::MissingTarget => write!(f, "target missing"),
+            RootStatus::Unknown => write!(f, "status unknown"),
+            RootStatus::Unchecked => write!(f, "unchecked"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What a single lstat says about a gcroot, see [GCRoot::metadata].
+pub struct RootMetadata {
+    /// Modification time of the gcroot itself.
+    pub mtime: SystemTime,
+    pub uid: u32,
+    pub gid: u32,
+    pub node: NodeType,
+    /// Size of the gcroot itself, for a symlink the length of its target.
+    pub size: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Why the metadata of a gcroot couldn't be read.
+pub enum MetadataError {
+    /// The gcroot disappeared since it was discovered, see [is_gone].
+    NotFound,
+    /// A parent directory can't be searched.
+    PermissionDenied,
+    Other(ErrorKind),
+}
+
+impl From<std::io::Error> for MetadataError {
+    fn from(error: std::io::Error) -> Self {
+        match error.kind() {
+            _ if is_gone(&error) => MetadataError::NotFound,
+            ErrorKind::PermissionDenied => MetadataError::PermissionDenied,
+            kind => MetadataError::Other(kind),
+        }
+    }
+}
+
+impl Display for MetadataError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MetadataError::NotFound => write!(f, "not found"),
+            MetadataError::PermissionDenied => write!(f, "permission denied"),
+            MetadataError::Other(kind) => write!(f, "{kind}"),
+        }
+    }
+}
+
+impl std::error::Error for MetadataError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Kind of file a gcroot is. Most are symlinks, but nix treats any file
+/// under the gcroots directory as a root.
+pub enum NodeType {
+    #[default]
+    Symlink,
+    /// A directory of links, each of them a root.
+    Directory,
+    /// A regular file naming a store path.
+    File,
+    /// Anything else, e.g. a socket.
+    Other,
+}
+
+impl NodeType {
+    pub fn of(metadata: &Metadata) -> Self {
+        let file_type = metadata.file_type();
+        match (
+            file_type.is_symlink(),
+            file_type.is_dir(),
+            file_type.is_file(),
+        ) {
+            (true, _, _) => NodeType::Symlink,
+            (_, true, _) => NodeType::Directory,
+            (_, _, true) => NodeType::File,
+            _ => NodeType::Other,
+        }
+    }
+}
+
+impl Display for NodeType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            NodeType::Symlink => write!(f, "symlink"),
+            NodeType::Directory => write!(f, "directory"),
+            NodeType::File => write!(f, "file"),
+            NodeType::Other => write!(f, "other"),
+        }
     }
 }
 
@@ -73,92 +526,641 @@ pub struct Profile {
     /// None if we don't know the active generation e.g. couldn't read the symlink.
     pub active_generation: Option<u64>,
     pub generations: BTreeMap<u64, GCRoot>,
+    /// Size of the closures of all generations together, see [GCRoots::gather_sizes].
+    pub closure_size: Option<u64>,
+}
+
+impl Profile {
+    /// Width of the closure sizes of the generations in a column,
+    /// None if they weren't gathered.
+    pub fn size_width(&self) -> Option<usize> {
+        self.closure_size?;
+        self.generations
+            .values()
+            .map(|generation| size::cell(generation.closure_size).len())
+            .max()
+    }
+
+    /// The generation with the highest number, which isn't necessarily the active one.
+    pub fn latest(&self) -> Option<(u64, &GCRoot)> {
+        self.generations
+            .iter()
+            .next_back()
+            .map(|(id, gcroot)| (*id, gcroot))
+    }
+
+    /// The generation with the lowest number.
+    pub fn oldest(&self) -> Option<(u64, &GCRoot)> {
+        self.generations
+            .iter()
+            .next()
+            .map(|(id, gcroot)| (*id, gcroot))
+    }
+
+    /// The gcroot of the active generation.
+    /// None if the active generation is unknown, or if the profile points at
+    /// a generation nix-store didn't list, e.g. one whose link was removed.
+    pub fn active(&self) -> Option<&GCRoot> {
+        self.generations.get(&self.active_generation?)
+    }
+
+    /// Number of generations.
+    pub fn len(&self) -> usize {
+        self.generations.len()
+    }
+
+    /// If the profile has no generations.
+    pub fn is_empty(&self) -> bool {
+        self.generations.is_empty()
+    }
+
+    /// Numbers and modification times of the generations, the time None if
+    /// it can't be read.
+    pub fn generation_mtimes(&self) -> Vec<(u64, Option<DateTime<Utc>>)> {
+        self.generations
+            .iter()
+            .map(|(id, gcroot)| (*id, gcroot.modified().ok().map(DateTime::from)))
+            .collect()
+    }
+
+    /// Numbers of the generations policy doesn't keep, highest first.
+    /// The active generation is always kept, see [RetentionPolicy::kept]
+    /// for the rest.
+    pub fn generations_to_remove(&self, policy: RetentionPolicy, now: DateTime<Utc>) -> Vec<u64> {
+        let kept = policy.kept(&self.generation_mtimes(), now);
+        self.generations_desc()
+            .map(|(id, _)| id)
+            .filter(|id| !kept.contains(id) && self.active_generation != Some(*id))
+            .collect()
+    }
+
+    /// Generations from the highest number to the lowest.
+    pub fn generations_desc(&self) -> impl Iterator<Item = (u64, &GCRoot)> {
+        self.generations
+            .iter()
+            .rev()
+            .map(|(id, gcroot)| (*id, gcroot))
+    }
+
+    /// Generations matching selector, lowest number first, with None for
+    /// requested numbers the profile doesn't have.
+    /// The active generation is included, it's up to the caller to refuse it.
+    pub fn select(
+        &self,
+        selector: &GenerationSelector,
+        now: SystemTime,
+    ) -> Vec<(u64, Option<&GCRoot>)> {
+        match selector {
+            GenerationSelector::Numbers(numbers) => {
+                let mut numbers = numbers.clone();
+                numbers.sort_unstable();
+                numbers.dedup();
+                numbers
+                    .into_iter()
+                    .map(|id| (id, self.generations.get(&id)))
+                    .collect()
+            }
+            GenerationSelector::OlderThan(max_age) => self
+                .generations
+                .iter()
+                .filter(|(_, gcroot)| {
+                    gcroot
+                        .modified()
+                        .ok()
+                        .and_then(|mtime| now.duration_since(mtime).ok())
+                        .is_some_and(|age| age > *max_age)
+                })
+                .map(|(id, gcroot)| (*id, Some(gcroot)))
+                .collect(),
+        }
+    }
+
+    /// Path of the link of generation, whether it exists or not.
+    pub fn generation_link(&self, generation: u64) -> Utf8PathBuf {
+        Utf8PathBuf::from(format!("{}-{}-link", self.path, generation))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Which generations of a profile to pick, see [Profile::select].
+pub enum GenerationSelector {
+    Numbers(Vec<u64>),
+    /// Generations whose link was last modified longer ago than this.
+    OlderThan(Duration),
 }
 
 impl Display for Profile {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.path)?;
+        if let Some(total) = self.closure_size {
+            write!(f, " ({} in total)", size::format(total))?;
+        }
         let digits = 1 + self
-            .generations
-            .keys()
-            .max()
-            .and_then(|m| m.checked_ilog10())
+            .latest()
+            .and_then(|(id, _)| id.checked_ilog10())
             .unwrap_or(0) as usize;
-        for (id, generation) in self.generations.iter().rev() {
+        let width = self.size_width();
+        for (id, generation) in self.generations_desc() {
             writeln!(f)?;
-            if self.active_generation == Some(*id) {
-                write!(f, "> {: >digits$} -> {}", id, generation.target)?;
-            } else {
-                write!(f, "  {: >digits$} -> {}", id, generation.target)?;
+            let marker = match self.active_generation == Some(id) {
+                true => '>',
+                false => ' ',
+            };
+            write!(f, "{} {: >digits$}", marker, id)?;
+            if let Some(width) = width {
+                write!(f, " {: >width$}", size::cell(generation.closure_size))?;
             }
+            write!(f, " -> {}", generation.target)?;
         }
         Ok(())
     }
 }
 
+#[derive(Debug, Clone)]
+/// Controls how much work discovery does beyond listing the roots.
+pub struct DiscoverOptions {
+    /// Read the profile symlinks to find out which generation is active.
+    /// When false every profile has an active_generation of None.
+    pub active_generations: bool,
+    /// Store directories besides [store_dir]. Others are detected from the
+    /// targets that look like `<prefix>/nix/store/<hash>-<name>`.
+    pub store_dirs: Vec<Utf8PathBuf>,
+    /// Group generations into profiles. When false every root is standalone,
+    /// see [GCRoots::ungrouped_generation].
+    pub group: bool,
+    /// Check if every root and its target still exist, see [RootStatus::check].
+    /// When false every root is [RootStatus::Unchecked].
+    pub root_status: bool,
+}
+
+impl Default for DiscoverOptions {
+    fn default() -> Self {
+        DiscoverOptions {
+            active_generations: true,
+            store_dirs: Vec::new(),
+            group: true,
+            root_status: false,
+        }
+    }
+}
+
 #[derive(Debug)]
 /// A collection of Nix Garbage Collection Roots.
 pub struct GCRoots {
     profiles: Vec<Profile>,
     /// GCRoots that don't belong to any profile.
     standalone: Vec<GCRoot>,
+    /// Targets of roots that aren't listed, like ones held by running processes
+    /// or censored ones. They still keep their closures alive.
+    hidden_targets: Vec<Rc<Utf8Path>>,
+    /// Store directories the targets were classified against.
+    store_dirs: StoreDirs,
+    /// Profiles that would have been grouped if grouping wasn't disabled,
+    /// their generations are listed as standalone roots.
+    ungrouped: Vec<Profile>,
+    /// If closure sizes were gathered, they're shown by the Display impl then.
+    sizes: bool,
+    /// Lines of the nix-store output that weren't roots.
+    ignored: Vec<String>,
+    /// Built on the first lookup, reset whenever the roots are reordered.
+    index: OnceCell<Index>,
+}
+
+#[derive(Debug, Clone, Copy)]
+/// A root found in [GCRoots], together with where it belongs.
+pub enum RootRef<'a> {
+    Standalone(&'a GCRoot),
+    Generation {
+        profile: &'a Profile,
+        generation: u64,
+        gcroot: &'a GCRoot,
+    },
+}
+
+impl<'a> RootRef<'a> {
+    pub fn gcroot(&self) -> &'a GCRoot {
+        match self {
+            RootRef::Standalone(gcroot) => gcroot,
+            RootRef::Generation { gcroot, .. } => gcroot,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+/// Position of a root in [GCRoots].
+enum Position {
+    Standalone(usize),
+    Generation(usize, u64),
+}
+
+#[derive(Debug, Default)]
+/// Positions of the roots by path and by target.
+struct Index {
+    by_path: HashMap<Rc<Utf8Path>, Position>,
+    by_target: HashMap<Target, Vec<Position>>,
 }
 
 impl GCRoots {
+    /// The root at path, if nix-store listed one there.
+    pub fn find(&self, path: &Utf8Path) -> Option<RootRef<'_>> {
+        let position = self.index().by_path.get(path)?;
+        Some(self.resolve(*position))
+    }
+
+    /// Every root pointing at target, generations first.
+    pub fn find_by_target(&self, target: &Utf8Path) -> Vec<RootRef<'_>> {
+        self.index()
+            .by_target
+            .get(target)
+            .into_iter()
+            .flatten()
+            .map(|position| self.resolve(*position))
+            .collect()
+    }
+
+    fn index(&self) -> &Index {
+        self.index.get_or_init(|| {
+            let mut index = Index::default();
+            let generations = self.profiles.iter().enumerate().flat_map(|(i, profile)| {
+                profile
+                    .generations
+                    .iter()
+                    .map(move |(id, gcroot)| (gcroot, Position::Generation(i, *id)))
+            });
+            let standalone = self
+                .standalone
+                .iter()
+                .enumerate()
+                .map(|(i, gcroot)| (gcroot, Position::Standalone(i)));
+            for (gcroot, position) in generations.chain(standalone) {
+                index.by_path.insert(gcroot.path.clone(), position);
+                index
+                    .by_target
+                    .entry(gcroot.target.clone())
+                    .or_default()
+                    .push(position);
+            }
+            index
+        })
+    }
+
+    fn resolve(&self, position: Position) -> RootRef<'_> {
+        match position {
+            Position::Standalone(i) => RootRef::Standalone(&self.standalone[i]),
+            Position::Generation(i, generation) => {
+                let profile = &self.profiles[i];
+                RootRef::Generation {
+                    profile,
+                    generation,
+                    gcroot: &profile.generations[&generation],
+                }
+            }
+        }
+    }
+}
+
+impl GCRoots {
+    /// Profiles sorted by path.
+    pub fn profiles(&self) -> &[Profile] {
+        &self.profiles
+    }
+
+    /// GCRoots that don't belong to any profile, sorted.
+    pub fn standalone(&self) -> &[GCRoot] {
+        &self.standalone
+    }
+
+    /// Queries the closure sizes of every target in a single nix invocation and
+    /// stores them in the roots, with the combined size of each profile.
+    /// Does nothing if they were gathered already.
+    pub fn gather_sizes(&mut self) -> Result<()> {
+        if self.sizes {
+            return Ok(());
+        }
+        let targets = self
+            .profiles
+            .iter()
+            .flat_map(|profile| profile.generations.values())
+            .chain(self.standalone.iter())
+            .map(|gcroot| &*gcroot.target)
+            .collect::<Vec<_>>();
+        let closures = size::Closures::query(&targets, &self.store_dirs)?;
+        for profile in self.profiles.iter_mut() {
+            for generation in profile.generations.values_mut() {
+                generation.closure_size = closures.size(&generation.target);
+            }
+            let targets = profile.generations.values().map(|g| &*g.target);
+            profile.closure_size = Some(closures.combined(targets));
+        }
+        for gcroot in self.standalone.iter_mut() {
+            gcroot.closure_size = closures.size(&gcroot.target);
+        }
+        self.sizes = true;
+        Ok(())
+    }
+
+    /// If [GCRoots::gather_sizes] was called.
+    pub fn sizes_gathered(&self) -> bool {
+        self.sizes
+    }
+
+    /// Width of the closure sizes of the standalone roots in a column,
+    /// None if they weren't gathered.
+    pub fn standalone_size_width(&self) -> Option<usize> {
+        self.sizes.then(|| {
+            self.standalone
+                .iter()
+                .map(|gcroot| size::cell(gcroot.closure_size).len())
+                .max()
+                .unwrap_or(0)
+        })
+    }
+
+    /// Targets of roots that aren't listed, like ones held by running processes
+    /// or censored ones.
+    pub fn hidden_targets(&self) -> impl Iterator<Item = &Utf8Path> {
+        self.hidden_targets.iter().map(|target| &**target)
+    }
+
+    /// Store directories the targets were classified against.
+    pub fn store_dirs(&self) -> &StoreDirs {
+        &self.store_dirs
+    }
+
+    /// Profiles whose generations are listed as standalone roots because
+    /// grouping was disabled, empty otherwise.
+    pub fn ungrouped(&self) -> &[Profile] {
+        &self.ungrouped
+    }
+
+    /// The profile and generation number of a standalone root that is a
+    /// generation, when grouping was disabled. Protections of generations
+    /// still apply to these, like keeping the active one.
+    pub fn ungrouped_generation(&self, path: &Utf8Path) -> Option<(&Profile, u64)> {
+        let file_name = path.file_name()?;
+        let mut parts = file_name.rsplitn(3, '-');
+        let (Some("link"), Some(generation)) = (parts.next(), parts.next()) else {
+            return None;
+        };
+        let generation = generation.parse().ok()?;
+        let profile = self
+            .ungrouped
+            .iter()
+            .find(|profile| profile.generation_link(generation) == path)?;
+        profile
+            .generations
+            .contains_key(&generation)
+            .then_some((profile, generation))
+    }
+
+    /// Aggregate numbers about the roots, see [Summary].
+    pub fn summary(&self, options: SummaryOptions) -> Summary {
+        Summary::of(self, options)
+    }
+
+    /// Reorders standalone roots and profiles by the given key, see [SortKey],
+    /// comparing text the way locale says.
+    pub fn sort(&mut self, key: SortKey, reverse: bool, locale: SortLocale) {
+        key.sort_roots(&mut self.standalone, reverse, locale);
+        key.sort_profiles(&mut self.profiles, reverse, locale);
+        self.index.take();
+    }
+
+    /// Keeps only the roots filter keeps. Profiles left without generations are
+    /// dropped, roots that aren't listed are kept.
+    pub fn retain(&mut self, filter: &Filter, now: SystemTime) {
+        for profile in self.profiles.iter_mut() {
+            let mut generations = std::mem::take(&mut profile.generations);
+            generations.retain(|_, gcroot| filter.keeps(gcroot, Some(profile), now));
+            profile.generations = generations;
+        }
+        self.profiles
+            .retain(|profile| !profile.generations.is_empty());
+        self.standalone
+            .retain(|gcroot| filter.keeps(gcroot, None, now));
+        self.index.take();
+    }
+
     /// Discovers GCRoots by running the nix-store command and parsing the output.
     pub fn from_nix_store_command() -> Result<Self> {
-        let output = Command::new("nix-store")
-            .args(["--gc", "--print-roots"])
-            .output()?;
-        let gcroots = Self::parse_nix_store_gc_output(output)?;
-        Self::group_gcroots(gcroots)
-    }
-
-    fn parse_nix_store_gc_output(output: Output) -> Result<Vec<GCRoot>> {
-        let output_bytes = output
-            .status
-            .success()
-            .then_some(output.stdout)
-            .ok_or_else(|| {
-                eyre!(
-                    "\"nix-store --gc --print-roots\" exited with code {}",
-                    output.status
-                )
-            })?;
-        let output_string = String::from_utf8(output_bytes)?;
-        let result = output_string
-            .lines()
-            .filter_map(Self::parse_nix_store_gc_line)
-            .collect();
-        Ok(result)
-    }
-
-    fn parse_nix_store_gc_line(line: &str) -> Option<GCRoot> {
-        let (path, target) = line
-            .rsplit_once(" -> ")
-            .expect("\"nix-store --gc --print-roots\" line containing \" -> \"");
-
-        if !(path.starts_with("/proc") || path.starts_with('{') && path.ends_with('}')) {
-            Some(GCRoot {
-                path: Utf8PathBuf::from(path).into(),
-                target: Utf8PathBuf::from(target).into(),
+        Self::from_nix_store_command_with(&DiscoverOptions::default())
+    }
+
+    /// Same as [GCRoots::from_nix_store_command] but only does the work requested by options.
+    pub fn from_nix_store_command_with(options: &DiscoverOptions) -> Result<Self> {
+        Self::discover(&[&NixStore], options).map(|(gcroots, _)| gcroots)
+    }
+
+    /// Discovers GCRoots by walking the Nix state directory instead of running
+    /// nix-store, see [Filesystem].
+    pub fn from_filesystem() -> Result<Self> {
+        let filesystem = Filesystem::new(doctor::state_dir(), StoreDirs::default());
+        Self::discover(&[&filesystem], &DiscoverOptions::default()).map(|(gcroots, _)| gcroots)
+    }
+
+    /// Discovers GCRoots from every source, reporting how each of them went.
+    /// Sources that fail are left out, it's only an error if all of them fail.
+    pub fn discover(
+        sources: &[&dyn Source],
+        options: &DiscoverOptions,
+    ) -> Result<(Self, DiscoveryReport)> {
+        let mut report = DiscoveryReport::default();
+        let mut listed = Vec::new();
+        let mut hidden = Vec::new();
+        let mut ignored = Vec::new();
+        let mut errors = Vec::new();
+        for source in sources {
+            let start = Instant::now();
+            let output = source.print_roots();
+            let elapsed = start.elapsed();
+            timing::record(Phase::Discovery, elapsed, None);
+            let (outcome, roots, ignored_lines) = match output {
+                Ok(output) => {
+                    let before = listed.len() + hidden.len();
+                    let start = Instant::now();
+                    let lines = Self::parse_nix_store_gc_output(&output, &mut listed, &mut hidden);
+                    timing::record(
+                        Phase::Parsing,
+                        start.elapsed(),
+                        Some(output.lines().count()),
+                    );
+                    let roots = listed.len() + hidden.len() - before;
+                    (SourceOutcome::Ok, roots, lines)
+                }
+                Err(e) => {
+                    let error = format!("{e:#}");
+                    errors.push(e);
+                    (SourceOutcome::Failed { error }, 0, Vec::new())
+                }
+            };
+            report.sources.push(SourceReport {
+                source: source.name().to_string(),
+                outcome,
+                roots,
+                ignored_lines: ignored_lines.len(),
+                format: source.format(),
+                elapsed,
+            });
+            ignored.extend(ignored_lines);
+        }
+        if !sources.is_empty() && errors.len() == sources.len() {
+            let error = errors.remove(0);
+            return Err(match sources.len() {
+                1 => error,
+                n => error.wrap_err(format!("all {n} sources failed")),
+            });
+        }
+        let gcroots = timing::time(Phase::Grouping, || {
+            Self::from_listed(listed, hidden, ignored, options)
+        })?;
+        Ok((gcroots, report))
+    }
+
+    /// Parses captured output of nix-store --gc --print-roots and groups the
+    /// roots like discovery does, reading the profile symlinks to find out the
+    /// active generations. Lines that aren't roots end up in [GCRoots::ignored_lines].
+    pub fn from_print_roots_output(output: &str) -> Result<Self> {
+        let mut listed = Vec::new();
+        let mut hidden = Vec::new();
+        let ignored = Self::parse_nix_store_gc_output(output, &mut listed, &mut hidden);
+        Self::from_listed(listed, hidden, ignored, &DiscoverOptions::default())
+    }
+
+    #[allow(clippy::type_complexity)]
+    fn from_listed(
+        listed: Vec<(Rc<Utf8Path>, Rc<Utf8Path>)>,
+        hidden: Vec<Rc<Utf8Path>>,
+        ignored: Vec<String>,
+        options: &DiscoverOptions,
+    ) -> Result<Self> {
+        let mut store_dirs = StoreDirs::new(options.store_dirs.iter().cloned());
+        let gcroots = Self::classify(listed, &mut store_dirs, options.root_status);
+        let mut gcroots = Self::group_gcroots(gcroots, options)?;
+        gcroots.hidden_targets = hidden;
+        gcroots.store_dirs = store_dirs;
+        gcroots.ignored = ignored;
+        Ok(gcroots)
+    }
+
+    /// Lines of the nix-store output that weren't roots, e.g. warnings
+    /// interleaved on stdout, trimmed.
+    pub fn ignored_lines(&self) -> &[String] {
+        &self.ignored
+    }
+
+    /// Parses output in the format of nix-store --gc --print-roots into the
+    /// (path, target) pairs of listed roots and the targets of hidden ones.
+    /// Returns the other, ignored lines.
+    #[allow(clippy::type_complexity)]
+    fn parse_nix_store_gc_output(
+        output: &str,
+        listed: &mut Vec<(Rc<Utf8Path>, Rc<Utf8Path>)>,
+        hidden: &mut Vec<Rc<Utf8Path>>,
+    ) -> Vec<String> {
+        let mut ignored = Vec::new();
+        for line in output.lines().filter(|line| !line.trim().is_empty()) {
+            match Self::parse_nix_store_gc_line(line) {
+                Some(Ok(root)) => listed.push(root),
+                Some(Err(target)) => hidden.push(target),
+                None => ignored.push(line.trim().to_string()),
+            }
+        }
+        ignored
+    }
+
+    /// Classifies the targets against store_dirs,
+    /// after registering the store directories detected in them.
+    fn classify(
+        listed: Vec<(Rc<Utf8Path>, Rc<Utf8Path>)>,
+        store_dirs: &mut StoreDirs,
+        root_status: bool,
+    ) -> Vec<GCRoot> {
+        for (_, target) in listed.iter() {
+            store_dirs.detect(target);
+        }
+        listed
+            .into_iter()
+            .map(|(path, target)| {
+                let status = match root_status {
+                    true => RootStatus::check(&path, &target),
+                    false => RootStatus::Unchecked,
+                };
+                GCRoot {
+                    status,
+                    ..GCRoot::new(path, Target::new(target, store_dirs))
+                }
             })
+            .collect()
+    }
+
+    /// Returns Some(Ok((path, target))) for listed roots, Some(Err(target)) for
+    /// roots that aren't, and None for lines that aren't roots at all, like
+    /// warnings interleaved on stdout.
+    #[allow(clippy::type_complexity)]
+    fn parse_nix_store_gc_line(
+        line: &str,
+    ) -> Option<Result<(Rc<Utf8Path>, Rc<Utf8Path>), Rc<Utf8Path>>> {
+        let (path, target) = Self::split_at_arrow(line)?;
+        let censored = path.starts_with('{') && path.ends_with('}');
+        if target.is_empty() || !(path.starts_with('/') || censored) {
+            return None;
+        }
+
+        if !(path.starts_with("/proc") || censored) {
+            Some(Ok((
+                Utf8PathBuf::from(path).into(),
+                Utf8PathBuf::from(target).into(),
+            )))
         } else {
-            None
+            Some(Err(Utf8PathBuf::from(target).into()))
         }
     }
 
-    fn group_gcroots(gcroots: Vec<GCRoot>) -> Result<Self> {
-        let mut profiles = Self::create_profiles(&gcroots)?;
-        let mut standalone = Self::populate_profiles(gcroots, &mut profiles);
+    /// Splits a line into root path and target at an arrow, tolerating any
+    /// amount of whitespace around it but requiring some. Store paths can't
+    /// contain arrows, so when the part after the last arrow is a store path
+    /// that's the target and earlier arrows belong to the root path. Otherwise
+    /// the root path ends at the first arrow and the rest is the target.
+    fn split_at_arrow(line: &str) -> Option<(&str, &str)> {
+        let split = |index: usize| {
+            let path = line[..index].strip_suffix(char::is_whitespace)?.trim();
+            let target = line[index + 2..].strip_prefix(char::is_whitespace)?.trim();
+            Some((path, target))
+        };
+        let last = line.rmatch_indices("->").find_map(|(i, _)| split(i))?;
+        let target = Utf8Path::new(last.1);
+        match StoreDirs::default().contains(target) || target.as_str().contains("/nix/store/") {
+            true => Some(last),
+            false => line.match_indices("->").find_map(|(i, _)| split(i)),
+        }
+    }
+
+    fn group_gcroots(gcroots: Vec<GCRoot>, options: &DiscoverOptions) -> Result<Self> {
+        let mut profiles = Self::create_profiles(&gcroots, options)?;
+        let (profiles, ungrouped, mut standalone) = match options.group {
+            true => {
+                let standalone = Self::populate_profiles(gcroots, &mut profiles);
+                (profiles, Vec::new(), standalone)
+            }
+            false => {
+                Self::populate_profiles(gcroots.clone(), &mut profiles);
+                (Vec::new(), profiles, gcroots)
+            }
+        };
         standalone.sort_unstable();
         Ok(GCRoots {
             profiles,
             standalone,
+            hidden_targets: Vec::new(),
+            store_dirs: StoreDirs::default(),
+            ungrouped,
+            sizes: false,
+            ignored: Vec::new(),
+            index: OnceCell::new(),
         })
     }
 
-    fn create_profiles(gcroots: &[GCRoot]) -> Result<Vec<Profile>> {
+    fn create_profiles(gcroots: &[GCRoot], options: &DiscoverOptions) -> Result<Vec<Profile>> {
         let mut profile_paths = gcroots
             .iter()
             .map(|gcroot| gcroot.get_profile_path())
@@ -169,26 +1171,42 @@ impl GCRoots {
         profile_paths.dedup();
         let mut profiles = Vec::with_capacity(profile_paths.len());
         for path in profile_paths {
-            let active_generation = Self::read_active_gen(&path)?;
+            let active_generation = match options.active_generations {
+                true => Self::read_active_gen(&path)?,
+                false => None,
+            };
             profiles.push(Profile {
                 path,
                 active_generation,
                 generations: BTreeMap::new(),
+                closure_size: None,
             })
         }
         profiles.sort_unstable_by(|p1, p2| p1.path.cmp(&p2.path));
         Ok(profiles)
     }
 
+    /// The generation the profile links to, from the digits of `<name>-N-link`.
+    /// The link is read as bytes, so that a target that isn't UTF-8 only
+    /// loses its active generation, with a warning, instead of failing discovery.
     fn read_active_gen(profile_path: &Utf8Path) -> Result<Option<u64>> {
-        if Self::can_read_file(profile_path) {
-            let link = profile_path.read_link_utf8()?;
-            let Some(name) = link.file_name() else { return Ok(None) };
-            let Some(generation) = name.rsplit('-').nth(1) else { return Ok(None) };
-            Ok(generation.parse().ok())
-        } else {
-            Ok(None)
+        if !Self::can_read_file(profile_path) {
+            return Ok(None);
+        }
+        let link = std::fs::read_link(profile_path)?;
+        let generation = link
+            .file_name()
+            .and_then(|name| name.as_bytes().rsplit(|b| *b == b'-').nth(1))
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| digits.parse().ok());
+        if generation.is_none() {
+            eprintln!(
+                "warning: couldn't tell the active generation of {} from its target {}",
+                profile_path,
+                link.display()
+            );
         }
+        Ok(generation)
     }
 
     fn can_read_file(path: &Utf8Path) -> bool {
@@ -231,22 +1249,26 @@ impl Display for GCRoots {
 
 impl GCRoots {
     fn fmt_plain(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        let mut written = false;
-        for profile in self.profiles.iter() {
-            for generation in profile.generations.values() {
-                if written {
-                    writeln!(f)?;
-                }
-                write!(f, "{}", generation)?;
-                written = true;
-            }
-        }
-        for gcroot in self.standalone.iter() {
-            if written {
+        let roots = self
+            .profiles
+            .iter()
+            .flat_map(|profile| profile.generations.values())
+            .chain(self.standalone.iter());
+        let width = self.sizes.then(|| {
+            roots
+                .clone()
+                .map(|gcroot| size::cell(gcroot.closure_size).len())
+                .max()
+                .unwrap_or(0)
+        });
+        for (index, gcroot) in roots.enumerate() {
+            if index != 0 {
                 writeln!(f)?;
             }
+            if let Some(width) = width {
+                write!(f, "{: >width$}  ", size::cell(gcroot.closure_size))?;
+            }
             write!(f, "{}", gcroot)?;
-            written = true;
         }
         Ok(())
     }
@@ -265,12 +1287,470 @@ impl GCRoots {
         if !self.standalone.is_empty() {
             write!(f, "\n\n")?;
         }
+        let width = self.standalone_size_width();
         for (index, standalone) in self.standalone.iter().enumerate() {
             if index != 0 {
                 writeln!(f)?;
             }
+            if let Some(width) = width {
+                write!(f, "{: >width$}  ", size::cell(standalone.closure_size))?;
+            }
             write!(f, "{}", standalone)?;
+            if standalone.node_type().ok() == Some(NodeType::Directory) {
+                for (link, target) in standalone
+                    .contained_links(&self.store_dirs)
+                    .unwrap_or_default()
+                {
+                    write!(f, "\n  {} -> {}", link, target)?;
+                }
+            }
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_dir() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        (dir, path)
+    }
+
+    /// Counts the lstats, all of them of a symlink owned by uid 1000.
+    struct CountingFs(std::cell::Cell<usize>);
+
+    impl Fs for CountingFs {
+        fn lstat(&self, _: &Utf8Path) -> std::result::Result<RootMetadata, MetadataError> {
+            self.0.set(self.0.get() + 1);
+            Ok(RootMetadata {
+                mtime: SystemTime::UNIX_EPOCH,
+                uid: 1000,
+                gid: 100,
+                node: NodeType::Symlink,
+                size: 17,
+            })
+        }
+    }
+
+    fn root(path: &str) -> GCRoot {
+        let target = Target::new(
+            Rc::from(Utf8Path::new("/nix/store/aaaa-a")),
+            &StoreDirs::default(),
+        );
+        GCRoot::new(Rc::from(Utf8Path::new(path)), target)
+    }
+
+    #[test]
+    fn metadata_is_read_once() {
+        let fs = CountingFs(std::cell::Cell::new(0));
+        let gcroot = root("/home/u/result");
+        assert_eq!(gcroot.metadata_with(&fs).unwrap().uid, 1000);
+        assert_eq!(gcroot.metadata_with(&fs).unwrap().size, 17);
+        assert_eq!(gcroot.modified(), Ok(SystemTime::UNIX_EPOCH));
+        assert_eq!(fs.0.get(), 1);
+    }
+
+    #[test]
+    fn cached_metadata_doesnt_affect_comparisons() {
+        let fs = CountingFs(std::cell::Cell::new(0));
+        let (read, unread) = (root("/home/u/result"), root("/home/u/result"));
+        read.metadata_with(&fs).unwrap();
+        assert_eq!(read, unread);
+    }
+
+    #[test]
+    fn metadata_errors_tell_missing_from_denied() {
+        let (_dir, dir) = temporary_dir();
+        assert_eq!(
+            root(dir.join("gone").as_str()).metadata(),
+            Err(MetadataError::NotFound)
+        );
+        let error = std::io::Error::from(ErrorKind::PermissionDenied);
+        assert_eq!(MetadataError::from(error), MetadataError::PermissionDenied);
+    }
+
+    #[test]
+    fn status_of_live_broken_and_missing_roots() {
+        let (_dir, dir) = temporary_dir();
+        let target = dir.join("target");
+        std::fs::write(&target, "").unwrap();
+        let live = dir.join("live");
+        std::os::unix::fs::symlink(&target, &live).unwrap();
+        let missing = dir.join("missing");
+        std::os::unix::fs::symlink(dir.join("deleted"), &missing).unwrap();
+        assert_eq!(RootStatus::check(&live, &target), RootStatus::Live);
+        assert_eq!(
+            RootStatus::check(&missing, &dir.join("deleted")),
+            RootStatus::MissingTarget
+        );
+        assert_eq!(
+            RootStatus::check(&dir.join("gone"), &target),
+            RootStatus::BrokenLink
+        );
+    }
+
+    #[test]
+    fn relative_targets_resolve_from_the_root() {
+        let (_dir, dir) = temporary_dir();
+        std::fs::write(dir.join("target"), "").unwrap();
+        let root = dir.join("root");
+        std::os::unix::fs::symlink("target", &root).unwrap();
+        assert_eq!(
+            RootStatus::check(&root, Utf8Path::new("target")),
+            RootStatus::Live
+        );
+    }
+
+    #[test]
+    fn status_is_only_checked_when_asked() {
+        let output = "/nonexistent/result -> /nix/store/aaaa-a\n";
+        let gcroots = GCRoots::from_print_roots_output(output).unwrap();
+        assert_eq!(gcroots.standalone()[0].status, RootStatus::Unchecked);
+        let mut listed = Vec::new();
+        let mut hidden = Vec::new();
+        let ignored = GCRoots::parse_nix_store_gc_output(output, &mut listed, &mut hidden);
+        let options = DiscoverOptions {
+            root_status: true,
+            ..DiscoverOptions::default()
+        };
+        let gcroots = GCRoots::from_listed(listed, hidden, ignored, &options).unwrap();
+        assert_eq!(gcroots.standalone()[0].status, RootStatus::BrokenLink);
+    }
+
+    fn split(line: &str) -> Option<(&str, &str)> {
+        GCRoots::split_at_arrow(line)
+    }
+
+    #[test]
+    fn arrows_split_path_and_target() {
+        assert_eq!(
+            split("/home/u/result -> /nix/store/aaaa-a"),
+            Some(("/home/u/result", "/nix/store/aaaa-a"))
+        );
+        assert_eq!(
+            split("  /home/u/result\t->   /nix/store/aaaa-a  "),
+            Some(("/home/u/result", "/nix/store/aaaa-a"))
+        );
+        assert_eq!(split("/home/u/result->/nix/store/aaaa-a"), None);
+        assert_eq!(split("warning: something happened"), None);
+    }
+
+    #[test]
+    fn arrows_in_the_root_path_stay_in_the_path() {
+        assert_eq!(
+            split("/home/u/a -> b/result -> /nix/store/aaaa-a"),
+            Some(("/home/u/a -> b/result", "/nix/store/aaaa-a"))
+        );
+    }
+
+    #[test]
+    fn arrows_in_a_non_store_target_stay_in_the_target() {
+        assert_eq!(
+            split("/home/u/result -> /srv/a -> b"),
+            Some(("/home/u/result", "/srv/a -> b"))
+        );
+    }
+
+    #[test]
+    fn hidden_and_ignored_lines() {
+        let output = "\
+            /proc/123/exe -> /nix/store/aaaa-bash\n\
+            {censored} -> /nix/store/bbbb-secret\n\
+            {memory:42} -> /nix/store/cccc-mapped\n\
+            warning: the group 'nixbld' specified in 'build-users-group' does not exist\n\
+            \n\
+            relative -> /nix/store/dddd-d\n\
+            /home/u/result -> /nix/store/eeee-e\n";
+        let gcroots = GCRoots::from_print_roots_output(output).unwrap();
+        let hidden = gcroots
+            .hidden_targets()
+            .map(|t| t.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            hidden,
+            [
+                "/nix/store/aaaa-bash",
+                "/nix/store/bbbb-secret",
+                "/nix/store/cccc-mapped"
+            ]
+        );
+        assert_eq!(
+            gcroots.ignored_lines(),
+            [
+                "warning: the group 'nixbld' specified in 'build-users-group' does not exist",
+                "relative -> /nix/store/dddd-d"
+            ]
+        );
+        assert_eq!(gcroots.standalone().len(), 1);
+        assert_eq!(&*gcroots.standalone()[0].path, "/home/u/result");
+    }
+
+    #[test]
+    fn generation_numbers_need_a_link_suffix() {
+        let generation = |path: &str| root(path).get_profile_gen();
+        assert_eq!(generation("/nix/var/nix/profiles/system-12-link"), Some(12));
+        assert_eq!(
+            generation("/nix/var/nix/profiles/my-profile-3-link"),
+            Some(3)
+        );
+        assert_eq!(generation("/nix/var/nix/profiles/system-12-lnk"), None);
+        assert_eq!(generation("/nix/var/nix/profiles/system-12"), None);
+        assert_eq!(generation("/nix/var/nix/profiles/system-link"), None);
+        assert_eq!(generation("/nix/var/nix/profiles/system-x-link"), None);
+        assert_eq!(generation("/nix/var/nix/profiles/system--link"), None);
+        assert_eq!(
+            root("/nix/var/nix/profiles/my-profile-3-link").get_profile_path(),
+            Some("/nix/var/nix/profiles/my-profile")
+        );
+    }
+
+    /// A profile in dir with generations 1 to 3, the second one active, and
+    /// the roots nix-store would list for them.
+    fn profile(dir: &Utf8Path) -> String {
+        let mut output = String::new();
+        for generation in 1..=3 {
+            let link = dir.join(format!("system-{generation}-link"));
+            std::os::unix::fs::symlink(dir, &link).unwrap();
+            output.push_str(&format!("{link} -> /nix/store/aaaa-system-{generation}\n"));
+        }
+        std::os::unix::fs::symlink("system-2-link", dir.join("system")).unwrap();
+        output
+    }
+
+    #[test]
+    fn generations_are_grouped_into_their_profile() {
+        let (_dir, dir) = temporary_dir();
+        let output = format!(
+            "{}{dir}/system-4-lnk -> /nix/store/bbbb-b\n\
+             {dir}/system-link -> /nix/store/cccc-c\n\
+             {dir}/other-1-link -> /nix/store/dddd-d\n",
+            profile(&dir)
+        );
+        let gcroots = GCRoots::from_print_roots_output(&output).unwrap();
+        let [profile] = gcroots.profiles() else {
+            panic!("expected one profile: {:?}", gcroots.profiles());
+        };
+        assert_eq!(profile.path, dir.join("system"));
+        assert_eq!(profile.active_generation, Some(2));
+        assert_eq!(
+            profile.generations.keys().copied().collect::<Vec<_>>(),
+            [1, 2, 3]
+        );
+        // Roots that don't fit the naming scheme, or whose profile doesn't
+        // exist, are standalone.
+        let standalone = gcroots
+            .standalone()
+            .iter()
+            .map(|gcroot| gcroot.path.file_name().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(standalone, ["other-1-link", "system-4-lnk", "system-link"]);
+    }
+
+    #[test]
+    fn ungrouped_generations_are_standalone() {
+        let (_dir, dir) = temporary_dir();
+        let output = profile(&dir);
+        let mut listed = Vec::new();
+        let mut hidden = Vec::new();
+        let ignored = GCRoots::parse_nix_store_gc_output(&output, &mut listed, &mut hidden);
+        let options = DiscoverOptions {
+            group: false,
+            ..DiscoverOptions::default()
+        };
+        let gcroots = GCRoots::from_listed(listed, hidden, ignored, &options).unwrap();
+        assert!(gcroots.profiles().is_empty());
+        assert_eq!(gcroots.standalone().len(), 3);
+        assert_eq!(gcroots.ungrouped().len(), 1);
+        let (profile, generation) = gcroots
+            .ungrouped_generation(&dir.join("system-3-link"))
+            .unwrap();
+        assert_eq!(
+            (profile.path.as_path(), generation),
+            (dir.join("system").as_path(), 3)
+        );
+    }
+
+    /// Roots of the profile in dir, and standalone ones of which b points at
+    /// the target of the first generation.
+    fn lookup_roots(dir: &Utf8Path) -> GCRoots {
+        let output = format!(
+            "{}/home/c/result -> /nix/store/cccc-c\n\
+             /home/b/result -> /nix/store/aaaa-system-1\n",
+            profile(dir)
+        );
+        GCRoots::from_print_roots_output(&output).unwrap()
+    }
+
+    #[test]
+    fn find_tells_generations_from_standalone_roots() {
+        let (_dir, dir) = temporary_dir();
+        let gcroots = lookup_roots(&dir);
+        match gcroots.find(&dir.join("system-3-link")) {
+            Some(RootRef::Generation {
+                profile,
+                generation,
+                gcroot,
+            }) => {
+                assert_eq!(profile.path, dir.join("system"));
+                assert_eq!(generation, 3);
+                assert_eq!(*gcroot.path, dir.join("system-3-link"));
+            }
+            other => panic!("expected generation 3: {other:?}"),
+        }
+        let standalone = gcroots.find(Utf8Path::new("/home/c/result"));
+        assert!(
+            matches!(standalone, Some(RootRef::Standalone(gcroot)) if gcroot.target.as_str() == "/nix/store/cccc-c")
+        );
+        assert!(gcroots.find(Utf8Path::new("/home/d/result")).is_none());
+        assert!(gcroots.find(&dir.join("system")).is_none());
+    }
+
+    #[test]
+    fn find_by_target_lists_generations_first() {
+        let (_dir, dir) = temporary_dir();
+        let gcroots = lookup_roots(&dir);
+        let paths = gcroots
+            .find_by_target(Utf8Path::new("/nix/store/aaaa-system-1"))
+            .iter()
+            .map(|root| root.gcroot().path.to_path_buf())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            paths,
+            [
+                dir.join("system-1-link"),
+                Utf8PathBuf::from("/home/b/result")
+            ]
+        );
+        assert!(gcroots
+            .find_by_target(Utf8Path::new("/nix/store/dddd-d"))
+            .is_empty());
+    }
+
+    #[test]
+    fn lookups_follow_retain_and_sort() {
+        let (_dir, dir) = temporary_dir();
+        let mut gcroots = lookup_roots(&dir);
+        let found = |gcroots: &GCRoots, path: &str| {
+            gcroots
+                .find(Utf8Path::new(path))
+                .map(|root| root.gcroot().path.to_string())
+        };
+        assert_eq!(
+            found(&gcroots, "/home/c/result").as_deref(),
+            Some("/home/c/result")
+        );
+        gcroots.sort(SortKey::Path, true, SortLocale::Byte);
+        assert_eq!(
+            found(&gcroots, "/home/b/result").as_deref(),
+            Some("/home/b/result")
+        );
+        assert_eq!(
+            found(&gcroots, "/home/c/result").as_deref(),
+            Some("/home/c/result")
+        );
+        let standalone_only = Filter {
+            kinds: crate::filter::RootKinds::Standalone,
+            ..Filter::default()
+        };
+        gcroots.retain(&standalone_only, SystemTime::now());
+        assert!(gcroots.find(&dir.join("system-1-link")).is_none());
+        let paths = gcroots
+            .find_by_target(Utf8Path::new("/nix/store/aaaa-system-1"))
+            .iter()
+            .map(|root| root.gcroot().path.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(paths, ["/home/b/result"]);
+    }
+
+    #[test]
+    fn active_generation_of_a_non_utf8_target() {
+        use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+        let (_dir, dir) = temporary_dir();
+        let link = |name: &[u8]| dir.as_std_path().join(OsStr::from_bytes(name));
+        std::os::unix::fs::symlink(&dir, link(b"syst\xffem-5-link")).unwrap();
+        std::os::unix::fs::symlink(link(b"syst\xffem-5-link"), dir.join("system")).unwrap();
+        assert_eq!(
+            GCRoots::read_active_gen(&dir.join("system")).unwrap(),
+            Some(5)
+        );
+        std::os::unix::fs::symlink(&dir, link(b"\xff-link")).unwrap();
+        std::os::unix::fs::symlink(link(b"\xff-link"), dir.join("other")).unwrap();
+        assert_eq!(GCRoots::read_active_gen(&dir.join("other")).unwrap(), None);
+    }
+
+    /// A source listing fixed output, or failing with an error.
+    struct FakeSource(
+        &'static str,
+        std::result::Result<&'static str, &'static str>,
+    );
+
+    impl Source for FakeSource {
+        fn name(&self) -> &str {
+            self.0
+        }
+
+        fn print_roots(&self) -> Result<String> {
+            self.1.map(str::to_string).map_err(|e| eyre::eyre!(e))
+        }
+    }
+
+    const LISTING: &str = "warning: stale\n/home/a/result -> /nix/store/aaaa-a\n";
+
+    #[test]
+    fn discovery_goes_on_past_failed_sources() {
+        let sources = [
+            &FakeSource("good", Ok(LISTING)) as &dyn Source,
+            &FakeSource("bad", Err("cannot connect to daemon")),
+        ];
+        let (gcroots, report) = GCRoots::discover(&sources, &DiscoverOptions::default()).unwrap();
+        assert_eq!(gcroots.standalone().len(), 1);
+        let outcomes = report
+            .sources
+            .iter()
+            .map(|source| (source.source.as_str(), &source.outcome, source.roots))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            outcomes,
+            [
+                ("good", &SourceOutcome::Ok, 1),
+                (
+                    "bad",
+                    &SourceOutcome::Failed {
+                        error: "cannot connect to daemon".to_string()
+                    },
+                    0
+                ),
+            ]
+        );
+        assert_eq!(report.sources[0].ignored_lines, 1);
+        assert!(report.incomplete());
+        assert_eq!(
+            report.to_string(),
+            "⚠ results may be incomplete: bad: cannot connect to daemon"
+        );
+    }
+
+    #[test]
+    fn discovery_is_complete_when_every_source_succeeds() {
+        let sources = [&FakeSource("good", Ok(LISTING)) as &dyn Source];
+        let (_, report) = GCRoots::discover(&sources, &DiscoverOptions::default()).unwrap();
+        assert!(!report.incomplete());
+        assert_eq!(report.to_string(), "");
+    }
+
+    #[test]
+    fn discovery_fails_when_every_source_fails() {
+        let sources = [
+            &FakeSource("first", Err("no nix-store")) as &dyn Source,
+            &FakeSource("second", Err("no input")),
+        ];
+        let error = GCRoots::discover(&sources, &DiscoverOptions::default()).unwrap_err();
+        assert_eq!(format!("{error:#}"), "all 2 sources failed: no nix-store");
+        let error = GCRoots::discover(&sources[1..], &DiscoverOptions::default()).unwrap_err();
+        assert_eq!(format!("{error:#}"), "no input");
+    }
+}
@@ -0,0 +1,24 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+
+use eyre::{Result, WrapErr};
+use nix::sys::signal::{self, SaFlags, SigAction, SigHandler, SigSet, Signal};
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+extern "C" fn handle_sigint(_: nix::libc::c_int) {
+    INTERRUPTED.store(true, Ordering::SeqCst);
+}
+
+/// Catches the first Ctrl-C instead of exiting, returning the flag it sets.
+/// A second Ctrl-C exits as usual.
+pub fn catch() -> Result<&'static AtomicBool> {
+    let action = SigAction::new(
+        SigHandler::Handler(handle_sigint),
+        SaFlags::SA_RESETHAND,
+        SigSet::empty(),
+    );
+    // The handler only stores to an atomic, which is async-signal-safe.
+    unsafe { signal::sigaction(Signal::SIGINT, &action) }
+        .wrap_err("couldn't install a Ctrl-C handler")?;
+    Ok(&INTERRUPTED)
+}
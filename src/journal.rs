@@ -0,0 +1,190 @@
+use std::{
+    fmt::Display,
+    fs::OpenOptions,
+    io::{BufRead, BufReader, ErrorKind, Write},
+    time::{Duration, SystemTime},
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    date::DateStyle,
+    plan::{count, DeleteReport},
+    state,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// How a destructive action went overall.
+pub enum Outcome {
+    /// Everything selected was removed.
+    Success,
+    /// Some roots were removed, others failed or were refused.
+    Partial,
+    /// Nothing that was selected could be removed.
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// A root removed by a destructive action.
+pub struct RemovedRoot {
+    pub path: Utf8PathBuf,
+    pub target: Utf8PathBuf,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// One destructive action performed by gcrs.
+pub struct Record {
+    pub timestamp: DateTime<Utc>,
+    /// Subcommand that performed the action.
+    pub command: String,
+    /// Full command line gcrs was run with.
+    pub args: Vec<String>,
+    pub removed: Vec<RemovedRoot>,
+    pub outcome: Outcome,
+    /// If garbage collection was run as part of the action.
+    pub gc: bool,
+}
+
+impl Record {
+    /// Creates a record of the roots removed according to the report.
+    pub fn new(command: &str, report: &DeleteReport) -> Self {
+        let outcome = match (report.success(), report.removed.is_empty()) {
+            (true, _) => Outcome::Success,
+            (false, false) => Outcome::Partial,
+            (false, true) => Outcome::Failed,
+        };
+        Record {
+            timestamp: Utc::now(),
+            command: command.to_string(),
+            args: std::env::args().collect(),
+            removed: report
+                .removed
+                .iter()
+                .map(|entry| RemovedRoot {
+                    path: entry.path.clone(),
+                    target: entry.target.clone(),
+                })
+                .collect(),
+            outcome,
+            gc: false,
+        }
+    }
+}
+
+/// Append-only log of every destructive action, one JSON record per line.
+pub struct Journal {
+    path: Utf8PathBuf,
+}
+
+impl Journal {
+    /// The journal at `$XDG_STATE_HOME/gcrs/journal.jsonl`, see [state::dir].
+    pub fn open() -> Result<Self> {
+        Ok(Journal {
+            path: state::dir(state::Kind::State)?.join("journal.jsonl"),
+        })
+    }
+
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    /// Appends a record as a single line, flushed to disk before returning.
+    pub fn append(&self, record: &Record) -> Result<()> {
+        state::create_dir(state::Kind::State)?;
+        let mut line = serde_json::to_string(record)?;
+        line.push('\n');
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .wrap_err_with(|| format!("couldn't open journal {}", self.path))?;
+        file.write_all(line.as_bytes())?;
+        file.sync_data()?;
+        Ok(())
+    }
+
+    /// Reads every record newer than `since` ago, or all of them.
+    /// Lines that can't be parsed are skipped with a warning on stderr.
+    pub fn read(&self, since: Option<Duration>) -> Result<Vec<Record>> {
+        let file = match std::fs::File::open(&self.path) {
+            Ok(file) => file,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => {
+                return Err(e).wrap_err_with(|| format!("couldn't open journal {}", self.path))
+            }
+        };
+        let cutoff = since
+            .and_then(|since| chrono::Duration::from_std(since).ok())
+            .and_then(|since| Utc::now().checked_sub_signed(since));
+        let mut records = Vec::new();
+        for (index, line) in BufReader::new(file).lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str::<Record>(&line) {
+                Ok(record) if cutoff.is_some_and(|cutoff| record.timestamp < cutoff) => {}
+                Ok(record) => records.push(record),
+                Err(e) => eprintln!(
+                    "warning: skipping corrupt line {} of {}: {}",
+                    index + 1,
+                    self.path,
+                    e
+                ),
+            }
+        }
+        Ok(records)
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Outcome::Success => write!(f, "success"),
+            Outcome::Partial => write!(f, "partial"),
+            Outcome::Failed => write!(f, "failed"),
+        }
+    }
+}
+
+impl Record {
+    /// Displays the record with its timestamp rendered in the given style.
+    pub fn display<'a>(&'a self, dates: &'a DateStyle) -> impl Display + 'a {
+        RecordDisplay {
+            record: self,
+            dates,
+        }
+    }
+}
+
+struct RecordDisplay<'a> {
+    record: &'a Record,
+    dates: &'a DateStyle,
+}
+
+impl Display for RecordDisplay<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let record = self.record;
+        write!(
+            f,
+            "{} {}: {}, removed {}",
+            self.dates
+                .render(record.timestamp.into(), SystemTime::now()),
+            record.command,
+            record.outcome,
+            count(record.removed.len(), "root", "roots"),
+        )?;
+        if record.gc {
+            write!(f, ", ran gc")?;
+        }
+        write!(f, "\n  {}", record.args.join(" "))?;
+        for root in record.removed.iter() {
+            write!(f, "\n  - {} -> {}", root.path, root.target)?;
+        }
+        Ok(())
+    }
+}
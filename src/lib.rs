@@ -1,19 +1,1410 @@
-use args::Command;
+use std::{
+    io::IsTerminal,
+    os::unix::process::CommandExt,
+    process::{ExitCode, Stdio},
+    time::{Duration, SystemTime},
+};
+
+use args::{ChannelsCommand, Command, JournalCommand, PolicyCommand};
+use cache::CountCache;
+use candidates::Candidates;
+use channel::Channels;
+use check::Thresholds;
 use clap::Parser;
+use config::Config;
+use date::DateStyle;
+use dead::DeadPaths;
+use eyre::{eyre, WrapErr};
+use filter::{Filter, Glob, RootKinds};
 
-use gcroot::GCRoots;
+use camino::{Utf8Path, Utf8PathBuf};
+use gcroot::{DiscoverOptions, GCRoots, GenerationSelector};
+use journal::{Journal, Record};
+use lock::Lock;
+use nix_config::GcSettings;
+use output::{out, outln};
+use pin::{Pin, Pins};
+use plan::{DeleteReport, ExecuteOptions, Plan, PlanEntry};
+use policy::Policy;
+use render::{Column, Decorations, Format, IconSet, Icons};
+use report::{Report, ReportOptions, Section};
+use retention::RetentionPolicy;
+use scope::UserScope;
+use size::Estimate;
+use sort::{SortKey, SortLocale};
+use source::{DiscoveryReport, Filesystem, InputFile, NixStore, Source, SourceKind};
+use status::Event;
+use strategy::Strategy;
+use summary::SummaryOptions;
+use timing::Phase;
+use trend::{History, Snapshot, Trend};
+use walk::WalkOptions;
 
 mod args;
+pub mod audit;
+mod browse;
+pub mod cache;
+pub mod candidates;
+pub mod channel;
+pub mod check;
+mod clipboard;
+mod config;
+mod date;
+pub mod dead;
+pub mod diff;
+mod doctor;
+mod duration;
+mod editor;
+#[cfg(feature = "ffi")]
+pub mod ffi;
+pub mod filter;
+pub mod fs;
 pub mod gcroot;
+mod interrupt;
+pub mod journal;
+mod lock;
+pub mod nix_command;
+mod nix_config;
+mod nix_profile;
+mod notify;
+mod output;
+pub mod pin;
+pub mod plan;
+mod policy;
+mod prompt;
+mod render;
+pub mod report;
+pub mod retention;
+pub mod scope;
+pub mod size;
+pub mod sort;
+pub mod source;
+mod state;
+pub mod stats;
+pub mod status;
+pub mod strategy;
+pub mod summary;
+pub mod timing;
+pub mod trend;
+pub mod user;
+pub mod walk;
+pub mod well_known;
+mod xdg;
+
+/// Exit code of destructive actions stopped with Ctrl-C.
+const EXIT_INTERRUPTED: u8 = 3;
+/// Exit code of `check` when any threshold is exceeded.
+const EXIT_VIOLATIONS: u8 = 4;
 
-pub fn run() -> eyre::Result<()> {
+pub fn run() -> eyre::Result<ExitCode> {
     let args = args::Args::parse();
-    match args.command {
-        Some(Command::Print { plain }) => match plain {
-            true => println!("{}", GCRoots::from_nix_store_command()?),
-            false => println!("{:#}", GCRoots::from_nix_store_command()?),
+    match (args.status_fd, args.status_json) {
+        (Some(fd), _) => status::to_fd(fd)?,
+        (_, true) => status::to_stderr()?,
+        _ => {}
+    }
+    let config = Config::load()?;
+    let globals = Globals {
+        sort: args.sort,
+        reverse: args.reverse,
+        sort_locale: args.sort_locale,
+        yes: args.yes,
+        notify: args.notify,
+        dates: DateStyle {
+            format: args
+                .date_format
+                .or(config.display.date_format)
+                .unwrap_or_default(),
+            utc: args.utc || config.display.utc,
         },
-        None => todo!(),
+        decorations: Decorations {
+            icons: args.icons.then(|| Icons {
+                set: match args.ascii {
+                    true => IconSet::Ascii,
+                    false => IconSet::detect(),
+                },
+                stale_after: config.check.max_age,
+            }),
+            hyperlinks: args.hyperlinks.enabled(),
+        },
+        execute: ExecuteOptions {
+            jobs: args.jobs.get(),
+            force_non_symlink: args.force_non_symlink,
+        },
+        journal: config.journal.enabled && !args.no_journal,
+        store_dirs: args.store_dirs,
+        timings: args.timings,
+        wait: args.wait,
+        inputs: args.inputs,
+        source: args.source,
+        group: config.discovery.group && !args.no_group,
+        retention: config.retention.policy(),
+        retention_keep_last: config.retention.keep_last,
+        report_sections: config.report.sections,
+        users: args.users,
+    };
+    let policy = Policy {
+        check: config.check,
+        retention: config.retention,
+    };
+    let code = command(args.command, policy, &globals);
+    if globals.timings {
+        eprintln!("{}", timing::timings());
+    }
+    code
+}
+
+/// Runs the subcommand, thresholds of check fall back to the local policy.
+fn command(command: Option<Command>, policy: Policy, globals: &Globals) -> eyre::Result<ExitCode> {
+    let config = &policy.check;
+    match command {
+        Some(Command::Print {
+            plain,
+            json,
+            format,
+            columns,
+            sizes,
+            profile,
+            user,
+            standalone_only,
+            profiles_only,
+            older_than,
+            dead,
+        }) => {
+            let format = match (plain, json) {
+                (true, _) => Format::Plain,
+                (_, true) => Format::Json,
+                _ => format,
+            };
+            let filter = Filter {
+                profile: profile.as_deref().map(Glob::new),
+                user,
+                kinds: match (standalone_only, profiles_only) {
+                    (true, _) => RootKinds::Standalone,
+                    (_, true) => RootKinds::Generations,
+                    _ => RootKinds::All,
+                },
+                older_than,
+                dead,
+            };
+            print(globals, format, columns, sizes, &filter)?
+        }
+        Some(Command::Check {
+            max_standalone,
+            max_age,
+            max_generations,
+            json,
+        }) => {
+            let thresholds = Thresholds {
+                max_standalone: max_standalone.or(config.max_standalone),
+                max_age: max_age.or(config.max_age),
+                max_generations: max_generations.or(config.max_generations),
+            };
+            return check(globals, &thresholds, json);
+        }
+        Some(Command::Report {
+            sections,
+            json,
+            markdown,
+            largest,
+            max_generations,
+            max_age,
+            sample,
+        }) => {
+            let mut sections = sections
+                .or(globals.report_sections.clone())
+                .unwrap_or_else(|| report::DEFAULT_SECTIONS.to_vec());
+            let mut seen = std::collections::BTreeSet::new();
+            sections.retain(|section| seen.insert(*section));
+            let options = ReportOptions {
+                sections,
+                largest,
+                max_generations: max_generations
+                    .or(config.max_generations)
+                    .unwrap_or(report::DEFAULT_MAX_GENERATIONS),
+                stale_after: max_age
+                    .or(config.max_age)
+                    .unwrap_or(report::DEFAULT_STALE_AFTER),
+                sample,
+            };
+            report(globals, &options, json, markdown)?
+        }
+        Some(Command::Doctor) => return Ok(doctor()),
+        Some(Command::Count {
+            standalone,
+            generations,
+            profiles,
+            stale,
+            older_than,
+            nonzero_exit,
+            cached,
+            max_age,
+            refresh_async,
+            refresh_cache,
+            sample,
+        }) => {
+            if let (Some(size), Some(older_than)) = (sample, older_than) {
+                return count_stale_sampled(globals, older_than, size, nonzero_exit);
+            }
+            let what = match (standalone, generations, profiles, stale, older_than) {
+                (true, _, _, _, _) => Counted::Standalone,
+                (_, true, _, _, _) => Counted::Generations,
+                (_, _, true, _, _) => Counted::Profiles,
+                (_, _, _, true, Some(older_than)) => Counted::Stale { older_than },
+                _ => Counted::All,
+            };
+            let source = match (cached, refresh_cache) {
+                (true, _) => CountSource::Cached {
+                    max_age,
+                    refresh_async,
+                },
+                (_, true) => CountSource::RefreshCache,
+                _ => CountSource::Discover,
+            };
+            return count(globals, what, source, nonzero_exit);
+        }
+        Some(Command::Clean {
+            older_than,
+            non_store,
+            keep_monthly,
+            keep_last,
+            keep_since,
+            free,
+            estimate,
+            strategy,
+            dry_run,
+            emit_plan,
+            interactive,
+            edit,
+            show_dead,
+            show_dead_limit,
+            recursive,
+            confirm_across_runs,
+            grace,
+        }) => {
+            let mode = match (dry_run, emit_plan, interactive, edit) {
+                (true, _, _, _) => CleanMode::DryRun,
+                (_, Some(path), _, _) => CleanMode::EmitPlan(path),
+                (_, _, true, _) => CleanMode::Interactive,
+                (_, _, _, true) => CleanMode::Edit { yes: globals.yes },
+                _ => CleanMode::Confirm { yes: globals.yes },
+            };
+            let show_dead = show_dead.then_some(show_dead_limit);
+            let with_keep_last = keep_last.or(globals.retention_keep_last).unwrap_or(0);
+            let retention = match (keep_monthly, keep_since, keep_last) {
+                (Some(months), _, _) => Some(RetentionPolicy::KeepMonthly {
+                    months,
+                    keep_last: with_keep_last,
+                }),
+                (None, Some(since), _) => Some(RetentionPolicy::KeepSince {
+                    since,
+                    keep_last: with_keep_last,
+                }),
+                (None, None, Some(n)) => Some(RetentionPolicy::KeepLast(n)),
+                (None, None, None) => None,
+            };
+            let options = CleanOptions {
+                older_than,
+                non_store,
+                retention,
+                free: free.map(|bytes| (bytes, estimate)),
+                strategy,
+                recursive,
+                show_dead,
+                grace: confirm_across_runs.then_some(grace),
+            };
+            return notify_error(globals, "clean", clean(globals, &options, mode));
+        }
+        Some(Command::Delete {
+            path,
+            generations,
+            older_than,
+            dry_run,
+        }) => {
+            let selector = match (generations.is_empty(), older_than) {
+                (false, _) => Some(GenerationSelector::Numbers(generations)),
+                (true, Some(older_than)) => Some(GenerationSelector::OlderThan(older_than)),
+                (true, None) => None,
+            };
+            return notify_error(
+                globals,
+                "delete",
+                delete(globals, &path, selector.as_ref(), dry_run),
+            );
+        }
+        Some(Command::ApplyPlan { plan }) => {
+            return notify_error(globals, "apply-plan", apply_plan(globals, &plan))
+        }
+        Some(Command::Journal {
+            command: JournalCommand::Show { since },
+        }) => journal_show(globals, since)?,
+        Some(Command::Channels { command: None }) => channels(globals)?,
+        Some(Command::Channels {
+            command: Some(ChannelsCommand::Prune { keep_last, dry_run }),
+        }) => {
+            return notify_error(
+                globals,
+                "channels prune",
+                channels_prune(globals, keep_last, dry_run),
+            )
+        }
+        Some(Command::PinGeneration {
+            profile,
+            generation,
+            reason,
+            list,
+            remove,
+        }) => match (list, profile, generation) {
+            (true, _, _) => pin_list()?,
+            (false, Some(profile), Some(generation)) if remove => {
+                unpin_generation(&profile, generation)?
+            }
+            (false, Some(profile), Some(generation)) => {
+                pin_generation(globals, &profile, generation, reason)?
+            }
+            _ => unreachable!("clap requires a profile and generation without --list"),
+        },
+        Some(Command::AuditLinks { dirs, register }) => audit_links(globals, dirs, register)?,
+        Some(Command::Trend { since, json }) => trend(globals, since, json)?,
+        Some(Command::Policy {
+            command: PolicyCommand::Export,
+        }) => out!("{}", policy.export()),
+        Some(Command::Policy {
+            command: PolicyCommand::Import { file, overwrite },
+        }) => policy_import(&file, overwrite)?,
+        None => {
+            return match std::io::stdin().is_terminal() && std::io::stdout().is_terminal() {
+                true => browse(globals),
+                false => print(globals, Format::Grouped, None, false, &Filter::default())
+                    .map(|()| ExitCode::SUCCESS),
+            }
+        }
+    }
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Flags shared by every subcommand.
+struct Globals {
+    sort: Option<SortKey>,
+    reverse: bool,
+    sort_locale: SortLocale,
+    yes: bool,
+    /// Show a desktop notification when a destructive action finishes.
+    notify: bool,
+    dates: DateStyle,
+    /// Icons and hyperlinks in the grouped and table formats.
+    decorations: Decorations,
+    execute: ExecuteOptions,
+    /// Record destructive actions in the journal.
+    journal: bool,
+    /// Store directories besides the default one.
+    store_dirs: Vec<Utf8PathBuf>,
+    /// Report how long each phase took.
+    timings: bool,
+    /// Wait for the [Lock] instead of failing if another run holds it.
+    wait: bool,
+    /// Files to read roots from instead of nix-store.
+    inputs: Vec<Utf8PathBuf>,
+    /// Where roots are discovered from without inputs.
+    source: SourceKind,
+    /// Group generations into profiles.
+    group: bool,
+    /// Generations clean keeps from its selection, from the config.
+    retention: Option<RetentionPolicy>,
+    /// Default of clean --keep-last.
+    retention_keep_last: Option<usize>,
+    /// Sections of report without --sections, from the config.
+    report_sections: Option<Vec<Section>>,
+    /// Other users whose roots may be removed when running as root.
+    users: Option<UserScope>,
+}
+
+impl Globals {
+    /// Global arguments changing which roots are discovered, passed on to
+    /// the background refresh of count and part of its cache key.
+    fn discovery_args(&self) -> Vec<String> {
+        let mut args = Vec::new();
+        for input in &self.inputs {
+            args.extend(["--input".to_string(), input.to_string()]);
+        }
+        if self.source == SourceKind::Filesystem {
+            args.extend(["--source".to_string(), "filesystem".to_string()]);
+        }
+        for store_dir in &self.store_dirs {
+            args.extend(["--store-dir".to_string(), store_dir.to_string()]);
+        }
+        if !self.group {
+            args.push("--no-group".to_string());
+        }
+        args
+    }
+}
+
+/// Discovers GCRoots and orders them as requested,
+/// warning on stderr if some source failed.
+fn discover(globals: &Globals) -> eyre::Result<(GCRoots, DiscoveryReport)> {
+    discover_with(globals, DiscoverOptions::default())
+}
+
+/// Same as [discover] but only does the work requested by options,
+/// whose store directories and grouping come from globals.
+fn discover_with(
+    globals: &Globals,
+    options: DiscoverOptions,
+) -> eyre::Result<(GCRoots, DiscoveryReport)> {
+    let options = DiscoverOptions {
+        store_dirs: globals.store_dirs.clone(),
+        group: globals.group,
+        ..options
+    };
+    let inputs = globals
+        .inputs
+        .iter()
+        .map(|path| InputFile::new(path.clone()))
+        .collect::<Vec<_>>();
+    let filesystem = Filesystem::new(
+        doctor::state_dir(),
+        gcroot::StoreDirs::new(globals.store_dirs.iter().cloned()),
+    );
+    let sources = match (inputs.is_empty(), globals.source) {
+        (true, SourceKind::Command) => vec![&NixStore as &dyn Source],
+        (true, SourceKind::Filesystem) => vec![&filesystem as &dyn Source],
+        (false, _) => inputs.iter().map(|input| input as &dyn Source).collect(),
+    };
+    status::emit(&Event::DiscoveryStarted);
+    let (mut gcroots, report) = GCRoots::discover(&sources, &options)?;
+    let summary = gcroots.summary(SummaryOptions::default());
+    status::emit(&Event::DiscoveryFinished {
+        roots: summary.roots(),
+        profiles: summary.profiles,
+        standalone: summary.standalone,
+        failed_sources: report.failed().count(),
+    });
+    if report.incomplete() {
+        eprintln!("{}", report);
+    }
+    // Sizes are only known once gathered, after discovery.
+    if globals.sort == Some(SortKey::Size) {
+        gcroots.gather_sizes()?;
+    }
+    let locale = globals.sort_locale;
+    match globals.sort {
+        Some(key) => gcroots.sort(key, globals.reverse, locale),
+        None if globals.reverse || locale != SortLocale::Byte => {
+            gcroots.sort(SortKey::Path, globals.reverse, locale)
+        }
+        None => {}
+    }
+    Ok((gcroots, report))
+}
+
+/// Restores the byte order of the text sort keys for JSON output,
+/// which doesn't follow --sort-locale.
+fn byte_order(globals: &Globals, gcroots: &mut GCRoots) {
+    if globals.sort_locale != SortLocale::Byte {
+        let key = globals.sort.unwrap_or(SortKey::Path);
+        gcroots.sort(key, globals.reverse, SortLocale::Byte);
+    }
+}
+
+fn print(
+    globals: &Globals,
+    format: Format,
+    columns: Option<Vec<Column>>,
+    sizes: bool,
+    filter: &Filter,
+) -> eyre::Result<()> {
+    if columns.is_some() && !matches!(format, Format::Table | Format::Csv) {
+        return Err(eyre!("--columns only applies to the table and csv formats"));
+    }
+    if sizes && format == Format::Plain {
+        return Err(eyre!(
+            "the plain format only has paths and targets, use --sizes with another format"
+        ));
+    }
+    if format == Format::Plain && std::io::stdout().is_terminal() {
+        eprintln!(
+            "note: the plain format is meant for scripts, \
+            drop --plain for roots grouped by profile"
+        );
+    }
+    let columns = columns.unwrap_or_else(|| render::DEFAULT_COLUMNS.to_vec());
+    let shows_status = match format {
+        Format::Grouped | Format::Json => true,
+        Format::Table | Format::Csv => columns.contains(&Column::Status),
+        Format::Plain => false,
+    };
+    let options = DiscoverOptions {
+        root_status: shows_status || filter.dead || globals.decorations.icons.is_some(),
+        ..DiscoverOptions::default()
+    };
+    let (mut gcroots, _) = discover_with(globals, options)?;
+    gcroots.retain(filter, SystemTime::now());
+    let size_column =
+        matches!(format, Format::Table | Format::Csv) && columns.contains(&Column::Size);
+    if sizes || size_column {
+        gcroots.gather_sizes()?;
+    }
+    if format == Format::Json {
+        byte_order(globals, &mut gcroots);
+    }
+    let output = timing::time(Phase::Rendering, || match format {
+        Format::Grouped => Ok(render::grouped(&gcroots, &globals.decorations)),
+        Format::Plain => Ok(render::plain(&gcroots)),
+        Format::Table => Ok(render::table(
+            &gcroots,
+            &columns,
+            &globals.dates,
+            &globals.decorations,
+        )),
+        Format::Csv => Ok(render::csv(&gcroots, &columns, &globals.dates)),
+        Format::Json => pretty_json(globals, &render::json(&gcroots)),
+    })?;
+    match format {
+        // Plain output ends every line itself, so that no roots print nothing.
+        Format::Plain => out!("{}", output),
+        _ => outln!("{}", output),
+    }
+    Ok(())
+}
+
+fn check(globals: &Globals, thresholds: &Thresholds, json: bool) -> eyre::Result<ExitCode> {
+    if thresholds.is_empty() {
+        return Err(eyre!(
+            "no thresholds given, pass --max-standalone, --max-age or --max-generations \
+            or set them in the [check] section of the config file"
+        ));
+    }
+    let (mut gcroots, report) = discover(globals)?;
+    if json {
+        byte_order(globals, &mut gcroots);
+    }
+    let violations = thresholds.check(&gcroots, SystemTime::now());
+    if json {
+        let output = serde_json::json!({
+            "violations": violations,
+            "summary": gcroots.summary(SummaryOptions {
+                metadata: true,
+                ..SummaryOptions::default()
+            }),
+            "discovery": report,
+        });
+        outln!("{}", pretty_json(globals, &output)?);
+    } else {
+        for violation in violations.iter() {
+            outln!("{}", violation);
+        }
+    }
+    match violations.is_empty() {
+        true => Ok(ExitCode::SUCCESS),
+        false => Ok(ExitCode::from(EXIT_VIOLATIONS)),
+    }
+}
+
+#[derive(serde::Serialize)]
+/// A JSON object with the phases that ran so far added at the end.
+struct WithTimings<'a, T: serde::Serialize> {
+    #[serde(flatten)]
+    output: &'a T,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    timings: Option<timing::Timings>,
+}
+
+/// Pretty-prints a JSON object, with the phases that ran so far under
+/// `"timings"` if --timings was given.
+fn pretty_json(globals: &Globals, output: &impl serde::Serialize) -> eyre::Result<String> {
+    let output = WithTimings {
+        output,
+        timings: globals.timings.then(timing::timings),
+    };
+    Ok(serde_json::to_string_pretty(&output)?)
+}
+
+fn report(
+    globals: &Globals,
+    options: &ReportOptions,
+    json: bool,
+    markdown: bool,
+) -> eyre::Result<()> {
+    let needs = report::Needs::of(&options.sections);
+    let discover_options = DiscoverOptions {
+        root_status: needs.status,
+        ..DiscoverOptions::default()
+    };
+    let (mut gcroots, _) = discover_with(globals, discover_options)?;
+    if needs.sizes {
+        gcroots.gather_sizes()?;
+    }
+    let report = Report::build(&gcroots, options, SystemTime::now());
+    match (json, markdown) {
+        (true, _) => outln!("{}", pretty_json(globals, &report)?),
+        (_, true) => out!("{}", report.markdown()),
+        _ => out!("{}", report),
+    }
+    Ok(())
+}
+
+fn doctor() -> ExitCode {
+    let diagnoses = doctor::diagnose();
+    for diagnosis in diagnoses.iter() {
+        outln!("{}", diagnosis);
+    }
+    match diagnoses.iter().any(|d| d.status == doctor::Status::Fail) {
+        true => ExitCode::FAILURE,
+        false => ExitCode::SUCCESS,
+    }
+}
+
+/// What the count subcommand counts.
+enum Counted {
+    All,
+    Standalone,
+    Generations,
+    Profiles,
+    Stale { older_than: Duration },
+}
+
+impl Counted {
+    /// Arguments of the count subcommand counting this.
+    fn args(&self) -> Vec<String> {
+        let flag = match self {
+            Counted::All => return vec!["count".to_string()],
+            Counted::Standalone => "--standalone",
+            Counted::Generations => "--generations",
+            Counted::Profiles => "--profiles",
+            Counted::Stale { older_than } => {
+                return ["count", "--stale", "--older-than"]
+                    .map(String::from)
+                    .into_iter()
+                    .chain([older_than.as_secs().to_string()])
+                    .collect()
+            }
+        };
+        vec!["count".to_string(), flag.to_string()]
+    }
+}
+
+/// Where count gets the number from.
+enum CountSource {
+    Discover,
+    /// The cache if the count there is at most max_age old.
+    Cached {
+        max_age: Duration,
+        /// Serve an outdated count and update the cache in the background.
+        refresh_async: bool,
+    },
+    /// Discover and update the cache without printing.
+    RefreshCache,
+}
+
+fn count(
+    globals: &Globals,
+    what: Counted,
+    source: CountSource,
+    nonzero_exit: bool,
+) -> eyre::Result<ExitCode> {
+    let args = globals
+        .discovery_args()
+        .into_iter()
+        .chain(what.args())
+        .collect::<Vec<_>>();
+    let key = args.join(" ");
+    let count = match source {
+        CountSource::Discover => count_roots(globals, what)?,
+        CountSource::RefreshCache => {
+            CountCache::open()?.set(&key, count_roots(globals, what)?)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        CountSource::Cached {
+            max_age,
+            refresh_async,
+        } => {
+            let cache = CountCache::open()?;
+            match cache.get(&key) {
+                Some(cached) if CountCache::is_fresh(&cached, max_age) => cached.count,
+                Some(cached) if refresh_async => {
+                    spawn_refresh(&args)?;
+                    cached.count
+                }
+                _ => {
+                    let count = count_roots(globals, what)?;
+                    if let Err(e) = cache.set(&key, count) {
+                        eprintln!("warning: couldn't update the count cache: {e}");
+                    }
+                    count
+                }
+            }
+        }
+    };
+    outln!("{}", count);
+    match nonzero_exit && count == 0 {
+        true => Ok(ExitCode::FAILURE),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// Starts a detached gcrs updating the cached count, which outlives this process.
+fn spawn_refresh(args: &[String]) -> eyre::Result<()> {
+    std::process::Command::new(std::env::current_exe()?)
+        .args(args)
+        .arg("--refresh-cache")
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .process_group(0)
+        .spawn()
+        .wrap_err("couldn't start a background refresh of the count cache")?;
+    Ok(())
+}
+
+fn count_roots(globals: &Globals, what: Counted) -> eyre::Result<usize> {
+    let options = DiscoverOptions {
+        active_generations: matches!(what, Counted::Stale { .. }),
+        ..DiscoverOptions::default()
+    };
+    let (gcroots, _) = discover_with(globals, options)?;
+    let summary = gcroots.summary(SummaryOptions::default());
+    let count = match what {
+        Counted::All => summary.roots(),
+        Counted::Standalone => summary.standalone,
+        Counted::Generations => summary.generations,
+        Counted::Profiles => summary.profiles,
+        Counted::Stale { older_than } => {
+            let now = SystemTime::now();
+            stale_candidates(&gcroots)
+                .into_iter()
+                .filter(|gcroot| is_stale(gcroot, older_than, now))
+                .count()
+        }
+    };
+    Ok(count)
+}
+
+/// Estimates the number of stale roots from the mtimes of a sample of them.
+fn count_stale_sampled(
+    globals: &Globals,
+    older_than: Duration,
+    size: usize,
+    nonzero_exit: bool,
+) -> eyre::Result<ExitCode> {
+    let (gcroots, _) = discover(globals)?;
+    let now = SystemTime::now();
+    let sample = stats::sample(stale_candidates(&gcroots), size, |gcroot| {
+        gcroot.path.as_str()
+    });
+    let stale = sample
+        .items
+        .iter()
+        .filter(|gcroot| is_stale(gcroot, older_than, now))
+        .count();
+    let estimate = sample.estimate_count(stale);
+    outln!("{}", estimate);
+    match nonzero_exit && estimate.value == 0.0 {
+        true => Ok(ExitCode::FAILURE),
+        false => Ok(ExitCode::SUCCESS),
+    }
+}
+
+/// Standalone roots and inactive generations, which can become stale.
+fn stale_candidates(gcroots: &GCRoots) -> Vec<&gcroot::GCRoot> {
+    let inactive_generations = gcroots.profiles().iter().flat_map(|profile| {
+        profile
+            .generations
+            .iter()
+            .filter(|(id, _)| profile.active_generation != Some(**id))
+            .map(|(_, gcroot)| gcroot)
+    });
+    inactive_generations.chain(gcroots.standalone()).collect()
+}
+
+fn is_stale(gcroot: &gcroot::GCRoot, older_than: Duration, now: SystemTime) -> bool {
+    gcroot
+        .modified()
+        .ok()
+        .and_then(|mtime| now.duration_since(mtime).ok())
+        .is_some_and(|age| age > older_than)
+}
+
+/// What clean does with the plan.
+enum CleanMode {
+    /// Only print it.
+    DryRun,
+    /// Write it to a file.
+    EmitPlan(Utf8PathBuf),
+    /// Ask about every entry, then confirm the chosen ones.
+    Interactive,
+    /// Let the user drop entries in an editor, then confirm the rest unless yes is set.
+    Edit { yes: bool },
+    /// Confirm the whole plan, unless yes is set.
+    Confirm { yes: bool },
+}
+
+/// What clean selects and how it presents the plan.
+struct CleanOptions {
+    older_than: Option<Duration>,
+    /// Also select roots pointing outside the store.
+    non_store: bool,
+    /// Select the generations this doesn't keep instead.
+    retention: Option<RetentionPolicy>,
+    /// Bytes to free and how to estimate what removing a root frees.
+    free: Option<(u64, Estimate)>,
+    strategy: Option<Strategy>,
+    /// Also select directory roots.
+    recursive: bool,
+    /// Some(limit) to list the store paths that become garbage.
+    show_dead: Option<usize>,
+    /// Some(grace) to only remove roots selected by an earlier run, see [Candidates].
+    grace: Option<Duration>,
+}
+
+fn clean(globals: &Globals, options: &CleanOptions, mode: CleanMode) -> eyre::Result<ExitCode> {
+    let CleanOptions {
+        older_than,
+        non_store,
+        retention,
+        free,
+        strategy,
+        recursive,
+        show_dead,
+        grace,
+    } = *options;
+    let (gcroots, _) = discover(globals)?;
+    let now = SystemTime::now();
+    let pins = Pins::open()?;
+    let (mut plan, mut skipped) = match retention {
+        Some(policy) => Plan::select_retained(&gcroots, policy, |_| true, now, &pins),
+        None => Plan::select(&gcroots, older_than, non_store, now, recursive, &pins),
+    };
+    if let (None, Some(policy)) = (retention, globals.retention) {
+        skipped.extend(plan.retain(&gcroots, policy, now));
+    }
+    plan.entries = scope_users(globals, plan.entries);
+    for skipped in skipped.iter() {
+        outln!("skipping {}", skipped);
+    }
+    let strategy = strategy.or(free.map(|_| Strategy::default()));
+    let needs = strategy.map(Strategy::needs).unwrap_or_default();
+    let targets = plan
+        .entries
+        .iter()
+        .map(|entry| entry.target.to_path_buf())
+        .collect::<Vec<_>>();
+    let targets = targets.iter().map(|t| t.as_path()).collect::<Vec<_>>();
+    let outside_store = plan
+        .entries
+        .iter()
+        .filter(|entry| !gcroots.store_dirs().contains(&entry.target))
+        .count();
+    if outside_store > 0
+        && (free.is_some() || needs.sizes || needs.substitutes || show_dead.is_some())
+    {
+        eprintln!(
+            "warning: {} outside the store, left out of size and closure queries",
+            plan::count(outside_store, "root points", "roots point")
+        );
+    }
+    if matches!(free, Some((_, Estimate::NarSize))) || needs.sizes {
+        let sizes = size::nar_sizes(&targets, gcroots.store_dirs())?;
+        for entry in plan.entries.iter_mut() {
+            entry.size = sizes.get(&entry.target).copied();
+        }
+    }
+    if needs.substitutes {
+        let substitutable = size::substitutable(&targets, gcroots.store_dirs())?;
+        for entry in plan.entries.iter_mut() {
+            entry.substitutable = substitutable.get(&entry.target).copied();
+        }
+    }
+    if let Some(strategy) = strategy {
+        plan.order(strategy);
+    }
+    let settings = match free.is_some() || show_dead.is_some() {
+        true => gc_settings(),
+        false => GcSettings::default(),
+    };
+    if let Some((free, estimate)) = free {
+        let note = match estimate {
+            Estimate::Closure => {
+                estimate_freed(&gcroots, &mut plan)?;
+                "note: sizes are the store paths only the removed roots keep alive"
+            }
+            Estimate::NarSize => {
+                "note: sizes are the NAR size of each target, shared dependencies \
+                make the space actually freed differ"
+            }
+        };
+        let limit = plan.limit_to_free(free);
+        outln!("{note}");
+        if let Some(note) = settings.estimate_note() {
+            outln!("{note}");
+        }
+        if limit.unknown > 0 {
+            eprintln!(
+                "warning: left {} of unknown size out of --free",
+                plan::count(limit.unknown, "root", "roots")
+            );
+        }
+        if !limit.reached {
+            eprintln!(
+                "warning: removing every candidate frees an estimated {}, less than the requested {}",
+                size::format(limit.estimated),
+                size::format(free)
+            );
+        }
+        plan = limit.plan;
+    }
+    if let Some(grace) = grace {
+        let mut candidates = Candidates::open()?;
+        let settled = candidates.settle(plan.entries, grace, now);
+        if !matches!(mode, CleanMode::DryRun) {
+            candidates.save()?;
+        }
+        outln!("note: {settled}");
+        plan.entries = settled.ready;
+    }
+    status::emit(&Event::PlanComputed {
+        entries: plan.entries.len(),
+    });
+    if let CleanMode::EmitPlan(path) = &mode {
+        plan.save(path)?;
+        outln!(
+            "wrote plan with {} to {}",
+            plan::count(plan.entries.len(), "root", "roots"),
+            path
+        );
+        return Ok(ExitCode::SUCCESS);
+    }
+    if plan.entries.is_empty() {
+        outln!("nothing to remove");
+        return Ok(ExitCode::SUCCESS);
+    }
+    let plan = match mode {
+        CleanMode::Interactive => {
+            if !std::io::stdin().is_terminal() {
+                return Err(eyre!("--interactive needs stdin to be a terminal"));
+            }
+            let entries = prompt::review(
+                plan.entries,
+                SystemTime::now(),
+                &mut std::io::stdin().lock(),
+                &mut std::io::stdout(),
+            )?;
+            match entries {
+                Some(entries) if !entries.is_empty() => Plan { entries, ..plan },
+                _ => {
+                    outln!("nothing to remove");
+                    return Ok(ExitCode::SUCCESS);
+                }
+            }
+        }
+        CleanMode::Edit { .. } => {
+            let entries = editor::edit(plan.entries, SystemTime::now())?;
+            Plan { entries, ..plan }
+        }
+        _ => plan,
+    };
+    outln!("{}", plan);
+    if retention.is_some() {
+        outln!("{}", retention::Outcome::of(&gcroots, &plan));
+    }
+    if let Some(limit) = show_dead {
+        outln!("{}", DeadPaths::of(&plan, &gcroots, limit, &settings)?);
+        if let (None, Some(note)) = (free, settings.estimate_note()) {
+            outln!("{note}");
+        }
+    }
+    let confirmed = match mode {
+        CleanMode::DryRun | CleanMode::EmitPlan(_) => false,
+        CleanMode::Interactive => prompt::confirm("Remove these roots?")?,
+        CleanMode::Edit { yes } | CleanMode::Confirm { yes } => {
+            prompt::confirm_deletion("Remove these roots?", yes)?
+        }
+    };
+    if !confirmed {
+        return Ok(ExitCode::SUCCESS);
+    }
+    execute(globals, "clean", plan.entries)
+}
+
+/// Sets the size of each entry to what removing it frees once the entries
+/// before it are removed too, from the closures of every root.
+fn estimate_freed(gcroots: &GCRoots, plan: &mut Plan) -> eyre::Result<()> {
+    let planned = plan
+        .entries
+        .iter()
+        .map(|entry| entry.path.as_path())
+        .collect::<std::collections::BTreeSet<_>>();
+    let remaining = DeadPaths::remaining_targets(gcroots, &planned);
+    let removed = plan
+        .entries
+        .iter()
+        .map(|entry| entry.target.as_path())
+        .collect::<Vec<_>>();
+    let queried = remaining
+        .iter()
+        .chain(&removed)
+        .copied()
+        .collect::<Vec<_>>();
+    let closures = size::Closures::query(&queried, gcroots.store_dirs())?;
+    let freed = closures.freed_in_order(remaining.iter().copied(), &removed);
+    for (entry, freed) in plan.entries.iter_mut().zip(freed) {
+        entry.size = freed;
+    }
+    Ok(())
+}
+
+fn channels(globals: &Globals) -> eyre::Result<()> {
+    let (gcroots, _) = discover(globals)?;
+    outln!("{}", Channels::new(&gcroots, SystemTime::now()));
+    Ok(())
+}
+
+fn delete(
+    globals: &Globals,
+    path: &Utf8Path,
+    selector: Option<&GenerationSelector>,
+    dry_run: bool,
+) -> eyre::Result<ExitCode> {
+    let path = Utf8PathBuf::try_from(std::path::absolute(path)?)?;
+    let (gcroots, _) = discover(globals)?;
+    let pins = Pins::open()?;
+    let (mut plan, skipped) =
+        Plan::select_delete(&gcroots, &path, selector, SystemTime::now(), &pins)?;
+    plan.entries = scope_users(globals, plan.entries);
+    status::emit(&Event::PlanComputed {
+        entries: plan.entries.len(),
+    });
+    for skipped in skipped.iter() {
+        outln!("skipping {}", skipped);
+    }
+    if plan.entries.is_empty() {
+        outln!("nothing to remove");
+        return Ok(ExitCode::SUCCESS);
+    }
+    outln!("{}", plan);
+    if dry_run || !prompt::confirm_deletion("Remove these roots?", globals.yes)? {
+        return Ok(ExitCode::SUCCESS);
+    }
+    execute(globals, "delete", plan.entries)
+}
+
+/// Deletes the roots marked in the interactive browser, after confirmation.
+/// Active and pinned generations are skipped like with delete.
+fn browse(globals: &Globals) -> eyre::Result<ExitCode> {
+    let options = DiscoverOptions {
+        root_status: true,
+        ..DiscoverOptions::default()
+    };
+    let (gcroots, _) = discover_with(globals, options)?;
+    let Some(marked) = browse::run(&gcroots, globals.decorations.icons.as_ref())? else {
+        return Ok(ExitCode::SUCCESS);
+    };
+    let now = SystemTime::now();
+    let pins = Pins::open()?;
+    let mut entries = Vec::new();
+    for path in marked.iter() {
+        let (plan, skipped) = Plan::select_delete(&gcroots, path, None, now, &pins)?;
+        for skipped in skipped.iter() {
+            outln!("skipping {}", skipped);
+        }
+        entries.extend(plan.entries);
+    }
+    let entries = scope_users(globals, entries);
+    status::emit(&Event::PlanComputed {
+        entries: entries.len(),
+    });
+    if entries.is_empty() {
+        outln!("nothing to remove");
+        return Ok(ExitCode::SUCCESS);
+    }
+    let plan = Plan {
+        version: plan::PLAN_VERSION,
+        strategy: None,
+        entries,
+    };
+    outln!("{}", plan);
+    if !prompt::confirm_deletion("Remove these roots?", globals.yes)? {
+        return Ok(ExitCode::SUCCESS);
+    }
+    execute(globals, "browse", plan.entries)
+}
+
+fn channels_prune(globals: &Globals, keep_last: usize, dry_run: bool) -> eyre::Result<ExitCode> {
+    let (gcroots, _) = discover(globals)?;
+    let pins = Pins::open()?;
+    let (mut plan, skipped) = Plan::select_retained(
+        &gcroots,
+        RetentionPolicy::KeepLast(keep_last),
+        channel::is_channel,
+        SystemTime::now(),
+        &pins,
+    );
+    plan.entries = scope_users(globals, plan.entries);
+    status::emit(&Event::PlanComputed {
+        entries: plan.entries.len(),
+    });
+    for skipped in skipped.iter() {
+        outln!("skipping {}", skipped);
+    }
+    if plan.entries.is_empty() {
+        outln!("nothing to remove");
+        return Ok(ExitCode::SUCCESS);
+    }
+    outln!("{}", plan);
+    outln!(
+        "note: only old generations are removed, use nix-channel --remove <name> \
+        to unregister a channel"
+    );
+    if dry_run || !prompt::confirm_deletion("Remove these roots?", globals.yes)? {
+        return Ok(ExitCode::SUCCESS);
+    }
+    execute(globals, "channels prune", plan.entries)
+}
+
+fn apply_plan(globals: &Globals, path: &Utf8Path) -> eyre::Result<ExitCode> {
+    let plan = Plan::load(path)?;
+    let strategy = plan.strategy;
+    let (gcroots, _) = discover(globals)?;
+    let (valid, drifted) = plan.validate(&gcroots, &Pins::open()?);
+    let valid = scope_users(globals, valid);
+    status::emit(&Event::PlanComputed {
+        entries: valid.len(),
+    });
+    let mut report = DeleteReport::default();
+    let mut _lock = None;
+    if !valid.is_empty() {
+        let plan = Plan {
+            version: plan::PLAN_VERSION,
+            strategy,
+            entries: valid,
+        };
+        outln!("{}", plan);
+        if prompt::confirm_deletion("Remove these roots?", globals.yes)? {
+            _lock = Some(Lock::acquire(globals.wait)?);
+            report = Plan::execute(plan.entries, &globals.execute, interrupt::catch()?);
+        }
+    }
+    let (gone, drifted): (Vec<_>, Vec<_>) = drifted.into_iter().partition(|(entry, drift)| {
+        *drift == plan::Drift::NoLongerRoot
+            && entry
+                .path
+                .symlink_metadata()
+                .is_err_and(|e| gcroot::is_gone(&e))
+    });
+    report
+        .gone
+        .splice(0..0, gone.into_iter().map(|(entry, _)| entry));
+    report.refused.splice(0..0, drifted);
+    Ok(finish(globals, "apply-plan", &report))
+}
+
+/// Leaves roots of other users out of removals run as root, unless --users
+/// includes them, with a warning about what was left out.
+fn scope_users(globals: &Globals, entries: Vec<PlanEntry>) -> Vec<PlanEntry> {
+    if !nix::unistd::Uid::effective().is_root() {
+        return entries;
+    }
+    let (kept, excluded) = scope::partition(entries, globals.users.as_ref(), scope::owner);
+    if let Some(notice) = scope::notice(&excluded) {
+        eprintln!("warning: {notice}");
+    }
+    kept
+}
+
+/// Removes the entries while holding the [Lock].
+fn execute(globals: &Globals, command: &str, entries: Vec<PlanEntry>) -> eyre::Result<ExitCode> {
+    let _lock = Lock::acquire(globals.wait)?;
+    let report = Plan::execute(entries, &globals.execute, interrupt::catch()?);
+    Ok(finish(globals, command, &report))
+}
+
+/// The garbage collection settings of Nix, its defaults with a warning if
+/// they can't be read.
+fn gc_settings() -> GcSettings {
+    GcSettings::read().unwrap_or_else(|e| {
+        eprintln!("warning: {e:#}, assuming the default keep-outputs and keep-derivations");
+        GcSettings::default()
+    })
+}
+
+/// Prints, records and notifies about the outcome of a destructive action.
+fn finish(globals: &Globals, command: &str, report: &DeleteReport) -> ExitCode {
+    outln!("{}", report);
+    status::emit(&Event::finished(report));
+    record(globals, command, report);
+    if globals.notify {
+        notify::send(&format!("gcrs {command} finished"), &report.summary());
+    }
+    exit_code(report)
+}
+
+/// Notifies about a destructive action that failed, if requested.
+fn notify_error<T>(globals: &Globals, command: &str, result: eyre::Result<T>) -> eyre::Result<T> {
+    if let (true, Err(e)) = (globals.notify, &result) {
+        notify::send(&format!("gcrs {command} failed"), &e.to_string());
+    }
+    result
+}
+
+fn exit_code(report: &DeleteReport) -> ExitCode {
+    match (report.success(), report.interrupted.is_empty()) {
+        (true, _) => ExitCode::SUCCESS,
+        (false, true) => ExitCode::FAILURE,
+        (false, false) => ExitCode::from(EXIT_INTERRUPTED),
+    }
+}
+
+/// Appends the outcome of a destructive action to the journal, if enabled.
+/// Failing to do so only warns, as the action itself already happened.
+fn record(globals: &Globals, command: &str, report: &DeleteReport) {
+    if !globals.journal || (report.removed.is_empty() && report.success()) {
+        return;
+    }
+    let result = Journal::open().and_then(|journal| journal.append(&Record::new(command, report)));
+    if let Err(e) = result {
+        eprintln!("warning: couldn't write to the journal: {e}");
+    }
+}
+
+fn audit_links(globals: &Globals, dirs: Vec<Utf8PathBuf>, register: bool) -> eyre::Result<()> {
+    let (gcroots, _) = discover(globals)?;
+    let (dirs, options) = match dirs.is_empty() {
+        true => (well_known::link_dirs(), WalkOptions::WELL_KNOWN),
+        false => (dirs, WalkOptions::SCAN),
+    };
+    let audit = audit::audit(&gcroots, &dirs, &options);
+    outln!("{}", audit);
+    if !register {
+        return Ok(());
+    }
+    for link in audit.unregistered.iter() {
+        let root = pin::register_indirect(&link.path)?;
+        outln!("registered {} as {}", link.path, root);
+    }
+    Ok(())
+}
+
+/// Records a snapshot of the current roots and prints how they changed.
+fn trend(globals: &Globals, since: Duration, json: bool) -> eyre::Result<()> {
+    let (gcroots, _) = discover(globals)?;
+    let mut history = History::open()?;
+    let snapshot = Snapshot::of(&gcroots, SystemTime::now());
+    let changes = history
+        .snapshots
+        .last()
+        .and_then(|previous| previous.changes(&snapshot));
+    history.push(snapshot)?;
+    if json {
+        let snapshots = &history.snapshots;
+        // The history is an array, with timings it's wrapped in an object.
+        let output = match globals.timings {
+            true => pretty_json(globals, &serde_json::json!({ "snapshots": snapshots }))?,
+            false => serde_json::to_string_pretty(snapshots)?,
+        };
+        outln!("{}", output);
+        return Ok(());
+    }
+    if history.snapshots.len() == 1 {
+        outln!("first recorded run, run gcrs trend again later to see changes");
+    }
+    if let Some(trend) = Trend::of(&history, since) {
+        out!("{}", trend);
+    }
+    if let Some(changes) = changes.filter(|changes| !changes.is_empty()) {
+        outln!("\nchanges since last run:\n{}", changes);
+    }
+    Ok(())
+}
+
+fn policy_import(file: &Utf8Path, overwrite: bool) -> eyre::Result<()> {
+    let policy = Policy::load(file)?;
+    let path = Config::path().ok_or_else(|| {
+        eyre!("couldn't locate the config file, neither XDG_CONFIG_HOME nor HOME is set")
+    })?;
+    let changes = policy.import(&path, overwrite)?;
+    if changes.is_empty() {
+        outln!("{path} already follows the policy");
+    }
+    for change in changes.iter() {
+        outln!("{}", change);
+    }
+    Ok(())
+}
+
+fn pin_list() -> eyre::Result<()> {
+    let pins = Pins::open()?;
+    if pins.pins().is_empty() {
+        outln!("no pinned generations");
+    }
+    for pin in pins.pins() {
+        outln!("{}", pin);
+    }
+    Ok(())
+}
+
+fn pin_generation(
+    globals: &Globals,
+    profile: &str,
+    generation: u64,
+    reason: Option<String>,
+) -> eyre::Result<()> {
+    let (gcroots, _) = discover(globals)?;
+    let profile = pin::resolve_profile(&gcroots, profile)?;
+    if !profile.generations.contains_key(&generation) {
+        return Err(eyre!(
+            "{} has no generation {generation}, see gcrs print",
+            profile.path
+        ));
+    }
+    let pin = Pin {
+        profile: profile.path.clone(),
+        generation,
+        reason,
+        pinned: chrono::Utc::now(),
+    };
+    let mut pins = Pins::open()?;
+    outln!("pinned {}", pin);
+    pins.add(pin);
+    pins.save()
+}
+
+/// Unpins by path or by name among the pins, the profile may be gone.
+fn unpin_generation(profile: &str, generation: u64) -> eyre::Result<()> {
+    let mut pins = Pins::open()?;
+    let matching = pins
+        .pins()
+        .iter()
+        .filter(|pin| pin.generation == generation)
+        .filter(|pin| pin.profile.as_str() == profile || pin.profile.file_name() == Some(profile))
+        .map(|pin| pin.profile.clone())
+        .collect::<Vec<_>>();
+    let path = match matching.as_slice() {
+        [path] => path,
+        [] => return Err(eyre!("generation {generation} of {profile} isn't pinned")),
+        _ => {
+            return Err(eyre!(
+                "generation {generation} is pinned in several profiles named {profile}, \
+                pass the path of one"
+            ))
+        }
+    };
+    if let Some(pin) = pins.remove(path, generation) {
+        outln!("unpinned {}", pin);
+    }
+    pins.save()
+}
+
+fn journal_show(globals: &Globals, since: Option<Duration>) -> eyre::Result<()> {
+    let journal = Journal::open()?;
+    let records = journal.read(since)?;
+    if records.is_empty() {
+        outln!("no entries in {}", journal.path());
+    }
+    for (index, record) in records.iter().enumerate() {
+        if index != 0 {
+            outln!();
+        }
+        outln!("{}", record.display(&globals.dates));
     }
     Ok(())
 }
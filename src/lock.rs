@@ -0,0 +1,162 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{Read, Seek, Write},
+    os::fd::AsRawFd,
+};
+
+use camino::Utf8Path;
+use eyre::{eyre, Result, WrapErr};
+use nix::{
+    errno::Errno,
+    fcntl::{self, FlockArg},
+    sys::signal,
+    unistd::Pid,
+};
+
+use crate::state;
+
+/// Advisory lock held while destructive commands remove roots, so that
+/// concurrent runs don't race on the same roots or interleave journal writes.
+/// Released when dropped, or by the kernel if the process dies, so a lock file
+/// left behind never blocks later runs.
+pub struct Lock {
+    _file: File,
+}
+
+impl Lock {
+    /// Takes the lock at `$XDG_STATE_HOME/gcrs/lock`, see [state::dir]. If another
+    /// process holds it, waits for it to be released if wait, and fails otherwise.
+    pub fn acquire(wait: bool) -> Result<Self> {
+        Self::acquire_at(&state::create_dir(state::Kind::State)?.join("lock"), wait)
+    }
+
+    fn acquire_at(path: &Utf8Path, wait: bool) -> Result<Self> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)
+            .wrap_err_with(|| format!("couldn't open lock file {path}"))?;
+        match fcntl::flock(file.as_raw_fd(), FlockArg::LockExclusiveNonblock) {
+            Ok(()) => {}
+            Err(Errno::EWOULDBLOCK) => {
+                let holder = holder(&mut file);
+                if !wait {
+                    return Err(eyre!(
+                        "another gcrs operation is in progress{}, pass --wait to wait for it",
+                        Self::pid_note(holder)
+                    ));
+                }
+                eprintln!(
+                    "waiting for another gcrs operation{} to finish",
+                    Self::pid_note(holder)
+                );
+                fcntl::flock(file.as_raw_fd(), FlockArg::LockExclusive)
+                    .wrap_err_with(|| format!("couldn't lock {path}"))?;
+            }
+            Err(e) => return Err(eyre!(e).wrap_err(format!("couldn't lock {path}"))),
+        }
+        file.set_len(0)?;
+        file.rewind()?;
+        write!(file, "{}", std::process::id())?;
+        Ok(Lock { _file: file })
+    }
+
+    fn pid_note(holder: Option<Pid>) -> String {
+        holder
+            .map(|pid| format!(" (pid {pid})"))
+            .unwrap_or_default()
+    }
+}
+
+/// The pid written by the process holding the lock, if it's still alive.
+/// Pids of dead processes are stale leftovers, e.g. from a holder that
+/// forked before writing.
+fn holder(file: &mut File) -> Option<Pid> {
+    let mut contents = String::new();
+    file.read_to_string(&mut contents).ok()?;
+    let pid = Pid::from_raw(contents.trim().parse().ok()?);
+    match signal::kill(pid, None) {
+        Ok(()) | Err(Errno::EPERM) => Some(pid),
+        Err(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+
+    use super::*;
+
+    fn lock_path() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().join("lock")).unwrap();
+        (dir, path)
+    }
+
+    /// Pid of a process that has exited.
+    fn dead_pid() -> String {
+        let mut child = std::process::Command::new("true").spawn().unwrap();
+        child.wait().unwrap();
+        child.id().to_string()
+    }
+
+    #[test]
+    fn held_lock_fails_fast_with_the_pid() {
+        let (_dir, path) = lock_path();
+        let lock = Lock::acquire_at(&path, false).unwrap();
+        let pid = std::process::id();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), pid.to_string());
+        let error = Lock::acquire_at(&path, false).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "another gcrs operation is in progress (pid {pid}), pass --wait to wait for it"
+            )
+        );
+        drop(lock);
+        Lock::acquire_at(&path, false).unwrap();
+    }
+
+    #[test]
+    fn lock_file_left_behind_doesnt_block() {
+        let (_dir, path) = lock_path();
+        std::fs::write(&path, dead_pid()).unwrap();
+        Lock::acquire_at(&path, false).unwrap();
+        assert_eq!(
+            std::fs::read_to_string(&path).unwrap(),
+            std::process::id().to_string()
+        );
+    }
+
+    #[test]
+    fn dead_and_unknown_holders_are_left_out() {
+        let (_dir, path) = lock_path();
+        for (contents, holder) in [
+            (std::process::id().to_string(), Some(Pid::this())),
+            (dead_pid(), None),
+            ("not a pid".to_string(), None),
+            (String::new(), None),
+        ] {
+            std::fs::write(&path, &contents).unwrap();
+            let mut file = File::open(&path).unwrap();
+            assert_eq!(super::holder(&mut file), holder, "{contents:?}");
+        }
+        assert_eq!(Lock::pid_note(None), "");
+    }
+
+    #[test]
+    fn waiting_takes_the_lock_once_released() {
+        let (_dir, path) = lock_path();
+        let lock = Lock::acquire_at(&path, false).unwrap();
+        let waiter = {
+            let path = path.clone();
+            std::thread::spawn(move || Lock::acquire_at(&path, true).map(|_| ()))
+        };
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(!waiter.is_finished());
+        drop(lock);
+        waiter.join().unwrap().unwrap();
+    }
+}
@@ -1,4 +1,6 @@
-fn main() -> eyre::Result<()> {
+use std::process::ExitCode;
+
+fn main() -> eyre::Result<ExitCode> {
     color_eyre::install()?;
     gcrs::run()
 }
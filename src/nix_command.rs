@@ -0,0 +1,92 @@
+use std::{
+    ffi::OsString,
+    io::ErrorKind,
+    path::Path,
+    process::{Command, Output},
+};
+
+use eyre::{eyre, Report, Result};
+
+/// Known failures of nix commands as (text in stderr, what to do about it),
+/// checked in order.
+pub const FAILURE_SIGNATURES: &[(&str, &str)] = &[
+    (
+        "is not allowed to connect to the Nix daemon",
+        "add your user to allowed-users in nix.conf",
+    ),
+    (
+        "cannot connect to socket at",
+        "the nix-daemon does not appear to be running; start it with systemctl start nix-daemon",
+    ),
+    (
+        "cannot connect to daemon",
+        "the nix-daemon does not appear to be running; start it with systemctl start nix-daemon",
+    ),
+    (
+        "daemon-socket/socket': Permission denied",
+        "you can't access the nix-daemon socket; check the permissions of /nix/var/nix/daemon-socket",
+    ),
+    (
+        "opening lock file",
+        "the Nix store belongs to another user; run gcrs as that user or use a multi-user install",
+    ),
+    (
+        "experimental Nix feature 'nix-command' is disabled",
+        "this Nix is too old for gcrs, upgrade to Nix 2.4 or newer",
+    ),
+];
+
+/// What to do about a failed nix command, if its stderr has a known signature.
+pub fn explain(stderr: &str) -> Option<&'static str> {
+    FAILURE_SIGNATURES
+        .iter()
+        .find(|(signature, _)| stderr.contains(signature))
+        .map(|(_, hint)| *hint)
+}
+
+/// A command running a Nix program, from `$GCRS_NIX_BIN_DIR` if set and PATH
+/// otherwise, so that a fake Nix can be substituted without changing PATH.
+pub fn command(program: &str) -> Command {
+    match bin_dir() {
+        Some(dir) => Command::new(Path::new(&dir).join(program)),
+        None => Command::new(program),
+    }
+}
+
+/// `$GCRS_NIX_BIN_DIR` if it's set and not empty.
+pub fn bin_dir() -> Option<OsString> {
+    std::env::var_os("GCRS_NIX_BIN_DIR").filter(|dir| !dir.is_empty())
+}
+
+/// Runs a nix command in the C locale, so that its output and errors aren't
+/// localized, turning a missing binary into an error saying so.
+/// A nonzero exit status isn't an error here, see [success].
+pub fn output(command: &mut Command) -> Result<Output> {
+    let program = command.get_program().to_string_lossy().into_owned();
+    command
+        .env("LC_ALL", "C")
+        .output()
+        .map_err(|e| match e.kind() {
+            ErrorKind::NotFound if program.contains('/') => eyre!("{program} not found"),
+            ErrorKind::NotFound => eyre!("{program} not found in PATH; is Nix installed?"),
+            _ => Report::new(e).wrap_err(format!("couldn't run {program}")),
+        })
+}
+
+/// Fails with the stderr of the described command if it exited with an error,
+/// adding what to do about it when the failure has a known signature.
+pub fn success(output: Output, description: &str) -> Result<Output> {
+    if output.status.success() {
+        return Ok(output);
+    }
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let error = eyre!(
+        "\"{description}\" exited with code {}: {}",
+        output.status,
+        stderr.trim()
+    );
+    match explain(&stderr) {
+        Some(hint) => Err(error.wrap_err(hint)),
+        None => Err(error),
+    }
+}
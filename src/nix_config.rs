@@ -0,0 +1,163 @@
+use std::fmt::Display;
+
+use eyre::{eyre, Result};
+
+use crate::nix_command;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Settings of nix.conf that keep store paths alive beyond the closures of roots.
+pub struct GcSettings {
+    /// Outputs of live derivations are kept.
+    pub keep_outputs: bool,
+    /// Derivations of live outputs are kept.
+    pub keep_derivations: bool,
+}
+
+impl Default for GcSettings {
+    /// The defaults of Nix.
+    fn default() -> Self {
+        GcSettings {
+            keep_outputs: false,
+            keep_derivations: true,
+        }
+    }
+}
+
+impl GcSettings {
+    /// Reads the effective settings with `nix config show`, falling back to
+    /// `nix show-config` of Nix versions before 2.20.
+    pub fn read() -> Result<Self> {
+        let mut errors = Vec::new();
+        for args in [&["config", "show"][..], &["show-config"][..]] {
+            let output = nix_command::output(
+                nix_command::command("nix")
+                    .args(["--extra-experimental-features", "nix-command"])
+                    .args(args),
+            )?;
+            match nix_command::success(output, &format!("nix {}", args.join(" "))) {
+                Ok(output) => return Ok(Self::parse(&String::from_utf8_lossy(&output.stdout))),
+                Err(e) => errors.push(e),
+            }
+        }
+        Err(eyre!(
+            "couldn't read the Nix configuration: {:#}",
+            errors.remove(0)
+        ))
+    }
+
+    /// Parses `key = value` lines, both commands print the same format.
+    /// Settings that aren't listed keep the default of Nix.
+    pub fn parse(output: &str) -> Self {
+        let mut settings = GcSettings::default();
+        for line in output.lines() {
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let value = match value.trim() {
+                "true" => true,
+                "false" => false,
+                _ => continue,
+            };
+            match key.trim() {
+                "keep-outputs" => settings.keep_outputs = value,
+                "keep-derivations" => settings.keep_derivations = value,
+                _ => {}
+            }
+        }
+        settings
+    }
+
+    /// Settings that make size estimates too high, for a note under them.
+    /// keep-derivations is left out, the derivations it keeps are small
+    /// and it's on by default.
+    pub fn estimate_note(&self) -> Option<&'static str> {
+        self.keep_outputs.then_some(
+            "note: keep-outputs is enabled, outputs of live derivations survive garbage \
+            collection, so the space actually freed may be lower",
+        )
+    }
+}
+
+impl Display for GcSettings {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "keep-outputs = {}, keep-derivations = {}",
+            self.keep_outputs, self.keep_derivations
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_reads_nix_config_show() {
+        let output = include_str!("../tests/fixtures/nix-config-show.txt");
+        assert_eq!(
+            GcSettings::parse(output),
+            GcSettings {
+                keep_outputs: false,
+                keep_derivations: true,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_reads_nix_show_config() {
+        let output = include_str!("../tests/fixtures/nix-show-config.txt");
+        assert_eq!(
+            GcSettings::parse(output),
+            GcSettings {
+                keep_outputs: true,
+                keep_derivations: false,
+            }
+        );
+    }
+
+    #[test]
+    fn parse_keeps_the_defaults_of_unlisted_settings() {
+        assert_eq!(GcSettings::parse(""), GcSettings::default());
+        assert_eq!(
+            GcSettings::parse("keep-env-derivations = true\nkeep-outputs = yes\nkeep-outputs\n"),
+            GcSettings::default()
+        );
+        assert_eq!(
+            GcSettings::parse("  keep-outputs=true  \n"),
+            GcSettings {
+                keep_outputs: true,
+                keep_derivations: true,
+            }
+        );
+    }
+
+    #[test]
+    fn estimates_are_noted_only_with_keep_outputs() {
+        assert_eq!(GcSettings::default().estimate_note(), None);
+        let settings = GcSettings {
+            keep_outputs: false,
+            keep_derivations: false,
+        };
+        assert_eq!(settings.estimate_note(), None);
+        let settings = GcSettings {
+            keep_outputs: true,
+            keep_derivations: false,
+        };
+        assert_eq!(
+            settings.estimate_note(),
+            Some(
+                "note: keep-outputs is enabled, outputs of live derivations survive garbage \
+                collection, so the space actually freed may be lower"
+            )
+        );
+    }
+
+    #[test]
+    fn display_lists_both_settings() {
+        assert_eq!(
+            GcSettings::default().to_string(),
+            "keep-outputs = false, keep-derivations = true"
+        );
+    }
+}
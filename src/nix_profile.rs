@@ -0,0 +1,58 @@
+use std::{io::ErrorKind, process::Command, sync::Once};
+
+use camino::Utf8Path;
+
+use crate::nix_command;
+
+/// If the profile is managed by `nix profile`, whose generations have a
+/// manifest.json instead of the manifest.nix of nix-env.
+pub fn is_new_style(profile: &Utf8Path) -> bool {
+    profile.join("manifest.json").exists()
+}
+
+/// Command deleting a single generation of profile through Nix, so that its
+/// generation bookkeeping stays consistent. `nix profile wipe-history` can only
+/// drop every generation older than an age, so this uses nix-env, which `nix
+/// profile history` shares the bookkeeping with.
+pub fn delete_generation_command(profile: &Utf8Path, generation: u64) -> Command {
+    let mut command = nix_command::command("nix-env");
+    command
+        .args(["--profile", profile.as_str()])
+        .args(["--delete-generations", &generation.to_string()]);
+    command
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Result of [delete_generation].
+pub enum Deletion {
+    Deleted,
+    /// Nix isn't available, the caller should unlink the generation itself.
+    Unavailable,
+    Failed(String),
+}
+
+/// Deletes the generation with [delete_generation_command].
+pub fn delete_generation(profile: &Utf8Path, generation: u64) -> Deletion {
+    let output = delete_generation_command(profile, generation)
+        .env("LC_ALL", "C")
+        .output();
+    match output {
+        Ok(output) if output.status.success() => Deletion::Deleted,
+        Ok(output) => Deletion::Failed(format!(
+            "nix-env --delete-generations {generation} exited with code {}: {}",
+            output.status,
+            String::from_utf8_lossy(&output.stderr).trim()
+        )),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            static WARNING: Once = Once::new();
+            WARNING.call_once(|| {
+                eprintln!(
+                    "warning: nix-env not found, unlinking generations of nix profile \
+                    profiles directly"
+                )
+            });
+            Deletion::Unavailable
+        }
+        Err(e) => Deletion::Failed(format!("couldn't run nix-env: {e}")),
+    }
+}
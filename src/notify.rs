@@ -0,0 +1,12 @@
+use std::process::{Command, Stdio};
+
+/// Shows a desktop notification with notify-send. Does nothing if there is no
+/// way to show one, as a missing notification shouldn't fail the command.
+pub fn send(summary: &str, body: &str) {
+    let _ = Command::new("notify-send")
+        .args(["--app-name", "gcrs", summary, body])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status();
+}
@@ -0,0 +1,62 @@
+use std::{
+    fmt::Arguments,
+    io::{ErrorKind, Write},
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+/// Set once stdout can't be written to, after which output is dropped.
+static CLOSED: AtomicBool = AtomicBool::new(false);
+
+/// Writes to stdout through a lock, unlike print! never panicking.
+/// When the reader went away, like head after enough lines, the output and
+/// everything after it is dropped quietly and the run goes on, so that a
+/// destructive action still finishes and is journaled. Other write errors
+/// are warned about once on stderr.
+pub fn write(args: Arguments, newline: bool) {
+    if CLOSED.load(Ordering::Relaxed) {
+        return;
+    }
+    let mut stdout = std::io::stdout().lock();
+    let result = stdout.write_fmt(args).and_then(|()| match newline {
+        true => stdout.write_all(b"\n"),
+        false => Ok(()),
+    });
+    if let Err(e) = result {
+        CLOSED.store(true, Ordering::Relaxed);
+        if e.kind() != ErrorKind::BrokenPipe {
+            eprintln!("warning: couldn't write to stdout, dropping further output: {e}");
+        }
+    }
+}
+
+/// Flushes stdout, see [write].
+pub fn flush() {
+    if CLOSED.load(Ordering::Relaxed) {
+        return;
+    }
+    if let Err(e) = std::io::stdout().lock().flush() {
+        CLOSED.store(true, Ordering::Relaxed);
+        if e.kind() != ErrorKind::BrokenPipe {
+            eprintln!("warning: couldn't write to stdout, dropping further output: {e}");
+        }
+    }
+}
+
+/// Like print!, through [write].
+macro_rules! out {
+    ($($arg:tt)*) => {
+        $crate::output::write(format_args!($($arg)*), false)
+    };
+}
+
+/// Like println!, through [write].
+macro_rules! outln {
+    () => {
+        $crate::output::write(format_args!(""), true)
+    };
+    ($($arg:tt)*) => {
+        $crate::output::write(format_args!($($arg)*), true)
+    };
+}
+
+pub(crate) use {out, outln};
@@ -0,0 +1,342 @@
+use std::{fmt::Display, io::Write};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use chrono::{DateTime, Utc};
+use eyre::{eyre, Result, WrapErr};
+use nix::unistd::{Uid, User};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    doctor,
+    gcroot::{GCRoots, Profile},
+    state,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// A generation protected from every selection, see [Pins].
+pub struct Pin {
+    pub profile: Utf8PathBuf,
+    pub generation: u64,
+    pub reason: Option<String>,
+    pub pinned: DateTime<Utc>,
+}
+
+/// Pinned generations, kept in `$XDG_STATE_HOME/gcrs/pins.json`.
+pub struct Pins {
+    path: Utf8PathBuf,
+    pins: Vec<Pin>,
+}
+
+impl Pins {
+    /// Reads the pins, a missing file has none.
+    pub fn open() -> Result<Self> {
+        Self::open_at(state::dir(state::Kind::State)?.join("pins.json"))
+    }
+
+    fn open_at(path: Utf8PathBuf) -> Result<Self> {
+        let pins = match std::fs::read(&path) {
+            Ok(json) => serde_json::from_slice(&json)
+                .wrap_err_with(|| format!("couldn't parse pins in {path}"))?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(eyre!(e).wrap_err(format!("couldn't read {path}"))),
+        };
+        Ok(Pins { path, pins })
+    }
+
+    pub fn path(&self) -> &Utf8Path {
+        &self.path
+    }
+
+    pub fn pins(&self) -> &[Pin] {
+        &self.pins
+    }
+
+    /// The pin of the generation, if it has one.
+    pub fn find(&self, profile: &Utf8Path, generation: u64) -> Option<&Pin> {
+        self.pins
+            .iter()
+            .find(|pin| pin.profile == profile && pin.generation == generation)
+    }
+
+    /// Pins the generation, replacing the reason if it was pinned already.
+    pub fn add(&mut self, pin: Pin) {
+        match self
+            .pins
+            .iter_mut()
+            .find(|p| p.profile == pin.profile && p.generation == pin.generation)
+        {
+            Some(existing) => *existing = pin,
+            None => self.pins.push(pin),
+        }
+        self.pins
+            .sort_by(|p1, p2| (&p1.profile, p1.generation).cmp(&(&p2.profile, p2.generation)));
+    }
+
+    /// Unpins the generation, returning its pin if it had one.
+    pub fn remove(&mut self, profile: &Utf8Path, generation: u64) -> Option<Pin> {
+        let index = self
+            .pins
+            .iter()
+            .position(|pin| pin.profile == profile && pin.generation == generation)?;
+        Some(self.pins.remove(index))
+    }
+
+    /// Writes the pins, replacing the file atomically.
+    pub fn save(&self) -> Result<()> {
+        state::create_dir(state::Kind::State)?;
+        self.write()
+    }
+
+    fn write(&self) -> Result<()> {
+        let dir = self.path.parent().unwrap_or(Utf8Path::new("."));
+        let temporary = dir.join(format!(".pins.json.{}", std::process::id()));
+        let mut file = std::fs::File::create(&temporary)
+            .wrap_err_with(|| format!("couldn't create {temporary}"))?;
+        file.write_all(serde_json::to_string_pretty(&self.pins)?.as_bytes())?;
+        file.sync_data()?;
+        std::fs::rename(&temporary, &self.path)
+            .wrap_err_with(|| format!("couldn't replace {}", self.path))
+    }
+
+    #[cfg(test)]
+    /// Pins that aren't read from or saved to a file.
+    pub(crate) fn of(pins: Vec<Pin>) -> Self {
+        Pins {
+            path: Utf8PathBuf::new(),
+            pins,
+        }
+    }
+
+    /// Pins whose generation link doesn't exist anymore.
+    pub fn dangling(&self) -> impl Iterator<Item = &Pin> {
+        self.pins
+            .iter()
+            .filter(|pin| pin.link().symlink_metadata().is_err())
+    }
+}
+
+impl Pin {
+    /// Path of the generation link, like `/nix/var/nix/profiles/system-120-link`.
+    pub fn link(&self) -> Utf8PathBuf {
+        Utf8PathBuf::from(format!("{}-{}-link", self.profile, self.generation))
+    }
+
+    /// Why the generation is skipped when it's selected.
+    pub fn skip_reason(&self) -> String {
+        match &self.reason {
+            Some(reason) => format!("pinned: {reason}"),
+            None => "pinned".to_string(),
+        }
+    }
+}
+
+/// Registers the symlink at link as an indirect root, by linking to it from the
+/// per-user gcroots directory, so that its target survives garbage collection.
+/// Returns the path of the new root.
+pub fn register_indirect(link: &Utf8Path) -> Result<Utf8PathBuf> {
+    let user = User::from_uid(Uid::effective())
+        .ok()
+        .flatten()
+        .ok_or_else(|| eyre!("couldn't determine the current user"))?;
+    let dir = doctor::state_dir()
+        .join("gcroots/per-user")
+        .join(&user.name);
+    let name = link.as_str().trim_start_matches('/').replace('/', "-");
+    let root = dir.join(name);
+    match root.read_link_utf8() {
+        Ok(existing) if existing == link => return Ok(root),
+        Ok(existing) => {
+            return Err(eyre!(
+                "{root} already exists and points to {existing}, not {link}"
+            ))
+        }
+        Err(_) => {}
+    }
+    std::os::unix::fs::symlink(link, &root).wrap_err_with(|| {
+        format!("couldn't create {root}, see the pins directory check of gcrs doctor")
+    })?;
+    Ok(root)
+}
+
+/// Resolves a profile given by path, or by name if only one profile has it,
+/// e.g. system for /nix/var/nix/profiles/system. Ungrouped profiles count too.
+pub fn resolve_profile<'a>(gcroots: &'a GCRoots, profile: &str) -> Result<&'a Profile> {
+    let profiles = gcroots.profiles().iter().chain(gcroots.ungrouped());
+    if let Some(found) = profiles.clone().find(|p| p.path.as_str() == profile) {
+        return Ok(found);
+    }
+    let named = profiles
+        .filter(|p| p.path.file_name() == Some(profile))
+        .collect::<Vec<_>>();
+    match named.as_slice() {
+        [found] => Ok(found),
+        [] => Err(eyre!("no profile {profile}, see gcrs print")),
+        _ => Err(eyre!(
+            "{} profiles are named {profile}, pass the path of one: {}",
+            named.len(),
+            named
+                .iter()
+                .map(|p| p.path.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        )),
+    }
+}
+
+impl Display for Pin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.profile, self.generation)?;
+        if let Some(reason) = &self.reason {
+            write!(f, " ({reason})")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_dir() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        (dir, path)
+    }
+
+    fn pin(profile: &str, generation: u64, reason: Option<&str>) -> Pin {
+        Pin {
+            profile: Utf8PathBuf::from(profile),
+            generation,
+            reason: reason.map(str::to_string),
+            pinned: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn pins_survive_a_round_trip() {
+        let (_dir, dir) = temporary_dir();
+        let path = dir.join("pins.json");
+        let mut pins = Pins::open_at(path.clone()).unwrap();
+        assert!(pins.pins().is_empty());
+        pins.add(pin(
+            "/nix/var/nix/profiles/system",
+            120,
+            Some("known-good kernel"),
+        ));
+        pins.add(pin("/home/a/.local/state/nix/profiles/profile", 3, None));
+        pins.write().unwrap();
+        let read = Pins::open_at(path.clone()).unwrap();
+        assert_eq!(read.pins(), pins.pins());
+        let json = std::fs::read_to_string(&path).unwrap();
+        assert!(
+            json.contains("\"pinned\": \"2023-11-14T22:13:20Z\""),
+            "{json}"
+        );
+        std::fs::write(&path, "[").unwrap();
+        let error = Pins::open_at(path.clone()).err().unwrap();
+        assert_eq!(error.to_string(), format!("couldn't parse pins in {path}"));
+    }
+
+    #[test]
+    fn pins_are_kept_sorted_and_replaced() {
+        let mut pins = Pins::of(Vec::new());
+        pins.add(pin("/b", 2, None));
+        pins.add(pin("/a", 10, None));
+        pins.add(pin("/a", 9, None));
+        pins.add(pin("/b", 2, Some("again")));
+        let keys = pins
+            .pins()
+            .iter()
+            .map(|pin| (pin.profile.as_str(), pin.generation))
+            .collect::<Vec<_>>();
+        assert_eq!(keys, [("/a", 9), ("/a", 10), ("/b", 2)]);
+        assert_eq!(
+            pins.find(Utf8Path::new("/b"), 2).unwrap().reason.as_deref(),
+            Some("again")
+        );
+        assert_eq!(pins.remove(Utf8Path::new("/a"), 9).unwrap().generation, 9);
+        assert!(pins.remove(Utf8Path::new("/a"), 9).is_none());
+    }
+
+    #[test]
+    fn generations_are_matched_by_profile_path_and_number() {
+        let pins = Pins::of(vec![pin("/nix/var/nix/profiles/system", 120, None)]);
+        assert!(pins
+            .find(Utf8Path::new("/nix/var/nix/profiles/system"), 120)
+            .is_some());
+        assert!(pins
+            .find(Utf8Path::new("/nix/var/nix/profiles/system"), 12)
+            .is_none());
+        assert!(pins
+            .find(Utf8Path::new("/nix/var/nix/profiles/other"), 120)
+            .is_none());
+        assert!(pins.find(Utf8Path::new("system"), 120).is_none());
+    }
+
+    #[test]
+    fn profiles_are_resolved_by_path_or_unique_name() {
+        let (_dir, dir) = temporary_dir();
+        let mut output = String::new();
+        for profile in ["a/system", "b/system", "a/home"] {
+            let profile = dir.join(profile);
+            std::fs::create_dir_all(profile.parent().unwrap()).unwrap();
+            let link = format!("{profile}-1-link");
+            std::os::unix::fs::symlink(&dir, &link).unwrap();
+            std::os::unix::fs::symlink(
+                format!("{}-1-link", profile.file_name().unwrap()),
+                &profile,
+            )
+            .unwrap();
+            output.push_str(&format!(
+                "{link} -> /nix/store/aaaa-{}\n",
+                profile.file_name().unwrap()
+            ));
+        }
+        let gcroots = GCRoots::from_print_roots_output(&output).unwrap();
+        let system = dir.join("b/system");
+        assert_eq!(
+            resolve_profile(&gcroots, system.as_str()).unwrap().path,
+            system
+        );
+        assert_eq!(
+            resolve_profile(&gcroots, "home").unwrap().path,
+            dir.join("a/home")
+        );
+        let error = resolve_profile(&gcroots, "system").unwrap_err().to_string();
+        assert!(
+            error.starts_with("2 profiles are named system, pass the path of one"),
+            "{error}"
+        );
+        assert_eq!(
+            resolve_profile(&gcroots, "user").unwrap_err().to_string(),
+            "no profile user, see gcrs print"
+        );
+    }
+
+    #[test]
+    fn pins_of_removed_generations_dangle() {
+        let (_dir, dir) = temporary_dir();
+        let profile = dir.join("system");
+        std::os::unix::fs::symlink(&dir, dir.join("system-2-link")).unwrap();
+        let pins = Pins::of(vec![
+            pin(profile.as_str(), 1, None),
+            pin(profile.as_str(), 2, None),
+        ]);
+        let dangling = pins
+            .dangling()
+            .map(|pin| pin.generation)
+            .collect::<Vec<_>>();
+        assert_eq!(dangling, [1]);
+        assert_eq!(pins.pins()[1].link(), dir.join("system-2-link"));
+    }
+
+    #[test]
+    fn reasons_are_shown() {
+        let with = pin("/p", 1, Some("known-good"));
+        assert_eq!(with.to_string(), "/p 1 (known-good)");
+        assert_eq!(with.skip_reason(), "pinned: known-good");
+        let without = pin("/p", 1, None);
+        assert_eq!(without.to_string(), "/p 1");
+        assert_eq!(without.skip_reason(), "pinned");
+    }
+}
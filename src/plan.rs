@@ -0,0 +1,1510 @@
+use std::{
+    collections::BTreeSet,
+    fmt::Display,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use eyre::{eyre, Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    duration,
+    gcroot::{self, GCRoot, GCRoots, GenerationSelector, NodeType, Profile, RootRef, StoreDirs},
+    nix_profile::{self, Deletion},
+    pin::Pins,
+    retention::RetentionPolicy,
+    size,
+    status::{self, DeletionOutcome, Event},
+    strategy::Strategy,
+};
+
+/// Version of the plan file format, bumped on incompatible changes.
+pub const PLAN_VERSION: u32 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+/// What a planned root was when the plan was made.
+pub enum EntryKind {
+    Generation {
+        profile: Utf8PathBuf,
+        generation: u64,
+    },
+    Standalone,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "rule", rename_all = "kebab-case")]
+/// A rule that selected a root for removal.
+pub enum SelectionReason {
+    /// clean without --older-than or --non-store, every inactive root.
+    Inactive,
+    /// The symlink was last modified longer than seconds ago.
+    OlderThan { seconds: u64 },
+    /// The target isn't in the store.
+    NonStore,
+    /// Not kept by [RetentionPolicy::KeepLast].
+    KeepLast { keep: usize },
+    /// Not kept by [RetentionPolicy::KeepMonthly].
+    KeepMonthly { months: usize, keep_last: usize },
+    /// Not kept by [RetentionPolicy::KeepSince], seconds being its duration.
+    KeepSince { seconds: u64, keep_last: usize },
+    /// Named explicitly, by delete or in the browser.
+    Requested,
+    /// Among the first roots in strategy order to free bytes.
+    Free { strategy: Strategy, bytes: u64 },
+}
+
+impl SelectionReason {
+    pub fn older_than(max_age: Duration) -> Self {
+        SelectionReason::OlderThan {
+            seconds: max_age.as_secs(),
+        }
+    }
+}
+
+impl From<RetentionPolicy> for SelectionReason {
+    fn from(policy: RetentionPolicy) -> Self {
+        match policy {
+            RetentionPolicy::KeepLast(keep) => SelectionReason::KeepLast { keep },
+            RetentionPolicy::KeepMonthly { months, keep_last } => {
+                SelectionReason::KeepMonthly { months, keep_last }
+            }
+            RetentionPolicy::KeepSince { since, keep_last } => SelectionReason::KeepSince {
+                seconds: since.as_secs(),
+                keep_last,
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// A root selected for removal.
+pub struct PlanEntry {
+    pub path: Utf8PathBuf,
+    pub target: Utf8PathBuf,
+    #[serde(flatten)]
+    pub kind: EntryKind,
+    /// Every rule that selected the root, in the order they were applied.
+    pub reasons: Vec<SelectionReason>,
+    /// What kind of file the root was, plans without it are of symlinks.
+    #[serde(default)]
+    pub node: NodeType,
+    /// Links inside a directory root, removed along with it.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub contents: Vec<Utf8PathBuf>,
+    /// Modification time of the symlink in seconds since the epoch, if known.
+    pub mtime: Option<u64>,
+    /// Estimated number of bytes removing the root frees, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    /// If the target can be substituted from a binary cache, if known.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub substitutable: Option<bool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Roots selected for removal, which can be reviewed before being executed.
+pub struct Plan {
+    pub version: u32,
+    /// Strategy the entries were ordered with, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub strategy: Option<Strategy>,
+    pub entries: Vec<PlanEntry>,
+}
+
+#[derive(Debug, Clone)]
+/// Result of [Plan::limit_to_free].
+pub struct FreeLimit {
+    pub plan: Plan,
+    /// Combined size of the kept entries.
+    pub estimated: u64,
+    /// If the kept entries are estimated to free the requested amount.
+    pub reached: bool,
+    /// Entries left out because their size is unknown.
+    pub unknown: usize,
+}
+
+#[derive(Debug, Clone)]
+/// A root that matched the selection but won't be removed.
+pub struct Skipped {
+    pub path: Utf8PathBuf,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// How a planned root differs from the live system.
+pub enum Drift {
+    /// The path is no longer reported as a GC root.
+    NoLongerRoot,
+    /// The root points somewhere else now.
+    TargetChanged(Utf8PathBuf),
+    /// The root moved between being standalone and a profile generation.
+    KindChanged,
+    /// The generation is now active, or the active generation can't be determined.
+    Active,
+    /// The root can't be deleted anymore.
+    NotDeletable,
+    /// The root is a different kind of file now.
+    NodeChanged(NodeType),
+    /// The generation was pinned since, with the reason.
+    Pinned(String),
+    /// The root isn't a symlink and removing those wasn't forced.
+    NotSymlink(NodeType),
+    /// The directory root holds this many entries besides the listed links.
+    NotEmpty(usize),
+    /// The links inside the directory root aren't the listed ones anymore.
+    ContentsChanged,
+}
+
+#[derive(Debug, Clone)]
+/// How [Plan::execute] removes roots.
+pub struct ExecuteOptions {
+    /// Number of roots removed in parallel.
+    pub jobs: usize,
+    /// Also remove roots that are directories, regular files or anything
+    /// else that isn't a symlink. These could hold data other than links.
+    pub force_non_symlink: bool,
+}
+
+#[derive(Debug, Default)]
+/// Outcome of executing a plan.
+pub struct DeleteReport {
+    pub removed: Vec<PlanEntry>,
+    /// Entries that were already gone, as good as removed.
+    pub gone: Vec<PlanEntry>,
+    /// Entries that weren't executed because they no longer match the live system.
+    pub refused: Vec<(PlanEntry, Drift)>,
+    pub failed: Vec<(PlanEntry, String)>,
+    /// Entries that weren't attempted because execution was interrupted.
+    pub interrupted: Vec<PlanEntry>,
+}
+
+impl PlanEntry {
+    /// The reasons the root was selected, separated by commas.
+    pub fn reason(&self) -> String {
+        self.reasons
+            .iter()
+            .map(|reason| reason.to_string())
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl Plan {
+    /// Number of entries selected by each reason, in order of first appearance.
+    /// Entries selected by several reasons count towards each of them.
+    pub fn reason_counts(&self) -> Vec<(SelectionReason, usize)> {
+        let mut counts: Vec<(SelectionReason, usize)> = Vec::new();
+        for reason in self.entries.iter().flat_map(|entry| entry.reasons.iter()) {
+            match counts.iter_mut().find(|(r, _)| r == reason) {
+                Some((_, n)) => *n += 1,
+                None => counts.push((*reason, 1)),
+            }
+        }
+        counts
+    }
+
+    /// Selects every standalone root and inactive generation whose symlink was
+    /// last modified longer than max_age ago, plus the ones pointing outside the
+    /// store if non_store is set. Selects all of them if neither is given.
+    /// Deletable roots end up in the plan, the others are returned as skipped.
+    /// Directory roots are only selected if recursive is set, pinned
+    /// generations never are.
+    pub fn select(
+        gcroots: &GCRoots,
+        max_age: Option<Duration>,
+        non_store: bool,
+        now: SystemTime,
+        recursive: bool,
+        pins: &Pins,
+    ) -> (Self, Vec<Skipped>) {
+        let mut entries = Vec::new();
+        let mut skipped = Vec::new();
+        let reasons = |gcroot: &GCRoot| {
+            let mut reasons = Vec::new();
+            if max_age.is_none() && !non_store {
+                reasons.push(SelectionReason::Inactive);
+            }
+            if let Some(max_age) = max_age {
+                let old = gcroot
+                    .modified()
+                    .ok()
+                    .and_then(|mtime| now.duration_since(mtime).ok())
+                    .is_some_and(|age| age > max_age);
+                if old {
+                    reasons.push(SelectionReason::older_than(max_age));
+                }
+            }
+            if non_store && !gcroot.target.is_store() {
+                reasons.push(SelectionReason::NonStore);
+            }
+            reasons
+        };
+        for profile in gcroots.profiles() {
+            for (id, gcroot) in profile.generations.iter() {
+                let reasons = reasons(gcroot);
+                if reasons.is_empty() {
+                    continue;
+                }
+                let skip = Self::generation_skip(profile, *id, pins);
+                let kind = EntryKind::Generation {
+                    profile: profile.path.clone(),
+                    generation: *id,
+                };
+                let selection = Selection {
+                    reasons,
+                    recursive,
+                    store_dirs: gcroots.store_dirs(),
+                    skip,
+                };
+                Self::push_entry(gcroot, kind, selection, &mut entries, &mut skipped);
+            }
+        }
+        for gcroot in gcroots.standalone() {
+            let reasons = reasons(gcroot);
+            if reasons.is_empty() {
+                continue;
+            }
+            let kind = EntryKind::Standalone;
+            let selection = Selection {
+                reasons,
+                recursive,
+                store_dirs: gcroots.store_dirs(),
+                skip: Self::standalone_skip(gcroots, &gcroot.path, pins),
+            };
+            Self::push_entry(gcroot, kind, selection, &mut entries, &mut skipped);
+        }
+        let plan = Plan {
+            version: PLAN_VERSION,
+            strategy: None,
+            entries,
+        };
+        (plan, skipped)
+    }
+
+    /// Selects the generations of the profiles matching filter
+    /// that the retention policy doesn't keep, see [Profile::generations_to_remove].
+    /// Pinned generations are skipped even if they're not kept.
+    pub fn select_retained(
+        gcroots: &GCRoots,
+        policy: RetentionPolicy,
+        filter: impl Fn(&Profile) -> bool,
+        now: SystemTime,
+        pins: &Pins,
+    ) -> (Self, Vec<Skipped>) {
+        let mut entries = Vec::new();
+        let mut skipped = Vec::new();
+        for profile in gcroots.profiles().iter().filter(|profile| filter(profile)) {
+            for id in profile.generations_to_remove(policy, now.into()) {
+                let gcroot = &profile.generations[&id];
+                let skip = Self::generation_skip(profile, id, pins);
+                let kind = EntryKind::Generation {
+                    profile: profile.path.clone(),
+                    generation: id,
+                };
+                let selection = Selection {
+                    reasons: vec![policy.into()],
+                    recursive: false,
+                    store_dirs: gcroots.store_dirs(),
+                    skip,
+                };
+                Self::push_entry(gcroot, kind, selection, &mut entries, &mut skipped);
+            }
+        }
+        let plan = Plan {
+            version: PLAN_VERSION,
+            strategy: None,
+            entries,
+        };
+        (plan, skipped)
+    }
+
+    /// Selects the root at path for delete: the generations of a profile
+    /// matching selector, or a single standalone root or generation without one.
+    /// Missing generations are returned as skipped, so are the active and
+    /// pinned ones. Fails if path isn't a root or a profile.
+    pub fn select_delete(
+        gcroots: &GCRoots,
+        path: &Utf8Path,
+        selector: Option<&GenerationSelector>,
+        now: SystemTime,
+        pins: &Pins,
+    ) -> Result<(Self, Vec<Skipped>)> {
+        let mut entries = Vec::new();
+        let mut skipped = Vec::new();
+        let profile = gcroots.profiles().iter().find(|p| p.path == path);
+        match (profile, selector, gcroots.find(path)) {
+            (Some(profile), Some(selector), _) => {
+                let reason = match selector {
+                    GenerationSelector::Numbers(_) => SelectionReason::Requested,
+                    GenerationSelector::OlderThan(max_age) => SelectionReason::older_than(*max_age),
+                };
+                for (id, gcroot) in profile.select(selector, now) {
+                    let Some(gcroot) = gcroot else {
+                        skipped.push(Skipped {
+                            path: profile.generation_link(id),
+                            reason: "no such generation".to_string(),
+                        });
+                        continue;
+                    };
+                    let selection = Selection {
+                        reasons: vec![reason],
+                        recursive: false,
+                        store_dirs: gcroots.store_dirs(),
+                        skip: Self::generation_skip(profile, id, pins),
+                    };
+                    let kind = EntryKind::Generation {
+                        profile: profile.path.clone(),
+                        generation: id,
+                    };
+                    Self::push_entry(gcroot, kind, selection, &mut entries, &mut skipped);
+                }
+            }
+            (Some(_), None, _) => {
+                return Err(eyre!(
+                    "{path} is a profile, pass --generations or --older-than to choose \
+                    which of its generations to delete"
+                ))
+            }
+            (None, Some(_), _) => {
+                return Err(eyre!(
+                    "{path} isn't a profile, --generations and --older-than only apply to profiles"
+                ))
+            }
+            (None, None, Some(root)) => {
+                let (kind, skip) = match root {
+                    RootRef::Standalone(gcroot) => (
+                        EntryKind::Standalone,
+                        Self::standalone_skip(gcroots, &gcroot.path, pins),
+                    ),
+                    RootRef::Generation {
+                        profile,
+                        generation,
+                        ..
+                    } => (
+                        EntryKind::Generation {
+                            profile: profile.path.clone(),
+                            generation,
+                        },
+                        Self::generation_skip(profile, generation, pins),
+                    ),
+                };
+                let selection = Selection {
+                    reasons: vec![SelectionReason::Requested],
+                    recursive: false,
+                    store_dirs: gcroots.store_dirs(),
+                    skip,
+                };
+                Self::push_entry(root.gcroot(), kind, selection, &mut entries, &mut skipped);
+            }
+            (None, None, None) => {
+                return Err(eyre!(
+                    "{path} isn't a garbage collection root or a profile, see gcrs print"
+                ))
+            }
+        }
+        let plan = Plan {
+            version: PLAN_VERSION,
+            strategy: None,
+            entries,
+        };
+        Ok((plan, skipped))
+    }
+
+    /// Takes the generations the retention policy keeps out of the plan,
+    /// returning them as skipped.
+    pub fn retain(
+        &mut self,
+        gcroots: &GCRoots,
+        policy: RetentionPolicy,
+        now: SystemTime,
+    ) -> Vec<Skipped> {
+        let kept = gcroots
+            .profiles()
+            .iter()
+            .flat_map(|profile| {
+                policy
+                    .kept(&profile.generation_mtimes(), now.into())
+                    .into_iter()
+                    .map(|id| (profile.path.clone(), id))
+            })
+            .collect::<BTreeSet<_>>();
+        let (retained, entries) = std::mem::take(&mut self.entries)
+            .into_iter()
+            .partition::<Vec<_>, _>(|entry| match &entry.kind {
+                EntryKind::Generation {
+                    profile,
+                    generation,
+                } => kept.contains(&(profile.clone(), *generation)),
+                EntryKind::Standalone => false,
+            });
+        self.entries = entries;
+        retained
+            .into_iter()
+            .map(|entry| Skipped {
+                path: entry.path,
+                reason: format!("kept by {policy}"),
+            })
+            .collect()
+    }
+
+    /// Some(reason) if the generation of profile is never selected.
+    fn generation_skip(profile: &Profile, generation: u64, pins: &Pins) -> Option<String> {
+        match profile.active_generation {
+            Some(active) if active == generation => Some("active generation".to_string()),
+            Some(_) => pins
+                .find(&profile.path, generation)
+                .map(|pin| pin.skip_reason()),
+            None => Some("active generation of the profile is unknown".to_string()),
+        }
+    }
+
+    /// Some(reason) if the standalone root is an ungrouped generation that
+    /// [Plan::generation_skip] would skip.
+    fn standalone_skip(gcroots: &GCRoots, path: &Utf8Path, pins: &Pins) -> Option<String> {
+        let (profile, generation) = gcroots.ungrouped_generation(path)?;
+        Self::generation_skip(profile, generation, pins)
+    }
+
+    fn push_entry(
+        gcroot: &GCRoot,
+        kind: EntryKind,
+        selection: Selection,
+        entries: &mut Vec<PlanEntry>,
+        skipped: &mut Vec<Skipped>,
+    ) {
+        let node = gcroot.node_type().unwrap_or_default();
+        let skip = selection
+            .skip
+            .or_else(|| (!gcroot.deletable()).then(|| "not deletable".to_string()))
+            .or_else(|| {
+                (node == NodeType::Directory && !selection.recursive)
+                    .then(|| "directory, needs --recursive".to_string())
+            });
+        let contents = match node {
+            NodeType::Directory => gcroot
+                .contained_links(selection.store_dirs)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|(link, _)| link)
+                .collect(),
+            _ => Vec::new(),
+        };
+        match skip {
+            Some(reason) => skipped.push(Skipped {
+                path: gcroot.path.to_path_buf(),
+                reason,
+            }),
+            None => entries.push(PlanEntry {
+                path: gcroot.path.to_path_buf(),
+                target: gcroot.target.to_path_buf(),
+                kind,
+                reasons: selection.reasons,
+                node,
+                contents,
+                mtime: gcroot
+                    .modified()
+                    .ok()
+                    .and_then(|mtime| mtime.duration_since(SystemTime::UNIX_EPOCH).ok())
+                    .map(|since_epoch| since_epoch.as_secs()),
+                size: None,
+                substitutable: None,
+            }),
+        }
+    }
+
+    /// Orders the entries according to the strategy.
+    pub fn order(&mut self, strategy: Strategy) {
+        strategy.order(&mut self.entries);
+        self.strategy = Some(strategy);
+    }
+
+    /// Keeps entries in order until their combined size reaches `bytes`,
+    /// ordering them with the default strategy first if they weren't yet.
+    /// Entries without a known size are left out, as they may free nothing.
+    pub fn limit_to_free(mut self, bytes: u64) -> FreeLimit {
+        let strategy = match self.strategy {
+            Some(strategy) => strategy,
+            None => {
+                self.order(Strategy::default());
+                Strategy::default()
+            }
+        };
+        let mut estimated = 0u64;
+        let mut unknown = 0;
+        let mut kept = Vec::new();
+        for mut entry in self.entries {
+            if estimated >= bytes {
+                break;
+            }
+            let Some(size) = entry.size else {
+                unknown += 1;
+                continue;
+            };
+            entry
+                .reasons
+                .push(SelectionReason::Free { strategy, bytes });
+            estimated = estimated.saturating_add(size);
+            kept.push(entry);
+        }
+        FreeLimit {
+            plan: Plan {
+                version: self.version,
+                strategy: self.strategy,
+                entries: kept,
+            },
+            estimated,
+            reached: estimated >= bytes,
+            unknown,
+        }
+    }
+
+    /// Writes the plan as JSON.
+    pub fn save(&self, path: &Utf8Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json + "\n").wrap_err_with(|| format!("couldn't write plan {path}"))
+    }
+
+    /// Reads a plan written by [Plan::save].
+    pub fn load(path: &Utf8Path) -> Result<Self> {
+        let json =
+            std::fs::read_to_string(path).wrap_err_with(|| format!("couldn't read plan {path}"))?;
+        let plan: Plan =
+            serde_json::from_str(&json).wrap_err_with(|| format!("invalid plan {path}"))?;
+        match plan.version {
+            PLAN_VERSION => Ok(plan),
+            version => Err(eyre!(
+                "plan {path} has version {version}, only version {PLAN_VERSION} is supported"
+            )),
+        }
+    }
+
+    /// Checks every entry against the live system and the pins, returning the
+    /// entries that still match it and the ones that drifted.
+    pub fn validate(
+        self,
+        live: &GCRoots,
+        pins: &Pins,
+    ) -> (Vec<PlanEntry>, Vec<(PlanEntry, Drift)>) {
+        let mut valid = Vec::new();
+        let mut drifted = Vec::new();
+        for entry in self.entries {
+            let pinned = match &entry.kind {
+                EntryKind::Generation {
+                    profile,
+                    generation,
+                } => pins.find(profile, *generation),
+                EntryKind::Standalone => live
+                    .ungrouped_generation(&entry.path)
+                    .and_then(|(profile, generation)| pins.find(&profile.path, generation)),
+            };
+            let drift = match pinned {
+                Some(pin) => Some(Drift::Pinned(pin.skip_reason())),
+                None => Self::drift(&entry, live),
+            };
+            match drift {
+                Some(drift) => drifted.push((entry, drift)),
+                None => valid.push(entry),
+            }
+        }
+        (valid, drifted)
+    }
+
+    fn drift(entry: &PlanEntry, live: &GCRoots) -> Option<Drift> {
+        let gcroot = match (&entry.kind, live.find(&entry.path)) {
+            (
+                EntryKind::Generation { .. },
+                Some(RootRef::Generation {
+                    profile,
+                    generation,
+                    gcroot,
+                }),
+            ) => {
+                if profile
+                    .active_generation
+                    .is_none_or(|active| active == generation)
+                {
+                    return Some(Drift::Active);
+                }
+                gcroot
+            }
+            (EntryKind::Standalone, Some(RootRef::Standalone(gcroot))) => {
+                let active =
+                    live.ungrouped_generation(&gcroot.path)
+                        .map(|(profile, generation)| {
+                            profile
+                                .active_generation
+                                .is_none_or(|active| active == generation)
+                        });
+                if active == Some(true) {
+                    return Some(Drift::Active);
+                }
+                gcroot
+            }
+            (_, None) => return Some(Drift::NoLongerRoot),
+            _ => return Some(Drift::KindChanged),
+        };
+        if *gcroot.target != *entry.target {
+            return Some(Drift::TargetChanged(gcroot.target.to_path_buf()));
+        }
+        if let Ok(node) = gcroot.node_type() {
+            if node != entry.node {
+                return Some(Drift::NodeChanged(node));
+            }
+        }
+        if entry.node == NodeType::Directory {
+            let links = gcroot.contained_links(live.store_dirs()).map(|links| {
+                links
+                    .into_iter()
+                    .map(|(link, _)| link)
+                    .collect::<BTreeSet<_>>()
+            });
+            if links.ok() != Some(entry.contents.iter().cloned().collect()) {
+                return Some(Drift::ContentsChanged);
+            }
+        }
+        if !gcroot.deletable() {
+            return Some(Drift::NotDeletable);
+        }
+        None
+    }
+
+    /// Removes every entry of the plan, carrying on past failures. Each root is
+    /// checked to still be a symlink to the planned target right before removing
+    /// it. Once `interrupt` is set no more entries are started, the ones in
+    /// progress are finished. The report lists entries in plan order.
+    pub fn execute(
+        entries: Vec<PlanEntry>,
+        options: &ExecuteOptions,
+        interrupt: &AtomicBool,
+    ) -> DeleteReport {
+        let jobs = options.jobs;
+        let next = AtomicUsize::new(0);
+        let outcomes = Mutex::new(Vec::with_capacity(entries.len()));
+        std::thread::scope(|scope| {
+            for _ in 0..jobs.clamp(1, entries.len().max(1)) {
+                scope.spawn(|| loop {
+                    if interrupt.load(Ordering::SeqCst) {
+                        break;
+                    }
+                    let index = next.fetch_add(1, Ordering::SeqCst);
+                    let Some(entry) = entries.get(index) else {
+                        break;
+                    };
+                    let outcome = Self::remove(entry, options.force_non_symlink);
+                    status::emit(&outcome.event(&entry.path));
+                    outcomes.lock().unwrap().push((index, outcome));
+                });
+            }
+        });
+        let mut outcomes = outcomes.into_inner().unwrap();
+        outcomes.sort_unstable_by_key(|(index, _)| *index);
+        let mut outcomes = outcomes.into_iter().peekable();
+        let mut report = DeleteReport::default();
+        for (index, entry) in entries.into_iter().enumerate() {
+            match outcomes.next_if(|(i, _)| *i == index) {
+                Some((_, Removal::Removed)) => report.removed.push(entry),
+                Some((_, Removal::Gone)) => report.gone.push(entry),
+                Some((_, Removal::Refused(drift))) => report.refused.push((entry, drift)),
+                Some((_, Removal::Failed(error))) => report.failed.push((entry, error)),
+                None => report.interrupted.push(entry),
+            }
+        }
+        report
+    }
+
+    fn remove(entry: &PlanEntry, force_non_symlink: bool) -> Removal {
+        match entry.path.symlink_metadata().map(|m| NodeType::of(&m)) {
+            Ok(node) if node != NodeType::Symlink && !force_non_symlink => {
+                return Removal::Refused(Drift::NotSymlink(node))
+            }
+            Ok(node) if node != entry.node => return Removal::Refused(Drift::NodeChanged(node)),
+            Ok(_) => {}
+            Err(e) if gcroot::is_gone(&e) => return Removal::Gone,
+            Err(e) => return Removal::Failed(e.to_string()),
+        }
+        if entry.node == NodeType::Symlink {
+            match entry.path.read_link_utf8() {
+                Ok(target) if target != entry.target => {
+                    return Removal::Refused(Drift::TargetChanged(target))
+                }
+                Ok(_) => {}
+                Err(e) if gcroot::is_gone(&e) => return Removal::Gone,
+                Err(e) => return Removal::Failed(e.to_string()),
+            }
+        }
+        if let EntryKind::Generation {
+            profile,
+            generation,
+        } = &entry.kind
+        {
+            if entry.node == NodeType::Symlink && nix_profile::is_new_style(profile) {
+                match nix_profile::delete_generation(profile, *generation) {
+                    Deletion::Deleted => return Removal::Removed,
+                    Deletion::Failed(error) => return Removal::Failed(error),
+                    Deletion::Unavailable => {}
+                }
+            }
+        }
+        let result = match entry.node {
+            NodeType::Directory => return Self::remove_directory(entry),
+            _ => std::fs::remove_file(&entry.path),
+        };
+        match result {
+            Ok(()) => Removal::Removed,
+            Err(e) if gcroot::is_gone(&e) => Removal::Gone,
+            Err(e) => Removal::Failed(e.to_string()),
+        }
+    }
+
+    /// Unlinks the links listed in the contents of a directory root and then
+    /// the directory itself. Refuses to touch anything if the directory holds
+    /// files the plan doesn't list, which are never removed, or if a listed
+    /// path isn't a symlink directly inside the directory anymore.
+    fn remove_directory(entry: &PlanEntry) -> Removal {
+        let listed = entry.contents.iter().collect::<BTreeSet<_>>();
+        let unlisted = match entry.path.read_dir_utf8() {
+            Ok(dir) => dir
+                .filter_map(|child| child.ok())
+                .filter(|child| !listed.contains(&child.path().to_path_buf()))
+                .count(),
+            Err(e) if gcroot::is_gone(&e) => return Removal::Gone,
+            Err(e) => return Removal::Failed(e.to_string()),
+        };
+        if unlisted > 0 {
+            return Removal::Refused(Drift::NotEmpty(unlisted));
+        }
+        let mut links = Vec::new();
+        for link in entry.contents.iter() {
+            if link.parent() != Some(entry.path.as_path()) {
+                return Removal::Refused(Drift::ContentsChanged);
+            }
+            match link.symlink_metadata().map(|m| NodeType::of(&m)) {
+                Ok(NodeType::Symlink) => links.push(link),
+                Ok(node) => return Removal::Refused(Drift::NotSymlink(node)),
+                Err(e) if gcroot::is_gone(&e) => {}
+                Err(e) => return Removal::Failed(format!("couldn't inspect {link}: {e}")),
+            }
+        }
+        for link in links {
+            match std::fs::remove_file(link) {
+                Ok(()) => {}
+                Err(e) if gcroot::is_gone(&e) => {}
+                Err(e) => return Removal::Failed(format!("couldn't remove {link}: {e}")),
+            }
+        }
+        match std::fs::remove_dir(&entry.path) {
+            Ok(()) => Removal::Removed,
+            Err(e) if gcroot::is_gone(&e) => Removal::Gone,
+            Err(e) if e.kind() == std::io::ErrorKind::DirectoryNotEmpty => {
+                Removal::Refused(Drift::NotEmpty(1))
+            }
+            Err(e) => Removal::Failed(e.to_string()),
+        }
+    }
+}
+
+/// How a root came to be selected, see [Plan::select].
+struct Selection<'a> {
+    reasons: Vec<SelectionReason>,
+    recursive: bool,
+    store_dirs: &'a StoreDirs,
+    /// Some(reason) if the root is to be skipped regardless of its file.
+    skip: Option<String>,
+}
+
+/// What happened to a single entry during [Plan::execute].
+enum Removal {
+    Removed,
+    /// The root disappeared before it could be removed.
+    Gone,
+    Refused(Drift),
+    Failed(String),
+}
+
+impl Removal {
+    fn event<'a>(&self, path: &'a Utf8Path) -> Event<'a> {
+        let (outcome, reason) = match self {
+            Removal::Removed => (DeletionOutcome::Removed, None),
+            Removal::Gone => (DeletionOutcome::Gone, None),
+            Removal::Refused(drift) => (DeletionOutcome::Refused, Some(drift.to_string())),
+            Removal::Failed(error) => (DeletionOutcome::Failed, Some(error.clone())),
+        };
+        Event::Deletion {
+            path,
+            outcome,
+            reason,
+        }
+    }
+}
+
+impl Display for PlanEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} -> {} ({})", self.path, self.target, self.reason())?;
+        match self.node {
+            NodeType::Symlink => Ok(()),
+            node => write!(f, " [{}]", node),
+        }
+    }
+}
+
+impl Display for Plan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} to remove",
+            count(self.entries.len(), "root", "roots")
+        )?;
+        match self.strategy {
+            Some(strategy) => write!(f, ", {} first:", strategy)?,
+            None => write!(f, ":")?,
+        }
+        let sized = self.entries.iter().any(|entry| entry.size.is_some());
+        let mut total = 0u64;
+        for entry in self.entries.iter() {
+            if sized {
+                total = total.saturating_add(entry.size.unwrap_or(0));
+                write!(f, "\n  {:>10}  {}", size::format(total), entry)?;
+            } else {
+                write!(f, "\n  {}", entry)?;
+            }
+            for link in entry.contents.iter() {
+                write!(f, "\n      contains {}", link)?;
+            }
+        }
+        let counts = self
+            .reason_counts()
+            .iter()
+            .map(|(reason, n)| format!("{n} {reason}"))
+            .collect::<Vec<_>>();
+        if !counts.is_empty() {
+            write!(f, "\nselected: {}", counts.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Display for SelectionReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match *self {
+            SelectionReason::Inactive => write!(f, "inactive"),
+            SelectionReason::OlderThan { seconds } => write!(
+                f,
+                "older than {}",
+                duration::format_exact(Duration::from_secs(seconds))
+            ),
+            SelectionReason::NonStore => write!(f, "pointing outside the store"),
+            SelectionReason::KeepLast { keep } => {
+                write!(f, "{}", RetentionPolicy::KeepLast(keep).reason())
+            }
+            SelectionReason::KeepMonthly { months, keep_last } => write!(
+                f,
+                "{}",
+                RetentionPolicy::KeepMonthly { months, keep_last }.reason()
+            ),
+            SelectionReason::KeepSince { seconds, keep_last } => write!(
+                f,
+                "{}",
+                RetentionPolicy::KeepSince {
+                    since: Duration::from_secs(seconds),
+                    keep_last
+                }
+                .reason()
+            ),
+            SelectionReason::Requested => write!(f, "requested"),
+            SelectionReason::Free { strategy, bytes } => {
+                write!(f, "{} first to free {}", strategy, size::format(bytes))
+            }
+        }
+    }
+}
+
+impl Display for Skipped {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.path, self.reason)
+    }
+}
+
+impl Display for Drift {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Drift::NoLongerRoot => write!(f, "no longer a gc root"),
+            Drift::TargetChanged(target) => write!(f, "target changed to {}", target),
+            Drift::KindChanged => write!(f, "moved between a profile and standalone roots"),
+            Drift::Active => write!(f, "generation is active or the active one is unknown"),
+            Drift::NotDeletable => write!(f, "no longer deletable"),
+            Drift::NodeChanged(node) => write!(f, "is a {} now", node),
+            Drift::Pinned(reason) => write!(f, "{}", reason),
+            Drift::NotSymlink(node) => write!(
+                f,
+                "not a symlink but a {}, skipped (use --force-non-symlink)",
+                node
+            ),
+            Drift::ContentsChanged => write!(f, "links inside the directory changed"),
+            Drift::NotEmpty(unlisted) => write!(
+                f,
+                "directory holds {} besides the links of the plan, left in place",
+                count(*unlisted, "other entry", "other entries")
+            ),
+        }
+    }
+}
+
+impl DeleteReport {
+    /// If every entry was removed or already gone.
+    pub fn success(&self) -> bool {
+        self.refused.is_empty() && self.failed.is_empty() && self.interrupted.is_empty()
+    }
+}
+
+impl DeleteReport {
+    /// One line summary, e.g. `removed 37 roots, refused 1, 2 failed`.
+    pub fn summary(&self) -> String {
+        let mut summary = format!("removed {}", count(self.removed.len(), "root", "roots"));
+        if !self.gone.is_empty() {
+            summary.push_str(&format!(", {} already gone", self.gone.len()));
+        }
+        if !self.refused.is_empty() {
+            summary.push_str(&format!(", refused {}", self.refused.len()));
+        }
+        if !self.failed.is_empty() {
+            summary.push_str(&format!(", {} failed", self.failed.len()));
+        }
+        if !self.interrupted.is_empty() {
+            summary.push_str(&format!(
+                ", interrupted with {} left",
+                self.interrupted.len()
+            ));
+        }
+        summary
+    }
+}
+
+impl Display for DeleteReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.summary())?;
+        let files = self
+            .removed
+            .iter()
+            .filter(|entry| entry.node == NodeType::File)
+            .collect::<Vec<_>>();
+        if !files.is_empty() {
+            match files.len() {
+                1 => write!(f, "\n1 of them was a regular file rather than a symlink:")?,
+                n => write!(f, "\n{n} of them were regular files rather than symlinks:")?,
+            }
+            for entry in files {
+                write!(f, "\n  {}", entry.path)?;
+            }
+        }
+        if !self.refused.is_empty() {
+            write!(
+                f,
+                "\nrefused to remove {}:",
+                count(self.refused.len(), "root", "roots")
+            )?;
+            for (entry, drift) in self.refused.iter() {
+                write!(f, "\n  {}: {}", entry.path, drift)?;
+            }
+        }
+        if !self.failed.is_empty() {
+            write!(
+                f,
+                "\nfailed to remove {}:",
+                count(self.failed.len(), "root", "roots")
+            )?;
+            for (entry, error) in self.failed.iter() {
+                write!(f, "\n  {}: {}", entry.path, error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Formats a count followed by the singular or plural noun.
+pub(crate) fn count(n: usize, singular: &str, plural: &str) -> String {
+    match n {
+        1 => format!("1 {singular}"),
+        n => format!("{n} {plural}"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::{Utf8Path, Utf8PathBuf};
+
+    use super::*;
+
+    fn directory_entry(path: &Utf8Path, contents: Vec<Utf8PathBuf>) -> PlanEntry {
+        PlanEntry {
+            path: path.to_path_buf(),
+            target: path.to_path_buf(),
+            kind: EntryKind::Standalone,
+            reasons: vec![SelectionReason::Requested],
+            node: NodeType::Directory,
+            contents,
+            mtime: None,
+            size: None,
+            substitutable: None,
+        }
+    }
+
+    fn temporary_dir() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().join("outs")).unwrap();
+        std::fs::create_dir(&path).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn directory_with_only_listed_links_is_removed() {
+        let (_dir, path) = temporary_dir();
+        let link = path.join("out");
+        std::os::unix::fs::symlink("/nix/store/aaaa-out", &link).unwrap();
+        let entry = directory_entry(&path, vec![link]);
+        assert!(matches!(Plan::remove(&entry, true), Removal::Removed));
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn directory_with_unlisted_files_is_left_alone() {
+        let (_dir, path) = temporary_dir();
+        let link = path.join("out");
+        std::os::unix::fs::symlink("/nix/store/aaaa-out", &link).unwrap();
+        std::fs::write(path.join("notes.txt"), "keep me").unwrap();
+        let entry = directory_entry(&path, vec![link.clone()]);
+        assert!(matches!(
+            Plan::remove(&entry, true),
+            Removal::Refused(Drift::NotEmpty(1))
+        ));
+        assert!(link.symlink_metadata().is_ok());
+        assert!(path.join("notes.txt").exists());
+    }
+
+    #[test]
+    fn directory_with_a_link_replaced_by_a_file_is_left_alone() {
+        let (_dir, path) = temporary_dir();
+        let link = path.join("out");
+        std::os::unix::fs::symlink("/nix/store/aaaa-out", &link).unwrap();
+        let replaced = path.join("dev");
+        std::fs::write(&replaced, "not a link").unwrap();
+        let entry = directory_entry(&path, vec![replaced.clone(), link.clone()]);
+        assert!(matches!(
+            Plan::remove(&entry, true),
+            Removal::Refused(Drift::NotSymlink(NodeType::File))
+        ));
+        assert!(link.symlink_metadata().is_ok());
+        assert!(replaced.exists());
+    }
+
+    #[test]
+    fn directory_listing_a_path_outside_it_is_left_alone() {
+        let (dir, path) = temporary_dir();
+        let link = path.join("out");
+        std::os::unix::fs::symlink("/nix/store/aaaa-out", &link).unwrap();
+        let outside = Utf8Path::from_path(dir.path()).unwrap().join("elsewhere");
+        std::os::unix::fs::symlink("/nix/store/aaaa-elsewhere", &outside).unwrap();
+        let entry = directory_entry(&path, vec![link.clone(), outside.clone()]);
+        assert!(matches!(
+            Plan::remove(&entry, true),
+            Removal::Refused(Drift::ContentsChanged)
+        ));
+        assert!(link.symlink_metadata().is_ok());
+        assert!(outside.symlink_metadata().is_ok());
+    }
+
+    fn sized_entry(path: &str, mtime: u64, size: Option<u64>) -> PlanEntry {
+        PlanEntry {
+            path: Utf8PathBuf::from(path),
+            target: Utf8PathBuf::from("/nix/store/aaaa-target"),
+            kind: EntryKind::Standalone,
+            reasons: vec![SelectionReason::Requested],
+            node: NodeType::Symlink,
+            contents: Vec::new(),
+            mtime: Some(mtime),
+            size,
+            substitutable: None,
+        }
+    }
+
+    fn sized_plan(entries: Vec<PlanEntry>) -> Plan {
+        Plan {
+            version: PLAN_VERSION,
+            strategy: None,
+            entries,
+        }
+    }
+
+    #[test]
+    fn free_limit_stops_once_reached() {
+        let plan = sized_plan(vec![
+            sized_entry("/a", 1, Some(10)),
+            sized_entry("/b", 2, Some(10)),
+            sized_entry("/c", 3, Some(10)),
+        ]);
+        let limit = plan.limit_to_free(15);
+        let paths = limit.plan.entries.iter().map(|e| e.path.as_str());
+        assert_eq!(paths.collect::<Vec<_>>(), ["/a", "/b"]);
+        assert_eq!(limit.estimated, 20);
+        assert!(limit.reached);
+        assert_eq!(limit.unknown, 0);
+    }
+
+    #[test]
+    fn free_limit_leaves_out_unknown_sizes() {
+        let plan = sized_plan(vec![
+            sized_entry("/a", 1, None),
+            sized_entry("/b", 2, Some(10)),
+            sized_entry("/c", 3, None),
+        ]);
+        let limit = plan.limit_to_free(15);
+        let paths = limit.plan.entries.iter().map(|e| e.path.as_str());
+        assert_eq!(paths.collect::<Vec<_>>(), ["/b"]);
+        assert_eq!(limit.estimated, 10);
+        assert!(!limit.reached);
+        assert_eq!(limit.unknown, 2);
+    }
+
+    /// A planned symlink at path that is something else now.
+    fn replaced_symlink(path: &Utf8Path) -> PlanEntry {
+        PlanEntry {
+            target: Utf8PathBuf::from("/nix/store/aaaa-out"),
+            node: NodeType::Symlink,
+            ..directory_entry(path, Vec::new())
+        }
+    }
+
+    #[test]
+    fn non_symlinks_are_refused_unless_forced() {
+        let (_dir, dir) = temporary_dir();
+        let file = dir.join("file");
+        std::fs::write(&file, "/nix/store/aaaa-out").unwrap();
+        let directory = dir.join("directory");
+        std::fs::create_dir(&directory).unwrap();
+        let fifo = dir.join("fifo");
+        nix::unistd::mkfifo(fifo.as_std_path(), nix::sys::stat::Mode::S_IRWXU).unwrap();
+        for (path, node) in [
+            (&file, NodeType::File),
+            (&directory, NodeType::Directory),
+            (&fifo, NodeType::Other),
+        ] {
+            let removal = Plan::remove(&replaced_symlink(path), false);
+            assert!(
+                matches!(&removal, Removal::Refused(Drift::NotSymlink(n)) if *n == node),
+                "{path}"
+            );
+            assert!(path.symlink_metadata().is_ok(), "{path} was removed");
+        }
+        assert_eq!(
+            Drift::NotSymlink(NodeType::File).to_string(),
+            "not a symlink but a file, skipped (use --force-non-symlink)"
+        );
+    }
+
+    #[test]
+    fn forcing_only_removes_the_planned_node() {
+        let (_dir, dir) = temporary_dir();
+        let file = dir.join("file");
+        std::fs::write(&file, "/nix/store/aaaa-out").unwrap();
+        assert!(matches!(
+            Plan::remove(&replaced_symlink(&file), true),
+            Removal::Refused(Drift::NodeChanged(NodeType::File))
+        ));
+        let entry = PlanEntry {
+            node: NodeType::File,
+            ..replaced_symlink(&file)
+        };
+        assert!(matches!(Plan::remove(&entry, true), Removal::Removed));
+        assert!(!file.exists());
+    }
+
+    #[test]
+    fn symlinks_are_checked_for_their_target() {
+        let (_dir, dir) = temporary_dir();
+        let link = dir.join("result");
+        std::os::unix::fs::symlink("/nix/store/bbbb-out", &link).unwrap();
+        let removal = Plan::remove(&replaced_symlink(&link), false);
+        assert!(matches!(
+            removal,
+            Removal::Refused(Drift::TargetChanged(target)) if target == "/nix/store/bbbb-out"
+        ));
+        std::fs::remove_file(&link).unwrap();
+        std::os::unix::fs::symlink("/nix/store/aaaa-out", &link).unwrap();
+        assert!(matches!(
+            Plan::remove(&replaced_symlink(&link), false),
+            Removal::Removed
+        ));
+        assert!(link.symlink_metadata().is_err());
+    }
+
+    #[test]
+    fn missing_directory_is_gone() {
+        let (_dir, path) = temporary_dir();
+        std::fs::remove_dir(&path).unwrap();
+        let entry = directory_entry(&path, Vec::new());
+        assert!(matches!(Plan::remove(&entry, true), Removal::Gone));
+    }
+
+    /// A plan entry for every root of a system profile with generations 1 to 3,
+    /// 2 active, and a standalone root, besides the print-roots output of the
+    /// live system.
+    fn planned(dir: &Utf8Path) -> (Vec<PlanEntry>, String) {
+        let mut output = String::new();
+        let mut entries = Vec::new();
+        for generation in 1..=3 {
+            let link = dir.join(format!("system-{generation}-link"));
+            std::os::unix::fs::symlink(dir, &link).unwrap();
+            let target = format!("/nix/store/aaaa-system-{generation}");
+            output.push_str(&format!("{link} -> {target}\n"));
+            entries.push(PlanEntry {
+                kind: EntryKind::Generation {
+                    profile: dir.join("system"),
+                    generation,
+                },
+                ..sized_entry(link.as_str(), 1, None)
+            });
+            entries.last_mut().unwrap().target = target.into();
+        }
+        std::os::unix::fs::symlink("system-2-link", dir.join("system")).unwrap();
+        let result = dir.join("result");
+        std::os::unix::fs::symlink("/nix/store/aaaa-target", &result).unwrap();
+        output.push_str(&format!("{result} -> /nix/store/aaaa-target\n"));
+        entries.push(sized_entry(result.as_str(), 1, None));
+        (entries, output)
+    }
+
+    fn validate(entries: Vec<PlanEntry>, output: &str, pins: &Pins) -> Vec<(Utf8PathBuf, Drift)> {
+        let live = GCRoots::from_print_roots_output(output).unwrap();
+        let (_, drifted) = sized_plan(entries).validate(&live, pins);
+        drifted
+            .into_iter()
+            .map(|(entry, drift)| (entry.path, drift))
+            .collect()
+    }
+
+    #[test]
+    fn unchanged_entries_are_valid() {
+        let (_dir, dir) = temporary_dir();
+        let (entries, output) = planned(&dir);
+        let live = GCRoots::from_print_roots_output(&output).unwrap();
+        let (valid, drifted) = sized_plan(entries).validate(&live, &Pins::of(Vec::new()));
+        let paths = valid.iter().map(|entry| entry.path.file_name().unwrap());
+        // Except for the active generation, which is never removed.
+        assert_eq!(
+            paths.collect::<Vec<_>>(),
+            ["system-1-link", "system-3-link", "result"]
+        );
+        assert_eq!(drifted.len(), 1);
+        assert_eq!(drifted[0].1, Drift::Active);
+    }
+
+    #[test]
+    fn removed_roots_are_no_longer_roots() {
+        let (_dir, dir) = temporary_dir();
+        let (entries, output) = planned(&dir);
+        let output = output
+            .lines()
+            .filter(|line| !line.contains("result"))
+            .map(|line| format!("{line}\n"))
+            .collect::<String>();
+        let drifted = validate(entries, &output, &Pins::of(Vec::new()));
+        assert_eq!(
+            drifted.last().unwrap(),
+            &(dir.join("result"), Drift::NoLongerRoot)
+        );
+    }
+
+    #[test]
+    fn retargeted_roots_are_refused() {
+        let (_dir, dir) = temporary_dir();
+        let (entries, output) = planned(&dir);
+        let output = output.replace("-> /nix/store/aaaa-target", "-> /nix/store/bbbb-target");
+        let drifted = validate(entries, &output, &Pins::of(Vec::new()));
+        assert_eq!(
+            drifted.last().unwrap(),
+            &(
+                dir.join("result"),
+                Drift::TargetChanged("/nix/store/bbbb-target".into())
+            )
+        );
+    }
+
+    #[test]
+    fn roots_leaving_their_profile_changed_kind() {
+        let (_dir, dir) = temporary_dir();
+        let (entries, output) = planned(&dir);
+        std::fs::remove_file(dir.join("system")).unwrap();
+        let live = GCRoots::from_print_roots_output(&output).unwrap();
+        let (valid, drifted) = sized_plan(entries).validate(&live, &Pins::of(Vec::new()));
+        assert_eq!(valid.len(), 1);
+        assert!(drifted
+            .iter()
+            .all(|(_, drift)| *drift == Drift::KindChanged));
+    }
+
+    #[test]
+    fn replaced_symlinks_changed_node() {
+        let (_dir, dir) = temporary_dir();
+        let (entries, output) = planned(&dir);
+        std::fs::remove_file(dir.join("result")).unwrap();
+        std::fs::create_dir(dir.join("result")).unwrap();
+        let drifted = validate(entries, &output, &Pins::of(Vec::new()));
+        assert_eq!(
+            drifted.last().unwrap(),
+            &(dir.join("result"), Drift::NodeChanged(NodeType::Directory))
+        );
+    }
+
+    #[test]
+    fn pinned_generations_are_refused() {
+        let (_dir, dir) = temporary_dir();
+        let (entries, output) = planned(&dir);
+        let pins = Pins::of(vec![crate::pin::Pin {
+            profile: dir.join("system"),
+            generation: 3,
+            reason: Some("known good".to_string()),
+            pinned: chrono::Utc::now(),
+        }]);
+        let drifted = validate(entries, &output, &pins);
+        assert_eq!(
+            drifted,
+            [
+                (dir.join("system-2-link"), Drift::Active),
+                (
+                    dir.join("system-3-link"),
+                    Drift::Pinned("pinned: known good".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn directories_whose_links_changed_are_refused() {
+        let (_dir, path) = temporary_dir();
+        let link = path.join("out");
+        std::os::unix::fs::symlink("/nix/store/aaaa-out", &link).unwrap();
+        let entry = PlanEntry {
+            target: "/nix/store/aaaa-outs".into(),
+            ..directory_entry(&path, vec![link.clone()])
+        };
+        let output = format!("{path} -> /nix/store/aaaa-outs\n");
+        assert!(validate(vec![entry.clone()], &output, &Pins::of(Vec::new())).is_empty());
+
+        std::os::unix::fs::symlink("/nix/store/aaaa-dev", path.join("dev")).unwrap();
+        let drifted = validate(vec![entry.clone()], &output, &Pins::of(Vec::new()));
+        assert_eq!(drifted, [(path.clone(), Drift::ContentsChanged)]);
+
+        std::fs::remove_file(path.join("dev")).unwrap();
+        std::fs::remove_file(&link).unwrap();
+        let drifted = validate(vec![entry], &output, &Pins::of(Vec::new()));
+        assert_eq!(drifted, [(path, Drift::ContentsChanged)]);
+    }
+
+    #[test]
+    fn reasons_are_counted_in_order_of_first_appearance() {
+        let with_reasons = |path: &str, reasons: Vec<SelectionReason>| PlanEntry {
+            reasons,
+            ..sized_entry(path, 0, None)
+        };
+        let older = SelectionReason::OlderThan { seconds: 86400 };
+        let keep_last = SelectionReason::KeepLast { keep: 2 };
+        let plan = sized_plan(vec![
+            with_reasons("/a", vec![keep_last]),
+            with_reasons("/b", vec![older, keep_last]),
+            with_reasons("/c", vec![SelectionReason::NonStore, older]),
+            with_reasons("/d", vec![older]),
+            with_reasons("/e", vec![SelectionReason::OlderThan { seconds: 3600 }]),
+        ]);
+        assert_eq!(
+            plan.reason_counts(),
+            [
+                (keep_last, 2),
+                (older, 3),
+                (SelectionReason::NonStore, 1),
+                (SelectionReason::OlderThan { seconds: 3600 }, 1),
+            ]
+        );
+        assert_eq!(sized_plan(Vec::new()).reason_counts(), []);
+    }
+
+    #[test]
+    fn summary_of_a_fully_executed_plan() {
+        let report = DeleteReport {
+            removed: vec![sized_entry("/a", 0, None), sized_entry("/b", 0, None)],
+            ..Default::default()
+        };
+        assert_eq!(report.summary(), "removed 2 roots");
+        let report = DeleteReport {
+            removed: vec![sized_entry("/a", 0, None)],
+            gone: vec![sized_entry("/b", 0, None)],
+            ..Default::default()
+        };
+        assert_eq!(report.summary(), "removed 1 root, 1 already gone");
+        assert_eq!(report.to_string(), "removed 1 root, 1 already gone");
+    }
+
+    #[test]
+    fn summary_of_a_partially_failed_plan() {
+        let report = DeleteReport {
+            removed: vec![sized_entry("/a", 0, None)],
+            gone: Vec::new(),
+            refused: vec![(sized_entry("/b", 0, None), Drift::ContentsChanged)],
+            failed: vec![
+                (sized_entry("/c", 0, None), "Permission denied".to_string()),
+                (
+                    sized_entry("/d", 0, None),
+                    "Read-only file system".to_string(),
+                ),
+            ],
+            interrupted: vec![sized_entry("/e", 0, None)],
+        };
+        assert_eq!(
+            report.summary(),
+            "removed 1 root, refused 1, 2 failed, interrupted with 1 left"
+        );
+        assert_eq!(
+            report.to_string(),
+            "\
+removed 1 root, refused 1, 2 failed, interrupted with 1 left
+refused to remove 1 root:
+  /b: links inside the directory changed
+failed to remove 2 roots:
+  /c: Permission denied
+  /d: Read-only file system"
+        );
+    }
+
+    #[test]
+    fn summary_of_a_plan_that_did_nothing() {
+        assert_eq!(DeleteReport::default().summary(), "removed 0 roots");
+        assert_eq!(DeleteReport::default().to_string(), "removed 0 roots");
+        let report = DeleteReport {
+            interrupted: vec![sized_entry("/a", 0, None), sized_entry("/b", 0, None)],
+            ..Default::default()
+        };
+        assert_eq!(report.summary(), "removed 0 roots, interrupted with 2 left");
+    }
+
+    #[test]
+    fn saved_plans_load_and_other_versions_are_refused() {
+        let (_dir, dir) = temporary_dir();
+        let (entries, _) = planned(&dir);
+        let path = dir.join("plan.json");
+        sized_plan(entries.clone()).save(&path).unwrap();
+        assert_eq!(Plan::load(&path).unwrap().entries, entries);
+        let json = std::fs::read_to_string(&path)
+            .unwrap()
+            .replace(&format!("\"version\": {PLAN_VERSION}"), "\"version\": 99");
+        std::fs::write(&path, json).unwrap();
+        let error = Plan::load(&path).unwrap_err().to_string();
+        assert_eq!(
+            error,
+            format!("plan {path} has version 99, only version {PLAN_VERSION} is supported")
+        );
+    }
+}
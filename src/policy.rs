@@ -0,0 +1,339 @@
+use std::fmt::Display;
+
+use camino::Utf8Path;
+use eyre::{eyre, Result, WrapErr};
+use serde::Deserialize;
+
+use crate::{
+    config::{CheckConfig, Config, RetentionConfig},
+    duration,
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+/// The shareable part of a config, the thresholds that decide what counts as
+/// too much and the generations clean keeps. Other sections are personal and
+/// aren't part of a policy.
+pub struct Policy {
+    pub check: CheckConfig,
+    pub retention: RetentionConfig,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A single threshold of a policy, with the local value it differs from.
+/// Keys are like `check.max-age`.
+pub enum Change {
+    /// Not set locally.
+    Added { key: String, to: String },
+    /// Stricter than the local value.
+    Tightened {
+        key: String,
+        from: String,
+        to: String,
+    },
+    /// Looser than the local value, only applied with --overwrite.
+    Loosened {
+        key: String,
+        from: String,
+        to: String,
+    },
+}
+
+/// A threshold of a policy and its local value, compared as numbers.
+struct Threshold {
+    section: &'static str,
+    key: &'static str,
+    local: Option<u64>,
+    policy: Option<u64>,
+    /// The policy value as it's written in the document.
+    value: Option<toml::Value>,
+    /// If higher values are stricter, like keeping more generations.
+    /// Lower ones are for limits like max-age.
+    higher_is_stricter: bool,
+}
+
+impl Policy {
+    /// Reads a policy document, reporting invalid durations and unknown
+    /// sections with their line and column.
+    pub fn load(path: &Utf8Path) -> Result<Self> {
+        let contents =
+            std::fs::read_to_string(path).wrap_err_with(|| format!("couldn't read {path}"))?;
+        toml::from_str(&contents).wrap_err_with(|| format!("invalid policy {path}"))
+    }
+
+    /// The policy as a standalone TOML document, with a section for every
+    /// part that sets something.
+    pub fn export(&self) -> String {
+        let mut document = toml::Table::new();
+        for threshold in Self::thresholds(&Policy::default(), self) {
+            let Some(value) = threshold.value else {
+                continue;
+            };
+            let section = document
+                .entry(threshold.section)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()));
+            if let Some(section) = section.as_table_mut() {
+                section.insert(threshold.key.to_string(), value);
+            }
+        }
+        format!(
+            "# gcrs cleanup policy, merge it into a config with gcrs policy import FILE\n{}",
+            toml::to_string(&document).unwrap_or_default()
+        )
+    }
+
+    /// How the thresholds of the policy differ from the local ones.
+    /// Thresholds that are equal or that the policy doesn't set aren't changes.
+    pub fn changes(&self, local: &Policy) -> Vec<Change> {
+        Self::thresholds(local, self)
+            .into_iter()
+            .filter_map(|threshold| {
+                let key = format!("{}.{}", threshold.section, threshold.key);
+                let rendered = threshold.value.as_ref().map(render)?;
+                let to = threshold.policy?;
+                let Some(from) = threshold.local else {
+                    return Some(Change::Added { key, to: rendered });
+                };
+                let from_rendered = Self::render_local(threshold.key, from);
+                match (to.cmp(&from), threshold.higher_is_stricter) {
+                    (std::cmp::Ordering::Equal, _) => None,
+                    (std::cmp::Ordering::Less, false) | (std::cmp::Ordering::Greater, true) => {
+                        Some(Change::Tightened {
+                            key,
+                            from: from_rendered,
+                            to: rendered,
+                        })
+                    }
+                    _ => Some(Change::Loosened {
+                        key,
+                        from: from_rendered,
+                        to: rendered,
+                    }),
+                }
+            })
+            .collect()
+    }
+
+    /// Merges the policy into the config file at path, applying loosened
+    /// thresholds only if overwrite is set. The previous file is kept next to
+    /// it with a `.bak` extension, since comments don't survive the rewrite.
+    /// Returns the changes that were applied.
+    pub fn import(&self, path: &Utf8Path, overwrite: bool) -> Result<Vec<Change>> {
+        let config = Config::load()?;
+        let local = Policy {
+            check: config.check,
+            retention: config.retention,
+        };
+        let changes = self.changes(&local);
+        let blocked = changes
+            .iter()
+            .filter(|change| matches!(change, Change::Loosened { .. }))
+            .collect::<Vec<_>>();
+        if !blocked.is_empty() && !overwrite {
+            let blocked = blocked
+                .iter()
+                .map(|change| change.to_string())
+                .collect::<Vec<_>>()
+                .join("\n  ");
+            return Err(eyre!(
+                "the policy loosens local thresholds, pass --overwrite to apply it anyway:\n  {blocked}"
+            ));
+        }
+        if changes.is_empty() {
+            return Ok(changes);
+        }
+        let contents = match std::fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).wrap_err_with(|| format!("couldn't read {path}")),
+        };
+        let mut document: toml::Table =
+            toml::from_str(&contents).wrap_err_with(|| format!("invalid config file {path}"))?;
+        for threshold in Self::thresholds(&local, self) {
+            let Some(value) = threshold.value else {
+                continue;
+            };
+            let section = threshold.section;
+            document
+                .entry(section)
+                .or_insert_with(|| toml::Value::Table(toml::Table::new()))
+                .as_table_mut()
+                .ok_or_else(|| eyre!("{section} in {path} isn't a table"))?
+                .insert(threshold.key.to_string(), value);
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)
+                .wrap_err_with(|| format!("couldn't create {parent}"))?;
+        }
+        if !contents.is_empty() {
+            let backup = path.with_extension("toml.bak");
+            std::fs::write(&backup, &contents)
+                .wrap_err_with(|| format!("couldn't back up {path} to {backup}"))?;
+        }
+        std::fs::write(path, toml::to_string(&document)?)
+            .wrap_err_with(|| format!("couldn't write {path}"))?;
+        Ok(changes)
+    }
+
+    fn thresholds(local: &Policy, policy: &Policy) -> Vec<Threshold> {
+        let count = |n: Option<usize>| n.map(|n| n as u64);
+        let secs = |d: Option<std::time::Duration>| d.map(|d| d.as_secs());
+        let integer = |n: Option<usize>| n.map(|n| toml::Value::Integer(n as i64));
+        let (check, retention) = (&policy.check, &policy.retention);
+        let threshold = |section, key, local, policy, value, higher_is_stricter| Threshold {
+            section,
+            key,
+            local,
+            policy,
+            value,
+            higher_is_stricter,
+        };
+        vec![
+            threshold(
+                "check",
+                "max-standalone",
+                count(local.check.max_standalone),
+                count(check.max_standalone),
+                integer(check.max_standalone),
+                false,
+            ),
+            threshold(
+                "check",
+                "max-age",
+                secs(local.check.max_age),
+                secs(check.max_age),
+                check
+                    .max_age
+                    .map(|d| toml::Value::String(duration::format_exact(d))),
+                false,
+            ),
+            threshold(
+                "check",
+                "max-generations",
+                count(local.check.max_generations),
+                count(check.max_generations),
+                integer(check.max_generations),
+                false,
+            ),
+            threshold(
+                "retention",
+                "keep-monthly",
+                count(local.retention.keep_monthly),
+                count(retention.keep_monthly),
+                integer(retention.keep_monthly),
+                true,
+            ),
+            threshold(
+                "retention",
+                "keep-last",
+                count(local.retention.keep_last),
+                count(retention.keep_last),
+                integer(retention.keep_last),
+                true,
+            ),
+        ]
+    }
+
+    fn render_local(key: &str, value: u64) -> String {
+        match key {
+            "max-age" => duration::format_exact(std::time::Duration::from_secs(value)),
+            _ => value.to_string(),
+        }
+    }
+}
+
+fn render(value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Change::Added { key, to } => write!(f, "{key}: set to {to}"),
+            Change::Tightened { key, from, to } => {
+                write!(f, "{key}: tightened from {from} to {to}")
+            }
+            Change::Loosened { key, from, to } => {
+                write!(f, "{key}: loosened from {from} to {to}")
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn policy(toml: &str) -> Policy {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn export_includes_retention() {
+        let exported = policy("[check]\nmax-age = \"30d\"\n[retention]\nkeep-last = 3\n").export();
+        let reread = policy(&exported);
+        assert_eq!(
+            reread.check.max_age,
+            Some(std::time::Duration::from_secs(30 * 86400))
+        );
+        assert_eq!(reread.retention.keep_last, Some(3));
+        assert_eq!(reread.retention.keep_monthly, None);
+    }
+
+    #[test]
+    fn export_leaves_out_unset_sections() {
+        let exported = policy("[check]\nmax-standalone = 10\n").export();
+        assert!(!exported.contains("[retention]"), "{exported}");
+    }
+
+    #[test]
+    fn keeping_fewer_generations_loosens() {
+        let local = policy("[retention]\nkeep-last = 5\nkeep-monthly = 2\n");
+        let changes = policy("[retention]\nkeep-last = 3\nkeep-monthly = 6\n").changes(&local);
+        assert_eq!(
+            changes,
+            [
+                Change::Tightened {
+                    key: "retention.keep-monthly".to_string(),
+                    from: "2".to_string(),
+                    to: "6".to_string(),
+                },
+                Change::Loosened {
+                    key: "retention.keep-last".to_string(),
+                    from: "5".to_string(),
+                    to: "3".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn lower_limits_tighten() {
+        let local = policy("[check]\nmax-age = \"90d\"\nmax-generations = 5\n");
+        let changes =
+            policy("[check]\nmax-age = \"30d\"\nmax-generations = 5\nmax-standalone = 1\n")
+                .changes(&local);
+        assert_eq!(
+            changes,
+            [
+                Change::Added {
+                    key: "check.max-standalone".to_string(),
+                    to: "1".to_string(),
+                },
+                Change::Tightened {
+                    key: "check.max-age".to_string(),
+                    from: "90d".to_string(),
+                    to: "30d".to_string(),
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn unknown_sections_are_rejected() {
+        assert!(toml::from_str::<Policy>("[display]\nutc = true\n").is_err());
+    }
+}
@@ -0,0 +1,191 @@
+use std::{
+    io::{BufRead, IsTerminal, Write},
+    time::{Duration, SystemTime},
+};
+
+use eyre::{eyre, Result};
+
+use crate::{
+    clipboard, duration,
+    output::{self, out},
+    plan::PlanEntry,
+};
+
+/// Asks for confirmation before deleting, unless `yes` is set.
+/// Without `yes` stdin has to be a terminal, so that automation never
+/// deletes based on whatever happens to be piped in.
+pub fn confirm_deletion(question: &str, yes: bool) -> Result<bool> {
+    if yes {
+        return Ok(true);
+    }
+    if !std::io::stdin().is_terminal() {
+        return Err(eyre!(
+            "refusing to delete without --yes in non-interactive mode"
+        ));
+    }
+    confirm(question)
+}
+
+/// Asks a yes/no question on stdout, reading the answer from stdin.
+/// Anything other than y or yes counts as no.
+pub fn confirm(question: &str) -> Result<bool> {
+    out!("{question} [y/N] ");
+    output::flush();
+    let mut answer = String::new();
+    std::io::stdin().lock().read_line(&mut answer)?;
+    Ok(matches!(answer.trim(), "y" | "Y" | "yes"))
+}
+
+/// Answer to the per-entry question of [review].
+enum Answer {
+    Yes,
+    No,
+    All,
+    Quit,
+    /// Copy the path of the entry to the clipboard and ask again.
+    Copy,
+}
+
+/// Asks about every entry whether it should be deleted, accepting y(es), n(o),
+/// a(ll remaining) and q(uit), and asking again on anything else.
+/// c(opy) puts the path of the entry on the clipboard.
+/// Returns the chosen entries, or None if the user quit, which keeps everything.
+/// Reaching the end of input also counts as quitting.
+pub fn review(
+    entries: Vec<PlanEntry>,
+    now: SystemTime,
+    input: &mut impl BufRead,
+    output: &mut impl Write,
+) -> Result<Option<Vec<PlanEntry>>> {
+    let mut chosen = Vec::new();
+    let mut entries = entries.into_iter();
+    while let Some(entry) = entries.next() {
+        writeln!(output, "{} -> {}", entry.path, entry.target)?;
+        if let Some(mtime) = entry.mtime {
+            let mtime = SystemTime::UNIX_EPOCH + Duration::from_secs(mtime);
+            if let Ok(age) = now.duration_since(mtime) {
+                writeln!(output, "  age: {}", duration::format(age))?;
+            }
+        }
+        let mut answer = ask(input, output)?;
+        while let Answer::Copy = answer {
+            match clipboard::copy(entry.path.as_str()) {
+                Ok(()) => writeln!(output, "copied 1 path")?,
+                Err(e) => writeln!(output, "couldn't copy: {e}")?,
+            }
+            answer = ask(input, output)?;
+        }
+        match answer {
+            Answer::Yes => chosen.push(entry),
+            Answer::No => {}
+            Answer::All => {
+                chosen.push(entry);
+                chosen.extend(entries);
+                break;
+            }
+            Answer::Quit => return Ok(None),
+            Answer::Copy => unreachable!("copying asks again"),
+        }
+    }
+    Ok(Some(chosen))
+}
+
+fn ask(input: &mut impl BufRead, output: &mut impl Write) -> Result<Answer> {
+    loop {
+        write!(output, "delete? [y/n/a/q/c] ")?;
+        output.flush()?;
+        let mut answer = String::new();
+        if input.read_line(&mut answer)? == 0 {
+            writeln!(output)?;
+            return Ok(Answer::Quit);
+        }
+        match answer.trim() {
+            "y" | "yes" => return Ok(Answer::Yes),
+            "n" | "no" => return Ok(Answer::No),
+            "a" | "all" => return Ok(Answer::All),
+            "q" | "quit" => return Ok(Answer::Quit),
+            "c" | "copy" => return Ok(Answer::Copy),
+            _ => writeln!(output, "please answer y, n, a, q or c")?,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use camino::Utf8PathBuf;
+
+    use super::*;
+    use crate::{
+        gcroot::NodeType,
+        plan::{EntryKind, SelectionReason},
+    };
+
+    const NOW: u64 = 100 * 24 * 60 * 60;
+
+    fn entry(path: &str) -> PlanEntry {
+        PlanEntry {
+            path: Utf8PathBuf::from(path),
+            target: Utf8PathBuf::from("/nix/store/aaaa-target"),
+            kind: EntryKind::Standalone,
+            reasons: vec![SelectionReason::Requested],
+            node: NodeType::Symlink,
+            contents: Vec::new(),
+            mtime: Some(NOW - 3 * 24 * 60 * 60),
+            size: None,
+            substitutable: None,
+        }
+    }
+
+    /// The paths chosen with the keystrokes and everything that was printed.
+    fn review_with(keys: &str) -> (Option<Vec<String>>, String) {
+        let entries = vec![entry("/a"), entry("/b"), entry("/c")];
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(NOW);
+        let mut output = Vec::new();
+        let chosen = review(entries, now, &mut Cursor::new(keys), &mut output).unwrap();
+        let chosen =
+            chosen.map(|entries| entries.into_iter().map(|e| e.path.to_string()).collect());
+        (chosen, String::from_utf8(output).unwrap())
+    }
+
+    #[test]
+    fn every_entry_is_asked_about() {
+        let (chosen, output) = review_with("y\nno\nyes\n");
+        assert_eq!(chosen.unwrap(), ["/a", "/c"]);
+        assert_eq!(
+            output.lines().next().unwrap(),
+            "/a -> /nix/store/aaaa-target"
+        );
+        assert_eq!(output.lines().nth(1).unwrap(), "  age: 3d");
+        assert_eq!(output.matches("delete? [y/n/a/q/c] ").count(), 3);
+    }
+
+    #[test]
+    fn invalid_answers_are_asked_again() {
+        let (chosen, output) = review_with("maybe\n\nn\nn\nn\n");
+        assert_eq!(chosen.unwrap(), Vec::<String>::new());
+        assert_eq!(output.matches("please answer y, n, a, q or c\n").count(), 2);
+        assert_eq!(output.matches("delete? ").count(), 5);
+    }
+
+    #[test]
+    fn all_accepts_the_remaining_entries() {
+        let (chosen, output) = review_with("n\na\n");
+        assert_eq!(chosen.unwrap(), ["/b", "/c"]);
+        assert!(!output.contains("/c -> "));
+    }
+
+    #[test]
+    fn quitting_keeps_everything() {
+        let (chosen, _) = review_with("y\nq\n");
+        assert_eq!(chosen, None);
+    }
+
+    #[test]
+    fn end_of_input_quits() {
+        let (chosen, output) = review_with("y\n");
+        assert_eq!(chosen, None);
+        assert!(output.ends_with("delete? [y/n/a/q/c] \n"));
+    }
+}
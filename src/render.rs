@@ -0,0 +1,866 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    io::IsTerminal,
+    sync::OnceLock,
+    time::{Duration, SystemTime},
+};
+
+use camino::Utf8Path;
+use clap::ValueEnum;
+use nix::unistd::{Uid, User};
+use serde::Serialize;
+
+use crate::{
+    date::DateStyle,
+    duration,
+    gcroot::{GCRoot, GCRoots, MetadataError, NodeType, Profile, RootMetadata, RootStatus, Target},
+    size,
+    timing::{self, Phase},
+    user::Attribution,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+/// Output format of the print subcommand.
+pub enum Format {
+    /// Profiles with their generations, followed by standalone roots
+    Grouped,
+    /// One root per line as path and target separated by a tab, sorted by path,
+    /// a stable format for scripts
+    Plain,
+    /// Aligned columns with a header
+    Table,
+    /// Comma-separated values with a header
+    Csv,
+    /// A JSON object, see [json] for the schema
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+/// A column of the table and csv formats.
+pub enum Column {
+    /// Location of the symlink
+    Path,
+    /// Where the symlink points to
+    Target,
+    /// Target without the store directory and hash
+    ShortTarget,
+    /// generation or standalone
+    Kind,
+    /// What kind of file the root is: symlink, directory, file or other
+    Node,
+    /// Profile the root is a generation of
+    Profile,
+    /// Generation number
+    Generation,
+    /// If the root is the active generation of its profile
+    Active,
+    /// Time since the symlink was last modified
+    Age,
+    /// Modification time of the symlink, rendered as set by --date-format
+    Mtime,
+    /// Owner of the symlink
+    Owner,
+    /// User the root belongs to, by the home directory it's in or else its owner
+    User,
+    /// Closure size of the target
+    Size,
+    /// If the root can be deleted
+    Deletable,
+    /// If the symlink could be inspected: ok or denied
+    Access,
+    /// live, broken, target missing or status unknown
+    Status,
+}
+
+/// Columns used when --columns isn't given.
+pub const DEFAULT_COLUMNS: &[Column] = &[
+    Column::Path,
+    Column::Target,
+    Column::Kind,
+    Column::Generation,
+    Column::Active,
+];
+
+impl Column {
+    fn header(self) -> &'static str {
+        match self {
+            Column::Path => "path",
+            Column::Target => "target",
+            Column::ShortTarget => "short-target",
+            Column::Kind => "kind",
+            Column::Node => "node",
+            Column::Profile => "profile",
+            Column::Generation => "generation",
+            Column::Active => "active",
+            Column::Age => "age",
+            Column::Mtime => "mtime",
+            Column::Owner => "owner",
+            Column::User => "user",
+            Column::Size => "size",
+            Column::Deletable => "deletable",
+            Column::Access => "access",
+            Column::Status => "status",
+        }
+    }
+
+    fn needs_metadata(self) -> bool {
+        matches!(
+            self,
+            Column::Node
+                | Column::Age
+                | Column::Mtime
+                | Column::Owner
+                | Column::User
+                | Column::Access
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// State of a root shown by an icon, see [ICONS].
+pub enum RootState {
+    /// Active generation of its profile.
+    Active,
+    /// The root or its target doesn't exist, see [RootStatus::is_dead].
+    Broken,
+    NotDeletable,
+    /// Older than the stale threshold.
+    Stale,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Glyphs used for icons.
+pub enum IconSet {
+    Unicode,
+    /// For terminals without UTF-8.
+    Ascii,
+}
+
+/// Icon of every state as (state, unicode glyph, its width in columns, ascii glyph),
+/// in order of priority when a root is in several states at once.
+pub const ICONS: &[(RootState, &str, usize, &str)] = &[
+    (RootState::Active, "✓", 1, "*"),
+    (RootState::Broken, "⚠", 1, "!"),
+    (RootState::NotDeletable, "🔒", 2, "#"),
+    (RootState::Stale, "⏳", 2, "~"),
+];
+
+/// Width in columns every icon is padded to.
+const ICON_WIDTH: usize = 2;
+
+#[derive(Debug, Clone)]
+/// How roots are prefixed with icons.
+pub struct Icons {
+    pub set: IconSet,
+    /// Roots older than this are stale, nothing is stale if None.
+    pub stale_after: Option<Duration>,
+}
+
+impl IconSet {
+    /// Unicode, unless the locale isn't UTF-8.
+    pub fn detect() -> Self {
+        let locale = ["LC_ALL", "LC_CTYPE", "LANG"]
+            .iter()
+            .filter_map(|var| std::env::var(var).ok())
+            .find(|value| !value.is_empty())
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match locale.contains("utf-8") || locale.contains("utf8") {
+            true => IconSet::Unicode,
+            false => IconSet::Ascii,
+        }
+    }
+}
+
+impl Icons {
+    /// The state of a root with the highest priority, if any.
+    /// active is Some if the root is a profile generation.
+    pub fn state(
+        &self,
+        gcroot: &GCRoot,
+        active: Option<bool>,
+        now: SystemTime,
+    ) -> Option<RootState> {
+        let in_state = |state: RootState| match state {
+            RootState::Active => active == Some(true),
+            RootState::Broken => gcroot.status.is_dead(),
+            RootState::NotDeletable => !gcroot.deletable(),
+            RootState::Stale => self.stale_after.is_some_and(|stale_after| {
+                gcroot
+                    .modified()
+                    .ok()
+                    .and_then(|mtime| now.duration_since(mtime).ok())
+                    .is_some_and(|age| age > stale_after)
+            }),
+        };
+        ICONS
+            .iter()
+            .map(|(state, ..)| *state)
+            .find(|state| in_state(*state))
+    }
+
+    /// Icon of the state and a space, padded to the same width for every state,
+    /// or blank if there is none.
+    pub fn prefix(&self, state: Option<RootState>) -> String {
+        let icon = ICONS.iter().find(|(s, ..)| Some(*s) == state);
+        let (glyph, width) = match (icon, self.set) {
+            (None, _) => ("", 0),
+            (Some((_, glyph, width, _)), IconSet::Unicode) => (*glyph, *width),
+            (Some((_, _, _, glyph)), IconSet::Ascii) => (*glyph, 1),
+        };
+        format!("{glyph}{} ", " ".repeat(ICON_WIDTH - width))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+/// When paths are printed as clickable OSC 8 hyperlinks.
+pub enum HyperlinkMode {
+    /// When stdout is a terminal and TERM isn't dumb
+    Auto,
+    Always,
+    Never,
+}
+
+impl HyperlinkMode {
+    /// If hyperlinks are printed in this mode.
+    pub fn enabled(self) -> bool {
+        match self {
+            HyperlinkMode::Auto => {
+                std::io::stdout().is_terminal()
+                    && std::env::var("TERM").is_ok_and(|term| !term.is_empty() && term != "dumb")
+            }
+            HyperlinkMode::Always => true,
+            HyperlinkMode::Never => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+/// Extras added to the grouped and table formats.
+pub struct Decorations {
+    pub icons: Option<Icons>,
+    /// Print root and profile paths as hyperlinks.
+    pub hyperlinks: bool,
+}
+
+impl Decorations {
+    fn prefix(&self, state: impl FnOnce(&Icons) -> Option<RootState>) -> String {
+        self.icons
+            .as_ref()
+            .map(|icons| icons.prefix(state(icons)))
+            .unwrap_or_default()
+    }
+
+    fn path(&self, path: &Utf8Path) -> String {
+        match self.hyperlinks {
+            true => hyperlink(path, path.as_str()),
+            false => path.to_string(),
+        }
+    }
+}
+
+const OSC8_START: &str = "\x1b]8;;";
+const OSC8_END: &str = "\x1b\\";
+
+/// Wraps text in an OSC 8 hyperlink to the file:// URL of the path.
+pub fn hyperlink(path: &Utf8Path, text: &str) -> String {
+    static HOST: OnceLock<String> = OnceLock::new();
+    let host = HOST.get_or_init(|| {
+        nix::unistd::gethostname()
+            .ok()
+            .and_then(|host| host.into_string().ok())
+            .unwrap_or_default()
+    });
+    let mut url = format!("file://{host}");
+    for byte in path.as_str().bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'.' | b'_' | b'~' => {
+                url.push(byte as char)
+            }
+            _ => {
+                let _ = write!(url, "%{byte:02X}");
+            }
+        }
+    }
+    format!("{OSC8_START}{url}{OSC8_END}{text}{OSC8_START}{OSC8_END}")
+}
+
+/// Number of terminal columns a cell takes up, counting icons that are
+/// wider than one column and skipping hyperlink escape sequences.
+fn display_width(cell: &str) -> usize {
+    let mut visible = String::new();
+    let mut rest = cell;
+    while let Some(start) = rest.find(OSC8_START) {
+        visible.push_str(&rest[..start]);
+        rest = &rest[start..];
+        rest = match rest.find(OSC8_END) {
+            Some(end) => &rest[end + OSC8_END.len()..],
+            None => "",
+        };
+    }
+    visible.push_str(rest);
+    let wide = ICONS
+        .iter()
+        .filter(|(_, _, width, _)| *width > 1)
+        .map(|(_, glyph, width, _)| visible.matches(glyph).count() * (width - 1))
+        .sum::<usize>();
+    visible.chars().count() + wide
+}
+
+/// Renders roots grouped like the default print format, with decorations.
+pub fn grouped(gcroots: &GCRoots, decorations: &Decorations) -> String {
+    let now = SystemTime::now();
+    let mut lines = Vec::new();
+    for profile in gcroots.profiles() {
+        if !lines.is_empty() {
+            lines.push(String::new());
+        }
+        let total = profile
+            .closure_size
+            .map(|total| format!(" ({} in total)", size::format(total)))
+            .unwrap_or_default();
+        lines.push(format!("{}{}", decorations.path(&profile.path), total));
+        let digits = profile.latest().map_or(1, |(id, _)| id.to_string().len());
+        let width = profile.size_width();
+        for (id, generation) in profile.generations_desc() {
+            let active = profile.active_generation == Some(id);
+            let marker = match active {
+                true => '>',
+                false => ' ',
+            };
+            let id = format!("{: >digits$}", id);
+            let id = match decorations.hyperlinks {
+                true => hyperlink(&generation.path, &id),
+                false => id,
+            };
+            let size = width
+                .map(|width| format!(" {: >width$}", size::cell(generation.closure_size)))
+                .unwrap_or_default();
+            lines.push(format!(
+                "{}{} {}{} -> {}{}",
+                decorations.prefix(|icons| icons.state(generation, Some(active), now)),
+                marker,
+                id,
+                size,
+                generation.target,
+                generation.status.annotation()
+            ));
+        }
+    }
+    if !gcroots.standalone().is_empty() && !lines.is_empty() {
+        lines.push(String::new());
+    }
+    let width = gcroots.standalone_size_width();
+    for gcroot in gcroots.standalone() {
+        let size = width
+            .map(|width| format!("{: >width$}  ", size::cell(gcroot.closure_size)))
+            .unwrap_or_default();
+        lines.push(format!(
+            "{}{}{} -> {}{}",
+            decorations.prefix(|icons| icons.state(gcroot, None, now)),
+            size,
+            decorations.path(&gcroot.path),
+            gcroot.target,
+            gcroot.status.annotation()
+        ));
+        if gcroot.node_type().ok() == Some(NodeType::Directory) {
+            for (link, target) in gcroot
+                .contained_links(gcroots.store_dirs())
+                .unwrap_or_default()
+            {
+                lines.push(format!(
+                    "{}  {} -> {}",
+                    decorations.prefix(|_| None),
+                    decorations.path(&link),
+                    target
+                ));
+            }
+        }
+    }
+    lines.join("\n")
+}
+
+/// A single root as displayed in the table and csv formats.
+struct Row<'a> {
+    gcroot: &'a GCRoot,
+    /// Some((profile, generation number)) if the root is a profile generation.
+    generation: Option<(&'a Profile, u64)>,
+    /// Result of the lstat, only read if a column needs it.
+    metadata: Option<std::result::Result<RootMetadata, MetadataError>>,
+}
+
+impl<'a> Row<'a> {
+    /// Roots found gone while reading their metadata are left out.
+    fn collect(gcroots: &'a GCRoots, columns: &[Column]) -> Vec<Row<'a>> {
+        let needs_metadata = columns.iter().any(|c| c.needs_metadata());
+        let generations = gcroots.profiles().iter().flat_map(|profile| {
+            profile
+                .generations
+                .iter()
+                .map(move |(id, gcroot)| (gcroot, Some((profile, *id))))
+        });
+        let standalone = gcroots.standalone().iter().map(|gcroot| (gcroot, None));
+        let rows = generations
+            .chain(standalone)
+            .map(|(gcroot, generation)| Row {
+                gcroot,
+                generation,
+                metadata: needs_metadata.then(|| gcroot.metadata()),
+            });
+        let collect = || {
+            rows.filter(|row| !matches!(row.metadata, Some(Err(MetadataError::NotFound))))
+                .collect()
+        };
+        match needs_metadata {
+            true => timing::time(Phase::Enrichment("metadata"), collect),
+            false => collect(),
+        }
+    }
+
+    fn cell(&self, column: Column, now: SystemTime, dates: &DateStyle) -> String {
+        let metadata = self.metadata.as_ref().and_then(|m| m.as_ref().ok());
+        match column {
+            Column::Path => self.gcroot.path.to_string(),
+            Column::Target => self.gcroot.target.as_str().to_string(),
+            Column::ShortTarget => short_root_target(&self.gcroot.target).to_string(),
+            Column::Kind => match self.generation {
+                Some(_) => "generation".to_string(),
+                None => "standalone".to_string(),
+            },
+            Column::Node => metadata.map(|m| m.node.to_string()).unwrap_or_default(),
+            Column::Profile => self
+                .generation
+                .map(|(profile, _)| profile.path.to_string())
+                .unwrap_or_default(),
+            Column::Generation => self
+                .generation
+                .map(|(_, id)| id.to_string())
+                .unwrap_or_default(),
+            Column::Active => self
+                .generation
+                .map(|(profile, id)| (profile.active_generation == Some(id)).to_string())
+                .unwrap_or_default(),
+            Column::Age => metadata
+                .and_then(|m| now.duration_since(m.mtime).ok())
+                .map(duration::format)
+                .unwrap_or_default(),
+            Column::Mtime => metadata
+                .map(|m| dates.render(m.mtime, now))
+                .unwrap_or_default(),
+            Column::Owner => metadata.map(|m| owner_name(m.uid)).unwrap_or_default(),
+            Column::User => Attribution::system()
+                .user_of(&self.gcroot.path, metadata.map(|m| m.uid))
+                .unwrap_or_default(),
+            Column::Size => size::cell(self.gcroot.closure_size),
+            Column::Deletable => self.gcroot.deletable().to_string(),
+            Column::Access => match &self.metadata {
+                Some(Ok(_)) => "ok".to_string(),
+                Some(Err(_)) => "denied".to_string(),
+                None => String::new(),
+            },
+            Column::Status => self.gcroot.status.to_string(),
+        }
+    }
+}
+
+/// Like [short_target] for targets in a store, targets elsewhere are kept whole.
+pub fn short_root_target(target: &Target) -> &str {
+    match target {
+        Target::Store(path) => short_target(path),
+        Target::Other(path) => path.as_str(),
+    }
+}
+
+/// Strips the store directory and hash from a store path,
+/// e.g. `/nix/store/<hash>-hello-2.12` becomes `hello-2.12`.
+/// Anything that doesn't look like a store path is returned unchanged.
+pub fn short_target(target: &camino::Utf8Path) -> &str {
+    target
+        .file_name()
+        .and_then(|name| name.split_once('-'))
+        .filter(|(hash, _)| hash.len() == 32)
+        .map(|(_, name)| name)
+        .unwrap_or(target.as_str())
+}
+
+/// Name of the user with the given uid, or the uid itself if there's no such user.
+pub(crate) fn owner_name(uid: u32) -> String {
+    User::from_uid(Uid::from_raw(uid))
+        .ok()
+        .flatten()
+        .map(|user| user.name)
+        .unwrap_or_else(|| uid.to_string())
+}
+
+fn cells(rows: &[Row], columns: &[Column], dates: &DateStyle) -> Vec<Vec<String>> {
+    let now = SystemTime::now();
+    let header = columns.iter().map(|c| c.header().to_string()).collect();
+    let rows = rows
+        .iter()
+        .map(|row| columns.iter().map(|c| row.cell(*c, now, dates)).collect());
+    std::iter::once(header).chain(rows).collect()
+}
+
+/// Renders roots as aligned columns with a header, with decorations.
+/// Targets outside the store are noted like in the grouped format.
+pub fn table(
+    gcroots: &GCRoots,
+    columns: &[Column],
+    dates: &DateStyle,
+    decorations: &Decorations,
+) -> String {
+    let rows = Row::collect(gcroots, columns);
+    let mut cells = cells(&rows, columns, dates);
+    if let Some(i) = columns.iter().position(|column| *column == Column::Target) {
+        for (row, cells) in rows.iter().zip(cells.iter_mut().skip(1)) {
+            if !row.gcroot.target.is_store() {
+                cells[i].push_str(" (not a store path)");
+            }
+        }
+    }
+    if decorations.hyperlinks {
+        let linked = columns
+            .iter()
+            .enumerate()
+            .filter(|(_, column)| matches!(column, Column::Path | Column::Profile));
+        for (i, _) in linked {
+            for row in cells.iter_mut().skip(1).filter(|row| !row[i].is_empty()) {
+                row[i] = hyperlink(Utf8Path::new(&row[i]), &row[i]);
+            }
+        }
+    }
+    if let Some(icons) = &decorations.icons {
+        let now = SystemTime::now();
+        // From the same rows as the cells, which leave out roots found gone.
+        let states = rows.iter().map(|row| {
+            let active = row
+                .generation
+                .map(|(profile, id)| profile.active_generation == Some(id));
+            icons.prefix(icons.state(row.gcroot, active, now))
+        });
+        let prefixes = std::iter::once(String::new()).chain(states);
+        for (row, prefix) in cells.iter_mut().zip(prefixes) {
+            row.insert(0, prefix);
+        }
+    }
+    let widths = (0..cells[0].len())
+        .map(|i| {
+            cells
+                .iter()
+                .map(|row| display_width(&row[i]))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect::<Vec<_>>();
+    let mut output = String::new();
+    for (index, row) in cells.iter().enumerate() {
+        if index != 0 {
+            output.push('\n');
+        }
+        let mut line = String::new();
+        for (i, cell) in row.iter().enumerate() {
+            if i + 1 < row.len() {
+                let padding = widths[i] - display_width(cell);
+                let _ = write!(line, "{}{}  ", cell, " ".repeat(padding));
+            } else {
+                line.push_str(cell);
+            }
+        }
+        output.push_str(line.trim_end());
+    }
+    output
+}
+
+/// Version of the print --format json schema, bumped on incompatible changes.
+/// Fields may be added without a bump.
+pub const JSON_VERSION: u32 = 1;
+
+#[derive(Serialize)]
+/// Schema of print --format json. Collections are always present, empty or not.
+struct JsonRoots<'a> {
+    version: u32,
+    profiles: Vec<JsonProfile<'a>>,
+    standalone: Vec<JsonRoot<'a>>,
+}
+
+#[derive(Serialize)]
+struct JsonProfile<'a> {
+    path: &'a Utf8Path,
+    /// null if it's unknown.
+    active_generation: Option<u64>,
+    /// By generation number, as JSON object keys are strings.
+    generations: BTreeMap<u64, JsonRoot<'a>>,
+    /// Only with print --sizes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    closure_size: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct JsonRoot<'a> {
+    path: &'a Utf8Path,
+    target: &'a Utf8Path,
+    deletable: bool,
+    status: RootStatus,
+    /// Only with print --sizes, null if the target isn't in the store.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    closure_size: Option<Option<u64>>,
+}
+
+impl<'a> JsonRoot<'a> {
+    fn of(gcroot: &'a GCRoot, sizes: bool) -> Self {
+        JsonRoot {
+            path: &gcroot.path,
+            target: &gcroot.target,
+            deletable: gcroot.deletable(),
+            status: gcroot.status,
+            closure_size: sizes.then_some(gcroot.closure_size),
+        }
+    }
+}
+
+/// The JSON document of roots:
+/// `{"version", "profiles": [{"path", "active_generation", "generations":
+/// {"N": root}}], "standalone": [root]}` where a root is
+/// `{"path", "target", "deletable", "status"}` with a status of `live`,
+/// `broken-link`, `missing-target` or `unknown`. Profiles and roots have a `"closure_size"`
+/// after print --sizes, null for roots whose target isn't in the store.
+pub fn json(gcroots: &GCRoots) -> impl Serialize + '_ {
+    let sizes = gcroots.sizes_gathered();
+    JsonRoots {
+        version: JSON_VERSION,
+        profiles: gcroots
+            .profiles()
+            .iter()
+            .map(|profile| JsonProfile {
+                path: &profile.path,
+                active_generation: profile.active_generation,
+                generations: profile
+                    .generations
+                    .iter()
+                    .map(|(id, gcroot)| (*id, JsonRoot::of(gcroot, sizes)))
+                    .collect(),
+                closure_size: profile.closure_size,
+            })
+            .collect(),
+        standalone: gcroots
+            .standalone()
+            .iter()
+            .map(|gcroot| JsonRoot::of(gcroot, sizes))
+            .collect(),
+    }
+}
+
+/// Renders roots in the plain format for scripts, which is kept stable
+/// regardless of how the other formats change: a line per listed root with
+/// its path, a tab and its target as they are, generations included and not
+/// grouped by profile, sorted by path. Nothing else is printed, not even an
+/// empty line without roots.
+pub fn plain(gcroots: &GCRoots) -> String {
+    let mut roots = gcroots
+        .profiles()
+        .iter()
+        .flat_map(|profile| profile.generations.values())
+        .chain(gcroots.standalone())
+        .collect::<Vec<_>>();
+    roots.sort_by(|a, b| a.path.cmp(&b.path));
+    roots
+        .into_iter()
+        .map(|gcroot| format!("{}\t{}\n", gcroot.path, gcroot.target.as_str()))
+        .collect()
+}
+
+/// Renders roots as comma-separated values with a header.
+pub fn csv(gcroots: &GCRoots, columns: &[Column], dates: &DateStyle) -> String {
+    cells(&Row::collect(gcroots, columns), columns, dates)
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|cell| csv_field(cell))
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn csv_field(cell: &str) -> String {
+    if cell.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", cell.replace('"', "\"\""))
+    } else {
+        cell.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+
+    use super::*;
+
+    fn decorations(set: IconSet) -> Decorations {
+        Decorations {
+            icons: Some(Icons {
+                set,
+                stale_after: None,
+            }),
+            hyperlinks: false,
+        }
+    }
+
+    /// Table of a root that can't be deleted, then one that can't be deleted
+    /// and is gone, then one that can be deleted, with the expected width of
+    /// the path column.
+    fn table_with(set: IconSet) -> (String, Utf8PathBuf, usize) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        std::os::unix::fs::symlink("/nix/store/aaaa-a", path.join("a")).unwrap();
+        let output = format!(
+            "/run -> /nix/store/bbbb-b\n\
+            /run/gcrs-gone-link -> /nix/store/cccc-c\n\
+            {path}/a -> /nix/store/aaaa-a\n"
+        );
+        let gcroots = GCRoots::from_print_roots_output(&output).unwrap();
+        let columns = [Column::Path, Column::Node];
+        let table = table(&gcroots, &columns, &DateStyle::default(), &decorations(set));
+        let width = path.join("a").as_str().len();
+        (table, path, width)
+    }
+
+    #[test]
+    fn unicode_icons_stay_with_their_rows() {
+        let (table, path, width) = table_with(IconSet::Unicode);
+        let expected = [
+            format!("     {:<width$}  node", "path"),
+            format!("🔒   {:<width$}  directory", "/run"),
+            format!("     {path}/a  symlink"),
+        ];
+        assert_eq!(table.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn ascii_icons_stay_with_their_rows() {
+        let (table, path, width) = table_with(IconSet::Ascii);
+        let expected = [
+            format!("     {:<width$}  node", "path"),
+            format!("#    {:<width$}  directory", "/run"),
+            format!("     {path}/a  symlink"),
+        ];
+        assert_eq!(table.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn csv_has_the_columns_in_the_requested_order() {
+        let output = "/home/a/result -> /nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-hello-2.12\n";
+        let gcroots = GCRoots::from_print_roots_output(output).unwrap();
+        let columns = [Column::ShortTarget, Column::Kind, Column::Path];
+        assert_eq!(
+            csv(&gcroots, &columns, &DateStyle::default()),
+            "short-target,kind,path\nhello-2.12,standalone,/home/a/result"
+        );
+    }
+
+    #[test]
+    fn csv_fields_are_quoted_when_needed() {
+        assert_eq!(csv_field("/home/a/result"), "/home/a/result");
+        assert_eq!(csv_field("/home/a,b/result"), "\"/home/a,b/result\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("two\nlines"), "\"two\nlines\"");
+    }
+
+    #[test]
+    fn every_column_is_named_like_its_value() {
+        for column in Column::value_variants() {
+            let value = column.to_possible_value().unwrap();
+            assert_eq!(value.get_name(), column.header());
+        }
+    }
+
+    #[test]
+    fn short_targets() {
+        let store = Target::Store(
+            Utf8Path::new("/nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-hello-2.12").into(),
+        );
+        assert_eq!(short_root_target(&store), "hello-2.12");
+        let other = Target::Other(Utf8Path::new("/home/a/hello-2.12").into());
+        assert_eq!(short_root_target(&other), "/home/a/hello-2.12");
+        assert_eq!(
+            short_target("/nix/store/short-name".into()),
+            "/nix/store/short-name"
+        );
+    }
+
+    #[test]
+    fn icons_are_padded_to_the_same_width() {
+        for set in [IconSet::Unicode, IconSet::Ascii] {
+            let icons = Icons {
+                set,
+                stale_after: None,
+            };
+            let widths = ICONS
+                .iter()
+                .map(|(state, ..)| display_width(&icons.prefix(Some(*state))))
+                .chain([display_width(&icons.prefix(None))])
+                .collect::<Vec<_>>();
+            assert!(
+                widths.iter().all(|width| *width == ICON_WIDTH + 1),
+                "{widths:?}"
+            );
+        }
+    }
+
+    /// The text of a cell without its hyperlink escape sequences.
+    fn without_hyperlinks(cell: &str) -> String {
+        let mut visible = String::new();
+        let mut rest = cell;
+        while let Some(start) = rest.find(OSC8_START) {
+            visible.push_str(&rest[..start]);
+            let end = rest[start..].find(OSC8_END).unwrap();
+            rest = &rest[start + end + OSC8_END.len()..];
+        }
+        visible + rest
+    }
+
+    #[test]
+    fn hyperlinks_are_framed_by_an_opening_and_closing_osc8() {
+        let link = hyperlink(Utf8Path::new("/home/a b/result"), "result");
+        let (open, rest) = link.split_once(OSC8_END).unwrap();
+        let url = open.strip_prefix(OSC8_START).unwrap();
+        assert!(url.starts_with("file://"), "{url}");
+        assert!(url.ends_with("/home/a%20b/result"), "{url}");
+        assert!(!url.contains('\x1b'));
+        assert_eq!(rest, format!("result{OSC8_START}{OSC8_END}"));
+    }
+
+    #[test]
+    fn hyperlinks_take_up_the_width_of_their_text() {
+        let link = hyperlink(Utf8Path::new("/home/a/result"), "/home/a/result");
+        assert_eq!(display_width(&link), "/home/a/result".len());
+        assert_eq!(display_width(&format!("{link} {link}")), 29);
+    }
+
+    #[test]
+    fn hyperlinked_tables_stay_aligned() {
+        let output = "/home/a/result -> /nix/store/aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa-hello-2.12\n\
+            /home/longer/path/result -> /nix/store/bbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb-hello-2.13\n";
+        let gcroots = GCRoots::from_print_roots_output(output).unwrap();
+        let columns = [Column::Path, Column::ShortTarget];
+        let plain = table(
+            &gcroots,
+            &columns,
+            &DateStyle::default(),
+            &Decorations::default(),
+        );
+        let decorations = Decorations {
+            hyperlinks: true,
+            ..Decorations::default()
+        };
+        let linked = table(&gcroots, &columns, &DateStyle::default(), &decorations);
+        assert_ne!(linked, plain);
+        assert_eq!(
+            linked.lines().map(without_hyperlinks).collect::<Vec<_>>(),
+            plain.lines().collect::<Vec<_>>()
+        );
+        assert!(!plain.contains('\x1b'));
+    }
+}
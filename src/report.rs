@@ -0,0 +1,543 @@
+use std::{
+    fmt::Display,
+    time::{Duration, SystemTime},
+};
+
+use camino::Utf8Path;
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    check::{Thresholds, Violation},
+    duration,
+    gcroot::{GCRoots, RootStatus},
+    size,
+    stats::{self, Estimate},
+    summary::{Summary, SummaryOptions},
+};
+
+/// Version of the JSON schema of [Report], bumped on breaking changes.
+pub const REPORT_VERSION: u32 = 1;
+
+/// Sections a report has when neither --sections nor the config choose.
+pub const DEFAULT_SECTIONS: &[Section] = &[
+    Section::Summary,
+    Section::Largest,
+    Section::Generations,
+    Section::Stale,
+    Section::Dead,
+];
+
+/// Standalone roots listed by the largest section by default.
+pub const DEFAULT_LARGEST: usize = 20;
+
+/// Generations a profile can have before the generations section lists it,
+/// when no max-generations threshold is configured.
+pub const DEFAULT_MAX_GENERATIONS: usize = 20;
+
+/// Age after which the stale section lists a standalone root,
+/// when no max-age threshold is configured.
+pub const DEFAULT_STALE_AFTER: Duration = Duration::from_secs(90 * 24 * 60 * 60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// A part of the report.
+pub enum Section {
+    /// Counts of profiles, generations and standalone roots
+    Summary,
+    /// Standalone roots with the largest closures, needs sizes from nix
+    Largest,
+    /// Profiles with more generations than the max-generations threshold
+    Generations,
+    /// Standalone roots older than the max-age threshold
+    Stale,
+    /// Roots whose symlink or target is gone
+    Dead,
+}
+
+/// Data besides discovery that sections need, gathered once for all of them.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Needs {
+    /// Closure sizes, see [GCRoots::gather_sizes].
+    pub sizes: bool,
+    /// Statuses of the roots, see [crate::gcroot::DiscoverOptions::root_status].
+    pub status: bool,
+}
+
+impl Section {
+    pub fn needs(self) -> Needs {
+        Needs {
+            sizes: self == Section::Largest,
+            status: self == Section::Dead,
+        }
+    }
+
+    fn title(self) -> &'static str {
+        match self {
+            Section::Summary => "Summary",
+            Section::Largest => "Largest standalone roots",
+            Section::Generations => "Profiles with too many generations",
+            Section::Stale => "Stale standalone roots",
+            Section::Dead => "Dead roots",
+        }
+    }
+}
+
+impl Needs {
+    /// What every section together needs.
+    pub fn of(sections: &[Section]) -> Self {
+        sections.iter().fold(Needs::default(), |needs, section| {
+            let other = section.needs();
+            Needs {
+                sizes: needs.sizes || other.sizes,
+                status: needs.status || other.status,
+            }
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+/// What a report contains and the limits its sections use.
+pub struct ReportOptions {
+    /// In the order they're rendered, without duplicates.
+    pub sections: Vec<Section>,
+    /// Standalone roots listed by the largest section.
+    pub largest: usize,
+    pub max_generations: usize,
+    pub stale_after: Duration,
+    /// Only read the metadata of this many roots for the summary and stale
+    /// sections, extrapolating their numbers, see [stats::sample].
+    pub sample: Option<usize>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// A root listed by a section.
+pub struct ReportRoot {
+    pub path: String,
+    pub target: String,
+    /// Only in the largest section, null if the target isn't in the store.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub closure_size: Option<Option<u64>>,
+    /// Only in the dead section.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<RootStatus>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "section", rename_all = "kebab-case")]
+/// The findings of a single section.
+pub enum SectionReport {
+    Summary {
+        summary: Summary,
+    },
+    Largest {
+        limit: usize,
+        roots: Vec<ReportRoot>,
+    },
+    Generations {
+        max: usize,
+        violations: Vec<Violation>,
+    },
+    Stale {
+        max_age_secs: u64,
+        /// The stale roots of the sample if sampled.
+        violations: Vec<Violation>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        sampled: Option<SampledStale>,
+    },
+    Dead {
+        roots: Vec<ReportRoot>,
+    },
+}
+
+#[derive(Debug, Clone, Copy, Serialize)]
+/// Number of stale roots extrapolated from a sample of the standalone roots.
+pub struct SampledStale {
+    /// Number of standalone roots whose age was read.
+    pub size: usize,
+    pub stale: Estimate,
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// Several analyses of the same discovered roots, rendered as one document.
+pub struct Report {
+    pub version: u32,
+    pub generated: DateTime<Utc>,
+    pub sections: Vec<SectionReport>,
+}
+
+impl Report {
+    /// Runs the sections of options on gcroots, which need to have what
+    /// [Needs::of] the sections asks for gathered already.
+    pub fn build(gcroots: &GCRoots, options: &ReportOptions, now: SystemTime) -> Self {
+        let sections = options
+            .sections
+            .iter()
+            .map(|section| Self::section(gcroots, *section, options, now))
+            .collect();
+        Report {
+            version: REPORT_VERSION,
+            generated: now.into(),
+            sections,
+        }
+    }
+
+    fn section(
+        gcroots: &GCRoots,
+        section: Section,
+        options: &ReportOptions,
+        now: SystemTime,
+    ) -> SectionReport {
+        match section {
+            Section::Summary => SectionReport::Summary {
+                summary: gcroots.summary(SummaryOptions {
+                    metadata: true,
+                    sample: options.sample,
+                }),
+            },
+            Section::Largest => {
+                let mut standalone = gcroots.standalone().iter().collect::<Vec<_>>();
+                standalone.sort_by_key(|gcroot| std::cmp::Reverse(gcroot.closure_size));
+                let roots = standalone
+                    .into_iter()
+                    .take(options.largest)
+                    .map(|gcroot| ReportRoot {
+                        path: gcroot.path.to_string(),
+                        target: gcroot.target.as_str().to_string(),
+                        closure_size: Some(gcroot.closure_size),
+                        status: None,
+                    })
+                    .collect();
+                SectionReport::Largest {
+                    limit: options.largest,
+                    roots,
+                }
+            }
+            Section::Generations => {
+                let thresholds = Thresholds {
+                    max_generations: Some(options.max_generations),
+                    ..Thresholds::default()
+                };
+                SectionReport::Generations {
+                    max: options.max_generations,
+                    violations: thresholds.check(gcroots, now),
+                }
+            }
+            Section::Stale => {
+                let standalone = gcroots.standalone().iter().collect();
+                let sample =
+                    stats::sample(standalone, options.sample.unwrap_or(usize::MAX), |gcroot| {
+                        gcroot.path.as_str()
+                    });
+                let violations: Vec<_> = sample
+                    .items
+                    .iter()
+                    .filter_map(|gcroot| {
+                        Thresholds::max_age_violation(gcroot, options.stale_after, now)
+                    })
+                    .collect();
+                let sampled = (!sample.is_exhaustive()).then(|| SampledStale {
+                    size: sample.items.len(),
+                    stale: sample.estimate_count(violations.len()),
+                });
+                SectionReport::Stale {
+                    max_age_secs: options.stale_after.as_secs(),
+                    violations,
+                    sampled,
+                }
+            }
+            Section::Dead => {
+                let roots = gcroots
+                    .profiles()
+                    .iter()
+                    .flat_map(|profile| profile.generations.values())
+                    .chain(gcroots.standalone())
+                    .filter(|gcroot| gcroot.status.is_dead())
+                    .map(|gcroot| ReportRoot {
+                        path: gcroot.path.to_string(),
+                        target: gcroot.target.as_str().to_string(),
+                        closure_size: None,
+                        status: Some(gcroot.status),
+                    })
+                    .collect();
+                SectionReport::Dead { roots }
+            }
+        }
+    }
+
+    /// Renders the report as Markdown, a heading and a list or table per
+    /// section, for pasting into a wiki or an issue.
+    pub fn markdown(&self) -> String {
+        let mut output = format!(
+            "# gcrs report\n\nGenerated {}.\n",
+            self.generated.format("%Y-%m-%d %H:%M UTC")
+        );
+        for section in self.sections.iter() {
+            output.push_str(&format!("\n## {}", section.kind().title()));
+            if let Some(limit) = section.limit() {
+                output.push_str(&format!(" ({limit})"));
+            }
+            output.push_str("\n\n");
+            let (header, rows) = section.rows();
+            match header {
+                None => {
+                    for row in rows.iter() {
+                        output.push_str(&format!("- {}\n", row.join(": ")));
+                    }
+                }
+                Some(_) if rows.is_empty() => output.push_str("None.\n"),
+                Some(header) => {
+                    output.push_str(&Self::markdown_row(header.iter().map(|h| h.to_string())));
+                    output.push_str(&Self::markdown_row(header.iter().map(|_| "---".into())));
+                    for row in rows {
+                        let cells = row.into_iter().map(|cell| match Self::is_path(&cell) {
+                            true => format!("`{}`", cell.replace('|', "\\|")),
+                            false => cell.replace('|', "\\|"),
+                        });
+                        output.push_str(&Self::markdown_row(cells));
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    /// If the cell holds a path, rendered as code in Markdown.
+    fn is_path(cell: &str) -> bool {
+        Utf8Path::new(cell).is_absolute()
+    }
+
+    fn is_number(cell: &str) -> bool {
+        cell == "-" || cell.starts_with(|c: char| c.is_ascii_digit())
+    }
+
+    fn markdown_row(cells: impl Iterator<Item = String>) -> String {
+        format!("| {} |\n", cells.collect::<Vec<_>>().join(" | "))
+    }
+}
+
+impl SectionReport {
+    fn kind(&self) -> Section {
+        match self {
+            SectionReport::Summary { .. } => Section::Summary,
+            SectionReport::Largest { .. } => Section::Largest,
+            SectionReport::Generations { .. } => Section::Generations,
+            SectionReport::Stale { .. } => Section::Stale,
+            SectionReport::Dead { .. } => Section::Dead,
+        }
+    }
+
+    /// The column names and the cells of every row shared by the human and
+    /// Markdown renderings. Without column names the rows are name and value
+    /// pairs.
+    fn rows(&self) -> (Option<&'static [&'static str]>, Vec<Vec<String>>) {
+        let date = |time: Option<DateTime<Utc>>| {
+            time.map_or("-".to_string(), |t| t.format("%Y-%m-%d").to_string())
+        };
+        match self {
+            SectionReport::Summary { summary } => {
+                let mut rows = vec![
+                    vec!["profiles".into(), summary.profiles.to_string()],
+                    vec!["generations".into(), summary.generations.to_string()],
+                    vec!["standalone roots".into(), summary.standalone.to_string()],
+                    vec!["censored roots".into(), summary.censored.to_string()],
+                ];
+                match (&summary.sampled, summary.inaccessible) {
+                    (Some(sampled), _) => {
+                        let inaccessible = sampled.inaccessible.to_string();
+                        rows.push(vec!["inaccessible roots".into(), inaccessible]);
+                        let sampled = format!("{} of {}", sampled.size, summary.roots());
+                        rows.push(vec!["sampled roots".into(), sampled]);
+                    }
+                    (None, Some(inaccessible)) => {
+                        rows.push(vec!["inaccessible roots".into(), inaccessible.to_string()]);
+                    }
+                    (None, None) => {}
+                }
+                rows.push(vec!["oldest root".into(), date(summary.oldest_root)]);
+                rows.push(vec!["newest root".into(), date(summary.newest_root)]);
+                (None, rows)
+            }
+            SectionReport::Largest { roots, .. } => {
+                let rows = roots
+                    .iter()
+                    .map(|root| {
+                        let size = size::cell(root.closure_size.flatten());
+                        vec![size, root.path.clone(), root.target.clone()]
+                    })
+                    .collect();
+                (Some(&["closure size", "root", "target"]), rows)
+            }
+            SectionReport::Generations { violations, .. } => {
+                let rows = violations
+                    .iter()
+                    .filter_map(|violation| match violation {
+                        Violation::MaxGenerations { profile, count, .. } => {
+                            Some(vec![profile.clone(), count.to_string()])
+                        }
+                        _ => None,
+                    })
+                    .collect();
+                (Some(&["profile", "generations"]), rows)
+            }
+            SectionReport::Stale { violations, .. } => {
+                let rows = violations
+                    .iter()
+                    .filter_map(|violation| match violation {
+                        Violation::MaxAge { path, age_secs, .. } => Some(vec![
+                            path.clone(),
+                            duration::format(Duration::from_secs(*age_secs)),
+                        ]),
+                        _ => None,
+                    })
+                    .collect();
+                (Some(&["root", "age"]), rows)
+            }
+            SectionReport::Dead { roots } => {
+                let rows = roots
+                    .iter()
+                    .map(|root| {
+                        let status = root.status.map(|s| s.to_string()).unwrap_or_default();
+                        vec![root.path.clone(), root.target.clone(), status]
+                    })
+                    .collect();
+                (Some(&["root", "target", "status"]), rows)
+            }
+        }
+    }
+
+    /// The limit the section applied, for its heading.
+    fn limit(&self) -> Option<String> {
+        match self {
+            SectionReport::Summary { .. } | SectionReport::Dead { .. } => None,
+            SectionReport::Largest { limit, .. } => Some(format!("top {limit}")),
+            SectionReport::Generations { max, .. } => Some(format!("more than {max}")),
+            SectionReport::Stale {
+                max_age_secs,
+                sampled,
+                ..
+            } => {
+                let limit = format!(
+                    "older than {}",
+                    duration::format_exact(Duration::from_secs(*max_age_secs))
+                );
+                Some(match sampled {
+                    Some(sampled) => format!(
+                        "{limit}, about {} in total, listed from a sample of {}",
+                        sampled.stale, sampled.size
+                    ),
+                    None => limit,
+                })
+            }
+        }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, section) in self.sections.iter().enumerate() {
+            if index > 0 {
+                writeln!(f)?;
+            }
+            write!(f, "{}", section.kind().title())?;
+            if let Some(limit) = section.limit() {
+                write!(f, " ({limit})")?;
+            }
+            writeln!(f, ":")?;
+            let (header, rows) = section.rows();
+            if rows.is_empty() {
+                writeln!(f, "  none")?;
+                continue;
+            }
+            let widths = (0..rows[0].len())
+                .map(|column| {
+                    rows.iter()
+                        .map(|row| row[column].chars().count())
+                        .max()
+                        .unwrap_or(0)
+                })
+                .collect::<Vec<_>>();
+            for row in rows.iter() {
+                let line = match header {
+                    None => format!("{}: {}", row[0], row[1]),
+                    Some(_) => row
+                        .iter()
+                        .zip(widths.iter())
+                        // Sizes, counts and ages line up on the right.
+                        .map(|(cell, width)| match Self::is_number(cell) {
+                            true => format!("{cell: >width$}"),
+                            false => format!("{cell: <width$}"),
+                        })
+                        .collect::<Vec<_>>()
+                        .join("  "),
+                };
+                writeln!(f, "  {}", line.trim_end())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn report(sections: Vec<SectionReport>) -> Report {
+        Report {
+            version: REPORT_VERSION,
+            generated: DateTime::<Utc>::default(),
+            sections,
+        }
+    }
+
+    #[test]
+    fn sampled_stale_section_shows_the_estimate() {
+        let report = report(vec![SectionReport::Stale {
+            max_age_secs: 90 * 24 * 60 * 60,
+            violations: vec![Violation::MaxAge {
+                path: "/home/user/result".to_string(),
+                age_secs: 100 * 24 * 60 * 60,
+                max_secs: 90 * 24 * 60 * 60,
+            }],
+            sampled: Some(SampledStale {
+                size: 1000,
+                stale: Estimate {
+                    value: 80.0,
+                    margin: Some(16.2),
+                },
+            }),
+        }]);
+        assert_eq!(
+            report.to_string(),
+            "Stale standalone roots (older than 90d, about 80 ± 17 in total, \
+             listed from a sample of 1000):\n  /home/user/result  100d\n"
+        );
+    }
+
+    #[test]
+    fn sampled_summary_shows_estimates() {
+        let summary = Summary {
+            standalone: 8000,
+            inaccessible: Some(40),
+            sampled: Some(crate::summary::SampledMetadata {
+                size: 1000,
+                disappeared: Estimate::exact(0.0),
+                inaccessible: Estimate {
+                    value: 40.0,
+                    margin: Some(12.0),
+                },
+            }),
+            ..Summary::default()
+        };
+        let rendered = report(vec![SectionReport::Summary { summary }]).to_string();
+        assert!(
+            rendered.contains("  inaccessible roots: 40 ± 12\n"),
+            "{rendered}"
+        );
+        assert!(
+            rendered.contains("  sampled roots: 1000 of 8000\n"),
+            "{rendered}"
+        );
+    }
+}
@@ -0,0 +1,311 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::Display,
+    time::Duration,
+};
+
+use camino::Utf8PathBuf;
+use chrono::{DateTime, Datelike, Utc};
+
+use crate::{
+    duration,
+    gcroot::GCRoots,
+    plan::{count, EntryKind, Plan},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Which generations of a profile are kept, the others can be removed.
+pub enum RetentionPolicy {
+    /// The newest n generations by number.
+    KeepLast(usize),
+    /// The newest generation of each of the last months calendar months, the
+    /// current one included, and the newest keep_last generations by number.
+    KeepMonthly { months: usize, keep_last: usize },
+    /// The generations modified less than since ago
+    /// and the newest keep_last generations by number.
+    KeepSince { since: Duration, keep_last: usize },
+}
+
+impl RetentionPolicy {
+    /// Numbers of the generations kept out of generations, given as
+    /// (number, modification time) in any order.
+    ///
+    /// Months are calendar months in UTC. When several generations share a
+    /// month the one modified last is kept, the higher number if they were
+    /// modified at the same time. Months without generations keep nothing, they
+    /// aren't made up for by keeping more of other months. Generations without
+    /// a modification time can't be placed in a month and are always kept, ones
+    /// modified after now count as modified in the current month. The same
+    /// goes for generations without a modification time under KeepSince.
+    pub fn kept(
+        &self,
+        generations: &[(u64, Option<DateTime<Utc>>)],
+        now: DateTime<Utc>,
+    ) -> BTreeSet<u64> {
+        let keep_last = match *self {
+            RetentionPolicy::KeepLast(n) => n,
+            RetentionPolicy::KeepMonthly { keep_last, .. } => keep_last,
+            RetentionPolicy::KeepSince { keep_last, .. } => keep_last,
+        };
+        let mut numbers = generations.iter().map(|(id, _)| *id).collect::<Vec<_>>();
+        numbers.sort_unstable_by(|a, b| b.cmp(a));
+        let mut kept = numbers.into_iter().take(keep_last).collect::<BTreeSet<_>>();
+        if let RetentionPolicy::KeepSince { since, .. } = *self {
+            let cutoff = chrono::Duration::from_std(since)
+                .ok()
+                .and_then(|since| now.checked_sub_signed(since))
+                .unwrap_or(DateTime::<Utc>::MIN_UTC);
+            let recent = generations
+                .iter()
+                .filter(|(_, mtime)| mtime.is_none_or(|mtime| mtime > cutoff))
+                .map(|(id, _)| *id);
+            kept.extend(recent);
+            return kept;
+        }
+        let RetentionPolicy::KeepMonthly { months, .. } = *self else {
+            return kept;
+        };
+        let current = month_index(now);
+        let mut newest = BTreeMap::new();
+        for (id, mtime) in generations {
+            let Some(mtime) = mtime else {
+                kept.insert(*id);
+                continue;
+            };
+            let month = month_index(*mtime).min(current);
+            if current - month >= months as i64 {
+                continue;
+            }
+            newest
+                .entry(month)
+                .and_modify(|newest: &mut (DateTime<Utc>, u64)| {
+                    *newest = (*newest).max((*mtime, *id))
+                })
+                .or_insert((*mtime, *id));
+        }
+        kept.extend(newest.into_values().map(|(_, id)| id));
+        kept
+    }
+
+    /// Why a generation that isn't kept is selected.
+    pub fn reason(&self) -> String {
+        match *self {
+            RetentionPolicy::KeepLast(n) => {
+                format!(
+                    "beyond the newest {}",
+                    count(n, "generation", "generations")
+                )
+            }
+            RetentionPolicy::KeepMonthly { months, keep_last } => {
+                let monthly = format!(
+                    "not the newest of its month in the last {}",
+                    count(months, "month", "months")
+                );
+                match keep_last {
+                    0 => monthly,
+                    n => format!(
+                        "{monthly} nor among the newest {}",
+                        count(n, "generation", "generations")
+                    ),
+                }
+            }
+            RetentionPolicy::KeepSince { since, keep_last } => {
+                let since = format!("modified more than {} ago", duration::format_exact(since));
+                match keep_last {
+                    0 => since,
+                    n => format!(
+                        "{since} and not among the newest {}",
+                        count(n, "generation", "generations")
+                    ),
+                }
+            }
+        }
+    }
+}
+
+/// Months since year 0, so that consecutive months differ by one across years.
+fn month_index(time: DateTime<Utc>) -> i64 {
+    time.year() as i64 * 12 + time.month0() as i64
+}
+
+impl Display for RetentionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RetentionPolicy::KeepLast(n) => write!(f, "keep-last {n}"),
+            RetentionPolicy::KeepMonthly { months, keep_last } => {
+                write!(f, "keep-monthly {months}")?;
+                match keep_last {
+                    0 => Ok(()),
+                    n => write!(f, ", keep-last {n}"),
+                }
+            }
+            RetentionPolicy::KeepSince { since, keep_last } => {
+                write!(f, "keep-since {}", duration::format_exact(*since))?;
+                match keep_last {
+                    0 => Ok(()),
+                    n => write!(f, ", keep-last {n}"),
+                }
+            }
+        }
+    }
+}
+
+/// How many generations of each profile a plan keeps and removes,
+/// printed by clean with a retention policy.
+pub struct Outcome {
+    /// (profile, kept, removed), profiles without generations left out.
+    rows: Vec<(Utf8PathBuf, usize, usize)>,
+}
+
+impl Outcome {
+    pub fn of(gcroots: &GCRoots, plan: &Plan) -> Self {
+        let mut removed = BTreeMap::new();
+        for entry in plan.entries.iter() {
+            if let EntryKind::Generation { profile, .. } = &entry.kind {
+                *removed.entry(profile).or_insert(0) += 1;
+            }
+        }
+        let rows = gcroots
+            .profiles()
+            .iter()
+            .filter(|profile| !profile.is_empty())
+            .map(|profile| {
+                let removed = removed.get(&profile.path).copied().unwrap_or(0);
+                (profile.path.clone(), profile.len() - removed, removed)
+            })
+            .collect();
+        Outcome { rows }
+    }
+}
+
+impl Display for Outcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let width = self
+            .rows
+            .iter()
+            .map(|(path, ..)| path.as_str().len())
+            .chain(["profile".len()])
+            .max()
+            .unwrap_or(0);
+        write!(
+            f,
+            "{: <width$}  {: >4}  {: >7}",
+            "profile", "kept", "removed"
+        )?;
+        for (path, kept, removed) in self.rows.iter() {
+            write!(
+                f,
+                "\n{: <width$}  {: >4}  {: >7}",
+                path.as_str(),
+                kept,
+                removed
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    fn at(year: i32, month: u32, day: u32) -> Option<DateTime<Utc>> {
+        Some(Utc.with_ymd_and_hms(year, month, day, 12, 0, 0).unwrap())
+    }
+
+    fn monthly(months: usize, keep_last: usize) -> RetentionPolicy {
+        RetentionPolicy::KeepMonthly { months, keep_last }
+    }
+
+    #[test]
+    fn kept_generations() {
+        let now = at(2025, 2, 15).unwrap();
+        let generations = [
+            (1, at(2023, 12, 31)),
+            (2, at(2024, 11, 2)),
+            (3, at(2024, 12, 1)),
+            (4, at(2024, 12, 31)),
+            (5, at(2025, 1, 1)),
+            (6, at(2025, 2, 3)),
+            (7, at(2025, 2, 10)),
+        ];
+        let cases: &[(RetentionPolicy, &[u64])] = &[
+            (RetentionPolicy::KeepLast(0), &[]),
+            (RetentionPolicy::KeepLast(2), &[6, 7]),
+            (RetentionPolicy::KeepLast(10), &[1, 2, 3, 4, 5, 6, 7]),
+            // February, across the year boundary to December and November.
+            (monthly(4, 0), &[2, 4, 5, 7]),
+            (monthly(3, 0), &[4, 5, 7]),
+            (monthly(1, 0), &[7]),
+            (monthly(0, 0), &[]),
+            (monthly(3, 3), &[4, 5, 6, 7]),
+            // 2023-12 is 14 months back, October 2024 has nothing.
+            (monthly(15, 0), &[1, 2, 4, 5, 7]),
+            (
+                RetentionPolicy::KeepSince {
+                    since: Duration::from_secs(60 * 24 * 60 * 60),
+                    keep_last: 1,
+                },
+                &[4, 5, 6, 7],
+            ),
+        ];
+        for (policy, kept) in cases {
+            let expected = kept.iter().copied().collect::<BTreeSet<_>>();
+            assert_eq!(policy.kept(&generations, now), expected, "{policy}");
+        }
+    }
+
+    #[test]
+    fn generations_without_a_modification_time_are_kept() {
+        let now = at(2025, 2, 15).unwrap();
+        let generations = [(1, None), (2, at(2020, 1, 1)), (3, at(2025, 2, 1))];
+        let cases: &[(RetentionPolicy, &[u64])] = &[
+            (monthly(2, 0), &[1, 3]),
+            (
+                RetentionPolicy::KeepSince {
+                    since: Duration::from_secs(24 * 60 * 60),
+                    keep_last: 0,
+                },
+                &[1],
+            ),
+            (RetentionPolicy::KeepLast(1), &[3]),
+        ];
+        for (policy, kept) in cases {
+            let expected = kept.iter().copied().collect::<BTreeSet<_>>();
+            assert_eq!(policy.kept(&generations, now), expected, "{policy}");
+        }
+    }
+
+    #[test]
+    fn ties_and_future_generations() {
+        let now = at(2025, 2, 15).unwrap();
+        // Modified at the same time, the higher number wins.
+        let same = [(1, at(2025, 2, 1)), (2, at(2025, 2, 1))];
+        assert_eq!(monthly(1, 0).kept(&same, now), BTreeSet::from([2]));
+        // From a clock that was ahead, counted in the current month.
+        let future = [(1, at(2025, 2, 14)), (2, at(2026, 6, 1))];
+        assert_eq!(monthly(1, 0).kept(&future, now), BTreeSet::from([2]));
+        // Numbers order keep-last regardless of modification times.
+        let reordered = [(1, at(2025, 2, 14)), (2, at(2025, 1, 1))];
+        assert_eq!(
+            RetentionPolicy::KeepLast(1).kept(&reordered, now),
+            BTreeSet::from([2])
+        );
+    }
+
+    #[test]
+    fn reasons_and_names() {
+        assert_eq!(
+            monthly(12, 0).reason(),
+            "not the newest of its month in the last 12 months"
+        );
+        assert_eq!(
+            monthly(1, 2).reason(),
+            "not the newest of its month in the last 1 month nor among the newest 2 generations"
+        );
+        assert_eq!(monthly(12, 3).to_string(), "keep-monthly 12, keep-last 3");
+        assert_eq!(RetentionPolicy::KeepLast(1).to_string(), "keep-last 1");
+    }
+}
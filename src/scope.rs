@@ -0,0 +1,215 @@
+use std::{collections::BTreeMap, os::unix::fs::MetadataExt};
+
+use crate::{
+    plan::{count, PlanEntry},
+    render,
+};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// Users whose roots may be removed when gcrs runs as root, see [partition].
+pub enum UserScope {
+    All,
+    Users(Vec<String>),
+}
+
+impl UserScope {
+    /// Parses `all` or a comma-separated list of user names.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        if input.trim() == "all" {
+            return Ok(UserScope::All);
+        }
+        let users = input
+            .split(',')
+            .map(|user| user.trim().to_string())
+            .collect::<Vec<_>>();
+        match users.iter().any(|user| user.is_empty()) {
+            true => Err(format!(
+                "invalid user list \"{input}\", expected all or names like alice,bob"
+            )),
+            false => Ok(UserScope::Users(users)),
+        }
+    }
+
+    pub fn includes(&self, user: &str) -> bool {
+        match self {
+            UserScope::All => true,
+            UserScope::Users(users) => users.iter().any(|u| u == user),
+        }
+    }
+}
+
+/// Owner of the root of the entry, None if it can't be inspected.
+pub fn owner(entry: &PlanEntry) -> Option<u32> {
+    entry.path.symlink_metadata().ok().map(|m| m.uid())
+}
+
+/// Splits entries into the ones kept and the ones owned by users outside
+/// scope, which are returned with the name of their owner. Roots owned by
+/// root and by nobody known are always kept, and so is everything when
+/// the scope is All. Without a scope every other user is outside it.
+pub fn partition(
+    entries: Vec<PlanEntry>,
+    scope: Option<&UserScope>,
+    owner: impl Fn(&PlanEntry) -> Option<u32>,
+) -> (Vec<PlanEntry>, Vec<(PlanEntry, String)>) {
+    let mut kept = Vec::new();
+    let mut excluded = Vec::new();
+    for entry in entries {
+        match owner(&entry)
+            .filter(|uid| *uid != 0)
+            .map(render::owner_name)
+        {
+            Some(user) if !scope.is_some_and(|scope| scope.includes(&user)) => {
+                excluded.push((entry, user))
+            }
+            _ => kept.push(entry),
+        }
+    }
+    (kept, excluded)
+}
+
+/// Notice about entries [partition] left out, None if there are none.
+pub fn notice(excluded: &[(PlanEntry, String)]) -> Option<String> {
+    if excluded.is_empty() {
+        return None;
+    }
+    let mut by_user = BTreeMap::new();
+    for (_, user) in excluded {
+        *by_user.entry(user.as_str()).or_insert(0) += 1;
+    }
+    let counts = by_user
+        .iter()
+        .map(|(user, n)| format!("{user}: {n}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let users = by_user.keys().copied().collect::<Vec<_>>().join(",");
+    Some(format!(
+        "running as root, left out {} owned by other users ({counts}), \
+        pass --users {users} or --users all to include them",
+        count(excluded.len(), "root", "roots")
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+
+    use super::*;
+    use crate::{
+        gcroot::NodeType,
+        plan::{EntryKind, SelectionReason},
+    };
+
+    /// Owners that don't exist, so that they're named by their uid.
+    const ALICE: u32 = 4_000_001;
+    const BOB: u32 = 4_000_002;
+
+    fn entry(path: &str) -> PlanEntry {
+        PlanEntry {
+            path: Utf8PathBuf::from(path),
+            target: Utf8PathBuf::from("/nix/store/aaaa-target"),
+            kind: EntryKind::Standalone,
+            reasons: vec![SelectionReason::Requested],
+            node: NodeType::Symlink,
+            contents: Vec::new(),
+            mtime: None,
+            size: None,
+            substitutable: None,
+        }
+    }
+
+    /// Entries owned by root, alice, bob, alice and nobody known.
+    fn entries() -> Vec<PlanEntry> {
+        ["/root", "/alice/1", "/bob", "/alice/2", "/unknown"]
+            .into_iter()
+            .map(entry)
+            .collect()
+    }
+
+    fn owner(entry: &PlanEntry) -> Option<u32> {
+        match entry.path.as_str() {
+            "/root" => Some(0),
+            "/bob" => Some(BOB),
+            "/unknown" => None,
+            _ => Some(ALICE),
+        }
+    }
+
+    fn paths(entries: &[PlanEntry]) -> Vec<&str> {
+        entries.iter().map(|entry| entry.path.as_str()).collect()
+    }
+
+    #[test]
+    fn scopes_are_parsed() {
+        assert_eq!(UserScope::parse("all"), Ok(UserScope::All));
+        assert_eq!(UserScope::parse(" all "), Ok(UserScope::All));
+        assert_eq!(
+            UserScope::parse("alice, bob"),
+            Ok(UserScope::Users(vec!["alice".into(), "bob".into()]))
+        );
+        for invalid in ["", "alice,", ",bob", "alice,,bob"] {
+            assert_eq!(
+                UserScope::parse(invalid),
+                Err(format!(
+                    "invalid user list \"{invalid}\", expected all or names like alice,bob"
+                ))
+            );
+        }
+    }
+
+    #[test]
+    fn without_a_scope_other_users_are_left_out() {
+        let (kept, excluded) = partition(entries(), None, owner);
+        assert_eq!(paths(&kept), ["/root", "/unknown"]);
+        let excluded = excluded
+            .iter()
+            .map(|(entry, user)| (entry.path.as_str(), user.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            excluded,
+            [
+                ("/alice/1", "4000001"),
+                ("/bob", "4000002"),
+                ("/alice/2", "4000001"),
+            ]
+        );
+    }
+
+    #[test]
+    fn listed_users_are_kept() {
+        let scope = UserScope::Users(vec![ALICE.to_string()]);
+        let (kept, excluded) = partition(entries(), Some(&scope), owner);
+        assert_eq!(paths(&kept), ["/root", "/alice/1", "/alice/2", "/unknown"]);
+        assert_eq!(excluded.len(), 1);
+    }
+
+    #[test]
+    fn everything_is_kept_with_all() {
+        let (kept, excluded) = partition(entries(), Some(&UserScope::All), owner);
+        assert_eq!(kept.len(), 5);
+        assert!(excluded.is_empty());
+        assert_eq!(notice(&excluded), None);
+    }
+
+    #[test]
+    fn notice_counts_the_roots_of_every_user() {
+        let (_, excluded) = partition(entries(), None, owner);
+        assert_eq!(
+            notice(&excluded).unwrap(),
+            "running as root, left out 3 roots owned by other users \
+            (4000001: 2, 4000002: 1), pass --users 4000001,4000002 or --users all \
+            to include them"
+        );
+    }
+
+    #[test]
+    fn notice_when_every_candidate_is_left_out() {
+        let (kept, excluded) = partition(vec![entry("/bob")], None, owner);
+        assert!(kept.is_empty());
+        assert_eq!(
+            notice(&excluded).unwrap(),
+            "running as root, left out 1 root owned by other users (4000002: 1), \
+            pass --users 4000002 or --users all to include them"
+        );
+    }
+}
@@ -0,0 +1,345 @@
+use std::{
+    collections::{HashMap, HashSet},
+    process::Output,
+    time::Instant,
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::ValueEnum;
+use eyre::{Result, WrapErr};
+use serde::Deserialize;
+
+use crate::{
+    gcroot::{self, StoreDirs},
+    nix_command,
+    timing::{self, Phase},
+};
+
+const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
+
+/// Parses a size like `20G`, `512M`, `1.5TiB` or a plain number of bytes.
+/// Units are powers of 1024.
+pub fn parse(input: &str) -> Result<u64, String> {
+    let input = input.trim();
+    let split = input
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(input.len());
+    let (number, unit) = input.split_at(split);
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| format!("invalid size \"{input}\", expected e.g. 20G"))?;
+    let exponent = match unit.trim().to_ascii_uppercase().as_str() {
+        "" | "B" => 0,
+        "K" | "KB" | "KIB" => 1,
+        "M" | "MB" | "MIB" => 2,
+        "G" | "GB" | "GIB" => 3,
+        "T" | "TB" | "TIB" => 4,
+        _ => return Err(format!("unknown size unit \"{unit}\" in \"{input}\"")),
+    };
+    let bytes = number * 1024f64.powi(exponent);
+    match bytes.is_finite() && bytes <= u64::MAX as f64 {
+        true => Ok(bytes as u64),
+        false => Err(format!("size \"{input}\" is too large")),
+    }
+}
+
+/// Formats a number of bytes with a binary unit, e.g. `1.2 GiB`.
+pub fn format(bytes: u64) -> String {
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit + 1 < UNITS.len() {
+        value /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{bytes} B"),
+        _ => format!("{value:.1} {}", UNITS[unit]),
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+/// How clean --free estimates the space removing a root frees.
+pub enum Estimate {
+    /// Store paths only the removed roots keep alive, from the closures of every root
+    #[default]
+    Closure,
+    /// NAR size of each target, cheaper but ignores shared and exclusive dependencies
+    NarSize,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PathInfo {
+    path: Option<Utf8PathBuf>,
+    nar_size: Option<u64>,
+    /// Only printed with --closure-size.
+    closure_size: Option<u64>,
+    #[serde(default)]
+    references: Vec<Utf8PathBuf>,
+    /// Set to false by older nix versions for paths that aren't in the store.
+    valid: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+/// nix path-info --json prints an array of objects in older versions
+/// and an object keyed by store path in newer ones.
+enum PathInfos {
+    List(Vec<PathInfo>),
+    Map(HashMap<Utf8PathBuf, Option<PathInfo>>),
+}
+
+/// Binary cache queried for substitutes.
+pub const SUBSTITUTER: &str = "https://cache.nixos.org";
+
+/// Queries the NAR size of each path in a single nix path-info invocation.
+/// Paths that don't exist or aren't store paths are left out of the query and the result.
+pub fn nar_sizes(paths: &[&Utf8Path], store_dirs: &StoreDirs) -> Result<HashMap<Utf8PathBuf, u64>> {
+    let existing = existing(paths, store_dirs);
+    if existing.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let start = Instant::now();
+    let output = path_info(&[], &existing);
+    timing::record(Phase::Sizes, start.elapsed(), Some(existing.len()));
+    let output = nix_command::success(output?, "nix path-info --json")?;
+    let infos = parse_path_infos(&output.stdout)?;
+    Ok(infos
+        .into_iter()
+        .filter_map(|(path, info)| Some((path, info?.nar_size?)))
+        .collect())
+}
+
+/// Closures of store paths, as described by a single nix path-info invocation.
+pub struct Closures {
+    /// Every path in the closures as (NAR size, closure size, references).
+    infos: HashMap<Utf8PathBuf, (u64, Option<u64>, Vec<Utf8PathBuf>)>,
+}
+
+impl Closures {
+    /// Queries the closures of paths with nix path-info --recursive --closure-size.
+    /// Paths that don't exist or aren't store paths are left out of the query,
+    /// their closures are unknown.
+    pub fn query(paths: &[&Utf8Path], store_dirs: &StoreDirs) -> Result<Self> {
+        let existing = existing(paths, store_dirs);
+        if existing.is_empty() {
+            return Ok(Closures {
+                infos: HashMap::new(),
+            });
+        }
+        let start = Instant::now();
+        let output = path_info(&["--recursive", "--closure-size"], &existing);
+        timing::record(Phase::Sizes, start.elapsed(), Some(existing.len()));
+        let output = nix_command::success(output?, "nix path-info --json --recursive")?;
+        let default_store_dir = gcroot::store_dir();
+        let infos = parse_path_infos(&output.stdout)?
+            .into_iter()
+            .filter_map(|(path, info)| {
+                let info = info?;
+                // Newer nix versions print references without the store directory.
+                let store_dir = store_dirs.store_dir_of(&path).unwrap_or(&default_store_dir);
+                let references = info
+                    .references
+                    .into_iter()
+                    .map(|reference| match reference.is_absolute() {
+                        true => reference,
+                        false => store_dir.join(reference),
+                    })
+                    .collect();
+                Some((path, (info.nar_size?, info.closure_size, references)))
+            })
+            .collect();
+        Ok(Closures { infos })
+    }
+
+    /// Closure size of path, None if it isn't in the store.
+    pub fn size(&self, path: &Utf8Path) -> Option<u64> {
+        let (_, closure_size, _) = self.infos.get(path)?;
+        closure_size.or_else(|| Some(self.combined([path])))
+    }
+
+    /// Size of the closures of paths together, counting paths they share once.
+    /// Paths that aren't in the store add nothing.
+    pub fn combined<'a>(&self, paths: impl IntoIterator<Item = &'a Utf8Path>) -> u64 {
+        self.walk(&mut HashSet::new(), paths)
+    }
+
+    /// How much removing each of removed frees when the ones before it are
+    /// removed too, while kept and the ones after it stay. None for paths
+    /// that aren't in the store.
+    pub fn freed_in_order<'a>(
+        &self,
+        kept: impl IntoIterator<Item = &'a Utf8Path>,
+        removed: &[&Utf8Path],
+    ) -> Vec<Option<u64>> {
+        let mut seen = HashSet::new();
+        self.walk(&mut seen, kept);
+        let mut freed = removed
+            .iter()
+            .rev()
+            .map(|path| {
+                self.infos
+                    .contains_key(*path)
+                    .then(|| self.walk(&mut seen, [*path]))
+            })
+            .collect::<Vec<_>>();
+        freed.reverse();
+        freed
+    }
+
+    /// Adds up the NAR sizes of the paths in the closures of paths that
+    /// aren't in seen yet, adding them to it.
+    fn walk<'s, 'a>(
+        &'s self,
+        seen: &mut HashSet<&'s Utf8Path>,
+        paths: impl IntoIterator<Item = &'a Utf8Path>,
+    ) -> u64 {
+        let mut queue = paths
+            .into_iter()
+            .filter_map(|path| self.infos.get_key_value(path))
+            .map(|(path, _)| path.as_path())
+            .collect::<Vec<_>>();
+        let mut total = 0;
+        while let Some(path) = queue.pop() {
+            let Some((path, (nar_size, _, references))) = self.infos.get_key_value(path) else {
+                continue;
+            };
+            let path = path.as_path();
+            if seen.insert(path) {
+                total += nar_size;
+                queue.extend(references.iter().map(|reference| reference.as_path()));
+            }
+        }
+        total
+    }
+}
+
+/// A closure size formatted for a column, `-` if it's unknown.
+pub fn cell(size: Option<u64>) -> String {
+    size.map(format).unwrap_or_else(|| "-".to_string())
+}
+
+/// Checks in a single nix path-info invocation which paths [SUBSTITUTER] has.
+/// Paths that don't exist locally or aren't store paths are left out of the query and the result.
+pub fn substitutable(
+    paths: &[&Utf8Path],
+    store_dirs: &StoreDirs,
+) -> Result<HashMap<Utf8PathBuf, bool>> {
+    let existing = existing(paths, store_dirs);
+    if existing.is_empty() {
+        return Ok(HashMap::new());
+    }
+    // path-info exits with an error when some paths are missing from the
+    // cache, but still describes the ones that are there.
+    let start = Instant::now();
+    let output = path_info(&["--store", SUBSTITUTER], &existing);
+    let substitutes = Phase::Enrichment("substitutes");
+    timing::record(substitutes, start.elapsed(), Some(existing.len()));
+    let output = output?;
+    let infos = parse_path_infos(&output.stdout).wrap_err_with(|| {
+        format!(
+            "couldn't query {SUBSTITUTER}: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )
+    })?;
+    Ok(existing
+        .iter()
+        .map(|path| {
+            let found = infos
+                .get(*path)
+                .is_some_and(|info| info.as_ref().is_some_and(|info| info.valid != Some(false)));
+            (path.to_path_buf(), found)
+        })
+        .collect())
+}
+
+fn existing<'a>(paths: &[&'a Utf8Path], store_dirs: &StoreDirs) -> Vec<&'a Utf8Path> {
+    let mut existing = paths
+        .iter()
+        .copied()
+        .filter(|path| store_dirs.contains(path) && path.exists())
+        .collect::<Vec<_>>();
+    existing.sort_unstable();
+    existing.dedup();
+    existing
+}
+
+fn path_info(args: &[&str], paths: &[&Utf8Path]) -> Result<Output> {
+    nix_command::output(
+        nix_command::command("nix")
+            .args([
+                "--extra-experimental-features",
+                "nix-command",
+                "path-info",
+                "--json",
+            ])
+            .args(args)
+            .args(paths.iter().map(|path| path.as_str())),
+    )
+}
+
+fn parse_path_infos(stdout: &[u8]) -> Result<HashMap<Utf8PathBuf, Option<PathInfo>>> {
+    let infos: PathInfos =
+        serde_json::from_slice(stdout).wrap_err("invalid nix path-info output")?;
+    Ok(match infos {
+        PathInfos::List(list) => list
+            .into_iter()
+            .filter_map(|info| Some((info.path.clone()?, Some(info))))
+            .collect(),
+        PathInfos::Map(map) => map,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Closures of the paths with (NAR size, references).
+    fn closures(infos: &[(&str, u64, &[&str])]) -> Closures {
+        let infos = infos
+            .iter()
+            .map(|(path, nar_size, references)| {
+                let references = references.iter().map(Utf8PathBuf::from).collect();
+                (Utf8PathBuf::from(path), (*nar_size, None, references))
+            })
+            .collect();
+        Closures { infos }
+    }
+
+    #[test]
+    fn combined_counts_shared_paths_once() {
+        let closures = closures(&[
+            ("/nix/store/a", 1, &["/nix/store/lib"]),
+            ("/nix/store/b", 2, &["/nix/store/lib"]),
+            ("/nix/store/lib", 10, &[]),
+        ]);
+        let paths = ["/nix/store/a", "/nix/store/b"].map(Utf8Path::new);
+        assert_eq!(closures.combined(paths), 13);
+    }
+
+    #[test]
+    fn freed_in_order_ignores_paths_kept_alive() {
+        let closures = closures(&[
+            ("/nix/store/a", 1, &["/nix/store/lib", "/nix/store/own"]),
+            ("/nix/store/b", 2, &["/nix/store/lib"]),
+            ("/nix/store/kept", 4, &["/nix/store/lib"]),
+            ("/nix/store/lib", 10, &[]),
+            ("/nix/store/own", 20, &[]),
+        ]);
+        let removed = ["/nix/store/a", "/nix/store/b", "/home/u/outside"].map(Utf8Path::new);
+        let freed = closures.freed_in_order([Utf8Path::new("/nix/store/kept")], &removed);
+        assert_eq!(freed, [Some(21), Some(2), None]);
+    }
+
+    #[test]
+    fn freed_in_order_counts_shared_paths_once_freed() {
+        let closures = closures(&[
+            ("/nix/store/a", 1, &["/nix/store/lib"]),
+            ("/nix/store/b", 2, &["/nix/store/lib"]),
+            ("/nix/store/lib", 10, &[]),
+        ]);
+        let removed = ["/nix/store/a", "/nix/store/b"].map(Utf8Path::new);
+        let freed = closures.freed_in_order([], &removed);
+        assert_eq!(freed, [Some(1), Some(12)]);
+    }
+}
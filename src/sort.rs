@@ -0,0 +1,373 @@
+use std::{cmp::Ordering, time::SystemTime};
+
+use clap::ValueEnum;
+
+use crate::{
+    gcroot::{GCRoot, Profile},
+    render,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+/// What to order standalone roots and profiles by.
+///
+/// Sorting is stable, ties and entries without a value for the key are ordered by path,
+/// and entries without a value always come last, even when reversed.
+/// Keys that don't apply to profiles (like target) leave them ordered by path.
+pub enum SortKey {
+    /// Location of the symlink
+    Path,
+    /// Where the symlink points to
+    Target,
+    /// Target without the store directory and hash
+    ShortTarget,
+    /// Youngest first, profiles by their newest generation
+    Age,
+    /// Oldest first, profiles by their newest generation
+    Mtime,
+    /// Owner of the symlink
+    Owner,
+    /// Largest closure first, profiles by the combined closure of their generations.
+    /// Gathers the sizes like --sizes
+    Size,
+    /// Number of generations, only applies to profiles
+    Generations,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+/// How text like paths is compared when sorting for display.
+/// JSON output is always in byte order, so that it's stable across settings.
+pub enum SortLocale {
+    /// By UTF-8 bytes, uppercase before lowercase and non-ASCII last
+    #[default]
+    Byte,
+    /// Case-insensitive, accented Latin letters with their base letter,
+    /// and runs of digits by their numeric value, so result-2 comes before result-10
+    Natural,
+}
+
+impl SortLocale {
+    /// Compares a and b, see [natural_cmp] for natural order.
+    pub fn cmp(self, a: &str, b: &str) -> Ordering {
+        match self {
+            SortLocale::Byte => a.cmp(b),
+            SortLocale::Natural => natural_cmp(a, b),
+        }
+    }
+}
+
+/// Natural order of strings for presentation: case-insensitive, with accented
+/// Latin letters folded to their base letter and runs of ASCII digits compared
+/// by value. Strings equal that way, like `a` and `A` or `01` and `1`, are
+/// ordered by bytes, so that this is a total order consistent with equality.
+/// Doesn't depend on the locale of the system.
+pub fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let mut chars1 = a.chars().peekable();
+    let mut chars2 = b.chars().peekable();
+    loop {
+        let ordering = match (chars1.peek().copied(), chars2.peek().copied()) {
+            (None, None) => return a.cmp(b),
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(c1), Some(c2)) if c1.is_ascii_digit() && c2.is_ascii_digit() => {
+                let digits1 = take_digits(&mut chars1);
+                let digits2 = take_digits(&mut chars2);
+                let (digits1, digits2) = (
+                    digits1.trim_start_matches('0'),
+                    digits2.trim_start_matches('0'),
+                );
+                digits1
+                    .len()
+                    .cmp(&digits2.len())
+                    .then_with(|| digits1.cmp(digits2))
+            }
+            (Some(c1), Some(c2)) => {
+                chars1.next();
+                chars2.next();
+                fold(c1).cmp(&fold(c2))
+            }
+        };
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
+fn take_digits(chars: &mut std::iter::Peekable<std::str::Chars>) -> String {
+    let mut digits = String::new();
+    while let Some(digit) = chars.next_if(|c| c.is_ascii_digit()) {
+        digits.push(digit);
+    }
+    digits
+}
+
+/// Base letters of U+00C0 to U+00FF, `_` for the ones that have none.
+const LATIN_1_BASES: &str = "AAAAAAACEEEEIIIIDNOOOOO_OUUUUY_sAAAAAAACEEEEIIIIDNOOOOO_OUUUUY_Y";
+
+/// Lowercase base letter of c for natural order.
+fn fold(c: char) -> char {
+    let base = match c {
+        '\u{c0}'..='\u{ff}' => LATIN_1_BASES
+            .chars()
+            .nth(c as usize - 0xc0)
+            .filter(|base| *base != '_')
+            .unwrap_or(c),
+        c => c,
+    };
+    base.to_lowercase().next().unwrap_or(base)
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    Number(u64),
+    Text(String),
+}
+
+impl Value {
+    fn cmp(&self, other: &Value, locale: SortLocale) -> Ordering {
+        match (self, other) {
+            (Value::Number(n1), Value::Number(n2)) => n1.cmp(n2),
+            (Value::Text(t1), Value::Text(t2)) => locale.cmp(t1, t2),
+            (Value::Number(_), Value::Text(_)) => Ordering::Less,
+            (Value::Text(_), Value::Number(_)) => Ordering::Greater,
+        }
+    }
+}
+
+impl SortKey {
+    fn root_value(self, gcroot: &GCRoot, now: SystemTime) -> Option<Value> {
+        match self {
+            SortKey::Path => Some(Value::Text(gcroot.path.to_string())),
+            SortKey::Target => Some(Value::Text(gcroot.target.as_str().to_string())),
+            SortKey::ShortTarget => Some(Value::Text(
+                render::short_root_target(&gcroot.target).to_string(),
+            )),
+            SortKey::Age => {
+                let age = now.duration_since(gcroot.modified().ok()?).ok()?;
+                Some(Value::Number(age.as_secs()))
+            }
+            SortKey::Mtime => {
+                let mtime = gcroot.modified().ok()?;
+                let secs = mtime.duration_since(SystemTime::UNIX_EPOCH).ok()?;
+                Some(Value::Number(secs.as_secs()))
+            }
+            SortKey::Owner => {
+                let uid = gcroot.metadata().ok()?.uid;
+                Some(Value::Text(render::owner_name(uid)))
+            }
+            SortKey::Size => gcroot.closure_size.map(Self::largest_first),
+            SortKey::Generations => None,
+        }
+    }
+
+    fn profile_value(self, profile: &Profile, now: SystemTime) -> Option<Value> {
+        match self {
+            SortKey::Path => Some(Value::Text(profile.path.to_string())),
+            SortKey::Generations => Some(Value::Number(profile.len() as u64)),
+            SortKey::Age | SortKey::Mtime => {
+                let (_, newest) = profile.latest()?;
+                self.root_value(newest, now)
+            }
+            SortKey::Size => profile.closure_size.map(Self::largest_first),
+            SortKey::Target | SortKey::ShortTarget | SortKey::Owner => None,
+        }
+    }
+
+    fn largest_first(size: u64) -> Value {
+        Value::Number(u64::MAX - size)
+    }
+
+    /// Sorts standalone roots by this key.
+    pub(crate) fn sort_roots(self, roots: &mut Vec<GCRoot>, reverse: bool, locale: SortLocale) {
+        let now = SystemTime::now();
+        sort_by_value(roots, reverse, locale, |gcroot| {
+            (self.root_value(gcroot, now), gcroot.path.to_string())
+        });
+    }
+
+    /// Sorts profiles by this key.
+    pub(crate) fn sort_profiles(
+        self,
+        profiles: &mut Vec<Profile>,
+        reverse: bool,
+        locale: SortLocale,
+    ) {
+        let now = SystemTime::now();
+        sort_by_value(profiles, reverse, locale, |profile| {
+            (self.profile_value(profile, now), profile.path.to_string())
+        });
+    }
+}
+
+/// Stable sort on values computed once per item, with missing values last.
+fn sort_by_value<T>(
+    items: &mut Vec<T>,
+    reverse: bool,
+    locale: SortLocale,
+    value: impl Fn(&T) -> (Option<Value>, String),
+) {
+    let mut keyed = std::mem::take(items)
+        .into_iter()
+        .map(|item| (value(&item), item))
+        .collect::<Vec<_>>();
+    keyed.sort_by(|((v1, path1), _), ((v2, path2), _)| {
+        let by_value = match (v1, v2) {
+            (Some(v1), Some(v2)) if reverse => v2.cmp(v1, locale),
+            (Some(v1), Some(v2)) => v1.cmp(v2, locale),
+            (Some(_), None) => Ordering::Less,
+            (None, Some(_)) => Ordering::Greater,
+            (None, None) => Ordering::Equal,
+        };
+        by_value.then_with(|| locale.cmp(path1, path2))
+    });
+    items.extend(keyed.into_iter().map(|(_, item)| item));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeMap, rc::Rc};
+
+    use camino::Utf8Path;
+
+    use super::*;
+    use crate::gcroot::{StoreDirs, Target};
+
+    fn root(path: &str, closure_size: Option<u64>) -> GCRoot {
+        let target = Target::new(
+            Rc::from(Utf8Path::new("/nix/store/aaaa-a")),
+            &StoreDirs::default(),
+        );
+        let mut gcroot = GCRoot::new(Rc::from(Utf8Path::new(path)), target);
+        gcroot.closure_size = closure_size;
+        gcroot
+    }
+
+    fn paths(roots: &[GCRoot]) -> Vec<&str> {
+        roots.iter().map(|gcroot| gcroot.path.as_str()).collect()
+    }
+
+    #[test]
+    fn size_sorts_largest_first() {
+        let mut roots = vec![
+            root("/a", Some(1)),
+            root("/b", Some(3)),
+            root("/c", Some(2)),
+        ];
+        SortKey::Size.sort_roots(&mut roots, false, SortLocale::Byte);
+        assert_eq!(paths(&roots), ["/b", "/c", "/a"]);
+        SortKey::Size.sort_roots(&mut roots, true, SortLocale::Byte);
+        assert_eq!(paths(&roots), ["/a", "/c", "/b"]);
+    }
+
+    #[test]
+    fn roots_without_sizes_sort_last() {
+        let mut roots = vec![root("/b", None), root("/c", Some(1)), root("/a", None)];
+        SortKey::Size.sort_roots(&mut roots, false, SortLocale::Byte);
+        assert_eq!(paths(&roots), ["/c", "/a", "/b"]);
+        SortKey::Size.sort_roots(&mut roots, true, SortLocale::Byte);
+        assert_eq!(paths(&roots), ["/c", "/a", "/b"]);
+    }
+
+    #[test]
+    fn profiles_sort_by_combined_size() {
+        let profile = |path: &str, closure_size| Profile {
+            path: path.into(),
+            active_generation: None,
+            generations: BTreeMap::new(),
+            closure_size,
+        };
+        let mut profiles = vec![
+            profile("/p1", None),
+            profile("/p2", Some(5)),
+            profile("/p3", Some(9)),
+        ];
+        SortKey::Size.sort_profiles(&mut profiles, false, SortLocale::Byte);
+        let paths = profiles.iter().map(|p| p.path.as_str()).collect::<Vec<_>>();
+        assert_eq!(paths, ["/p3", "/p2", "/p1"]);
+    }
+
+    #[test]
+    fn natural_order_compares_numbers_by_value() {
+        assert_eq!(natural_cmp("result-2", "result-10"), Ordering::Less);
+        assert_eq!(natural_cmp("Élan", "emu"), Ordering::Less);
+        assert_eq!(natural_cmp("b", "A"), Ordering::Greater);
+    }
+
+    #[test]
+    fn natural_order_is_total() {
+        // Equal but for case or leading zeros, ordered by bytes.
+        assert_eq!(natural_cmp("A", "a"), Ordering::Less);
+        assert_eq!(natural_cmp("a", "A"), Ordering::Greater);
+        assert_eq!(natural_cmp("01", "1"), Ordering::Less);
+        assert_eq!(natural_cmp("abc", "abc"), Ordering::Equal);
+        let words = [
+            "result-10",
+            "Result-2",
+            "result-02",
+            "Ärger",
+            "ärger",
+            "Zz",
+            "aa",
+            "",
+        ];
+        for a in words {
+            for b in words {
+                assert_eq!(natural_cmp(a, b), natural_cmp(b, a).reverse(), "{a} {b}");
+                assert_eq!(natural_cmp(a, b) == Ordering::Equal, a == b, "{a} {b}");
+            }
+        }
+    }
+
+    #[test]
+    fn natural_order_of_paths() {
+        let mut words = vec![
+            "/home/Zz/result",
+            "/home/aa/result-10",
+            "/home/Ärger/result",
+            "/home/aa/result-2",
+            "/home/birne/result",
+            "/home/apfel/result",
+            "/home/aa/result-99999999999999999999999",
+        ];
+        let mut bytes = words.clone();
+        bytes.sort_by(|a, b| SortLocale::Byte.cmp(a, b));
+        assert_eq!(bytes.first(), Some(&"/home/Zz/result"));
+        assert_eq!(bytes.last(), Some(&"/home/Ärger/result"));
+        words.sort_by(|a, b| SortLocale::Natural.cmp(a, b));
+        assert_eq!(
+            words,
+            [
+                "/home/aa/result-2",
+                "/home/aa/result-10",
+                "/home/aa/result-99999999999999999999999",
+                "/home/apfel/result",
+                "/home/Ärger/result",
+                "/home/birne/result",
+                "/home/Zz/result",
+            ]
+        );
+    }
+
+    #[test]
+    fn roots_sort_in_natural_order() {
+        let mut roots = vec![
+            root("/home/b/result-10", None),
+            root("/home/B/result-2", None),
+            root("/home/a/result", None),
+        ];
+        SortKey::Path.sort_roots(&mut roots, false, SortLocale::Natural);
+        assert_eq!(
+            paths(&roots),
+            ["/home/a/result", "/home/B/result-2", "/home/b/result-10"]
+        );
+        SortKey::Path.sort_roots(&mut roots, true, SortLocale::Natural);
+        assert_eq!(
+            paths(&roots),
+            ["/home/b/result-10", "/home/B/result-2", "/home/a/result"]
+        );
+        SortKey::Path.sort_roots(&mut roots, false, SortLocale::Byte);
+        assert_eq!(
+            paths(&roots),
+            ["/home/B/result-2", "/home/a/result", "/home/b/result-10"]
+        );
+    }
+}
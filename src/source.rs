@@ -0,0 +1,409 @@
+use std::{cell::Cell, collections::BTreeMap, fmt::Display, time::Duration};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use clap::ValueEnum;
+use eyre::{eyre, Result, WrapErr};
+use serde::Serialize;
+
+use crate::{
+    gcroot::{self, StoreDirs},
+    nix_command,
+    plan::count,
+    walk::{self, WalkOptions},
+};
+
+/// Somewhere gcroots are discovered from.
+pub trait Source {
+    /// Short name identifying the source in a [DiscoveryReport].
+    fn name(&self) -> &str;
+
+    /// The roots in the format of `nix-store --gc --print-roots`,
+    /// one `path -> target` per line.
+    fn print_roots(&self) -> Result<String>;
+
+    /// The format the last listing was read in, for sources that detect it.
+    fn format(&self) -> Option<InputFormat> {
+        None
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Roots known to the local Nix store, listed by nix-store.
+pub struct NixStore;
+
+impl Source for NixStore {
+    fn name(&self) -> &str {
+        "nix-store"
+    }
+
+    fn print_roots(&self) -> Result<String> {
+        let output =
+            nix_command::output(nix_command::command("nix-store").args(["--gc", "--print-roots"]))?;
+        let output = nix_command::success(output, "nix-store --gc --print-roots")?;
+        Ok(String::from_utf8(output.stdout)?)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+/// Where roots are discovered from when no --input is given.
+pub enum SourceKind {
+    /// Ask nix-store --gc --print-roots
+    #[default]
+    Command,
+    /// Walk the gcroots and profiles directories, without Nix or its daemon.
+    /// Misses roots only held by running processes
+    Filesystem,
+}
+
+#[derive(Debug, Clone)]
+/// Roots found by walking the gcroots and profiles directories of the Nix state
+/// directory like the garbage collector does, for when nix-store is missing,
+/// can't reach the daemon or is slow. Roots of running processes aren't found.
+pub struct Filesystem {
+    state_dir: Utf8PathBuf,
+    /// Targets count as store paths if these recognize them.
+    store_dirs: StoreDirs,
+}
+
+impl Filesystem {
+    pub fn new(state_dir: Utf8PathBuf, store_dirs: StoreDirs) -> Self {
+        Filesystem {
+            state_dir,
+            store_dirs,
+        }
+    }
+
+    /// Roots of the symlinks below dir, by path. A missing dir has none,
+    /// unreadable directories below it are skipped with a warning.
+    fn roots_in(
+        &self,
+        dir: &Utf8Path,
+        roots: &mut BTreeMap<Utf8PathBuf, Utf8PathBuf>,
+    ) -> Result<()> {
+        let options = WalkOptions {
+            skip: &[],
+            ..WalkOptions::SCAN
+        };
+        let walk = match walk::walk(dir, &options) {
+            Ok(walk) => walk,
+            Err(e) if gcroot::is_gone(&e) => return Ok(()),
+            Err(e) => return Err(eyre!(e).wrap_err(format!("couldn't read {dir}"))),
+        };
+        for (unreadable, e) in walk.errors.iter() {
+            eprintln!("warning: skipping {unreadable}, it can't be read: {e}");
+        }
+        for entry in walk
+            .entries
+            .iter()
+            .filter(|entry| entry.file_type.is_symlink())
+        {
+            if let Some((path, target)) = self.resolve(&entry.path) {
+                roots.insert(path, target);
+            }
+        }
+        Ok(())
+    }
+
+    /// The root the symlink at link makes, as (path, target). A link to a store
+    /// path is a root itself, a link to another symlink is an indirect root
+    /// whose path is the other symlink, like the result links gcroots/auto
+    /// points at. Links whose target is gone are returned as is, so that they
+    /// show up as roots pointing outside the store instead of vanishing.
+    fn resolve(&self, link: &Utf8Path) -> Option<(Utf8PathBuf, Utf8PathBuf)> {
+        let target = Self::read_link(link)?;
+        if self.store_dirs.recognizes(&target) {
+            return Some((link.to_path_buf(), target));
+        }
+        match target.symlink_metadata() {
+            Err(e) if gcroot::is_gone(&e) => Some((link.to_path_buf(), target)),
+            Ok(metadata) if metadata.file_type().is_symlink() => {
+                let store_path = Self::read_link(&target)?;
+                self.store_dirs
+                    .recognizes(&store_path)
+                    .then_some((target, store_path))
+            }
+            _ => None,
+        }
+    }
+
+    /// Target of the symlink at link, relative ones resolved against its directory.
+    fn read_link(link: &Utf8Path) -> Option<Utf8PathBuf> {
+        let target = link.read_link_utf8().ok()?;
+        Some(match link.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        })
+    }
+}
+
+impl Source for Filesystem {
+    fn name(&self) -> &str {
+        "filesystem"
+    }
+
+    fn print_roots(&self) -> Result<String> {
+        let mut roots = BTreeMap::new();
+        self.roots_in(&self.state_dir.join("gcroots"), &mut roots)?;
+        self.roots_in(&self.state_dir.join("profiles"), &mut roots)?;
+        Ok(roots
+            .iter()
+            .map(|(path, target)| format!("{path} -> {target}\n"))
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// Formats of root listings an [InputFile] can hold.
+pub enum InputFormat {
+    /// `path -> target` lines, from `nix-store --gc --print-roots`.
+    Arrows,
+    /// Root paths alone, from `nix-store --query --roots <path>`.
+    /// Targets are read from the roots themselves.
+    BarePaths,
+}
+
+#[derive(Debug)]
+/// Roots read from a file captured earlier, in any [InputFormat].
+pub struct InputFile {
+    path: Utf8PathBuf,
+    name: String,
+    format: Cell<Option<InputFormat>>,
+}
+
+impl InputFile {
+    pub fn new(path: Utf8PathBuf) -> Self {
+        InputFile {
+            name: format!("input {path}"),
+            path,
+            format: Cell::new(None),
+        }
+    }
+
+    /// Detects the format of contents from its lines. Lines that are neither
+    /// roots nor bare paths are ignored, like warnings in captured output,
+    /// but a mix of both formats or no roots at all is an error.
+    pub fn detect(contents: &str) -> Result<InputFormat> {
+        let (mut arrows, mut bare, mut unrecognized) = (0, 0, 0);
+        for line in contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+        {
+            match (
+                line.contains("->"),
+                line.starts_with('/') || line.starts_with('{'),
+            ) {
+                (true, true) => arrows += 1,
+                (false, true) if !line.contains(char::is_whitespace) => bare += 1,
+                _ => unrecognized += 1,
+            }
+        }
+        match (arrows, bare) {
+            (1.., 0) => Ok(InputFormat::Arrows),
+            (0, 1..) => Ok(InputFormat::BarePaths),
+            _ => Err(eyre!(
+                "unrecognized format, found {}, {} and {}",
+                count(arrows, "root line", "root lines"),
+                count(bare, "bare path", "bare paths"),
+                count(unrecognized, "unrecognized line", "unrecognized lines"),
+            )),
+        }
+    }
+
+    /// Turns bare root paths into arrow lines by reading their targets.
+    /// Roots whose target can't be read are kept as they are, to be ignored.
+    fn resolve_bare_paths(contents: &str) -> String {
+        contents
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| match Utf8Path::new(line).read_link_utf8() {
+                Ok(target) => format!("{line} -> {target}"),
+                Err(_) => line.to_string(),
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl Source for InputFile {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn print_roots(&self) -> Result<String> {
+        let contents = std::fs::read_to_string(&self.path)
+            .wrap_err_with(|| format!("couldn't read {}", self.path))?;
+        let format = Self::detect(&contents).wrap_err_with(|| format!("in {}", self.path))?;
+        self.format.set(Some(format));
+        match format {
+            InputFormat::Arrows => Ok(contents),
+            InputFormat::BarePaths => Ok(Self::resolve_bare_paths(&contents)),
+        }
+    }
+
+    fn format(&self) -> Option<InputFormat> {
+        self.format.get()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "outcome", rename_all = "kebab-case")]
+/// How discovering roots from a single source went.
+pub enum SourceOutcome {
+    Ok,
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// What a single source contributed to discovery.
+pub struct SourceReport {
+    pub source: String,
+    #[serde(flatten)]
+    pub outcome: SourceOutcome,
+    /// Roots listed by the source, hidden ones included.
+    pub roots: usize,
+    /// Lines of output that weren't roots, e.g. localized warnings.
+    pub ignored_lines: usize,
+    /// Detected format of the listing, for sources that detect it.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub format: Option<InputFormat>,
+    #[serde(rename = "elapsed_ms", serialize_with = "serialize_millis")]
+    pub elapsed: Duration,
+}
+
+pub(crate) fn serialize_millis<S: serde::Serializer>(
+    duration: &Duration,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(duration.as_millis() as u64)
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+/// Every source discovery attempted, to tell complete results from partial ones.
+pub struct DiscoveryReport {
+    pub sources: Vec<SourceReport>,
+}
+
+impl DiscoveryReport {
+    /// Sources that failed, with their errors.
+    pub fn failed(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.sources
+            .iter()
+            .filter_map(|report| match &report.outcome {
+                SourceOutcome::Ok => None,
+                SourceOutcome::Failed { error } => Some((report.source.as_str(), error.as_str())),
+            })
+    }
+
+    /// If any source failed, so roots may be missing.
+    pub fn incomplete(&self) -> bool {
+        self.failed().next().is_some()
+    }
+}
+
+impl Display for DiscoveryReport {
+    /// A warning for every failed source, nothing if all succeeded.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, (source, error)) in self.failed().enumerate() {
+            if index != 0 {
+                writeln!(f)?;
+            }
+            write!(f, "⚠ results may be incomplete: {source}: {error}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_dir() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        (dir, path)
+    }
+
+    #[test]
+    fn arrow_lines_are_detected() {
+        let contents = "\
+finding garbage collector roots...
+/home/a/result -> /nix/store/aaaa-a
+{censored} -> /nix/store/bbbb-b
+";
+        assert_eq!(InputFile::detect(contents).unwrap(), InputFormat::Arrows);
+    }
+
+    #[test]
+    fn bare_paths_are_detected() {
+        let contents = "\n/home/a/result\n  /home/b/result  \n";
+        assert_eq!(InputFile::detect(contents).unwrap(), InputFormat::BarePaths);
+    }
+
+    #[test]
+    fn mixed_formats_are_an_error() {
+        let contents = "/home/a/result -> /nix/store/aaaa-a\n/home/b/result\n";
+        assert_eq!(
+            InputFile::detect(contents).unwrap_err().to_string(),
+            "unrecognized format, found 1 root line, 1 bare path and 0 unrecognized lines"
+        );
+    }
+
+    #[test]
+    fn warnings_alone_are_an_error() {
+        let contents = "warning: something\nerror: cannot connect to daemon\n";
+        assert_eq!(
+            InputFile::detect(contents).unwrap_err().to_string(),
+            "unrecognized format, found 0 root lines, 0 bare paths and 2 unrecognized lines"
+        );
+        assert!(InputFile::detect("").is_err());
+    }
+
+    #[test]
+    fn bare_paths_with_whitespace_are_unrecognized() {
+        // Whitespace is what tells warnings from bare paths.
+        let contents = "/home/a b/result\n/home/c/result\n";
+        assert_eq!(InputFile::detect(contents).unwrap(), InputFormat::BarePaths);
+        assert!(InputFile::detect("/home/a b/result\n").is_err());
+    }
+
+    #[test]
+    fn bare_paths_are_resolved_to_their_targets() {
+        let (_dir, dir) = temporary_dir();
+        let link = dir.join("result");
+        std::os::unix::fs::symlink("/nix/store/aaaa-a", &link).unwrap();
+        let missing = dir.join("missing");
+        let input = dir.join("roots.txt");
+        std::fs::write(&input, format!("{link}\n{missing}\n")).unwrap();
+        let input = InputFile::new(input);
+        assert_eq!(input.format(), None);
+        // The unreadable one is kept as is, for discovery to ignore.
+        assert_eq!(
+            input.print_roots().unwrap(),
+            format!("{link} -> /nix/store/aaaa-a\n{missing}")
+        );
+        assert_eq!(input.format(), Some(InputFormat::BarePaths));
+    }
+
+    #[test]
+    fn arrow_lines_are_read_as_they_are() {
+        let (_dir, dir) = temporary_dir();
+        let path = dir.join("roots.txt");
+        let contents = "/home/a/result -> /nix/store/aaaa-a\n";
+        std::fs::write(&path, contents).unwrap();
+        let input = InputFile::new(path.clone());
+        assert_eq!(input.name(), format!("input {path}"));
+        assert_eq!(input.print_roots().unwrap(), contents);
+        assert_eq!(input.format(), Some(InputFormat::Arrows));
+    }
+
+    #[test]
+    fn unreadable_inputs_are_errors() {
+        let (_dir, dir) = temporary_dir();
+        let path = dir.join("missing.txt");
+        let error = InputFile::new(path.clone()).print_roots().unwrap_err();
+        assert_eq!(error.to_string(), format!("couldn't read {path}"));
+    }
+}
@@ -0,0 +1,271 @@
+use std::{
+    fmt::Display,
+    fs::DirBuilder,
+    os::unix::fs::{DirBuilderExt, MetadataExt},
+};
+
+use camino::{Utf8Path, Utf8PathBuf};
+use eyre::{eyre, Result, WrapErr};
+use nix::unistd::Uid;
+
+use crate::{render, xdg};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A kind of file gcrs creates, deciding where it lives.
+pub enum Kind {
+    /// Worth keeping, like the journal.
+    State,
+    /// Disposable, like cached counts.
+    Cache,
+}
+
+impl Kind {
+    fn override_var(self) -> &'static str {
+        match self {
+            Kind::State => "GCRS_STATE_DIR",
+            Kind::Cache => "GCRS_CACHE_DIR",
+        }
+    }
+
+    fn xdg_home(self) -> Option<Utf8PathBuf> {
+        match self {
+            Kind::State => xdg::state_home(),
+            Kind::Cache => xdg::cache_home(),
+        }
+    }
+
+    fn system_dir(self) -> &'static Utf8Path {
+        match self {
+            Kind::State => Utf8Path::new("/var/lib/gcrs"),
+            Kind::Cache => Utf8Path::new("/var/cache/gcrs"),
+        }
+    }
+}
+
+/// The directory files of kind are kept in, `gcrs` in the XDG base directory.
+/// Root uses /var/lib/gcrs or /var/cache/gcrs instead if the base directory
+/// belongs to someone else, like when sudo kept the HOME of the invoking user,
+/// so that root-owned files don't break later runs as that user.
+/// `$GCRS_STATE_DIR` and `$GCRS_CACHE_DIR` replace all of this.
+pub fn dir(kind: Kind) -> Result<Utf8PathBuf> {
+    let base = kind.xdg_home();
+    let base_owner = base.as_deref().and_then(owner);
+    resolve(
+        kind,
+        std::env::var(kind.override_var()).ok(),
+        base,
+        base_owner,
+        Uid::effective(),
+    )
+}
+
+/// [dir] of kind for the value of its override variable, the XDG base
+/// directory, the owner of the base directory and the effective user.
+fn resolve(
+    kind: Kind,
+    override_dir: Option<String>,
+    base: Option<Utf8PathBuf>,
+    base_owner: Option<u32>,
+    euid: Uid,
+) -> Result<Utf8PathBuf> {
+    if let Some(dir) = override_dir.filter(|dir| !dir.is_empty()) {
+        return Ok(Utf8PathBuf::from(dir));
+    }
+    match base {
+        Some(_) if euid.is_root() && base_owner.is_some_and(|uid| uid != euid.as_raw()) => {
+            Ok(kind.system_dir().to_path_buf())
+        }
+        Some(base) => Ok(base.join("gcrs")),
+        None if euid.is_root() => Ok(kind.system_dir().to_path_buf()),
+        None => Err(eyre!(
+            "couldn't locate the {kind} directory, neither {} nor HOME is set",
+            match kind {
+                Kind::State => "XDG_STATE_HOME",
+                Kind::Cache => "XDG_CACHE_HOME",
+            }
+        )),
+    }
+}
+
+/// Creates the directory of kind and its parents, readable only by this user
+/// unless it's a system directory. A cache directory left behind by another
+/// user, e.g. root, is moved aside, other directories of other users are
+/// refused.
+pub fn create_dir(kind: Kind) -> Result<Utf8PathBuf> {
+    let dir = dir(kind)?;
+    create(kind, dir, Uid::effective().as_raw())
+}
+
+/// [create_dir] of dir as the user euid.
+fn create(kind: Kind, dir: Utf8PathBuf, euid: u32) -> Result<Utf8PathBuf> {
+    if let Some(uid) = dir.symlink_metadata().ok().map(|m| m.uid()) {
+        if uid == euid {
+            return Ok(dir);
+        }
+        let user = render::owner_name(uid);
+        if kind == Kind::State {
+            return Err(eyre!(
+                "refusing to write to {dir}, it belongs to {user}, probably from running \
+                gcrs as them; give it back with chown -R"
+            ));
+        }
+        let aside = dir.with_file_name(format!("gcrs.{user}-owned.{}", std::process::id()));
+        std::fs::rename(&dir, &aside).wrap_err_with(|| {
+            format!("{dir} belongs to {user} and couldn't be moved aside, remove it as {user}")
+        })?;
+        eprintln!("warning: moved {dir} of {user} aside to {aside}, remove it as {user}");
+    }
+    let mode = match dir.starts_with(kind.system_dir()) {
+        true => 0o755,
+        false => 0o700,
+    };
+    DirBuilder::new()
+        .recursive(true)
+        .mode(mode)
+        .create(&dir)
+        .wrap_err_with(|| format!("couldn't create {dir}"))?;
+    Ok(dir)
+}
+
+/// Owner of path, or of its nearest existing ancestor.
+fn owner(path: &Utf8Path) -> Option<u32> {
+    path.ancestors()
+        .find_map(|ancestor| ancestor.symlink_metadata().ok())
+        .map(|metadata| metadata.uid())
+}
+
+impl Display for Kind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Kind::State => write!(f, "state"),
+            Kind::Cache => write!(f, "cache"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use super::*;
+
+    const ROOT: Uid = Uid::from_raw(0);
+    const ALICE: Uid = Uid::from_raw(1000);
+
+    fn resolved(kind: Kind, base: Option<&str>, base_owner: Option<u32>, euid: Uid) -> String {
+        resolve(kind, None, base.map(Utf8PathBuf::from), base_owner, euid)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn users_keep_state_in_their_xdg_directories() {
+        let state = Some("/home/alice/.local/state");
+        let cache = Some("/home/alice/.cache");
+        assert_eq!(
+            resolved(Kind::State, state, Some(1000), ALICE),
+            "/home/alice/.local/state/gcrs"
+        );
+        assert_eq!(
+            resolved(Kind::Cache, cache, Some(1000), ALICE),
+            "/home/alice/.cache/gcrs"
+        );
+        // Whoever owns it, it's refused later if it belongs to someone else.
+        assert_eq!(
+            resolved(Kind::State, state, Some(0), ALICE),
+            "/home/alice/.local/state/gcrs"
+        );
+        let error = resolve(Kind::Cache, None, None, None, ALICE).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "couldn't locate the cache directory, neither XDG_CACHE_HOME nor HOME is set"
+        );
+    }
+
+    #[test]
+    fn root_uses_system_directories_outside_its_home() {
+        let state = Some("/home/alice/.local/state");
+        assert_eq!(
+            resolved(Kind::State, state, Some(1000), ROOT),
+            "/var/lib/gcrs"
+        );
+        assert_eq!(
+            resolved(Kind::Cache, Some("/home/alice/.cache"), Some(1000), ROOT),
+            "/var/cache/gcrs"
+        );
+        assert_eq!(
+            resolved(Kind::State, Some("/root/.local/state"), Some(0), ROOT),
+            "/root/.local/state/gcrs"
+        );
+        assert_eq!(resolved(Kind::State, None, None, ROOT), "/var/lib/gcrs");
+        assert_eq!(resolved(Kind::Cache, None, None, ROOT), "/var/cache/gcrs");
+    }
+
+    #[test]
+    fn override_variables_win() {
+        for euid in [ROOT, ALICE] {
+            let dir = resolve(
+                Kind::State,
+                Some("/srv/gcrs".to_string()),
+                Some("/home/alice/.local/state".into()),
+                Some(1000),
+                euid,
+            );
+            assert_eq!(dir.unwrap(), "/srv/gcrs");
+        }
+        let empty = resolve(Kind::State, Some(String::new()), None, None, ROOT);
+        assert_eq!(empty.unwrap(), "/var/lib/gcrs");
+    }
+
+    fn temporary_dir() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        (dir, path)
+    }
+
+    fn me() -> u32 {
+        Uid::effective().as_raw()
+    }
+
+    #[test]
+    fn directories_are_created_for_this_user_only() {
+        let (_dir, dir) = temporary_dir();
+        let state = dir.join("state/gcrs");
+        assert_eq!(create(Kind::State, state.clone(), me()).unwrap(), state);
+        let mode = state.metadata().unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o700);
+        // Existing directories of this user are used as they are.
+        assert_eq!(create(Kind::State, state.clone(), me()).unwrap(), state);
+    }
+
+    #[test]
+    fn state_of_another_user_is_refused() {
+        let (_dir, dir) = temporary_dir();
+        let state = dir.join("gcrs");
+        std::fs::create_dir(&state).unwrap();
+        std::fs::write(state.join("journal.jsonl"), "").unwrap();
+        let owner = render::owner_name(me());
+        let error = create(Kind::State, state.clone(), me() + 1).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "refusing to write to {state}, it belongs to {owner}, probably from running \
+                 gcrs as them; give it back with chown -R"
+            )
+        );
+        assert!(state.join("journal.jsonl").exists());
+    }
+
+    #[test]
+    fn cache_of_another_user_is_moved_aside() {
+        let (_dir, dir) = temporary_dir();
+        let cache = dir.join("gcrs");
+        std::fs::create_dir(&cache).unwrap();
+        std::fs::write(cache.join("counts.json"), "{}").unwrap();
+        let owner = render::owner_name(me());
+        assert_eq!(create(Kind::Cache, cache.clone(), me() + 1).unwrap(), cache);
+        assert_eq!(std::fs::read_dir(&cache).unwrap().count(), 0);
+        let aside = dir.join(format!("gcrs.{owner}-owned.{}", std::process::id()));
+        assert!(aside.join("counts.json").exists());
+    }
+}
@@ -0,0 +1,158 @@
+use std::fmt::Display;
+
+use serde::{Deserialize, Serialize};
+
+/// z-score of the 95% confidence intervals of estimates.
+const Z_95: f64 = 1.96;
+
+#[derive(Debug, Clone)]
+/// Items picked from a population to stand in for all of it.
+pub struct Sample<'a, T> {
+    pub items: Vec<&'a T>,
+    /// Number of items the sample was picked from.
+    pub population: usize,
+}
+
+/// Picks size evenly spaced items after sorting them by key. The offset of the
+/// first item is seeded from the sorted keys, so that the same population
+/// always yields the same sample. Takes everything if size covers it.
+pub fn sample<'a, T>(
+    mut items: Vec<&'a T>,
+    size: usize,
+    key: impl Fn(&T) -> &str,
+) -> Sample<'a, T> {
+    let population = items.len();
+    if size >= population {
+        return Sample { items, population };
+    }
+    items.sort_by(|a, b| key(a).cmp(key(b)));
+    let seed = items
+        .iter()
+        .fold(FNV_OFFSET, |hash, item| fnv1a(hash, key(item)));
+    let stride = population as f64 / size.max(1) as f64;
+    let offset = (seed % 1_000_000) as f64 / 1_000_000.0 * stride;
+    let items = (0..size)
+        .map(|i| items[((offset + i as f64 * stride) as usize).min(population - 1)])
+        .collect();
+    Sample { items, population }
+}
+
+impl<T> Sample<'_, T> {
+    /// If every item of the population is in the sample, so estimates are exact.
+    pub fn is_exhaustive(&self) -> bool {
+        self.items.len() >= self.population
+    }
+
+    /// Extrapolates how many items of the population match, from the number of
+    /// sampled items that do.
+    pub fn estimate_count(&self, matching: usize) -> Estimate {
+        let (n, population) = (self.items.len() as f64, self.population as f64);
+        if self.is_exhaustive() || n == 0.0 {
+            return Estimate::exact(matching as f64);
+        }
+        let p = matching as f64 / n;
+        let margin = Z_95 * population * (p * (1.0 - p) / n * self.correction()).sqrt();
+        Estimate {
+            value: p * population,
+            margin: Some(margin),
+        }
+    }
+
+    /// Finite population correction, sampling most of a population is
+    /// more accurate than sampling a small part of it.
+    fn correction(&self) -> f64 {
+        let (n, population) = (self.items.len() as f64, self.population as f64);
+        match population > 1.0 {
+            true => (population - n) / (population - 1.0),
+            false => 0.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+/// A number extrapolated from a [Sample], or an exact one.
+pub struct Estimate {
+    pub value: f64,
+    /// Half of the 95% confidence interval, None if the value is exact.
+    pub margin: Option<f64>,
+}
+
+impl Estimate {
+    pub fn exact(value: f64) -> Self {
+        Estimate {
+            value,
+            margin: None,
+        }
+    }
+}
+
+impl Display for Estimate {
+    /// Rounded to whole numbers, like `1234 ± 56` for estimates.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:.0}", self.value)?;
+        if let Some(margin) = self.margin {
+            write!(f, " ± {:.0}", margin.ceil())?;
+        }
+        Ok(())
+    }
+}
+
+const FNV_OFFSET: u64 = 0xcbf2_9ce4_8422_2325;
+
+fn fnv1a(hash: u64, key: &str) -> u64 {
+    key.bytes().fold(hash, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100_0000_01b3)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn population(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("/tmp/root-{i:03}")).collect()
+    }
+
+    #[test]
+    fn sample_is_deterministic_and_ignores_the_order() {
+        let items = population(100);
+        let mut reversed: Vec<&String> = items.iter().rev().collect();
+        let first = sample(items.iter().collect(), 10, |item| item.as_str());
+        let second = sample(std::mem::take(&mut reversed), 10, |item| item.as_str());
+        assert_eq!(first.items, second.items);
+        assert_eq!(first.items.len(), 10);
+        assert_eq!(first.population, 100);
+        let mut distinct = first.items.clone();
+        distinct.dedup();
+        assert_eq!(distinct.len(), 10);
+    }
+
+    #[test]
+    fn sample_covering_the_population_is_exhaustive() {
+        let items = population(5);
+        let sample = sample(items.iter().collect(), 5, |item| item.as_str());
+        assert!(sample.is_exhaustive());
+        assert_eq!(sample.estimate_count(2), Estimate::exact(2.0));
+        assert_eq!(sample.estimate_count(2).to_string(), "2");
+    }
+
+    #[test]
+    fn estimate_extrapolates_with_a_margin() {
+        let items = population(1000);
+        let sample = sample(items.iter().collect(), 100, |item| item.as_str());
+        let estimate = sample.estimate_count(50);
+        assert_eq!(estimate.value, 500.0);
+        // 1.96 * 1000 * sqrt(0.25 / 100 * 900 / 999)
+        let margin = estimate.margin.unwrap();
+        assert!((margin - 93.02).abs() < 0.01, "{margin}");
+        assert_eq!(estimate.to_string(), "500 ± 94");
+    }
+
+    #[test]
+    fn empty_sample_is_exact() {
+        let items = population(10);
+        let sample = sample(items.iter().collect(), 0, |item| item.as_str());
+        assert!(sample.items.is_empty());
+        assert_eq!(sample.estimate_count(0), Estimate::exact(0.0));
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    fs::File,
+    io::Write,
+    os::fd::{FromRawFd, RawFd},
+    sync::{Mutex, OnceLock},
+};
+
+use camino::Utf8Path;
+use eyre::{eyre, Result};
+use nix::fcntl::{self, FcntlArg};
+use serde::Serialize;
+
+use crate::plan::DeleteReport;
+
+/// Version of the event schema, bumped on incompatible changes.
+pub const STATUS_VERSION: u32 = 1;
+
+/// Where events go, unset unless requested.
+static SINK: OnceLock<Mutex<Box<dyn Write + Send>>> = OnceLock::new();
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "kebab-case")]
+/// Something that happened during a run, for tools wrapping gcrs. Written as
+/// a line of JSON with the schema version and the kebab-case kind, e.g.
+/// `{"version":1,"event":"plan-computed","entries":3}`.
+pub enum Event<'a> {
+    DiscoveryStarted,
+    DiscoveryFinished {
+        /// Listed roots, generations and standalone ones.
+        roots: usize,
+        profiles: usize,
+        standalone: usize,
+        failed_sources: usize,
+    },
+    PlanComputed {
+        entries: usize,
+    },
+    /// Emitted for every root as soon as it's handled, in no particular order.
+    Deletion {
+        path: &'a Utf8Path,
+        outcome: DeletionOutcome,
+        /// Why the root was refused or removing it failed.
+        #[serde(skip_serializing_if = "Option::is_none")]
+        reason: Option<String>,
+    },
+    /// Totals of a destructive action, the last event.
+    Finished {
+        removed: usize,
+        gone: usize,
+        refused: usize,
+        failed: usize,
+        interrupted: usize,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+/// What happened to a single root in a [Event::Deletion].
+pub enum DeletionOutcome {
+    Removed,
+    Gone,
+    Refused,
+    Failed,
+}
+
+#[derive(Serialize)]
+struct Versioned<'a> {
+    version: u32,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Sends events to the open file descriptor fd.
+pub fn to_fd(fd: RawFd) -> Result<()> {
+    fcntl::fcntl(fd, FcntlArg::F_GETFD)
+        .map_err(|e| eyre!("--status-fd {fd} isn't an open file descriptor: {e}"))?;
+    // The descriptor was checked to be open, and is owned from now on.
+    let file = unsafe { File::from_raw_fd(fd) };
+    set_sink(Box::new(file))
+}
+
+/// Sends events to stderr.
+pub fn to_stderr() -> Result<()> {
+    set_sink(Box::new(std::io::stderr()))
+}
+
+fn set_sink(sink: Box<dyn Write + Send>) -> Result<()> {
+    SINK.set(Mutex::new(sink))
+        .map_err(|_| eyre!("status events already have a destination"))
+}
+
+/// Writes the event as a line, if events were requested.
+/// Failing to write is ignored, the tool reading them may have gone away.
+pub fn emit(event: &Event) {
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+    let Ok(mut line) = serde_json::to_string(&Versioned {
+        version: STATUS_VERSION,
+        event,
+    }) else {
+        return;
+    };
+    line.push('\n');
+    let mut sink = sink.lock().unwrap_or_else(|e| e.into_inner());
+    let _ = sink.write_all(line.as_bytes()).and_then(|_| sink.flush());
+}
+
+impl<'a> Event<'a> {
+    /// The totals of a finished destructive action.
+    pub fn finished(report: &DeleteReport) -> Self {
+        Event::Finished {
+            removed: report.removed.len(),
+            gone: report.gone.len(),
+            refused: report.refused.len(),
+            failed: report.failed.len(),
+            interrupted: report.interrupted.len(),
+        }
+    }
+}
@@ -0,0 +1,189 @@
+use std::fmt::Display;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use crate::plan::PlanEntry;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+/// Order in which clean considers candidate roots, which decides what
+/// --free removes first.
+pub enum Strategy {
+    /// Least recently modified symlinks first
+    #[default]
+    Oldest,
+    /// Largest estimated size first
+    Largest,
+    /// Roots whose target can be substituted from a binary cache first
+    Cheapest,
+}
+
+/// Data about a candidate that a strategy needs gathered before scoring.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Needs {
+    pub sizes: bool,
+    pub substitutes: bool,
+}
+
+impl Strategy {
+    /// What has to be known about candidates for this strategy to order them.
+    pub fn needs(self) -> Needs {
+        match self {
+            Strategy::Oldest => Needs::default(),
+            Strategy::Largest => Needs {
+                sizes: true,
+                ..Needs::default()
+            },
+            Strategy::Cheapest => Needs {
+                substitutes: true,
+                ..Needs::default()
+            },
+        }
+    }
+
+    /// Score of a candidate, lower scores are considered first.
+    /// None if the data needed to score the candidate is missing,
+    /// such candidates are considered last.
+    pub fn score(self, entry: &PlanEntry) -> Option<u64> {
+        match self {
+            Strategy::Oldest => entry.mtime,
+            Strategy::Largest => entry.size.map(|size| u64::MAX - size),
+            Strategy::Cheapest => entry
+                .substitutable
+                .map(|substitutable| match substitutable {
+                    true => 0,
+                    false => 1,
+                }),
+        }
+    }
+
+    /// Stable sort of candidates by score, ties are ordered oldest first, then by path.
+    pub fn order(self, entries: &mut [PlanEntry]) {
+        entries.sort_by(|e1, e2| {
+            let score1 = self.score(e1);
+            let score2 = self.score(e2);
+            (
+                score1.is_none(),
+                score1,
+                e1.mtime.is_none(),
+                e1.mtime,
+                &e1.path,
+            )
+                .cmp(&(
+                    score2.is_none(),
+                    score2,
+                    e2.mtime.is_none(),
+                    e2.mtime,
+                    &e2.path,
+                ))
+        });
+    }
+}
+
+impl Display for Strategy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Strategy::Oldest => write!(f, "oldest"),
+            Strategy::Largest => write!(f, "largest"),
+            Strategy::Cheapest => write!(f, "cheapest"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+
+    use super::*;
+    use crate::{
+        gcroot::NodeType,
+        plan::{EntryKind, SelectionReason},
+    };
+
+    fn entry(
+        path: &str,
+        mtime: Option<u64>,
+        size: Option<u64>,
+        substitutable: Option<bool>,
+    ) -> PlanEntry {
+        PlanEntry {
+            path: Utf8PathBuf::from(path),
+            target: Utf8PathBuf::from("/nix/store/aaaa-target"),
+            kind: EntryKind::Standalone,
+            reasons: vec![SelectionReason::Requested],
+            node: NodeType::Symlink,
+            contents: Vec::new(),
+            mtime,
+            size,
+            substitutable,
+        }
+    }
+
+    fn ordered(strategy: Strategy, mut entries: Vec<PlanEntry>) -> Vec<String> {
+        strategy.order(&mut entries);
+        entries
+            .into_iter()
+            .map(|entry| entry.path.into_string())
+            .collect()
+    }
+
+    #[test]
+    fn oldest_orders_by_mtime_unknown_last() {
+        let entries = vec![
+            entry("/unknown", None, None, None),
+            entry("/new", Some(30), None, None),
+            entry("/old-b", Some(10), None, None),
+            entry("/old-a", Some(10), None, None),
+            entry("/middle", Some(20), None, None),
+        ];
+        assert_eq!(
+            ordered(Strategy::Oldest, entries),
+            ["/old-a", "/old-b", "/middle", "/new", "/unknown"]
+        );
+    }
+
+    #[test]
+    fn largest_orders_by_size_then_mtime_then_path() {
+        let entries = vec![
+            entry("/unsized-old", Some(1), None, None),
+            entry("/small", Some(1), Some(10), None),
+            entry("/large-new", Some(20), Some(100), None),
+            entry("/large-old-b", Some(10), Some(100), None),
+            entry("/large-old-a", Some(10), Some(100), None),
+            entry("/large-undated", None, Some(100), None),
+        ];
+        assert_eq!(
+            ordered(Strategy::Largest, entries),
+            [
+                "/large-old-a",
+                "/large-old-b",
+                "/large-new",
+                "/large-undated",
+                "/small",
+                "/unsized-old",
+            ]
+        );
+    }
+
+    #[test]
+    fn cheapest_orders_substitutable_first() {
+        let entries = vec![
+            entry("/unknown", Some(1), None, None),
+            entry("/local-old", Some(1), None, Some(false)),
+            entry("/cached-new", Some(20), None, Some(true)),
+            entry("/cached-old", Some(10), None, Some(true)),
+        ];
+        assert_eq!(
+            ordered(Strategy::Cheapest, entries),
+            ["/cached-old", "/cached-new", "/local-old", "/unknown"]
+        );
+    }
+
+    #[test]
+    fn needs_match_the_scores() {
+        assert!(!Strategy::Oldest.needs().sizes && !Strategy::Oldest.needs().substitutes);
+        assert!(Strategy::Largest.needs().sizes);
+        assert!(Strategy::Cheapest.needs().substitutes);
+    }
+}
@@ -0,0 +1,191 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    gcroot::{GCRoots, MetadataError},
+    stats::{self, Estimate},
+    timing::{self, Phase},
+};
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Controls which fields of a [Summary] are computed.
+pub struct SummaryOptions {
+    /// Fill the fields that need an lstat of every root.
+    pub metadata: bool,
+    /// Only lstat this many roots, see [stats::sample], and extrapolate.
+    pub sample: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+/// Aggregate numbers about discovered GCRoots.
+/// Fields that need metadata are None unless it was requested.
+pub struct Summary {
+    pub profiles: usize,
+    /// Generations of every profile, active ones included.
+    pub generations: usize,
+    /// Profiles whose active generation is known and listed.
+    pub active: usize,
+    pub standalone: usize,
+    /// Roots nix-store doesn't list, held by running processes or censored.
+    pub censored: usize,
+    /// Listed roots that disappeared during the scan.
+    pub disappeared: Option<usize>,
+    /// Listed roots whose metadata can't be read for any other reason.
+    pub inaccessible: Option<usize>,
+    /// Modification time of the oldest listed root.
+    pub oldest_root: Option<DateTime<Utc>>,
+    /// Modification time of the newest listed root.
+    pub newest_root: Option<DateTime<Utc>>,
+    /// Set if the metadata fields were extrapolated from a sample of the
+    /// roots, the oldest and newest root are the ones of the sample then.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sampled: Option<SampledMetadata>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// The estimates behind the metadata fields of a sampled [Summary].
+pub struct SampledMetadata {
+    /// Number of roots whose metadata was read.
+    pub size: usize,
+    pub disappeared: Estimate,
+    pub inaccessible: Estimate,
+}
+
+impl Summary {
+    pub fn of(gcroots: &GCRoots, options: SummaryOptions) -> Self {
+        let profiles = gcroots.profiles();
+        let mut summary = Summary {
+            profiles: profiles.len(),
+            generations: profiles.iter().map(|profile| profile.len()).sum(),
+            active: profiles
+                .iter()
+                .filter(|profile| profile.active().is_some())
+                .count(),
+            standalone: gcroots.standalone().len(),
+            censored: gcroots.hidden_targets().count(),
+            ..Summary::default()
+        };
+        if options.metadata {
+            timing::time(Phase::Enrichment("metadata"), || {
+                summary.add_metadata(gcroots, options.sample)
+            });
+        }
+        summary
+    }
+
+    /// Number of listed roots, generations and standalone ones.
+    pub fn roots(&self) -> usize {
+        self.generations + self.standalone
+    }
+
+    fn add_metadata(&mut self, gcroots: &GCRoots, sample: Option<usize>) {
+        let mut disappeared = 0;
+        let mut inaccessible = 0;
+        let roots = gcroots
+            .profiles()
+            .iter()
+            .flat_map(|profile| profile.generations.values())
+            .chain(gcroots.standalone())
+            .collect();
+        let sample = stats::sample(roots, sample.unwrap_or(usize::MAX), |gcroot| {
+            gcroot.path.as_str()
+        });
+        for gcroot in sample.items.iter() {
+            match gcroot.metadata() {
+                Ok(metadata) => {
+                    let mtime = DateTime::<Utc>::from(metadata.mtime);
+                    self.oldest_root = Some(self.oldest_root.map_or(mtime, |t| t.min(mtime)));
+                    self.newest_root = Some(self.newest_root.map_or(mtime, |t| t.max(mtime)));
+                }
+                Err(MetadataError::NotFound) => disappeared += 1,
+                Err(_) => inaccessible += 1,
+            }
+        }
+        self.disappeared = Some(disappeared);
+        self.inaccessible = Some(inaccessible);
+        if !sample.is_exhaustive() {
+            let disappeared = sample.estimate_count(disappeared);
+            let inaccessible = sample.estimate_count(inaccessible);
+            self.disappeared = Some(disappeared.value.round() as usize);
+            self.inaccessible = Some(inaccessible.value.round() as usize);
+            self.sampled = Some(SampledMetadata {
+                size: sample.items.len(),
+                disappeared,
+                inaccessible,
+            });
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use camino::Utf8PathBuf;
+
+    use super::*;
+
+    /// Ten roots in a temporary directory, the odd ones gone.
+    fn gcroots() -> (tempfile::TempDir, GCRoots) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        let mut output = String::new();
+        for i in 0..10 {
+            let root = path.join(format!("result-{i}"));
+            if i % 2 == 0 {
+                std::os::unix::fs::symlink("/nix/store/aaaa-a", &root).unwrap();
+            }
+            output.push_str(&format!("{root} -> /nix/store/aaaa-a\n"));
+        }
+        (dir, GCRoots::from_print_roots_output(&output).unwrap())
+    }
+
+    #[test]
+    fn metadata_of_every_root() {
+        let (_dir, gcroots) = gcroots();
+        let summary = Summary::of(
+            &gcroots,
+            SummaryOptions {
+                metadata: true,
+                sample: None,
+            },
+        );
+        assert_eq!(summary.standalone, 10);
+        assert_eq!(summary.disappeared, Some(5));
+        assert_eq!(summary.inaccessible, Some(0));
+        assert!(summary.oldest_root.is_some());
+        assert!(summary.sampled.is_none());
+    }
+
+    #[test]
+    fn metadata_of_a_sample_is_extrapolated() {
+        let (_dir, gcroots) = gcroots();
+        let options = SummaryOptions {
+            metadata: true,
+            sample: Some(4),
+        };
+        let summary = Summary::of(&gcroots, options);
+        let sampled = summary.sampled.clone().unwrap();
+        assert_eq!(sampled.size, 4);
+        assert!(sampled.disappeared.margin.is_some());
+        assert_eq!(
+            summary.disappeared,
+            Some(sampled.disappeared.value.round() as usize)
+        );
+        // The value is a multiple of a quarter of the roots.
+        assert_eq!(sampled.disappeared.value % 2.5, 0.0);
+        assert_eq!(Summary::of(&gcroots, options), summary);
+    }
+
+    #[test]
+    fn sample_covering_every_root_is_exact() {
+        let (_dir, gcroots) = gcroots();
+        let summary = Summary::of(
+            &gcroots,
+            SummaryOptions {
+                metadata: true,
+                sample: Some(10),
+            },
+        );
+        assert_eq!(summary.disappeared, Some(5));
+        assert!(summary.sampled.is_none());
+    }
+}
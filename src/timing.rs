@@ -0,0 +1,185 @@
+use std::{
+    fmt::Display,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use serde::Serialize;
+
+use crate::source;
+
+/// Phases recorded so far in this run, in the order they first ran.
+static TIMINGS: Mutex<Vec<PhaseTiming>> = Mutex::new(Vec::new());
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A stage of the pipeline whose duration is recorded.
+pub enum Phase {
+    /// Waiting for sources to list roots, e.g. the nix-store subprocess.
+    Discovery,
+    /// Parsing the listed roots, per line.
+    Parsing,
+    /// Classifying targets and grouping roots into profiles.
+    Grouping,
+    /// Reading extra information about roots, per kind, e.g. metadata.
+    Enrichment(&'static str),
+    /// Querying the NAR sizes of targets.
+    Sizes,
+    /// Formatting output, including the enrichment it triggers.
+    Rendering,
+}
+
+#[derive(Debug, Clone, Serialize)]
+/// Total time spent in a phase.
+pub struct PhaseTiming {
+    #[serde(serialize_with = "serialize_phase")]
+    pub phase: Phase,
+    #[serde(rename = "elapsed_ms", serialize_with = "source::serialize_millis")]
+    pub elapsed: Duration,
+    /// Number of items processed, e.g. lines parsed, if the phase counts them.
+    pub items: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(transparent)]
+/// Every phase that ran, for `--timings`.
+pub struct Timings {
+    pub phases: Vec<PhaseTiming>,
+}
+
+/// Runs f, adding its duration to phase.
+pub fn time<T>(phase: Phase, f: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = f();
+    record(phase, start.elapsed(), None);
+    result
+}
+
+/// Adds elapsed and items to the totals of phase.
+pub fn record(phase: Phase, elapsed: Duration, items: Option<usize>) {
+    let mut timings = TIMINGS.lock().unwrap_or_else(|e| e.into_inner());
+    match timings.iter_mut().find(|timing| timing.phase == phase) {
+        Some(timing) => {
+            timing.elapsed += elapsed;
+            timing.items = match (timing.items, items) {
+                (None, None) => None,
+                (a, b) => Some(a.unwrap_or(0) + b.unwrap_or(0)),
+            };
+        }
+        None => timings.push(PhaseTiming {
+            phase,
+            elapsed,
+            items,
+        }),
+    }
+}
+
+/// The phases recorded so far.
+pub fn timings() -> Timings {
+    let timings = TIMINGS.lock().unwrap_or_else(|e| e.into_inner());
+    Timings {
+        phases: timings.clone(),
+    }
+}
+
+impl Phase {
+    /// What the items of the phase are.
+    fn unit(self) -> &'static str {
+        match self {
+            Phase::Parsing => "lines",
+            Phase::Sizes => "paths",
+            _ => "roots",
+        }
+    }
+}
+
+fn serialize_phase<S: serde::Serializer>(phase: &Phase, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(phase)
+}
+
+impl Display for Phase {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Phase::Discovery => write!(f, "discovery"),
+            Phase::Parsing => write!(f, "parsing"),
+            Phase::Grouping => write!(f, "grouping"),
+            Phase::Enrichment(kind) => write!(f, "enrichment ({kind})"),
+            Phase::Sizes => write!(f, "size queries"),
+            Phase::Rendering => write!(f, "rendering"),
+        }
+    }
+}
+
+impl Display for Timings {
+    /// One aligned line per phase with its duration and rate.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names = self
+            .phases
+            .iter()
+            .map(|timing| timing.phase.to_string())
+            .collect::<Vec<_>>();
+        let width = names.iter().map(|name| name.len()).max().unwrap_or(0);
+        for (index, (timing, name)) in self.phases.iter().zip(names).enumerate() {
+            if index != 0 {
+                writeln!(f)?;
+            }
+            let millis = timing.elapsed.as_secs_f64() * 1000.0;
+            write!(f, "{name: <width$}  {millis: >9.1} ms")?;
+            let seconds = timing.elapsed.as_secs_f64();
+            if let Some(items) = timing.items.filter(|_| seconds > 0.0) {
+                let rate = items as f64 / seconds;
+                write!(f, "  {items} {}, {rate:.0}/s", timing.phase.unit())?;
+            }
+        }
+        if self.phases.is_empty() {
+            write!(f, "no phases ran")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(phase: Phase, millis: u64, items: Option<usize>) -> PhaseTiming {
+        PhaseTiming {
+            phase,
+            elapsed: Duration::from_millis(millis),
+            items,
+        }
+    }
+
+    #[test]
+    fn table_lists_the_phases_in_the_order_they_ran() {
+        let timings = Timings {
+            phases: vec![
+                timing(Phase::Discovery, 1500, None),
+                timing(Phase::Parsing, 500, Some(1000)),
+                timing(Phase::Enrichment("metadata"), 2, Some(4)),
+            ],
+        };
+        assert_eq!(
+            timings.to_string(),
+            "\
+discovery                 1500.0 ms
+parsing                    500.0 ms  1000 lines, 2000/s
+enrichment (metadata)        2.0 ms  4 roots, 2000/s"
+        );
+    }
+
+    #[test]
+    fn no_phases() {
+        assert_eq!(Timings::default().to_string(), "no phases ran");
+    }
+
+    #[test]
+    fn phases_are_serialized_by_name() {
+        let timings = Timings {
+            phases: vec![timing(Phase::Sizes, 3, Some(2))],
+        };
+        assert_eq!(
+            serde_json::to_string(&timings).unwrap(),
+            r#"[{"phase":"size queries","elapsed_ms":3,"items":2}]"#
+        );
+    }
+}
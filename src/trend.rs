@@ -0,0 +1,277 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Display,
+    io::Write,
+    time::{Duration, SystemTime},
+};
+
+use camino::Utf8PathBuf;
+use chrono::{DateTime, Utc};
+use eyre::{Result, WrapErr};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    diff::{Diff, Listing},
+    duration,
+    gcroot::GCRoots,
+    state,
+    summary::{Summary, SummaryOptions},
+};
+
+/// Number of snapshots kept in the history.
+pub const HISTORY_LEN: usize = 90;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The numbers of a single run of trend.
+pub struct Snapshot {
+    pub timestamp: DateTime<Utc>,
+    pub summary: Summary,
+    /// Number of generations of every profile.
+    pub generations: BTreeMap<Utf8PathBuf, usize>,
+    /// Roots of the run, only kept for the newest snapshot in the history.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listing: Option<Listing>,
+}
+
+impl Snapshot {
+    pub fn of(gcroots: &GCRoots, now: SystemTime) -> Self {
+        Snapshot {
+            timestamp: now.into(),
+            summary: gcroots.summary(SummaryOptions::default()),
+            generations: gcroots
+                .profiles()
+                .iter()
+                .map(|profile| (profile.path.clone(), profile.len()))
+                .collect(),
+            listing: Some(Listing::of(gcroots)),
+        }
+    }
+
+    /// What changed between this snapshot and a newer one,
+    /// None if either doesn't have its roots.
+    pub fn changes(&self, newer: &Snapshot) -> Option<Diff> {
+        Some(Diff::of(self.listing.as_ref()?, newer.listing.as_ref()?))
+    }
+}
+
+/// Snapshots of previous runs, one JSON object per line in
+/// `$XDG_STATE_HOME/gcrs/trend.jsonl`, oldest first.
+pub struct History {
+    path: Utf8PathBuf,
+    pub snapshots: Vec<Snapshot>,
+}
+
+impl History {
+    /// Reads the history, a missing file is empty.
+    /// Lines that can't be parsed are skipped with a warning on stderr.
+    pub fn open() -> Result<Self> {
+        let path = state::dir(state::Kind::State)?.join("trend.jsonl");
+        let contents = match std::fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e).wrap_err_with(|| format!("couldn't read {path}")),
+        };
+        let mut snapshots = Vec::new();
+        for (index, line) in contents.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(snapshot) => snapshots.push(snapshot),
+                Err(e) => eprintln!(
+                    "warning: skipping corrupt line {} of {}: {}",
+                    index + 1,
+                    path,
+                    e
+                ),
+            }
+        }
+        Ok(History { path, snapshots })
+    }
+
+    /// Adds the snapshot and writes the newest [HISTORY_LEN] snapshots back,
+    /// replacing the file atomically. Earlier snapshots drop their roots.
+    pub fn push(&mut self, snapshot: Snapshot) -> Result<()> {
+        for earlier in self.snapshots.iter_mut() {
+            earlier.listing = None;
+        }
+        self.snapshots.push(snapshot);
+        let excess = self.snapshots.len().saturating_sub(HISTORY_LEN);
+        self.snapshots.drain(..excess);
+        let dir = state::create_dir(state::Kind::State)?;
+        let temporary = dir.join(format!(".trend.jsonl.{}", std::process::id()));
+        let mut file = std::fs::File::create(&temporary)
+            .wrap_err_with(|| format!("couldn't create {temporary}"))?;
+        for snapshot in self.snapshots.iter() {
+            writeln!(file, "{}", serde_json::to_string(snapshot)?)?;
+        }
+        file.sync_data()?;
+        std::fs::rename(&temporary, &self.path)
+            .wrap_err_with(|| format!("couldn't replace {}", self.path))
+    }
+
+    /// The newest snapshot taken at least ago before the latest one.
+    pub fn before(&self, ago: Duration) -> Option<&Snapshot> {
+        let latest = self.snapshots.last()?;
+        let cutoff = latest.timestamp - chrono::Duration::from_std(ago).ok()?;
+        self.snapshots
+            .iter()
+            .rev()
+            .find(|snapshot| snapshot.timestamp <= cutoff)
+    }
+}
+
+/// The latest snapshot compared to the previous one and to one from a while ago.
+pub struct Trend<'a> {
+    pub current: &'a Snapshot,
+    pub previous: Option<&'a Snapshot>,
+    /// (how long ago, snapshot) to compare against.
+    pub earlier: Option<(Duration, &'a Snapshot)>,
+}
+
+impl<'a> Trend<'a> {
+    /// The trend of the latest snapshot in history, compared to the one ago before it.
+    pub fn of(history: &'a History, ago: Duration) -> Option<Self> {
+        let (current, rest) = history.snapshots.split_last()?;
+        Some(Trend {
+            current,
+            previous: rest.last(),
+            earlier: history.before(ago).map(|snapshot| (ago, snapshot)),
+        })
+    }
+
+    fn line(
+        &self,
+        f: &mut std::fmt::Formatter<'_>,
+        label: &str,
+        value: impl Fn(&Snapshot) -> Option<usize>,
+    ) -> std::fmt::Result {
+        let Some(current) = value(self.current) else {
+            return Ok(());
+        };
+        write!(f, "{label}: {current}")?;
+        let mut deltas = Vec::new();
+        if let Some(previous) = self.previous {
+            deltas.push(format!(
+                "{} since last run",
+                delta(current, value(previous))
+            ));
+        }
+        if let Some((ago, earlier)) = self.earlier {
+            deltas.push(format!(
+                "{} since {} ago",
+                delta(current, value(earlier)),
+                duration::format(ago)
+            ));
+        }
+        if !deltas.is_empty() {
+            write!(f, " ({})", deltas.join(", "))?;
+        }
+        writeln!(f)
+    }
+}
+
+/// Change from before to now, like +14, −2 or new if there was nothing before.
+fn delta(now: usize, before: Option<usize>) -> String {
+    match before {
+        None => "new".to_string(),
+        Some(before) if now > before => format!("+{}", now - before),
+        Some(before) if now < before => format!("−{}", before - now),
+        Some(_) => "±0".to_string(),
+    }
+}
+
+impl Display for Trend<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        self.line(f, "standalone roots", |s| Some(s.summary.standalone))?;
+        self.line(f, "profiles", |s| Some(s.summary.profiles))?;
+        self.line(f, "generations", |s| Some(s.summary.generations))?;
+        for profile in self.current.generations.keys() {
+            let name = profile.file_name().unwrap_or(profile.as_str());
+            self.line(f, &format!("{name} generations"), |s| {
+                s.generations.get(profile).copied()
+            })?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(output: &str, days: u64) -> Snapshot {
+        let gcroots = GCRoots::from_print_roots_output(output).unwrap();
+        let now = SystemTime::UNIX_EPOCH + Duration::from_secs(days * 24 * 60 * 60);
+        Snapshot::of(&gcroots, now)
+    }
+
+    fn history(snapshots: Vec<Snapshot>) -> History {
+        History {
+            path: "/nonexistent/trend.jsonl".into(),
+            snapshots,
+        }
+    }
+
+    #[test]
+    fn deltas() {
+        assert_eq!(delta(3, None), "new");
+        assert_eq!(delta(5, Some(3)), "+2");
+        assert_eq!(delta(3, Some(5)), "−2");
+        assert_eq!(delta(3, Some(3)), "±0");
+    }
+
+    #[test]
+    fn before_picks_the_newest_old_enough_snapshot() {
+        let history = history(vec![
+            snapshot("", 1),
+            snapshot("", 2),
+            snapshot("", 6),
+            snapshot("", 10),
+        ]);
+        let day = Duration::from_secs(24 * 60 * 60);
+        let before = |days| history.before(day * days).map(|s| s.timestamp);
+        assert_eq!(before(7), Some(history.snapshots[1].timestamp));
+        assert_eq!(before(4), Some(history.snapshots[2].timestamp));
+        assert_eq!(before(30), None);
+    }
+
+    #[test]
+    fn trend_compares_to_previous_and_earlier_runs() {
+        let history = history(vec![
+            snapshot("/tmp/a -> /nix/store/aaaa-a\n", 0),
+            snapshot(
+                "/tmp/a -> /nix/store/aaaa-a\n\
+                 /tmp/b -> /nix/store/bbbb-b\n\
+                 /tmp/c -> /nix/store/cccc-c\n",
+                6,
+            ),
+            snapshot(
+                "/tmp/a -> /nix/store/aaaa-a\n/tmp/b -> /nix/store/bbbb-b\n",
+                7,
+            ),
+        ]);
+        let trend = Trend::of(&history, Duration::from_secs(7 * 24 * 60 * 60)).unwrap();
+        let rendered = trend.to_string();
+        assert_eq!(
+            rendered.lines().next(),
+            Some("standalone roots: 2 (−1 since last run, +1 since 7d ago)")
+        );
+    }
+
+    #[test]
+    fn changes_need_the_roots_of_both_snapshots() {
+        let old = snapshot("/tmp/a -> /nix/store/aaaa-a\n", 0);
+        let new = snapshot("/tmp/b -> /nix/store/bbbb-b\n", 1);
+        let changes = old.changes(&new).unwrap();
+        assert_eq!(changes.added.len(), 1);
+        assert_eq!(changes.removed.len(), 1);
+        let mut stripped = old.clone();
+        stripped.listing = None;
+        assert!(stripped.changes(&new).is_none());
+        let json = serde_json::to_string(&stripped).unwrap();
+        assert!(!json.contains("listing"));
+        let parsed: Snapshot = serde_json::from_str(&json).unwrap();
+        assert!(parsed.listing.is_none());
+    }
+}
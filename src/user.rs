@@ -0,0 +1,173 @@
+use std::{ffi::CStr, sync::OnceLock};
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+use crate::render;
+
+/// Home directories that don't say anything about who owns what's under them.
+const SHARED_HOMES: &[&str] = &["/", "/nonexistent", "/var/empty", "/dev/null"];
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A user account with its home directory.
+pub struct Account {
+    pub name: String,
+    pub home: Utf8PathBuf,
+}
+
+/// Somewhere user accounts are read from.
+pub trait Passwd {
+    fn accounts(&self) -> Vec<Account>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+/// Accounts of the system user database as NSS lists them, so that users of
+/// LDAP, systemd-homed and the like are included, not only /etc/passwd.
+pub struct SystemPasswd;
+
+impl Passwd for SystemPasswd {
+    /// Accounts whose name or home isn't UTF-8 are left out.
+    /// Not thread-safe, like the getpwent it calls.
+    fn accounts(&self) -> Vec<Account> {
+        let mut accounts = Vec::new();
+        // SAFETY: getpwent returns null or an entry that stays valid until
+        // the next call, it's copied out before that.
+        unsafe {
+            nix::libc::setpwent();
+            loop {
+                let entry = nix::libc::getpwent();
+                if entry.is_null() {
+                    break;
+                }
+                let name = CStr::from_ptr((*entry).pw_name).to_str();
+                let home = CStr::from_ptr((*entry).pw_dir).to_str();
+                if let (Ok(name), Ok(home)) = (name, home) {
+                    accounts.push(Account {
+                        name: name.to_string(),
+                        home: Utf8PathBuf::from(home),
+                    });
+                }
+            }
+            nix::libc::endpwent();
+        }
+        accounts
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Attributes roots to users by the home directory they're in,
+/// falling back to the owner of the root itself.
+pub struct Attribution {
+    /// (home, user), longest home first so that nested homes win.
+    homes: Vec<(Utf8PathBuf, String)>,
+}
+
+impl Attribution {
+    pub fn new(passwd: &dyn Passwd) -> Self {
+        let mut homes = passwd
+            .accounts()
+            .into_iter()
+            .filter(|account| {
+                account.home.is_absolute() && !SHARED_HOMES.contains(&account.home.as_str())
+            })
+            .map(|account| (account.home, account.name))
+            .collect::<Vec<_>>();
+        homes.sort_by(|(h1, _), (h2, _)| {
+            h2.components()
+                .count()
+                .cmp(&h1.components().count())
+                .then_with(|| h1.cmp(h2))
+        });
+        homes.dedup_by(|(h1, _), (h2, _)| h1 == h2);
+        Attribution { homes }
+    }
+
+    /// Attribution from the system user database, read once.
+    pub fn system() -> &'static Attribution {
+        static SYSTEM: OnceLock<Attribution> = OnceLock::new();
+        SYSTEM.get_or_init(|| Attribution::new(&SystemPasswd))
+    }
+
+    /// The user whose home directory path is in.
+    pub fn user_by_home(&self, path: &Utf8Path) -> Option<&str> {
+        self.homes
+            .iter()
+            .find(|(home, _)| path.starts_with(home))
+            .map(|(_, user)| user.as_str())
+    }
+
+    /// The user a root at path belongs to, by home directory or otherwise by
+    /// the uid owning the root, if known.
+    pub fn user_of(&self, path: &Utf8Path, owner_uid: Option<u32>) -> Option<String> {
+        match self.user_by_home(path) {
+            Some(user) => Some(user.to_string()),
+            None => owner_uid.map(render::owner_name),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct Accounts(Vec<(&'static str, &'static str)>);
+
+    impl Passwd for Accounts {
+        fn accounts(&self) -> Vec<Account> {
+            self.0
+                .iter()
+                .map(|(name, home)| Account {
+                    name: name.to_string(),
+                    home: Utf8PathBuf::from(home),
+                })
+                .collect()
+        }
+    }
+
+    fn attribution() -> Attribution {
+        Attribution::new(&Accounts(vec![
+            ("alice", "/home/alice"),
+            ("build", "/home/alice/build"),
+            ("nobody", "/nonexistent"),
+            ("bob", "/srv/users/bob"),
+        ]))
+    }
+
+    #[test]
+    fn nested_homes_win() {
+        let attribution = attribution();
+        let user = |path| attribution.user_by_home(Utf8Path::new(path));
+        assert_eq!(user("/home/alice/project/result"), Some("alice"));
+        assert_eq!(user("/home/alice/build/result"), Some("build"));
+        assert_eq!(user("/srv/users/bob/result"), Some("bob"));
+    }
+
+    #[test]
+    fn shared_homes_attribute_nothing() {
+        let attribution = attribution();
+        assert_eq!(
+            attribution.user_by_home(Utf8Path::new("/nonexistent/result")),
+            None
+        );
+        assert_eq!(
+            attribution.user_by_home(Utf8Path::new("/home/alicia/result")),
+            None
+        );
+    }
+
+    #[test]
+    fn owner_is_the_fallback() {
+        let attribution = attribution();
+        let user = attribution.user_of(Utf8Path::new("/tmp/result"), Some(0));
+        assert_eq!(user.as_deref(), Some("root"));
+        assert_eq!(
+            attribution.user_of(Utf8Path::new("/tmp/result"), None),
+            None
+        );
+    }
+
+    #[test]
+    fn system_accounts_include_root() {
+        let accounts = SystemPasswd.accounts();
+        assert!(accounts.iter().any(|account| account.name == "root"));
+    }
+}
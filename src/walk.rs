@@ -0,0 +1,223 @@
+use std::fs::FileType;
+
+use camino::{Utf8Path, Utf8PathBuf};
+
+/// Directories that are mostly noise when looking for roots.
+pub const NOISE_DIRS: &[&str] = &[".git", "node_modules", ".cache"];
+
+#[derive(Debug, Clone)]
+/// Guards against huge or pathological directory trees, like recursive bind
+/// mounts or giant node_modules.
+pub struct WalkOptions {
+    /// How deep to descend, entries directly in the root are at depth 1.
+    pub max_depth: usize,
+    /// Stop after visiting this many entries, the walk is then truncated.
+    pub max_entries: usize,
+    /// Names of directories not descended into, unless one is the root itself.
+    pub skip: &'static [&'static str],
+    /// Descend into symlinks to directories.
+    pub follow_symlinks: bool,
+}
+
+impl WalkOptions {
+    /// For scanning arbitrary directories.
+    pub const SCAN: WalkOptions = WalkOptions {
+        max_depth: 8,
+        max_entries: 1_000_000,
+        skip: NOISE_DIRS,
+        follow_symlinks: false,
+    };
+
+    /// For directories with a known layout, like the gcroots or profiles directories.
+    pub const WELL_KNOWN: WalkOptions = WalkOptions {
+        max_depth: 3,
+        ..WalkOptions::SCAN
+    };
+}
+
+impl Default for WalkOptions {
+    fn default() -> Self {
+        WalkOptions::SCAN
+    }
+}
+
+#[derive(Debug, Clone)]
+/// An entry found by [walk].
+pub struct WalkEntry {
+    pub path: Utf8PathBuf,
+    pub depth: usize,
+    /// Type of the entry itself, symlinks aren't followed.
+    pub file_type: FileType,
+}
+
+#[derive(Debug, Default)]
+/// Everything a [walk] found.
+pub struct Walk {
+    /// Entries in depth first order, sorted by name within a directory.
+    pub entries: Vec<WalkEntry>,
+    /// If max_entries was hit, so entries are incomplete.
+    pub truncated: bool,
+    /// Directories below the root that couldn't be read.
+    pub errors: Vec<(Utf8PathBuf, std::io::Error)>,
+}
+
+/// Walks the directory tree at root within the limits of options,
+/// warning if it's truncated. Only failing to read root itself is an error.
+pub fn walk(root: &Utf8Path, options: &WalkOptions) -> std::io::Result<Walk> {
+    let mut walk = Walk::default();
+    let mut stack = vec![(0, read_dir(root)?)];
+    while let Some((depth, children)) = stack.pop() {
+        let mut subdirs = Vec::new();
+        for (path, file_type) in children {
+            if walk.entries.len() >= options.max_entries {
+                eprintln!(
+                    "warning: stopped walking {} after {} entries, results are incomplete",
+                    root, options.max_entries
+                );
+                walk.truncated = true;
+                return Ok(walk);
+            }
+            let depth = depth + 1;
+            if depth < options.max_depth && descends(&path, file_type, options) {
+                subdirs.push((path.clone(), depth));
+            }
+            walk.entries.push(WalkEntry {
+                path,
+                depth,
+                file_type,
+            });
+        }
+        for (subdir, depth) in subdirs.into_iter().rev() {
+            match read_dir(&subdir) {
+                Ok(children) => stack.push((depth, children)),
+                Err(e) => walk.errors.push((subdir, e)),
+            }
+        }
+    }
+    Ok(walk)
+}
+
+fn descends(path: &Utf8Path, file_type: FileType, options: &WalkOptions) -> bool {
+    let is_dir = match file_type.is_symlink() {
+        true => options.follow_symlinks && path.is_dir(),
+        false => file_type.is_dir(),
+    };
+    is_dir
+        && !path
+            .file_name()
+            .is_some_and(|name| options.skip.contains(&name))
+}
+
+/// Entries of a directory sorted by name, skipping ones that aren't UTF-8.
+fn read_dir(dir: &Utf8Path) -> std::io::Result<Vec<(Utf8PathBuf, FileType)>> {
+    let mut children = Vec::new();
+    for entry in dir.as_std_path().read_dir()? {
+        let entry = entry?;
+        let Ok(path) = Utf8PathBuf::from_path_buf(entry.path()) else {
+            continue;
+        };
+        children.push((path, entry.file_type()?));
+    }
+    children.sort_unstable_by(|(p1, _), (p2, _)| p1.cmp(p2));
+    Ok(children)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A tree of a/b/c/file, a/node_modules/pkg, a/.git/HEAD, a/file and a
+    /// link to a/b next to a.
+    fn tree() -> (tempfile::TempDir, Utf8PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().to_path_buf()).unwrap();
+        std::fs::create_dir_all(root.join("a/b/c")).unwrap();
+        std::fs::create_dir_all(root.join("a/node_modules/pkg")).unwrap();
+        std::fs::create_dir_all(root.join("a/.git")).unwrap();
+        std::fs::write(root.join("a/.git/HEAD"), "").unwrap();
+        std::fs::write(root.join("a/b/c/file"), "").unwrap();
+        std::fs::write(root.join("a/file"), "").unwrap();
+        std::os::unix::fs::symlink(root.join("a/b"), root.join("link")).unwrap();
+        (dir, root)
+    }
+
+    fn found(root: &Utf8Path, options: &WalkOptions) -> Vec<String> {
+        let mut found = walk(root, options)
+            .unwrap()
+            .entries
+            .into_iter()
+            .map(|entry| entry.path.strip_prefix(root).unwrap().to_string())
+            .collect::<Vec<_>>();
+        found.sort();
+        found
+    }
+
+    #[test]
+    fn noise_dirs_are_listed_but_not_descended_into() {
+        let (_dir, root) = tree();
+        assert_eq!(
+            found(&root, &WalkOptions::SCAN),
+            [
+                "a",
+                "a/.git",
+                "a/b",
+                "a/b/c",
+                "a/b/c/file",
+                "a/file",
+                "a/node_modules",
+                "link"
+            ]
+        );
+    }
+
+    #[test]
+    fn noise_dirs_are_walked_when_they_are_the_root() {
+        let (_dir, root) = tree();
+        assert_eq!(found(&root.join("a/.git"), &WalkOptions::SCAN), ["HEAD"]);
+    }
+
+    #[test]
+    fn max_depth_limits_the_descent() {
+        let (_dir, root) = tree();
+        let options = WalkOptions {
+            max_depth: 2,
+            ..WalkOptions::SCAN
+        };
+        assert_eq!(
+            found(&root, &options),
+            ["a", "a/.git", "a/b", "a/file", "a/node_modules", "link"]
+        );
+        let walk = walk(&root, &options).unwrap();
+        assert!(walk.entries.iter().all(|entry| entry.depth <= 2));
+        assert!(!walk.truncated);
+    }
+
+    #[test]
+    fn max_entries_truncates_the_walk() {
+        let (_dir, root) = tree();
+        let options = WalkOptions {
+            max_entries: 3,
+            ..WalkOptions::SCAN
+        };
+        let walk = walk(&root, &options).unwrap();
+        assert_eq!(walk.entries.len(), 3);
+        assert!(walk.truncated);
+    }
+
+    #[test]
+    fn symlinked_directories_are_only_followed_when_asked_to() {
+        let (_dir, root) = tree();
+        let walk = walk(&root, &WalkOptions::SCAN).unwrap();
+        let link = walk.entries.iter().find(|e| e.path == root.join("link"));
+        assert!(link.unwrap().file_type.is_symlink());
+        assert!(!found(&root, &WalkOptions::SCAN)
+            .iter()
+            .any(|path| path.starts_with("link/")));
+        let options = WalkOptions {
+            follow_symlinks: true,
+            ..WalkOptions::SCAN
+        };
+        let found = found(&root, &options);
+        assert!(found.contains(&"link/c/file".to_string()), "{found:?}");
+    }
+}
@@ -0,0 +1,50 @@
+use camino::Utf8PathBuf;
+
+/// Directories that commonly hold symlinks into the store that may or may not
+/// be registered as roots, `~` standing for the home directory.
+pub const LINK_DIRS: &[&str] = &[
+    "/etc",
+    "~/.config/systemd/user",
+    "~/.local/bin",
+    "~/.local/share/applications",
+];
+
+/// [LINK_DIRS] with `~` expanded, leaving out the home ones if HOME isn't set.
+pub fn link_dirs() -> Vec<Utf8PathBuf> {
+    link_dirs_in(std::env::var("HOME").ok().filter(|home| !home.is_empty()))
+}
+
+fn link_dirs_in(home: Option<String>) -> Vec<Utf8PathBuf> {
+    LINK_DIRS
+        .iter()
+        .filter_map(|dir| match dir.strip_prefix("~/") {
+            Some(relative) => home
+                .as_ref()
+                .map(|home| Utf8PathBuf::from(home).join(relative)),
+            None => Some(Utf8PathBuf::from(dir)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn home_dirs_are_expanded() {
+        assert_eq!(
+            link_dirs_in(Some("/home/alice".to_string())),
+            [
+                "/etc",
+                "/home/alice/.config/systemd/user",
+                "/home/alice/.local/bin",
+                "/home/alice/.local/share/applications",
+            ]
+        );
+    }
+
+    #[test]
+    fn home_dirs_are_left_out_without_a_home() {
+        assert_eq!(link_dirs_in(None), ["/etc"]);
+    }
+}
@@ -0,0 +1,30 @@
+use std::env;
+
+use camino::Utf8PathBuf;
+
+/// `$XDG_CONFIG_HOME`, falling back to `~/.config`.
+pub fn config_home() -> Option<Utf8PathBuf> {
+    base_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// `$XDG_STATE_HOME`, falling back to `~/.local/state`.
+pub fn state_home() -> Option<Utf8PathBuf> {
+    base_dir("XDG_STATE_HOME", ".local/state")
+}
+
+/// `$XDG_CACHE_HOME`, falling back to `~/.cache`.
+pub fn cache_home() -> Option<Utf8PathBuf> {
+    base_dir("XDG_CACHE_HOME", ".cache")
+}
+
+fn base_dir(var: &str, fallback: &str) -> Option<Utf8PathBuf> {
+    env::var(var)
+        .ok()
+        .filter(|dir| !dir.is_empty())
+        .map(Utf8PathBuf::from)
+        .or_else(|| {
+            env::var("HOME")
+                .ok()
+                .map(|home| Utf8PathBuf::from(home).join(fallback))
+        })
+}
@@ -0,0 +1,1094 @@
+mod common;
+
+use common::*;
+
+#[test]
+fn print_grouped() {
+    let nix = FakeNix::new();
+    let output = nix.gcrs().arg("print").output().unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        nix.unsubstitute(&stdout(&output)),
+        "\
+@ROOT@/nix/var/nix/profiles/system
+> 3 -> @ROOT@/nix/store/33333333333333333333333333333333-nixos-system-3
+  2 -> @ROOT@/nix/store/22222222222222222222222222222222-nixos-system-2
+  1 -> @ROOT@/nix/store/11111111111111111111111111111111-nixos-system-1
+
+@ROOT@/home/broken/result -> @ROOT@/nix/store/66666666666666666666666666666666-gone-1.0 (target missing)
+@ROOT@/home/old/result -> @ROOT@/nix/store/55555555555555555555555555555555-old-1.0
+@ROOT@/home/project/result -> @ROOT@/nix/store/44444444444444444444444444444444-hello-2.12
+"
+    );
+    assert_eq!(stderr(&output), "");
+}
+
+#[test]
+fn print_table() {
+    let nix = FakeNix::new();
+    let output = nix
+        .gcrs()
+        .args(["print", "--format", "table"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = nix.unsubstitute(&stdout(&output));
+    let lines = stdout.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 7);
+    let header = lines[0].split_whitespace().collect::<Vec<_>>();
+    assert_eq!(header, ["path", "target", "kind", "generation", "active"]);
+    let active = lines[3].split_whitespace().collect::<Vec<_>>();
+    assert_eq!(
+        active,
+        [
+            "@ROOT@/nix/var/nix/profiles/system-3-link",
+            "@ROOT@/nix/store/33333333333333333333333333333333-nixos-system-3",
+            "generation",
+            "3",
+            "true"
+        ]
+    );
+    // The columns line up, for the real paths.
+    let raw = common::stdout(&output);
+    let raw = raw.lines().collect::<Vec<_>>();
+    let target_column = raw[0].find("target").unwrap();
+    let store = nix.path("nix/store");
+    assert!(raw[1..]
+        .iter()
+        .all(|line| line[target_column..].starts_with(store.as_str())));
+}
+
+/// Cells of a table line, split where columns are padded apart.
+fn cells(line: &str) -> Vec<&str> {
+    line.split("  ")
+        .map(str::trim)
+        .filter(|cell| !cell.is_empty())
+        .collect()
+}
+
+#[test]
+fn non_store_targets_are_noted_in_grouped_and_table() {
+    let nix = FakeNix::with_fixture("non-store.txt");
+    let output = nix.gcrs().arg("print").output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        nix.unsubstitute(&stdout(&output)),
+        "\
+@ROOT@/home/absolute/result -> @ROOT@/home/data/absolute (not a store path) (target missing)
+@ROOT@/home/project/result -> @ROOT@/nix/store/44444444444444444444444444444444-hello-2.12
+@ROOT@/home/relative/result -> ../data/relative (not a store path) (target missing)
+"
+    );
+    let output = nix
+        .gcrs()
+        .args(["print", "--format", "table", "--columns", "path,target"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let table = nix.unsubstitute(&stdout(&output));
+    assert_eq!(
+        table.lines().map(cells).collect::<Vec<_>>(),
+        [
+            vec!["path", "target"],
+            vec![
+                "@ROOT@/home/absolute/result",
+                "@ROOT@/home/data/absolute (not a store path)"
+            ],
+            vec![
+                "@ROOT@/home/project/result",
+                "@ROOT@/nix/store/44444444444444444444444444444444-hello-2.12"
+            ],
+            vec![
+                "@ROOT@/home/relative/result",
+                "../data/relative (not a store path)"
+            ],
+        ]
+    );
+}
+
+#[test]
+fn non_store_targets_are_kept_as_they_are_in_plain_and_json() {
+    let nix = FakeNix::with_fixture("non-store.txt");
+    let output = nix.gcrs().args(["print", "--plain"]).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        nix.unsubstitute(&stdout(&output)),
+        "\
+@ROOT@/home/absolute/result\t@ROOT@/home/data/absolute
+@ROOT@/home/project/result\t@ROOT@/nix/store/44444444444444444444444444444444-hello-2.12
+@ROOT@/home/relative/result\t../data/relative
+"
+    );
+    let output = nix.gcrs().args(["print", "--json"]).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let json = serde_json::from_str::<serde_json::Value>(&stdout(&output)).unwrap();
+    let roots = json["standalone"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|root| {
+            (
+                nix.unsubstitute(root["path"].as_str().unwrap()),
+                nix.unsubstitute(root["target"].as_str().unwrap()),
+                root["status"].as_str().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        roots,
+        [
+            (
+                "@ROOT@/home/absolute/result".to_string(),
+                "@ROOT@/home/data/absolute".to_string(),
+                "missing-target"
+            ),
+            (
+                "@ROOT@/home/project/result".to_string(),
+                "@ROOT@/nix/store/44444444444444444444444444444444-hello-2.12".to_string(),
+                "live"
+            ),
+            (
+                "@ROOT@/home/relative/result".to_string(),
+                "../data/relative".to_string(),
+                "missing-target"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn clean_non_store_removes_only_roots_outside_the_store() {
+    let nix = FakeNix::with_fixture("non-store.txt");
+    let output = nix
+        .gcrs()
+        .args(["clean", "--non-store", "--yes"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).ends_with("removed 2 roots\n"),
+        "{}",
+        stdout(&output)
+    );
+    assert!(!nix.path("home/absolute/result").is_symlink());
+    assert!(!nix.path("home/relative/result").is_symlink());
+    assert!(nix.path("home/project/result").is_symlink());
+}
+
+#[test]
+fn print_hyperlinks_only_when_asked_for_or_on_a_terminal() {
+    let nix = FakeNix::new();
+    let print = |hyperlinks: &str| {
+        let output = nix
+            .gcrs()
+            .env("TERM", "xterm-256color")
+            .args(["print", "--format", "table", "--hyperlinks", hyperlinks])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        stdout(&output)
+    };
+    // Output to a pipe isn't a terminal, so auto means none.
+    assert!(!print("never").contains('\x1b'));
+    assert!(!print("auto").contains('\x1b'));
+    assert!(print("always").contains("\x1b]8;;file://"));
+}
+
+#[test]
+fn print_csv() {
+    let nix = FakeNix::new();
+    let output = nix
+        .gcrs()
+        .args(["print", "--format", "csv", "--columns", "path,kind,active"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        nix.unsubstitute(&stdout(&output)),
+        "\
+path,kind,active
+@ROOT@/nix/var/nix/profiles/system-1-link,generation,false
+@ROOT@/nix/var/nix/profiles/system-2-link,generation,false
+@ROOT@/nix/var/nix/profiles/system-3-link,generation,true
+@ROOT@/home/broken/result,standalone,
+@ROOT@/home/old/result,standalone,
+@ROOT@/home/project/result,standalone,
+"
+    );
+}
+
+#[test]
+fn print_size_column_gathers_sizes() {
+    let nix = FakeNix::new();
+    let output = nix
+        .gcrs()
+        .args(["print", "--format", "csv", "--columns", "short-target,size"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        stdout(&output),
+        "\
+short-target,size
+nixos-system-1,1.0 MiB
+nixos-system-2,1.0 MiB
+nixos-system-3,1.0 MiB
+gone-1.0,-
+old-1.0,1.0 MiB
+hello-2.12,1.0 MiB
+"
+    );
+}
+
+#[test]
+fn targets_in_a_second_store_count_as_store_paths() {
+    let nix = FakeNix::with_fixture("two-stores.txt");
+    let output = nix
+        .gcrs()
+        .args(["print", "--format", "csv", "--columns", "path,size"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        nix.unsubstitute(&stdout(&output)),
+        "\
+path,size
+@ROOT@/home/cross/result,1.0 MiB
+@ROOT@/home/notes/result,-
+@ROOT@/home/project/result,1.0 MiB
+"
+    );
+    let output = nix
+        .gcrs()
+        .args([
+            "clean",
+            "--dry-run",
+            "--free",
+            "1G",
+            "--strategy",
+            "largest",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stderr(&output).contains("warning: 1 root points outside the store"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn print_unknown_column_lists_the_valid_ones() {
+    let nix = FakeNix::new();
+    let output = nix
+        .gcrs()
+        .args(["print", "--format", "csv", "--columns", "path,colour"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    let stderr = stderr(&output);
+    assert!(stderr.contains("invalid value 'colour'"), "{stderr}");
+    assert!(stderr.contains("short-target"), "{stderr}");
+    assert!(stderr.contains("deletable"), "{stderr}");
+}
+
+#[test]
+fn print_columns_need_a_table_or_csv() {
+    let nix = FakeNix::new();
+    let output = nix
+        .gcrs()
+        .args(["print", "--columns", "path"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("--columns only applies to the table and csv formats"));
+}
+
+#[test]
+fn print_json() {
+    let nix = FakeNix::new();
+    let output = nix.gcrs().args(["print", "--json"]).output().unwrap();
+    assert!(output.status.success());
+    let json: serde_json::Value =
+        serde_json::from_str(&nix.unsubstitute(&stdout(&output))).unwrap();
+    assert_eq!(json["version"], 1);
+    let profile = &json["profiles"][0];
+    assert_eq!(profile["path"], "@ROOT@/nix/var/nix/profiles/system");
+    assert_eq!(profile["active_generation"], 3);
+    assert_eq!(profile["generations"].as_object().unwrap().len(), 3);
+    let standalone = json["standalone"].as_array().unwrap();
+    let statuses = standalone
+        .iter()
+        .map(|root| {
+            (
+                root["path"].as_str().unwrap(),
+                root["status"].as_str().unwrap(),
+            )
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        statuses,
+        [
+            ("@ROOT@/home/broken/result", "missing-target"),
+            ("@ROOT@/home/old/result", "live"),
+            ("@ROOT@/home/project/result", "live"),
+        ]
+    );
+}
+
+#[test]
+fn print_into_a_closed_pipe_exits_cleanly() {
+    use std::os::fd::FromRawFd;
+
+    let nix = FakeNix::new();
+    for args in [&["print"][..], &["print", "--json"], &["print", "--plain"]] {
+        let (reader, writer) = nix::unistd::pipe().unwrap();
+        // The reader is gone before gcrs writes anything.
+        nix::unistd::close(reader).unwrap();
+        let writer = unsafe { std::fs::File::from_raw_fd(writer) };
+        let output = std::process::Command::new(assert_cmd::cargo::cargo_bin("gcrs"))
+            .args(args)
+            .env_clear()
+            .envs(nix.env())
+            .current_dir(nix.path("home"))
+            .stdout(writer)
+            .output()
+            .unwrap();
+        assert_eq!(
+            output.status.code(),
+            Some(0),
+            "{args:?}: {}",
+            stderr(&output)
+        );
+        assert!(!stderr(&output).contains("panicked"), "{}", stderr(&output));
+    }
+}
+
+/// Names of the phases in the --timings table on stderr.
+fn timed_phases(output: &std::process::Output) -> Vec<String> {
+    stderr(output)
+        .lines()
+        .filter_map(|line| {
+            line.split_once("  ")
+                .map(|(name, _)| name.trim().to_string())
+        })
+        .collect()
+}
+
+#[test]
+fn timings_list_the_phases_that_ran() {
+    let nix = FakeNix::new();
+    let output = nix
+        .gcrs()
+        .args(["print", "--format", "table", "--timings"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        timed_phases(&output),
+        ["discovery", "parsing", "grouping", "rendering"]
+    );
+    let output = nix
+        .gcrs()
+        .args([
+            "print",
+            "--format",
+            "table",
+            "--columns",
+            "path,size",
+            "--timings",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(
+        timed_phases(&output),
+        [
+            "discovery",
+            "parsing",
+            "grouping",
+            "size queries",
+            "rendering"
+        ]
+    );
+}
+
+#[test]
+fn json_output_has_the_timings_with_timings() {
+    let nix = FakeNix::new();
+    for args in [
+        &["print", "--json"][..],
+        &["report", "--json"],
+        &["trend", "--json"],
+    ] {
+        let json = |timings: bool| {
+            let output = nix
+                .gcrs()
+                .args(args)
+                .args(timings.then_some("--timings"))
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{args:?}: {}", stderr(&output));
+            serde_json::from_str::<serde_json::Value>(&stdout(&output)).unwrap()
+        };
+        let untimed = json(false);
+        assert!(untimed.get("timings").is_none(), "{args:?}");
+        let timed = json(true);
+        let phases = timed["timings"].as_array().unwrap();
+        assert_eq!(phases[0]["phase"], "discovery", "{args:?}");
+        assert!(phases.iter().all(|phase| phase["elapsed_ms"].is_u64()));
+        match args[0] {
+            // The history of trend is an array, wrapped with timings.
+            "trend" => {
+                assert!(untimed.is_array());
+                assert_eq!(timed["snapshots"].as_array().unwrap().len(), 2);
+            }
+            _ => assert_eq!(
+                timed.as_object().unwrap().len(),
+                untimed.as_object().unwrap().len() + 1
+            ),
+        }
+    }
+}
+
+#[test]
+fn print_without_nix_store_fails() {
+    let nix = FakeNix::new();
+    std::fs::remove_file(nix.path("bin/nix-store")).unwrap();
+    let output = nix.gcrs().arg("print").output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stdout(&output), "");
+    assert!(
+        nix.unsubstitute(&stderr(&output))
+            .contains("@ROOT@/bin/nix-store not found"),
+        "{}",
+        stderr(&output)
+    );
+}
+
+#[test]
+fn clean_dry_run_removes_nothing() {
+    let nix = FakeNix::new();
+    let output = nix
+        .gcrs()
+        .args(["clean", "--older-than", "150d", "--dry-run"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        nix.unsubstitute(&stdout(&output)),
+        "\
+3 roots to remove:
+  @ROOT@/nix/var/nix/profiles/system-1-link -> @ROOT@/nix/store/11111111111111111111111111111111-nixos-system-1 (older than 150d)
+  @ROOT@/nix/var/nix/profiles/system-2-link -> @ROOT@/nix/store/22222222222222222222222222222222-nixos-system-2 (older than 150d)
+  @ROOT@/home/old/result -> @ROOT@/nix/store/55555555555555555555555555555555-old-1.0 (older than 150d)
+selected: 3 older than 150d
+"
+    );
+    for root in [
+        "nix/var/nix/profiles/system-1-link",
+        "nix/var/nix/profiles/system-2-link",
+        "home/old/result",
+    ] {
+        assert!(nix.path(root).is_symlink(), "{root} was removed");
+    }
+    assert!(!nix.path("home/.local/state/gcrs/journal.jsonl").exists());
+}
+
+#[test]
+fn clean_older_than_the_epoch_selects_nothing() {
+    let nix = FakeNix::new();
+    let output = nix
+        .gcrs()
+        .args(["clean", "--older-than", &u64::MAX.to_string(), "--dry-run"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stdout(&output), "nothing to remove\n");
+}
+
+#[test]
+fn clean_removes_old_roots_but_not_the_active_generation() {
+    let nix = FakeNix::new();
+    nix.age("nix/var/nix/profiles/system-3-link", 300);
+    let output = nix
+        .gcrs()
+        .args(["clean", "--older-than", "150d", "--yes"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).ends_with("removed 3 roots\n"),
+        "{}",
+        stdout(&output)
+    );
+    assert!(!nix.path("nix/var/nix/profiles/system-1-link").exists());
+    assert!(!nix.path("nix/var/nix/profiles/system-2-link").exists());
+    assert!(!nix.path("home/old/result").exists());
+    assert!(nix.path("nix/var/nix/profiles/system-3-link").is_symlink());
+    assert!(nix.path("home/project/result").is_symlink());
+    let journal =
+        std::fs::read_to_string(nix.path("home/.local/state/gcrs/journal.jsonl")).unwrap();
+    assert!(journal.contains("old/result"), "{journal}");
+}
+
+#[test]
+fn clean_without_grouping_handles_generations_as_standalone_roots() {
+    let nix = FakeNix::new();
+    let output = nix
+        .gcrs()
+        .args(["--no-group", "clean", "--older-than", "150d", "--yes"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        nix.unsubstitute(&stdout(&output)),
+        "\
+3 roots to remove:
+  @ROOT@/home/old/result -> @ROOT@/nix/store/55555555555555555555555555555555-old-1.0 (older than 150d)
+  @ROOT@/nix/var/nix/profiles/system-1-link -> @ROOT@/nix/store/11111111111111111111111111111111-nixos-system-1 (older than 150d)
+  @ROOT@/nix/var/nix/profiles/system-2-link -> @ROOT@/nix/store/22222222222222222222222222222222-nixos-system-2 (older than 150d)
+selected: 3 older than 150d
+removed 3 roots
+"
+    );
+    assert!(!nix.path("nix/var/nix/profiles/system-1-link").exists());
+    assert!(!nix.path("nix/var/nix/profiles/system-2-link").exists());
+    assert!(nix.path("nix/var/nix/profiles/system-3-link").is_symlink());
+    assert_eq!(
+        std::fs::read_link(nix.path("nix/var/nix/profiles/system")).unwrap(),
+        std::path::Path::new("system-3-link")
+    );
+}
+
+#[test]
+fn delete_without_grouping_takes_single_generation_links() {
+    let nix = FakeNix::new();
+    let delete = |path: &str| {
+        nix.gcrs()
+            .args(["--no-group", "delete", nix.path(path).as_str(), "--yes"])
+            .output()
+            .unwrap()
+    };
+    let output = delete("nix/var/nix/profiles/system-2-link");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).ends_with("removed 1 root\n"));
+    assert!(!nix.path("nix/var/nix/profiles/system-2-link").exists());
+    assert!(nix.path("nix/var/nix/profiles/system-1-link").is_symlink());
+
+    let output = delete("nix/var/nix/profiles/system-3-link");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        nix.unsubstitute(&stdout(&output)),
+        "\
+skipping @ROOT@/nix/var/nix/profiles/system-3-link (active generation)
+nothing to remove
+"
+    );
+    assert!(nix.path("nix/var/nix/profiles/system-3-link").is_symlink());
+
+    let output = nix
+        .gcrs()
+        .args(["--no-group", "delete"])
+        .arg(nix.path("nix/var/nix/profiles/system"))
+        .args(["--generations", "1", "--yes"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output)
+            .contains("isn't a profile, --generations and --older-than only apply to profiles"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(nix.path("nix/var/nix/profiles/system-1-link").is_symlink());
+}
+
+#[test]
+fn clean_status_fd_emits_the_events_in_order() {
+    let nix = FakeNix::new();
+    let events = nix.path("events.jsonl");
+    // The shell opens fd 3 for gcrs, like a wrapping tool would.
+    let output = std::process::Command::new("/bin/sh")
+        .args([
+            "-c",
+            "exec \"$0\" clean --older-than 150d --yes --status-fd 3 3>\"$1\"",
+        ])
+        .arg(assert_cmd::cargo::cargo_bin("gcrs"))
+        .arg(&events)
+        .env_clear()
+        .envs(nix.env())
+        .current_dir(nix.path("home"))
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let events = std::fs::read_to_string(events)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+        .collect::<Vec<_>>();
+    assert!(events.iter().all(|event| event["version"] == 1));
+    let kinds = events
+        .iter()
+        .map(|event| event["event"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        [
+            "discovery-started",
+            "discovery-finished",
+            "plan-computed",
+            "deletion",
+            "deletion",
+            "deletion",
+            "finished",
+        ]
+    );
+    assert_eq!(events[1]["roots"], 6);
+    assert_eq!(events[2]["entries"], 3);
+    let mut deleted = events[3..6]
+        .iter()
+        .map(|event| {
+            assert_eq!(event["outcome"], "removed");
+            nix.unsubstitute(event["path"].as_str().unwrap())
+        })
+        .collect::<Vec<_>>();
+    deleted.sort();
+    assert_eq!(
+        deleted,
+        [
+            "@ROOT@/home/old/result",
+            "@ROOT@/nix/var/nix/profiles/system-1-link",
+            "@ROOT@/nix/var/nix/profiles/system-2-link",
+        ]
+    );
+    assert_eq!(events[6]["removed"], 3);
+    assert_eq!(events[6]["failed"], 0);
+}
+
+#[test]
+fn clean_users_must_be_all_or_names() {
+    let nix = FakeNix::new();
+    let output = nix
+        .gcrs()
+        .args([
+            "clean",
+            "--older-than",
+            "150d",
+            "--dry-run",
+            "--users",
+            "alice,",
+        ])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(
+        stderr(&output).contains("invalid user list \"alice,\""),
+        "{}",
+        stderr(&output)
+    );
+    let output = nix
+        .gcrs()
+        .args([
+            "clean",
+            "--older-than",
+            "150d",
+            "--dry-run",
+            "--users",
+            "all",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+}
+
+#[test]
+fn clean_edit_keeps_the_roots_deleted_from_the_plan() {
+    let nix = FakeNix::new();
+    nix.write("edit.sh", "#!/bin/sh\nsed -i '/home\\/old\\//d' \"$1\"\n");
+    let editor = format!("sh {}", nix.path("edit.sh"));
+    let output = nix
+        .gcrs()
+        .env("EDITOR", &editor)
+        .args(["clean", "--older-than", "150d", "--edit", "--yes"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(
+        stdout(&output).starts_with("2 roots to remove:\n"),
+        "{}",
+        stdout(&output)
+    );
+    assert!(!nix.path("nix/var/nix/profiles/system-1-link").exists());
+    assert!(!nix.path("nix/var/nix/profiles/system-2-link").exists());
+    assert!(nix.path("home/old/result").is_symlink());
+}
+
+#[test]
+fn clean_edit_aborts_when_the_editor_fails() {
+    let nix = FakeNix::new();
+    let output = nix
+        .gcrs()
+        .env("EDITOR", "false")
+        .args(["clean", "--older-than", "150d", "--edit", "--yes"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output).contains("editor false failed with exit status: 1, aborting"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(nix.path("home/old/result").is_symlink());
+}
+
+#[test]
+fn apply_plan_refuses_roots_that_changed() {
+    let nix = FakeNix::new();
+    let plan = nix.path("plan.json");
+    let output = nix
+        .gcrs()
+        .args([
+            "clean",
+            "--older-than",
+            "150d",
+            "--emit-plan",
+            plan.as_str(),
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(nix.path("home/old/result").is_symlink());
+    // old/result is rebuilt in between.
+    let roots = std::fs::read_to_string(nix.path("print-roots.txt")).unwrap();
+    nix.write(
+        "print-roots.txt",
+        &roots.replace(
+            "55555555555555555555555555555555-old",
+            "77777777777777777777777777777777-old",
+        ),
+    );
+    let output = nix
+        .gcrs()
+        .args(["apply-plan", plan.as_str(), "--yes"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1), "{}", stderr(&output));
+    let stdout = nix.unsubstitute(&stdout(&output));
+    assert!(
+        stdout.ends_with(
+            "\
+removed 2 roots, refused 1
+refused to remove 1 root:
+  @ROOT@/home/old/result: target changed to @ROOT@/nix/store/77777777777777777777777777777777-old-1.0
+"
+        ),
+        "{stdout}"
+    );
+    assert!(!nix.path("nix/var/nix/profiles/system-1-link").exists());
+    assert!(!nix.path("nix/var/nix/profiles/system-2-link").exists());
+    assert!(nix.path("home/old/result").is_symlink());
+}
+
+#[test]
+fn channels_prune_keeps_the_last_generations() {
+    let nix = FakeNix::with_fixture("channels.txt");
+    nix.profile("nix/var/nix/profiles/per-user/alice/channels", 3);
+    nix.profile("nix/var/nix/profiles/system", 2);
+    let output = nix
+        .gcrs()
+        .args(["channels", "prune", "--keep-last", "2", "--yes"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stdout = nix.unsubstitute(&stdout(&output));
+    assert!(
+        stdout.contains(
+            "note: only old generations are removed, use nix-channel --remove <name> \
+             to unregister a channel\n"
+        ),
+        "{stdout}"
+    );
+    assert!(stdout.ends_with("removed 1 root\n"), "{stdout}");
+    let channels = "nix/var/nix/profiles/per-user/alice/channels";
+    assert!(!nix.path(&format!("{channels}-1-link")).exists());
+    assert!(nix.path(&format!("{channels}-2-link")).is_symlink());
+    assert!(nix.path("nix/var/nix/profiles/system-1-link").is_symlink());
+}
+
+#[test]
+fn clean_fails_fast_while_another_run_holds_the_lock() {
+    use std::os::fd::AsRawFd;
+
+    let nix = FakeNix::new();
+    std::fs::create_dir_all(nix.path("home/.local/state/gcrs")).unwrap();
+    let lock = std::fs::File::create(nix.path("home/.local/state/gcrs/lock")).unwrap();
+    nix::fcntl::flock(
+        lock.as_raw_fd(),
+        nix::fcntl::FlockArg::LockExclusiveNonblock,
+    )
+    .unwrap();
+    let output = nix
+        .gcrs()
+        .args(["clean", "--older-than", "150d", "--yes"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(
+        stderr(&output)
+            .contains("another gcrs operation is in progress, pass --wait to wait for it"),
+        "{}",
+        stderr(&output)
+    );
+    assert!(nix.path("home/old/result").is_symlink());
+    // Commands that don't remove anything don't need the lock.
+    let output = nix.gcrs().arg("print").output().unwrap();
+    assert!(output.status.success());
+}
+
+#[test]
+fn delete_with_yes_removes_the_generation() {
+    let nix = FakeNix::new();
+    let profile = nix.path("nix/var/nix/profiles/system");
+    let output = nix
+        .gcrs()
+        .args(["delete", profile.as_str(), "--generations", "1", "--yes"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        nix.unsubstitute(&stdout(&output)),
+        "\
+1 root to remove:
+  @ROOT@/nix/var/nix/profiles/system-1-link -> @ROOT@/nix/store/11111111111111111111111111111111-nixos-system-1 (requested)
+selected: 1 requested
+removed 1 root
+"
+    );
+    assert!(!nix.path("nix/var/nix/profiles/system-1-link").exists());
+    assert!(nix.path("nix/var/nix/profiles/system-2-link").is_symlink());
+    // The store path stays for the garbage collector.
+    assert!(nix
+        .path("nix/store/11111111111111111111111111111111-nixos-system-1")
+        .is_dir());
+}
+
+#[test]
+fn delete_keeps_the_active_generation() {
+    let nix = FakeNix::new();
+    let profile = nix.path("nix/var/nix/profiles/system");
+    let output = nix
+        .gcrs()
+        .args(["delete", profile.as_str(), "--generations", "3", "--yes"])
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    assert_eq!(
+        nix.unsubstitute(&stdout(&output)),
+        "skipping @ROOT@/nix/var/nix/profiles/system-3-link (active generation)\nnothing to remove\n"
+    );
+    assert!(nix.path("nix/var/nix/profiles/system-3-link").is_symlink());
+}
+
+#[test]
+fn pinned_generations_are_skipped_until_unpinned() {
+    let nix = FakeNix::new();
+    let pin = |args: &[&str]| {
+        let output = nix
+            .gcrs()
+            .arg("pin-generation")
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        nix.unsubstitute(&stdout(&output))
+    };
+    assert_eq!(
+        pin(&["--profile", "system", "1", "--reason", "known-good kernel"]),
+        "pinned @ROOT@/nix/var/nix/profiles/system 1 (known-good kernel)\n"
+    );
+    assert_eq!(
+        pin(&["--list"]),
+        "@ROOT@/nix/var/nix/profiles/system 1 (known-good kernel)\n"
+    );
+    let output = nix
+        .gcrs()
+        .args(["clean", "--older-than", "150d", "--dry-run"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(nix.unsubstitute(&stdout(&output)).contains(
+        "skipping @ROOT@/nix/var/nix/profiles/system-1-link (pinned: known-good kernel)"
+    ));
+    assert_eq!(
+        pin(&["--profile", "system", "1", "--remove"]),
+        "unpinned @ROOT@/nix/var/nix/profiles/system 1 (known-good kernel)\n"
+    );
+    assert_eq!(pin(&["--list"]), "no pinned generations\n");
+}
+
+#[test]
+fn delete_without_yes_refuses_when_not_interactive() {
+    let nix = FakeNix::new();
+    let root = nix.path("home/old/result");
+    let output = nix.gcrs().args(["delete", root.as_str()]).output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output).contains("refusing to delete without --yes in non-interactive mode"));
+    assert!(root.is_symlink());
+}
+
+#[test]
+fn doctor_passes_in_a_healthy_installation() {
+    let nix = FakeNix::new();
+    let output = nix.gcrs().arg("doctor").output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    let stdout = nix.unsubstitute(&stdout(&output));
+    let user = nix::unistd::User::from_uid(nix::unistd::Uid::current())
+        .unwrap()
+        .unwrap()
+        .name;
+    assert_eq!(
+        stdout,
+        format!(
+            "\
+[pass] nix-store binary: @ROOT@/bin/nix-store
+[pass] print roots: nix-store --gc --print-roots succeeded
+[pass] state directory: @ROOT@/nix/var/nix
+[pass] profile directory: @ROOT@/home/.local/state/nix/profiles
+[pass] censored roots: 0 of 7 roots are censored
+[pass] gc settings: keep-outputs = false, keep-derivations = true
+[pass] pins directory: @ROOT@/nix/var/nix/gcroots/per-user/{user}
+[pass] pinned generations: 0 pinned, all exist
+"
+        )
+    );
+}
+
+#[test]
+fn gc_settings_are_read_with_show_config_before_nix_2_20() {
+    let nix = FakeNix::new();
+    nix.without_nix_config_show();
+    let output = nix.gcrs().arg("doctor").output().unwrap();
+    assert_eq!(output.status.code(), Some(0));
+    assert!(
+        stdout(&output)
+            .contains("[pass] gc settings: keep-outputs = true, keep-derivations = false, "),
+        "{}",
+        stdout(&output)
+    );
+}
+
+#[test]
+fn estimates_are_noted_when_keep_outputs_is_enabled() {
+    const NOTE: &str = "note: keep-outputs is enabled, outputs of live derivations survive \
+        garbage collection, so the space actually freed may be lower\n";
+    let nix = FakeNix::new();
+    let clean = |args: &[&str]| {
+        let output = nix
+            .gcrs()
+            .args(["clean", "--dry-run"])
+            .args(args)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{}", stderr(&output));
+        stdout(&output)
+    };
+    let free = ["--free", "1G", "--estimate", "nar-size"];
+    assert!(!clean(&free).contains(NOTE));
+    nix.without_nix_config_show();
+    let stdout = clean(&free);
+    assert!(
+        stdout.contains(&format!(
+            "note: sizes are the NAR size of each target, shared dependencies \
+            make the space actually freed differ\n{NOTE}"
+        )),
+        "{stdout}"
+    );
+    let stdout = clean(&["--older-than", "150d", "--show-dead"]);
+    assert_eq!(stdout.matches(NOTE).count(), 1, "{stdout}");
+    assert!(stdout.ends_with(NOTE), "{stdout}");
+}
+
+#[test]
+fn unreadable_gc_settings_fall_back_to_the_defaults() {
+    let nix = FakeNix::new();
+    nix.without_nix_config_show();
+    std::fs::remove_file(nix.path("nix-show-config.txt")).unwrap();
+    let output = nix
+        .gcrs()
+        .args([
+            "clean",
+            "--dry-run",
+            "--free",
+            "1G",
+            "--estimate",
+            "nar-size",
+        ])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    let stderr = stderr(&output);
+    assert!(
+        stderr.contains("warning: couldn't read the Nix configuration: "),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains("'config' is not a recognised command"),
+        "{stderr}"
+    );
+    assert!(
+        stderr.contains(", assuming the default keep-outputs and keep-derivations\n"),
+        "{stderr}"
+    );
+    assert!(!stdout(&output).contains("keep-outputs"));
+}
+
+#[test]
+fn doctor_fails_without_a_state_directory() {
+    let nix = FakeNix::new();
+    std::fs::remove_dir_all(nix.path("nix/var")).unwrap();
+    let output = nix.gcrs().arg("doctor").output().unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    let stdout = nix.unsubstitute(&stdout(&output));
+    assert!(
+        stdout.contains(
+            "[fail] state directory: @ROOT@/nix/var/nix does not exist\n       \
+             hint: set NIX_STATE_DIR if Nix uses a non-default location\n"
+        ),
+        "{stdout}"
+    );
+}
+
+#[test]
+fn check_exits_with_4_on_violations() {
+    let nix = FakeNix::new();
+    let output = nix
+        .gcrs()
+        .args(["check", "--max-standalone", "2", "--max-generations", "3"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(stdout(&output), "3 standalone roots (max 2)\n");
+    let output = nix
+        .gcrs()
+        .args(["check", "--max-standalone", "3"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0));
+}
+
+#[test]
+fn check_thresholds_come_from_the_config() {
+    let nix = FakeNix::new();
+    std::fs::create_dir_all(nix.path("home/.config/gcrs")).unwrap();
+    nix.write(
+        "home/.config/gcrs/config.toml",
+        "[check]\nmax-age = \"365d\"\n",
+    );
+    let output = nix.gcrs().arg("check").output().unwrap();
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(
+        nix.unsubstitute(&stdout(&output)),
+        "@ROOT@/home/old/result is 400d old (max 365d)\n"
+    );
+}
@@ -0,0 +1,297 @@
+//! A fake Nix installation in a temporary directory for running the gcrs
+//! binary end to end: nix-store and nix scripts answering from fixture files,
+//! a store, the profiles of the Nix state directory and a home directory that
+//! HOME and the XDG directories point into.
+
+// Every test crate uses a different part of the harness.
+#![allow(dead_code)]
+
+use std::{
+    os::unix::fs::{symlink, PermissionsExt},
+    time::{Duration, SystemTime},
+};
+
+use assert_cmd::Command;
+use camino::{Utf8Path, Utf8PathBuf};
+use nix::{
+    sys::{
+        stat::{utimensat, UtimensatFlags},
+        time::TimeSpec,
+    },
+    unistd::{Uid, User},
+};
+
+/// Stands for the temporary directory in fixtures.
+const ROOT: &str = "@ROOT@";
+
+const NIX_STORE: &str = r#"#!/bin/sh
+case "$1 $2" in
+"--gc --print-roots")
+    printf '%s\n' "${LC_ALL-}" >@ROOT@/print-roots.lc_all
+    [ -f @ROOT@/print-roots.stderr ] && cat @ROOT@/print-roots.stderr >&2
+    cat @ROOT@/print-roots.txt
+    ;;
+"--query --requisites")
+    shift 2
+    printf '%s\n' "$@"
+    ;;
+*)
+    echo "fake nix-store: unsupported arguments: $*" >&2
+    exit 1
+    ;;
+esac
+"#;
+
+/// Every store path is a MiB on its own and references nothing. The
+/// configuration is captured output of `nix config show` and of the
+/// `nix show-config` of Nix before 2.20.
+const NIX: &str = r#"#!/bin/sh
+[ "$1" = --extra-experimental-features ] && shift 2
+case "$1" in
+config)
+    [ -f @ROOT@/nix-config-show.txt ] || {
+        echo "error: 'config' is not a recognised command" >&2
+        exit 1
+    }
+    cat @ROOT@/nix-config-show.txt
+    ;;
+show-config)
+    cat @ROOT@/nix-show-config.txt
+    ;;
+path-info)
+    shift
+    printf '{'
+    separator=''
+    for arg in "$@"; do
+        case "$arg" in
+        -*) ;;
+        *)
+            printf '%s"%s":{"narSize":1048576,"closureSize":1048576,"references":[]}' "$separator" "$arg"
+            separator=','
+            ;;
+        esac
+    done
+    printf '}\n'
+    ;;
+*)
+    echo "fake nix: unsupported arguments: $*" >&2
+    exit 1
+    ;;
+esac
+"#;
+
+/// Records its arguments and deletes the generation like nix-env does.
+const NIX_ENV: &str = r#"#!/bin/sh
+printf '%s\n' "$*" >>@ROOT@/nix-env.args
+if [ "$1" = --profile ] && [ "$3" = --delete-generations ]; then
+    rm "$2-$4-link"
+else
+    echo "fake nix-env: unsupported arguments: $*" >&2
+    exit 1
+fi
+"#;
+
+pub const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// The fake installation, removed when dropped.
+pub struct FakeNix {
+    _dir: tempfile::TempDir,
+    pub root: Utf8PathBuf,
+}
+
+impl FakeNix {
+    /// The installation of fixtures/print-roots.txt: a system profile with
+    /// three generations, the third one active, and standalone roots in
+    /// home of which old/result was modified 400 days ago and the target of
+    /// broken/result is gone. Generations are 300, 200 and 100 days old.
+    pub fn new() -> Self {
+        let nix = Self::with_fixture("print-roots.txt");
+        nix.profile("nix/var/nix/profiles/system", 3);
+        for (generation, days) in [(1, 300), (2, 200), (3, 100)] {
+            nix.age(
+                &format!("nix/var/nix/profiles/system-{generation}-link"),
+                days,
+            );
+        }
+        nix.age("home/old/result", 400);
+        nix.age("home/project/result", 1);
+        nix.age("home/broken/result", 1);
+        nix
+    }
+
+    /// An installation listing the roots of the fixture as nix-store output,
+    /// with @ROOT@ standing for the temporary directory. Listed roots in the
+    /// directory are created pointing at their targets, which are created in
+    /// their store unless their name contains -gone-. Lines that aren't
+    /// roots in the directory are left to nix-store to print.
+    pub fn with_fixture(fixture: &str) -> Self {
+        let dir = tempfile::tempdir().unwrap();
+        let root = Utf8PathBuf::try_from(dir.path().canonicalize().unwrap()).unwrap();
+        let nix = FakeNix { _dir: dir, root };
+        for dir in [
+            "bin",
+            "nix/store",
+            "nix/var/nix/profiles",
+            "home/.config",
+            "home/.cache",
+            "home/.local/state/nix/profiles",
+        ] {
+            std::fs::create_dir_all(nix.path(dir)).unwrap();
+        }
+        let user = User::from_uid(Uid::current()).unwrap().unwrap().name;
+        std::fs::create_dir_all(nix.path(&format!("nix/var/nix/gcroots/per-user/{user}"))).unwrap();
+        nix.script("bin/nix-store", NIX_STORE);
+        nix.script("bin/nix", NIX);
+        nix.write("nix-config-show.txt", &fixture_text("nix-config-show.txt"));
+        nix.write("nix-show-config.txt", &fixture_text("nix-show-config.txt"));
+        let roots = nix.substitute(&fixture_text(fixture));
+        nix.write("print-roots.txt", &roots);
+        for line in roots.lines() {
+            let Some((path, target)) = line.split_once(" -> ") else {
+                continue;
+            };
+            let (path, target) = (path.trim(), target.trim());
+            if !path.starts_with(nix.root.as_str()) {
+                continue;
+            }
+            let target = Utf8Path::new(target);
+            let in_a_store =
+                target.starts_with(&nix.root) && target.as_str().contains("/nix/store/");
+            if in_a_store && !target.as_str().contains("-gone-") {
+                std::fs::create_dir_all(target).unwrap();
+            }
+            std::fs::create_dir_all(Utf8Path::new(path).parent().unwrap()).unwrap();
+            symlink(target, path).unwrap();
+        }
+        nix
+    }
+
+    /// Path in the temporary directory.
+    pub fn path(&self, relative: &str) -> Utf8PathBuf {
+        self.root.join(relative)
+    }
+
+    /// Replaces @ROOT@ with the temporary directory.
+    pub fn substitute(&self, text: &str) -> String {
+        text.replace(ROOT, self.root.as_str())
+    }
+
+    /// Replaces the temporary directory with @ROOT@, for comparing output to
+    /// fixtures.
+    pub fn unsubstitute(&self, text: &str) -> String {
+        text.replace(self.root.as_str(), ROOT)
+    }
+
+    pub fn write(&self, relative: &str, contents: &str) {
+        std::fs::write(self.path(relative), contents).unwrap();
+    }
+
+    /// Installs a nix-env that records the arguments of every run,
+    /// see [FakeNix::nix_env_runs].
+    pub fn install_nix_env(&self) {
+        self.script("bin/nix-env", NIX_ENV);
+    }
+
+    /// Arguments of every nix-env run, one run per line.
+    pub fn nix_env_runs(&self) -> String {
+        std::fs::read_to_string(self.path("nix-env.args")).unwrap_or_default()
+    }
+
+    /// Makes nix a version before 2.20, which only has `nix show-config`.
+    pub fn without_nix_config_show(&self) {
+        std::fs::remove_file(self.path("nix-config-show.txt")).unwrap();
+    }
+
+    /// Locale nix-store last listed the roots in, $LC_ALL.
+    pub fn print_roots_locale(&self) -> String {
+        std::fs::read_to_string(self.path("print-roots.lc_all"))
+            .unwrap()
+            .trim_end()
+            .to_string()
+    }
+
+    /// Writes lines nix-store prints to stderr before listing the roots.
+    pub fn print_roots_stderr(&self, stderr: &str) {
+        self.write("print-roots.stderr", &self.substitute(stderr));
+    }
+
+    fn script(&self, relative: &str, contents: &str) {
+        let path = self.path(relative);
+        std::fs::write(&path, self.substitute(contents)).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+    }
+
+    /// Points the profile at its generation.
+    pub fn profile(&self, relative: &str, active: u64) {
+        let path = self.path(relative);
+        let _ = std::fs::remove_file(&path);
+        let name = path.file_name().unwrap();
+        symlink(format!("{name}-{active}-link"), &path).unwrap();
+    }
+
+    /// Sets the modification time of the symlink to days ago.
+    pub fn age(&self, relative: &str, days: u32) {
+        let mtime = SystemTime::now() - DAY * days;
+        let since_epoch = mtime.duration_since(SystemTime::UNIX_EPOCH).unwrap();
+        let time = TimeSpec::from_duration(since_epoch);
+        utimensat(
+            None,
+            self.path(relative).as_std_path(),
+            &time,
+            &time,
+            UtimensatFlags::NoFollowSymlink,
+        )
+        .unwrap();
+    }
+
+    /// gcrs in the installation, with nothing of the environment of the
+    /// tests but [FakeNix::env].
+    pub fn gcrs(&self) -> Command {
+        let mut command = Command::cargo_bin("gcrs").unwrap();
+        command
+            .env_clear()
+            .envs(self.env())
+            .current_dir(self.path("home"));
+        command
+    }
+
+    /// Environment of gcrs in the installation. PATH only has the system
+    /// directories the scripts need, Nix comes from `$GCRS_NIX_BIN_DIR`.
+    pub fn env(&self) -> Vec<(&'static str, String)> {
+        let home = self.path("home");
+        [
+            ("PATH", "/usr/bin:/bin".into()),
+            ("LANG", "C.UTF-8".into()),
+            ("HOME", home.clone()),
+            ("XDG_CONFIG_HOME", home.join(".config")),
+            ("XDG_CACHE_HOME", home.join(".cache")),
+            ("XDG_STATE_HOME", home.join(".local/state")),
+            ("GCRS_STATE_DIR", home.join(".local/state/gcrs")),
+            ("GCRS_CACHE_DIR", home.join(".cache/gcrs")),
+            ("GCRS_NIX_BIN_DIR", self.path("bin")),
+            ("NIX_STORE_DIR", self.path("nix/store")),
+            ("NIX_STATE_DIR", self.path("nix/var/nix")),
+        ]
+        .into_iter()
+        .map(|(name, value): (&'static str, Utf8PathBuf)| (name, value.into_string()))
+        .collect()
+    }
+}
+
+/// Contents of a file in tests/fixtures.
+pub fn fixture_text(name: &str) -> String {
+    let path = Utf8Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures")
+        .join(name);
+    std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("couldn't read {path}: {e}"))
+}
+
+/// Stdout of a finished command, as text.
+pub fn stdout(output: &std::process::Output) -> String {
+    String::from_utf8(output.stdout.clone()).unwrap()
+}
+
+/// Stderr of a finished command, as text.
+pub fn stderr(output: &std::process::Output) -> String {
+    String::from_utf8(output.stderr.clone()).unwrap()
+}
@@ -0,0 +1,35 @@
+//! The C functions of the ffi feature called from C, see tests/ffi/roundtrip.c.
+//! Only built with the internal ffi-roundtrip feature, which compiles the C side.
+#![cfg(feature = "ffi-roundtrip")]
+
+mod common;
+
+use std::ffi::{c_char, c_int, CString};
+
+use common::*;
+
+extern "C" {
+    fn gcrs_check_discover(root: *const c_char) -> c_int;
+    fn gcrs_check_error(expected: *const c_char) -> c_int;
+}
+
+/// Runs both checks in one test, as they share the environment of the process.
+#[test]
+fn roundtrip_from_c() {
+    // The C code is linked after gcrs, whose functions are only kept when
+    // Rust uses them.
+    std::hint::black_box((
+        gcrs::ffi::gcrs_discover_json as extern "C" fn() -> *mut c_char,
+        gcrs::ffi::gcrs_last_error as extern "C" fn() -> *const c_char,
+        gcrs::ffi::gcrs_free as unsafe extern "C" fn(*mut c_char),
+    ));
+    let nix = FakeNix::new();
+    for (name, value) in nix.env() {
+        std::env::set_var(name, value);
+    }
+    let root = CString::new(nix.path("home/old/result").as_str()).unwrap();
+    assert_eq!(unsafe { gcrs_check_discover(root.as_ptr()) }, 0);
+    std::fs::remove_file(nix.path("bin/nix-store")).unwrap();
+    let expected = CString::new("nix-store not found").unwrap();
+    assert_eq!(unsafe { gcrs_check_error(expected.as_ptr()) }, 0);
+}
@@ -0,0 +1,45 @@
+/* Calls gcrs like a C program would, for tests/ffi.rs. Each check returns 0
+ * on success and prints what went wrong to stderr otherwise. */
+
+#include <stdio.h>
+#include <string.h>
+
+#include "gcrs.h"
+
+/* Discovers the roots, which have to include the path root. */
+int gcrs_check_discover(const char *root) {
+    char *json = gcrs_discover_json();
+    if (json == NULL) {
+        const char *error = gcrs_last_error();
+        fprintf(stderr, "gcrs_discover_json failed: %s\n", error ? error : "without an error");
+        return 1;
+    }
+    int failed = 0;
+    if (gcrs_last_error() != NULL) {
+        fprintf(stderr, "gcrs_last_error is set after success: %s\n", gcrs_last_error());
+        failed = 1;
+    }
+    if (strstr(json, "\"version\": 1") == NULL || strstr(json, root) == NULL) {
+        fprintf(stderr, "unexpected JSON:\n%s\n", json);
+        failed = 1;
+    }
+    gcrs_free(json);
+    gcrs_free(NULL);
+    return failed;
+}
+
+/* Fails to discover the roots, with an error mentioning expected. */
+int gcrs_check_error(const char *expected) {
+    char *json = gcrs_discover_json();
+    if (json != NULL) {
+        fprintf(stderr, "gcrs_discover_json succeeded:\n%s\n", json);
+        gcrs_free(json);
+        return 1;
+    }
+    const char *error = gcrs_last_error();
+    if (error == NULL || strstr(error, expected) == NULL) {
+        fprintf(stderr, "unexpected error: %s\n", error ? error : "none");
+        return 1;
+    }
+    return 0;
+}
@@ -0,0 +1,65 @@
+mod common;
+
+use common::*;
+
+/// The installation of print-roots.txt listed by a nix-store that interleaves
+/// warnings of the locale with its roots, on stdout and stderr.
+fn localized(locale: &str) -> FakeNix {
+    let nix = FakeNix::with_fixture(&format!("locale-{locale}.txt"));
+    nix.profile("nix/var/nix/profiles/system", 3);
+    nix.print_roots_stderr(&fixture_text(&format!("locale-{locale}.stderr")));
+    nix
+}
+
+fn roots_are_extracted(locale: &str) {
+    let nix = localized(locale);
+    let output = nix
+        .gcrs()
+        .env("LANG", format!("{locale}.UTF-8"))
+        .args(["print", "--plain"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(
+        nix.unsubstitute(&stdout(&output)),
+        fixture_text("print-roots.plain")
+    );
+    assert_eq!(nix.print_roots_locale(), "C");
+}
+
+fn warnings_are_counted_as_ignored_lines(locale: &str) {
+    let nix = localized(locale);
+    let output = nix
+        .gcrs()
+        .env("LANG", format!("{locale}.UTF-8"))
+        .args(["check", "--json", "--max-standalone", "100"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(0), "{}", stderr(&output));
+    let json: serde_json::Value = serde_json::from_str(&stdout(&output)).unwrap();
+    let source = &json["discovery"]["sources"][0];
+    assert_eq!(source["outcome"], "ok", "{source}");
+    assert_eq!(source["roots"], 7, "{source}");
+    assert_eq!(source["ignored_lines"], 3, "{source}");
+    assert_eq!(json["summary"]["standalone"], 3);
+}
+
+#[test]
+fn german_roots_are_extracted() {
+    roots_are_extracted("de_DE");
+}
+
+#[test]
+fn japanese_roots_are_extracted() {
+    roots_are_extracted("ja_JP");
+}
+
+#[test]
+fn german_warnings_are_ignored_lines() {
+    warnings_are_counted_as_ignored_lines("de_DE");
+}
+
+#[test]
+fn japanese_warnings_are_ignored_lines() {
+    warnings_are_counted_as_ignored_lines("ja_JP");
+}
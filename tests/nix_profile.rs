@@ -0,0 +1,84 @@
+mod common;
+
+use common::*;
+
+/// The installation of print-roots.txt with the system profile managed by
+/// `nix profile`, whose active generation has a manifest.json.
+fn new_style() -> FakeNix {
+    let nix = FakeNix::new();
+    nix.write(
+        "nix/store/33333333333333333333333333333333-nixos-system-3/manifest.json",
+        "{\"version\":3,\"elements\":{}}\n",
+    );
+    nix
+}
+
+fn delete(nix: &FakeNix, generations: &str) -> std::process::Output {
+    let profile = nix.path("nix/var/nix/profiles/system");
+    nix.gcrs()
+        .args([
+            "delete",
+            profile.as_str(),
+            "--generations",
+            generations,
+            "--yes",
+        ])
+        .output()
+        .unwrap()
+}
+
+#[test]
+fn new_style_generations_are_deleted_through_nix_env() {
+    let nix = new_style();
+    nix.install_nix_env();
+    let output = delete(&nix, "1");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).ends_with("removed 1 root\n"));
+    assert_eq!(
+        nix.unsubstitute(&nix.nix_env_runs()),
+        "--profile @ROOT@/nix/var/nix/profiles/system --delete-generations 1\n"
+    );
+    assert!(!nix.path("nix/var/nix/profiles/system-1-link").exists());
+    assert!(nix.path("nix/var/nix/profiles/system-2-link").is_symlink());
+}
+
+#[test]
+fn old_style_generations_are_unlinked() {
+    let nix = FakeNix::new();
+    nix.install_nix_env();
+    let output = delete(&nix, "1");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(nix.nix_env_runs(), "");
+    assert!(!nix.path("nix/var/nix/profiles/system-1-link").exists());
+}
+
+#[test]
+fn new_style_generations_are_unlinked_with_a_warning_without_nix_env() {
+    let nix = new_style();
+    let output = delete(&nix, "1,2");
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).ends_with("removed 2 roots\n"));
+    assert_eq!(
+        stderr(&output),
+        "warning: nix-env not found, unlinking generations of nix profile profiles directly\n"
+    );
+    assert!(!nix.path("nix/var/nix/profiles/system-1-link").exists());
+    assert!(!nix.path("nix/var/nix/profiles/system-2-link").exists());
+}
+
+#[test]
+fn ungrouped_new_style_generations_are_unlinked_without_nix_env() {
+    let nix = new_style();
+    nix.install_nix_env();
+    let link = nix.path("nix/var/nix/profiles/system-1-link");
+    let output = nix
+        .gcrs()
+        .args(["--no-group", "delete", link.as_str(), "--yes"])
+        .output()
+        .unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert!(stdout(&output).ends_with("removed 1 root\n"));
+    assert_eq!(nix.nix_env_runs(), "");
+    assert!(!link.exists());
+    assert!(nix.path("nix/var/nix/profiles/system-2-link").is_symlink());
+}
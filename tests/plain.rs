@@ -0,0 +1,72 @@
+//! The plain format is a contract with scripts, its output for the fixtures
+//! is kept in golden files next to them: tests/fixtures/<fixture>.plain.
+
+mod common;
+
+use common::*;
+
+/// Output of print with args, as in the golden files.
+fn plain(nix: &FakeNix, args: &[&str]) -> String {
+    let output = nix.gcrs().args(args).output().unwrap();
+    assert!(output.status.success(), "{}", stderr(&output));
+    assert_eq!(stderr(&output), "");
+    nix.unsubstitute(&stdout(&output))
+}
+
+#[test]
+fn standard_fixture() {
+    let nix = FakeNix::new();
+    let golden = fixture_text("print-roots.plain");
+    assert_eq!(plain(&nix, &["print", "--plain"]), golden);
+    assert_eq!(plain(&nix, &["print", "--format", "plain"]), golden);
+}
+
+#[test]
+fn channels_fixture() {
+    let nix = FakeNix::with_fixture("channels.txt");
+    nix.profile("nix/var/nix/profiles/per-user/alice/channels", 3);
+    nix.profile("nix/var/nix/profiles/system", 2);
+    assert_eq!(
+        plain(&nix, &["print", "--plain"]),
+        fixture_text("channels.plain")
+    );
+}
+
+#[test]
+fn display_options_dont_change_it() {
+    let nix = FakeNix::new();
+    let args = [
+        "--sort",
+        "age",
+        "--reverse",
+        "--sort-locale",
+        "natural",
+        "--icons",
+        "--hyperlinks",
+        "always",
+        "--no-group",
+        "print",
+        "--plain",
+    ];
+    assert_eq!(plain(&nix, &args), fixture_text("print-roots.plain"));
+}
+
+#[test]
+fn no_roots_print_nothing() {
+    let nix = FakeNix::with_fixture("print-roots.txt");
+    nix.write("print-roots.txt", "");
+    assert_eq!(plain(&nix, &["print", "--plain"]), "");
+}
+
+#[test]
+fn sizes_are_refused() {
+    let nix = FakeNix::new();
+    let output = nix
+        .gcrs()
+        .args(["print", "--plain", "--sizes"])
+        .output()
+        .unwrap();
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr(&output)
+        .contains("the plain format only has paths and targets, use --sizes with another format"));
+}